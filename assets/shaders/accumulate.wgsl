@@ -0,0 +1,21 @@
+#pragma once
+
+// the latest frame, blended into the accumulated frames with the blend constant,
+// see `Renderer::accumulate`
+@group(0) @binding(0)
+var frame_texture: texture_2d<f32>;
+
+struct FragmentInput {
+	@location(0) 
+	clip: vec4<f32>,
+	@location(1) 
+	uv: vec2<f32>,
+};
+
+@fragment
+fn main(in: FragmentInput) -> @location(0) vec4<f32> {
+	let dimensions = textureDimensions(frame_texture);
+	let index = vec2<i32>(in.uv * vec2<f32>(dimensions));
+
+	return textureLoad(frame_texture, index, 0);
+}
@@ -0,0 +1,36 @@
+#pragma once
+
+@group(0) @binding(0)
+var depth_texture: texture_2d<f32>;
+
+struct Uniforms {
+	taa_samples: u32,
+	// the clip planes of the camera, used to linearize depth
+	near: f32,
+	far: f32,
+}
+
+@group(0) @binding(1)
+var<uniform> uniforms: Uniforms;
+
+struct FragmentInput {
+	@location(0) 
+	clip: vec4<f32>,
+	@location(1) 
+	uv: vec2<f32>,
+};
+
+@fragment
+fn main(in: FragmentInput) -> @location(0) vec4<f32> {
+	let dimensions = textureDimensions(depth_texture);
+	let index = vec2<i32>(in.uv * vec2<f32>(dimensions));
+
+	let depth = textureLoad(depth_texture, index, 0).r;
+
+	// invert the perspective projection, giving the view distance along the z axis
+	let near = uniforms.near;
+	let far = uniforms.far;
+	let linear = near * far / (far - depth * (far - near));
+
+	return vec4<f32>(vec3<f32>(linear / far), 1.0);
+}
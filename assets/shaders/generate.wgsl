@@ -0,0 +1,96 @@
+// fills the octree texture with a complete tree, see `GeneratePipeline`
+//
+// nodes are stored breadth first, the nodes at each depth are ordered by their
+// path, so the children of a node are found without walking the tree
+
+// see `PackedNode`
+const PARENT_BIT = 2u;
+const FLAG_BITS = 4u;
+
+// see `DrawOctree::PAGE_SIZE`
+const PAGE_SIZE = 2048u;
+const WORKGROUP_SIZE = 64u;
+
+struct GenerateUniform {
+	// the depth of the leaves
+	depth: u32,
+	// the height of the pages of the octree texture
+	page_height: u32,
+	// the sphere, see `Sphere`
+	resolution: u32,
+	radius: f32,
+	// the packed node of solid leaves
+	solid: u32,
+}
+
+@group(0) @binding(0)
+var voxels: texture_storage_3d<r32uint, write>;
+
+@group(0) @binding(1)
+var<uniform> generate: GenerateUniform;
+
+// the index of the first node at `depth`
+fn level_start(depth: u32) -> u32 {
+	return ((1u << (3u * depth)) - 1u) / 7u;
+}
+
+// returns the unsigned cell of the node `code` at `depth`,
+// where each group of three bits is a child index, the deepest first
+fn code_cell(code: u32, depth: u32) -> vec3<u32> {
+	var cell = vec3<u32>(0u);
+
+	for (var i = 0u; i < depth; i += 1u) {
+		let child = (code >> (3u * i)) & 7u;
+		let bits = vec3<u32>(child, child >> 1u, child >> 2u) & vec3<u32>(1u);
+		cell |= bits << vec3<u32>(i);
+	}
+
+	return cell;
+}
+
+// samples the sphere like `sample_cell` does on the cpu
+fn sphere_contains(cell: vec3<i32>) -> bool {
+	let resolution = i32(generate.resolution);
+
+	if any(cell < vec3<i32>(-resolution)) || any(cell >= vec3<i32>(resolution)) {
+		return false;
+	}
+
+	let point = (vec3<f32>(cell) + 0.5) / f32(resolution);
+	return length(point) < generate.radius;
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn main(
+	@builtin(global_invocation_id) global_id: vec3<u32>,
+	@builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+	let index = global_id.x + global_id.y * workgroups.x * WORKGROUP_SIZE;
+	let depth = generate.depth;
+
+	if index >= level_start(depth + 1u) { return; }
+
+	var level = 0u;
+	while index >= level_start(level + 1u) {
+		level += 1u;
+	}
+
+	let code = index - level_start(level);
+
+	var node = 0u;
+	if level < depth {
+		let pointer = level_start(level + 1u) + code * 8u;
+		node = PARENT_BIT | (pointer << FLAG_BITS);
+	} else {
+		let half = i32((1u << depth) >> 1u);
+		let cell = vec3<i32>(code_cell(code, depth)) - half;
+
+		if sphere_contains(cell) {
+			node = generate.solid;
+		}
+	}
+
+	let row = index / PAGE_SIZE;
+	let texel = vec3<u32>(index % PAGE_SIZE, row % generate.page_height, row / generate.page_height);
+	textureStore(voxels, texel, vec4<u32>(node, 0u, 0u, 0u));
+}
@@ -6,9 +6,26 @@
 const SOLID_BIT = 1u;
 const PARENT_BIT = 2u;
 const SHADOW_BIT = 4u;
+const EMISSIVE_BIT = 8u;
 
 const EMPTY_MASK = 3u;
 
+// the solid nodes a ray stops at, nodes without `SHADOW_BIT` are translucent
+const HIT_SOLID = 0u;
+const HIT_OPAQUE = 1u;
+const HIT_TRANSLUCENT = 2u;
+
+// nodes are packed into a single u32, see `PackedNode`
+const FLAG_BITS = 4u;
+const FLAG_MASK = 15u;
+// the payload of solid nodes holds the palette index below the normal code
+const INDEX_BITS = 20u;
+const INDEX_MASK = 0xFFFFFu;
+
+// solid nodes may store an octahedral normal in the top byte, see `Node::with_normal`
+const NORMAL_SHIFT = 24u;
+const NORMAL_LEVELS = 15u;
+
 struct Node {
 	flags: u32,
 	data: u32,
@@ -26,14 +43,43 @@ fn node_is_shadow(node: Node) -> bool {
 	return (node.flags & SHADOW_BIT) != 0u;
 }
 
+fn node_is_emissive(node: Node) -> bool {
+	return (node.flags & EMISSIVE_BIT) != 0u;
+}
+
 fn node_is_empty(node: Node) -> bool {
 	return (node.flags & EMPTY_MASK) == 0u;
 }
 
+// returns true if a ray cast with `hits` stops at the solid `node`
+fn node_is_hit(node: Node, hits: u32) -> bool {
+	if hits == HIT_OPAQUE { return node_is_shadow(node); }
+	if hits == HIT_TRANSLUCENT { return !node_is_shadow(node); }
+	return true;
+}
+
 fn node_color(node: Node) -> vec4<f32> {
 	return unpack4x8unorm(node.data);
 }
 
+// returns the stored normal in octree space, or zero if there is none
+fn node_normal(node: Node) -> vec3<f32> {
+	let code = node.data >> NORMAL_SHIFT;
+	if code == 0u { return vec3<f32>(0.0); }
+
+	let level = vec2<f32>(f32((code - 1u) / NORMAL_LEVELS), f32((code - 1u) % NORMAL_LEVELS));
+	let oct = level / f32(NORMAL_LEVELS - 1u) * 2.0 - 1.0;
+	let z = 1.0 - abs(oct.x) - abs(oct.y);
+
+	var xy = oct;
+	if z < 0.0 {
+		let sign = select(vec2<f32>(-1.0), vec2<f32>(1.0), oct >= vec2<f32>(0.0));
+		xy = (1.0 - abs(oct.yx)) * sign;
+	}
+
+	return normalize(vec3<f32>(xy, z));
+}
+
 fn node_pointer(node: Node) -> u32 {
 	return node.data;
 }
@@ -44,27 +90,60 @@ var voxels: texture_3d<u32>;
 struct OctreeUniform {
 	model: mat4x4<f32>,
 	model_inv: mat4x4<f32>,
+	occupancy_resolution: u32,
+	// the alpha the octree is blended with, below 1 nothing is drawn where it's missed
+	opacity: f32,
 }
 
 @group(2) @binding(1)
 var<uniform> octree: OctreeUniform;
 
+// one bit per cell of a coarse grid, set if the cell contains solid nodes
+@group(2) @binding(2)
+var<storage, read> occupancy: array<u32>;
+
+// colors of solid nodes, indexed by the payload of packed nodes
+@group(2) @binding(3)
+var<storage, read> palette: array<u32>;
+
 struct OctreeHit {
 	hit: bool,
 	node: Node,
 	normal: vec3<f32>,
 	position: vec3<f32>,
 	step_count: u32,
+	// the hit position within the cell that was hit, from 0 to 1 along each axis
+	local: vec3<f32>,
+	// the size of the cell that was hit in normalized space
+	cell_size: f32,
+	// the distance from the hit to the closest edge of its face, relative to the cell size
+	edge: f32,
+	// the direction from the hit towards that edge, along the face
+	edge_direction: vec3<f32>,
+	// the cell that was hit, signed like `Branch`
+	path: vec3<i32>,
+	depth: u32,
 }
 
 fn octree_get_node(pointer: u32) -> Node {
-	// indices are encoded as 12 bits for x, 12 bits for y, 8 bits for z
-	let x = (pointer >>  0u) & 0xFFFu;
-	let y = (pointer >> 12u) & 0xFFFu;
-	let z = (pointer >> 24u) & 0xFFu;
+	// indices are encoded as 11 bits for x, 11 bits for y, 10 bits for z
+	let x = (pointer >>  0u) & 0x7FFu;
+	let y = (pointer >> 11u) & 0x7FFu;
+	let z = (pointer >> 22u) & 0x3FFu;
+
+	let bits = textureLoad(voxels, vec3<u32>(x, y, z), 0).r;
+	return node_unpack(bits);
+}
+
+fn node_unpack(bits: u32) -> Node {
+	let flags = bits & FLAG_MASK;
+	var data = bits >> FLAG_BITS;
+
+	if (flags & PARENT_BIT) == 0u && (flags & SOLID_BIT) != 0u {
+		data = palette[data & INDEX_MASK] | ((data >> INDEX_BITS) << NORMAL_SHIFT);
+	}
 
-	let data = textureLoad(voxels, vec3<u32>(x, y, z), 0);
-	return Node(data.r, data.g);
+	return Node(flags, data);
 }
 
 fn octree_in_bounds(point: vec3<f32>) -> bool {
@@ -120,7 +199,63 @@ fn octree_extract_child(path: vec3<i32>, depth: u32) -> u32 {
 		| (u32((path.z & (1 << depth)) != 0) << 2u);
 }
 
-fn octree_ray_cast_normalized(ray: Ray, main_ray: bool) -> OctreeHit {
+fn octree_is_occupied(cell: vec3<i32>) -> bool {
+	let resolution = octree.occupancy_resolution;
+	let index = u32(cell.x) + (u32(cell.y) + u32(cell.z) * resolution) * resolution;
+	return (occupancy[index / 32u] & (1u << (index % 32u))) != 0u;
+}
+
+// the cell of the occupancy grid containing `position`,
+// nudged along `direction` so positions on a boundary pick the cell the ray enters
+fn octree_occupancy_cell(position: vec3<f32>, direction: vec3<f32>) -> vec3<i32> {
+	let resolution = i32(octree.occupancy_resolution);
+	let point = position + normalize(direction) * EPSILON;
+	let cell = vec3<i32>(floor((point + 1.0) * f32(resolution) / 2.0));
+	return clamp(cell, vec3<i32>(0), vec3<i32>(resolution - 1));
+}
+
+// step through empty cells of the occupancy grid,
+// returns false if the ray leaves the octree before reaching an occupied cell
+fn octree_skip_empty(hit: ptr<function, OctreeHit>, direction: vec3<f32>) -> bool {
+	let resolution = i32(octree.occupancy_resolution);
+	if resolution == 0 { return true; }
+
+	let cell_size = 2.0 / f32(resolution);
+	let dir = vec3<i32>(sign(direction));
+	let upper = vec3<i32>(dir > vec3<i32>(0));
+
+	var cell = octree_occupancy_cell((*hit).position, direction);
+
+	for (var _i = 0; _i < resolution * 3; _i += 1) {
+		if octree_is_occupied(cell) { return true; }
+
+		(*hit).step_count += 1u;
+
+		let bounds = vec3<f32>(cell + upper) * cell_size - 1.0;
+		var t = (bounds - (*hit).position) / direction;
+		t = select(t, vec3<f32>(1e30), dir == vec3<i32>(0));
+
+		let tmin = min(t.x, min(t.y, t.z));
+		if tmin == t.x {
+			cell.x += dir.x;
+			(*hit).normal = vec3<f32>(f32(-dir.x), 0.0, 0.0);
+		} else if tmin == t.y {
+			cell.y += dir.y;
+			(*hit).normal = vec3<f32>(0.0, f32(-dir.y), 0.0);
+		} else {
+			cell.z += dir.z;
+			(*hit).normal = vec3<f32>(0.0, 0.0, f32(-dir.z));
+		}
+
+		(*hit).position += direction * tmin;
+
+		if any(cell < vec3<i32>(0)) || any(cell >= vec3<i32>(resolution)) { return false; }
+	}
+
+	return false;
+}
+
+fn octree_ray_cast_normalized(ray: Ray, hits: u32) -> OctreeHit {
 	var hit: OctreeHit;
 	hit.hit = false;
 	hit.step_count = 0u;
@@ -134,15 +269,39 @@ fn octree_ray_cast_normalized(ray: Ray, main_ray: bool) -> OctreeHit {
 	hit.normal = vec3<f32>(sign(hit.position)) * vec3<f32>(abs(hit.position) > 1.0 - EPSILON);
 	let dir = vec3<i32>(sign(ray.direction));	
 
+	// the ray starts inside the octree, face back along the dominant axis of the ray
+	if all(hit.normal == vec3<f32>(0.0)) {
+		let abs_dir = abs(ray.direction);
+		if abs_dir.x >= abs_dir.y && abs_dir.x >= abs_dir.z {
+			hit.normal = vec3<f32>(-sign(ray.direction.x), 0.0, 0.0);
+		} else if abs_dir.y >= abs_dir.z {
+			hit.normal = vec3<f32>(0.0, -sign(ray.direction.y), 0.0);
+		} else {
+			hit.normal = vec3<f32>(0.0, 0.0, -sign(ray.direction.z));
+		}
+	}
+
 	// handle root node
 	let root = octree_get_node(0u);
 	if node_is_empty(root) { return hit; }
 	if node_is_solid(root) { 
+		if !node_is_hit(root, hits) { return hit; }
+
 		hit.hit = true; 
 		hit.node = root;
+		hit.cell_size = 2.0;
+		hit.local = (hit.position + 1.0) / 2.0;
+		hit.path = vec3<i32>(0);
+		hit.depth = 0u;
 		return hit; 
 	}
 
+	// the depth of the cells of the occupancy grid
+	var skip_depth = 32u;
+	if octree.occupancy_resolution != 0u {
+		skip_depth = firstTrailingBit(octree.occupancy_resolution);
+	}
+
 	// initialize traversal
 	var parent = node_pointer(root);
 	var depth = 0u;
@@ -156,6 +315,21 @@ fn octree_ray_cast_normalized(ray: Ray, main_ray: bool) -> OctreeHit {
 
 		let node = octree_get_node(parent + child);
 
+		// parents within an empty cell of the grid and nodes smaller than one only
+		// exist in trees that keep empty parents, like those generated on the gpu,
+		// skip the empty cells and start over from the root at the next occupied one
+		let level = depth + 1u;
+		let fine = level > skip_depth || (level == skip_depth && node_is_parent(node));
+		if fine && !octree_is_occupied(octree_occupancy_cell(hit.position, ray.direction)) {
+			if !octree_skip_empty(&hit, ray.direction) { break; }
+
+			parent = node_pointer(root);
+			depth = 0u;
+			child = octree_select_initial_child(hit.position);
+			path = octree_add_child(vec3<i32>(0), child);
+			continue;
+		}
+
 		if node_is_parent(node) {
 			parent = node_pointer(node);
 			child = octree_select_child(path, hit.position, depth);
@@ -166,10 +340,20 @@ fn octree_ray_cast_normalized(ray: Ray, main_ray: bool) -> OctreeHit {
 			continue;
 		}
 
+		// opaque nodes hide the translucent ones behind them, so there is no point going on
+		if hits == HIT_TRANSLUCENT && node_is_solid(node) && node_is_shadow(node) {
+			break;
+		}
+
 		// if node is solid, return hit
-		if node_is_solid(node) && (main_ray || node_is_shadow(node)) {
+		if node_is_solid(node) && node_is_hit(node, hits) {
 			hit.hit = true;
 			hit.node = node;
+			hit.cell_size = 1.0 / f32(1u << depth);
+			hit.local = fract((hit.position + 1.0) / hit.cell_size);
+			// the traversal path is unsigned and one level deeper than `depth`
+			hit.path = path - vec3<i32>(1 << depth);
+			hit.depth = depth + 1u;
 			break;
 		}
 
@@ -216,12 +400,27 @@ fn octree_ray_cast_normalized(ray: Ray, main_ray: bool) -> OctreeHit {
 	return hit;
 }
 
-fn octree_ray_cast(ray: Ray, main_ray: bool) -> OctreeHit {
+fn octree_ray_cast(ray: Ray, hits: u32) -> OctreeHit {
 	let normalized_ray = ray_transform(ray, octree.model_inv);
-	var hit = octree_ray_cast_normalized(normalized_ray, main_ray);
+	var hit = octree_ray_cast_normalized(normalized_ray, hits);
 
 	if !hit.hit { return hit; }
 
+	// ignore the axis along the normal, the hit lies on that face
+	let edge = min(hit.local, 1.0 - hit.local);
+	let tangent = select(edge, vec3<f32>(1.0), abs(hit.normal) > vec3<f32>(0.5));
+	hit.edge = min(tangent.x, min(tangent.y, tangent.z));
+
+	let side = select(vec3<f32>(1.0), vec3<f32>(-1.0), hit.local < vec3<f32>(0.5));
+	var across = vec3<f32>(0.0, 0.0, side.z);
+	if hit.edge == tangent.x {
+		across = vec3<f32>(side.x, 0.0, 0.0);
+	} else if hit.edge == tangent.y {
+		across = vec3<f32>(0.0, side.y, 0.0);
+	}
+
+	hit.edge_direction = normalize((octree.model * vec4<f32>(across, 0.0)).xyz);
+
 	let position = octree.model * vec4<f32>(hit.position, 1.0);
 	let normal = octree.model * vec4<f32>(hit.normal, 0.0);
 
@@ -6,6 +6,8 @@
 #include "camera.wgsl"
 #include "poisson.wgsl"
 
+const EMISSIVE_STRENGTH = 2.0;
+
 @group(0) @binding(1)
 var render_target: texture_storage_2d_array<rgba16float, write>;
 
@@ -18,17 +20,23 @@ var<uniform> taa_sample: i32;
 fn sample(clip: vec2<f32>) -> vec4<f32> {
 	let ray = camera_ray(clip);
 
-	let hit = octree_ray_cast(ray, true);
+	let hit = octree_ray_cast(ray, HIT_SOLID);
 	if !hit.hit { return vec4<f32>(0.48, 0.84, 0.83, 1.0); }
 
 	let sun_dir = normalize(vec3<f32>(0.9, 1.0, -0.8));
 	var sun_dif = abs(dot(sun_dir, normalize(hit.normal))) * 0.35 + 0.65;	
 
 	let shadow_ray = Ray(hit.position + hit.normal * EPSILON, sun_dir);
-	let shadow_hit = octree_ray_cast(shadow_ray, false);
+	let shadow_hit = octree_ray_cast(shadow_ray, HIT_OPAQUE);
 	let shadow = f32(!shadow_hit.hit) * 0.3 + sun_dif * 0.7;
 
-	let color = node_color(hit.node).rgb * shadow;
+	var color = node_color(hit.node).rgb * shadow;
+
+	// emissive nodes glow regardless of lighting
+	if node_is_emissive(hit.node) {
+		color = node_color(hit.node).rgb * EMISSIVE_STRENGTH;
+	}
+
 	return vec4<f32>(color, 1.0);
 }
 
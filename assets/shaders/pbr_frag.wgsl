@@ -6,14 +6,62 @@
 #include "camera.wgsl"
 #include "poisson.wgsl"
 
+const EMISSIVE_STRENGTH = 2.0;
+
+// the alpha translucent nodes are blended over what's behind them with
+const TRANSLUCENT_OPACITY = 0.5;
+
+// the traversal steps shown as the hottest color of the heatmap
+const HEATMAP_STEPS = 128.0;
+
+const GRID_COLOR = vec3<f32>(0.15, 0.15, 0.15);
+const GRID_OPACITY = 0.6;
+// the distance at which the ground grid has faded out completely
+const GRID_FADE = 60.0;
+
 struct Uniforms {
 	taa_sample: u32,
+	// scene time in seconds
+	time: f32,
 	dimensions: vec2<u32>,
+	// soften voxel edges based on their coverage of the pixel
+	aa_enabled: u32,
+	// the depth of the cells outlined on the ground plane, 0 hides the grid
+	grid_depth: u32,
+	// draw the traversal steps of each pixel instead of the scene, see `RenderMode::Steps`
+	steps: u32,
+	// draw the surface normal of each pixel instead of the scene, see `RenderMode::Normals`
+	normals: u32,
+	// surfaces fade towards the fog color by `exp(-fog_density * distance)`
+	fog_color: vec3<f32>,
+	fog_density: f32,
+	// light reaching every surface, so faces in shadow aren't black
+	ambient_color: vec3<f32>,
 }
 
 @group(0) @binding(1)
 var<uniform> uniforms: Uniforms;
 
+// see `MAX_LIGHTS` and `LightUniform`
+const MAX_LIGHTS = 8u;
+const LIGHT_POINT = 1u;
+
+struct Light {
+	// the position of point lights, or the direction towards directional lights
+	position: vec3<f32>,
+	kind: u32,
+	color: vec3<f32>,
+	intensity: f32,
+}
+
+struct Lights {
+	lights: array<Light, MAX_LIGHTS>,
+	count: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> lights: Lights;
+
 struct FragmentInput {
 	#include "fullscreen_input.wgsl"
 }
@@ -25,31 +73,208 @@ struct FragmentOutput {
 	color: vec4<f32>,	
 }
 
-fn sample(clip: vec2<f32>) -> FragmentOutput {
-	let ray = camera_ray(clip);
+// estimate how much of the pixel is covered by the face that was hit, from 1 for
+// pixels within the face to 0.5 for those centered on its edge
+fn edge_coverage(hit: OctreeHit, distance: f32) -> f32 {
+	// the size of a pixel at the hit, relative to the size of the cell
+	let footprint = pixel_size(distance) / cell_size(hit);
 
-	let hit = octree_ray_cast(ray, true);
-	if !hit.hit { discard; }
+	return clamp(0.5 + hit.edge / footprint, 0.5, 1.0);
+}
+
+// the size of the cell that was hit, in world space
+fn cell_size(hit: OctreeHit) -> f32 {
+	return hit.cell_size * length(octree.model[0].xyz);
+}
+
+// the size of a pixel in world space, `distance` away from the camera
+fn pixel_size(distance: f32) -> f32 {
+	return distance * 2.0 / (camera.proj[1][1] * f32(uniforms.dimensions.y));
+}
+
+// outline the cells of the octree at `uniforms.grid_depth` where `ray` hits the y = 0 plane
+fn ground_grid(ray: Ray) -> FragmentOutput {
+	let t = -ray.origin.y / ray.direction.y;
+	if uniforms.grid_depth == 0u || t <= 0.0 { discard; }
+
+	let position = ray.origin + ray.direction * t;
+	let local = (octree.model_inv * vec4<f32>(position, 1.0)).xz;
+
+	// the distance to the closest line, in cells
+	let cells = f32(1u << (uniforms.grid_depth - 1u));
+	let line = abs(local * cells - round(local * cells));
+
+	let cell = length(octree.model[0].xyz) / cells;
+	let footprint = pixel_size(t) / cell;
+	let coverage = 1.0 - smoothstep(0.0, footprint, min(line.x, line.y));
+
+	// fade out in the distance, and before the lines are denser than the pixels
+	let fade = (1.0 - smoothstep(0.0, GRID_FADE, t)) * (1.0 - smoothstep(0.2, 0.5, footprint));
+
+	let alpha = coverage * fade * GRID_OPACITY;
+	if alpha <= 0.0 { discard; }
+
+	let clip = world_to_clip(position);
+
+	var out: FragmentOutput;
+	out.depth = clip.z / clip.w;
+	out.color = vec4<f32>(GRID_COLOR, alpha);
+
+	return out;
+}
+
+// the light reaching `position` from the lights other than the sun, which cast no shadows
+fn light_irradiance(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+	var irradiance = vec3<f32>(0.0);
+
+	for (var i = 0u; i < min(lights.count, MAX_LIGHTS); i += 1u) {
+		let light = lights.lights[i];
+
+		var direction = light.position;
+		var attenuation = 1.0;
+
+		if light.kind == LIGHT_POINT {
+			let offset = light.position - position;
+			let distance = length(offset);
+			direction = offset / max(distance, EPSILON);
+			attenuation = 1.0 / (1.0 + distance * distance);
+		}
+
+		irradiance += light.color * light.intensity * max(dot(normal, direction), 0.0) * attenuation;
+	}
+
+	return irradiance;
+}
+
+// the normal `hit` is shaded with, the stored one if there is one, the silhouette stays that of the cells
+fn surface_normal(hit: OctreeHit) -> vec3<f32> {
+	let stored = node_normal(hit.node);
+	if any(stored != vec3<f32>(0.0)) {
+		return normalize((octree.model * vec4<f32>(stored, 0.0)).xyz);
+	}
+
+	return hit.normal;
+}
+
+// the lit color of `hit`, faded into the fog
+fn surface_color(ray: Ray, hit: OctreeHit) -> vec3<f32> {
+	let normal = surface_normal(hit);
 
 	let sun_dir = normalize(vec3<f32>(0.9, 1.0, -0.8));
-	var sun_dif = abs(dot(sun_dir, normalize(hit.normal))) * 0.5 + 0.5;
+	var sun_dif = abs(dot(sun_dir, normal)) * 0.5 + 0.5;
 
 	let shadow_ray = Ray(hit.position + hit.normal * EPSILON, sun_dir);
-	let shadow_hit = octree_ray_cast(shadow_ray, false);
+	let shadow_hit = octree_ray_cast(shadow_ray, HIT_OPAQUE);
 	let shadow = f32(!shadow_hit.hit) * 0.2 + sun_dif * 0.8;
 
+	let irradiance = shadow + light_irradiance(hit.position, normal) + uniforms.ambient_color;
+	var color = node_color(hit.node).rgb * irradiance;
+
+	// emissive nodes glow regardless of lighting
+	if node_is_emissive(hit.node) {
+		color = node_color(hit.node).rgb * EMISSIVE_STRENGTH;
+	}
+
+	let distance = distance(ray.origin, hit.position);
+	return mix(uniforms.fog_color, color, exp(-uniforms.fog_density * distance));
+}
+
+// shade `hit`, found by casting `ray` against `hits`, blended with `alpha`
+fn shade(ray: Ray, hit: OctreeHit, hits: u32, alpha: f32) -> FragmentOutput {
+	var color = surface_color(ray, hit);
+	var alpha = alpha;
+
+	// blend pixels on the edge of the face with what shows through across the edge
+	let distance = distance(ray.origin, hit.position);
+	let coverage = edge_coverage(hit, distance);
+	if uniforms.aa_enabled != 0u && coverage < 1.0 {
+		let offset = hit.edge * cell_size(hit) + pixel_size(distance) * 0.5;
+		let across = hit.position + hit.edge_direction * offset;
+
+		let behind_ray = Ray(ray.origin, normalize(across - ray.origin));
+		let behind = octree_ray_cast(behind_ray, hits);
+
+		if behind.hit {
+			color = mix(surface_color(behind_ray, behind), color, coverage);
+		} else {
+			// nothing else is hit, the pipeline blends with what's already drawn
+			alpha *= coverage;
+		}
+	}
+
+	let clip = world_to_clip(hit.position);
+
+	var out: FragmentOutput;
+	out.depth = clip.z / clip.w;
+	out.color = vec4<f32>(color, alpha);
+
+	return out;
+}
+
+// color the steps taken by `hit` from blue for none, over green, to red for `HEATMAP_STEPS` or more
+fn heatmap(hit: OctreeHit) -> FragmentOutput {
+	let t = min(f32(hit.step_count) / HEATMAP_STEPS, 1.0);
+
+	var out: FragmentOutput;
+	out.depth = 1.0;
+	out.color = vec4<f32>(t, 1.0 - abs(t * 2.0 - 1.0), 1.0 - t, 1.0);
+
+	if hit.hit {
+		let clip = world_to_clip(hit.position);
+		out.depth = clip.z / clip.w;
+	}
+
+	return out;
+}
+
+// color the surface normal of `hit` with each axis mapped from -1..1 to 0..1, without lighting
+fn normal_color(hit: OctreeHit) -> FragmentOutput {
+	if !hit.hit { discard; }
+
 	let clip = world_to_clip(hit.position);
-	let color = node_color(hit.node).rgb * shadow;
 
 	var out: FragmentOutput;
 	out.depth = clip.z / clip.w;
-	out.color = vec4<f32>(color, 1.0);
+	out.color = vec4<f32>(surface_normal(hit) * 0.5 + 0.5, 1.0);
 
 	return out;
 }
 
+fn jitter(clip: vec2<f32>) -> vec2<f32> {
+	return clip + POISSON_DISK[uniforms.taa_sample] / vec2<f32>(uniforms.dimensions);
+}
+
+// the opaque pass, writes depth so translucent nodes behind opaque ones are hidden
 @fragment
 fn main(in: FragmentInput) -> FragmentOutput {
-	let offset = POISSON_DISK[uniforms.taa_sample] / vec2<f32>(uniforms.dimensions);
-	return sample(in.clip.xy + offset);
+	let ray = camera_ray(jitter(in.clip.xy));
+
+	if uniforms.steps != 0u {
+		return heatmap(octree_ray_cast(ray, HIT_SOLID));
+	}
+
+	if uniforms.normals != 0u {
+		return normal_color(octree_ray_cast(ray, HIT_SOLID));
+	}
+
+	let hit = octree_ray_cast(ray, HIT_OPAQUE);
+	if !hit.hit {
+		// a translucent octree is drawn over another, which draws the ground
+		if octree.opacity < 1.0 { discard; }
+		return ground_grid(ray);
+	}
+
+	return shade(ray, hit, HIT_OPAQUE, octree.opacity);
+}
+
+// the translucent pass, drawn after the opaque one without writing depth,
+// rays end as misses at the first opaque node, which the opaque pass drew
+@fragment
+fn translucent(in: FragmentInput) -> FragmentOutput {
+	let ray = camera_ray(jitter(in.clip.xy));
+
+	let hit = octree_ray_cast(ray, HIT_TRANSLUCENT);
+	if !hit.hit { discard; }
+
+	return shade(ray, hit, HIT_TRANSLUCENT, TRANSLUCENT_OPACITY * octree.opacity);
 }
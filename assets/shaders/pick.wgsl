@@ -0,0 +1,43 @@
+// casts rays through pixels with the camera of the passes, see `Renderer::pick`
+//
+// unlike the opaque pass the rays stop at translucent nodes too, like `World::pick`
+// on the cpu, so picking a glass cell picks the glass and not what is behind it
+
+#include "octree.wgsl"
+#include "camera.wgsl"
+
+struct PickUniform {
+	// the size of the view in physical pixels
+	dimensions: vec2<u32>,
+	// the number of pixels to pick
+	count: u32,
+}
+
+// pixel positions from the top left of the view
+@group(0) @binding(1)
+var<storage, read> pixels: array<vec2<f32>>;
+
+// the path and depth of the hit cell, the depth is -1 for misses
+@group(0) @binding(2)
+var<storage, read_write> picks: array<vec4<i32>>;
+
+@group(0) @binding(3)
+var<uniform> pick: PickUniform;
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+	let index = global_id.x;
+	if index >= pick.count { return; }
+
+	let dimensions = vec2<f32>(pick.dimensions);
+	let pixel = pixels[index];
+	let clip = vec2<f32>(pixel.x / dimensions.x * 2.0 - 1.0, pixel.y / dimensions.y * -2.0 + 1.0);
+
+	let hit = octree_ray_cast(camera_ray(clip), HIT_SOLID);
+	if !hit.hit {
+		picks[index] = vec4<i32>(0, 0, 0, -1);
+		return;
+	}
+
+	picks[index] = vec4<i32>(hit.path, i32(hit.depth));
+}
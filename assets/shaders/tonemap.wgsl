@@ -3,6 +3,14 @@
 @group(0) @binding(0)
 var hdr_texture: texture_2d_array<f32>;
 
+struct Uniforms {
+	// every layer of the hdr texture is a taa sample, the latest one written by the octree pass
+	taa_samples: u32,
+}
+
+@group(0) @binding(1)
+var<uniform> uniforms: Uniforms;
+
 struct FragmentInput {
 	@location(0) 
 	clip: vec4<f32>,
@@ -19,19 +27,28 @@ fn tonemap_aces(rgb: vec3<f32>) -> vec3<f32> {
 	return (rgb * (a * rgb + b)) / (rgb * (c * rgb + d) + e);
 }
 
-@fragment
-fn main(in: FragmentInput) -> @location(0) vec4<f32> {
+// the average of every taa sample
+fn resolve(in: FragmentInput) -> vec4<f32> {
 	let dimensions = textureDimensions(hdr_texture);
 	let index = vec2<i32>(in.uv * vec2<f32>(dimensions));
 
 	var color: vec4<f32> = vec4<f32>(0.0);
 
-	let samples = textureNumLayers(hdr_texture);
+	let samples = i32(uniforms.taa_samples);
 	for (var i = 0; i < samples; i += 1) {
 		color = color + textureLoad(hdr_texture, index, i, 0);
 	}
 
-	color = color / f32(samples);
+	return color / f32(samples);
+}
 
-	return vec4<f32>(tonemap_aces(color.rgb), 1.0);
+@fragment
+fn main(in: FragmentInput) -> @location(0) vec4<f32> {
+	return vec4<f32>(tonemap_aces(resolve(in).rgb), 1.0);
+}
+
+// for render modes that draw data instead of light, see `TonemapPipeline::raw_pipeline`
+@fragment
+fn raw(in: FragmentInput) -> @location(0) vec4<f32> {
+	return vec4<f32>(resolve(in).rgb, 1.0);
 }
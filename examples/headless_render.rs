@@ -0,0 +1,40 @@
+//! Generate a sphere and render it without a window, saving the image as a png.
+//!
+//! ```sh
+//! cargo run --example headless_render --features headless -- sphere.png
+//! ```
+
+use std::env;
+
+use glam::Vec3;
+use oakum::{
+    error::OakumError,
+    generate::Sphere,
+    octree::{DynamicOctree, Node, Octree},
+    render::Renderer,
+    world::World,
+};
+
+fn main() -> Result<(), OakumError> {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("headless_render.png"));
+
+    let mut world = World::new();
+    let sphere = Sphere::new(8, 4).with_color(Node::solid(200, 120, 60));
+    world.octree = DynamicOctree::new(Octree::generate(&sphere));
+
+    world.camera.distance = 30.0;
+    world.camera.look_at(Vec3::ZERO);
+
+    let mut renderer = Renderer::headless(512, 512)?;
+
+    // a single sample is enough for one frame, more would need more frames to converge
+    renderer.set_taa_samples(1)?;
+    renderer.render_frame(&world)?;
+    renderer.save_png(&path)?;
+
+    println!("Saved {}", path);
+
+    Ok(())
+}
@@ -1,21 +1,26 @@
-use std::time::Instant;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use glam::{Mat4, Vec2, Vec3};
+use glam::{UVec2, Vec2};
+use log::{error, info, warn};
 use winit::{
-    event::{
-        DeviceEvent, ElementState, Event, KeyboardInput, MouseButton,
-        MouseScrollDelta::{LineDelta, PixelDelta},
-        WindowEvent,
-    },
-    window::Window,
+    event::{Event, WindowEvent},
+    window::{CursorGrabMode, Window},
 };
 
 use crate::{
-    generate::{GrassBlock, Sphere},
-    input::{Key, Keyboard, Mouse},
-    octree::{Branch, Octree},
-    render::Renderer,
-    world::World,
+    config::Config,
+    editor::{EditMode, Editor},
+    error::OakumError,
+    format::Format,
+    generate::Sphere,
+    input::{InputEvent, Key, Keyboard, Mouse, Recording},
+    octree::Octree,
+    render::{CameraPath, RenderMode, Renderer},
+    session::Session,
+    world::{Brush, BrushLibrary, World},
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -23,48 +28,116 @@ pub struct UpdateContext<'a> {
     pub delta: f32,
     pub mouse: &'a Mouse,
     pub keyboard: &'a Keyboard,
+    /// The cursor position, the center of the viewport while the cursor is grabbed.
+    pub cursor: Vec2,
+    /// The size of the viewport in pixels.
+    pub viewport: UVec2,
 }
 
 pub struct App {
-    pub world: World,
+    pub editor: Editor,
     pub renderer: Renderer,
     pub window: Window,
     pub mouse: Mouse,
     pub keyboard: Keyboard,
     pub last_frame: Instant,
-    pub sphere: Octree,
+    /// Frames per second to limit rendering to, `None` renders as fast as possible.
+    pub max_fps: Option<u32>,
+    /// Toggles grabbing the cursor for free look, `Escape` always releases it.
+    pub grab_key: Key,
+    pub cursor_grabbed: bool,
+    pub reload_shaders_key: Key,
+    pub grid_key: Key,
+    pub heatmap_key: Key,
+    pub normals_key: Key,
+    /// Adds the camera as a keyframe to [`App::camera_path`] and saves it.
+    pub keyframe_key: Key,
+    /// The camera path being recorded and when its first keyframe was added.
+    pub camera_path: Option<(Instant, CameraPath)>,
+    /// Save the session to [`Session::PATH`] when closing.
+    pub save_session: bool,
+    /// The input received since [`App::start_recording`], if recording.
+    pub recording: Option<(Instant, Recording)>,
+    /// The scale factor of the window, cursor positions and sizes are all in
+    /// physical pixels, the scale factor is only needed when it changes.
+    pub scale_factor: f64,
 }
 
 impl App {
-    pub unsafe fn new(window: Window) -> Self {
-        let renderer = Renderer::new(&window).unwrap();
+    /// # Safety
+    ///
+    /// See [`Renderer::new`], the app owns `window` so it outlives the renderer.
+    pub unsafe fn new(window: Window, config: &Config) -> Result<Self, OakumError> {
+        let mut renderer = Renderer::new(&window, config.backend.backends())?;
+        renderer.set_present_mode(config.present_mode.present_mode());
+
         let mut world = World::new();
 
-        let grass = Octree::generate(&GrassBlock);
-        let sphere = Octree::generate(&Sphere::new(32, 6));
+        // an explicitly given world or scene takes precedence over the last session
+        let session = match config.resumes_session() {
+            true => Self::restore_session(),
+            false => None,
+        };
 
-        for x in -8..8 {
-            for y in -8..8 {
-                for z in -8..8 {
-                    world
-                        .octree
-                        .union((x * 16 + 8, y * 16 + 8, z * 16 + 8, 10), 5, &grass);
-                }
+        match (&config.world, &session) {
+            (Some(path), _) => {
+                let octree = Format::from_path(path)?.read(path)?;
+                world.octree.replace(octree);
             }
+            (None, Some(session)) => world.octree.replace(session.octree.clone()),
+            (None, None) => world
+                .octree
+                .replace(config.scene.unwrap_or_default().build()),
+        }
+
+        match session {
+            Some(ref session) => world.camera = session.camera.clone(),
+            None => config.camera.apply(&mut world.camera),
+        }
+
+        let sphere = Octree::generate(&Sphere::new(32, 6));
+        let mut brushes = BrushLibrary::new(Brush::new("sphere", sphere));
+
+        for path in &config.brushes {
+            brushes.load(path)?;
+        }
+
+        if let Some(ref session) = session {
+            brushes.select_name(&session.brush);
+        }
+
+        let mut editor = Editor::new(world, brushes, &config.keys);
+        if let Some(session) = session {
+            editor.edit_mode = session.edit_mode;
         }
 
-        Self {
-            world,
+        let app = Self {
+            editor,
             renderer,
+            scale_factor: window.scale_factor(),
             window,
             mouse: Mouse::default(),
             keyboard: Keyboard::default(),
             last_frame: Instant::now(),
-            sphere,
-        }
+            max_fps: config.max_fps,
+            grab_key: config.keys.grab,
+            cursor_grabbed: false,
+            reload_shaders_key: config.keys.reload_shaders,
+            grid_key: config.keys.grid,
+            heatmap_key: config.keys.heatmap,
+            normals_key: config.keys.normals,
+            keyframe_key: config.keys.keyframe,
+            camera_path: None,
+            save_session: config.session,
+            recording: None,
+        };
+
+        app.update_title();
+
+        Ok(app)
     }
 
-    pub fn update(&mut self) -> anyhow::Result<()> {
+    pub fn update(&mut self) -> Result<(), OakumError> {
         let now = Instant::now();
         let delta = now - self.last_frame;
         self.last_frame = now;
@@ -73,90 +146,199 @@ impl App {
             delta: delta.as_secs_f32(),
             mouse: &self.mouse,
             keyboard: &self.keyboard,
+            cursor: self.cursor_position(),
+            viewport: self.renderer.size(),
         };
 
-        self.world.update(cx);
+        let edit_mode = self.editor.edit_mode;
+        self.editor.update(cx);
 
-        if self.mouse.is_pressed(MouseButton::Right)
-            || self.mouse.is_held(MouseButton::Right) && self.keyboard.is_held(Key::F)
-        {
-            let w = self.window.inner_size().width;
-            let h = self.window.inner_size().height;
-            let ray = self.world.camera.mouse_ray(w, h, self.mouse.position);
-
-            let scale = Mat4::from_scale(Vec3::splat(10.0));
-            if let Some(hit) = self.world.octree.raycast(scale, ray) {
-                let mut branch = Branch::from_point(scale, hit.point, 10);
-                branch.path += hit.normal;
-                self.world.octree.difference(branch, 4, &self.sphere);
-            }
-        } else if self.mouse.is_pressed(MouseButton::Left)
-            || self.mouse.is_held(MouseButton::Left) && self.keyboard.is_held(Key::F)
-        {
-            let w = self.window.inner_size().width;
-            let h = self.window.inner_size().height;
-            let ray = self.world.camera.mouse_ray(w, h, self.mouse.position);
-
-            let scale = Mat4::from_scale(Vec3::splat(10.0));
-            if let Some(hit) = self.world.octree.raycast(scale, ray) {
-                let mut branch = Branch::from_point(scale, hit.point, 10);
-                branch.path += hit.normal;
-                self.world.octree.union(branch, 4, &self.sphere);
+        if self.editor.edit_mode != edit_mode {
+            self.update_title();
+        }
+
+        if self.keyboard.is_pressed(self.grab_key) {
+            self.set_cursor_grab(!self.cursor_grabbed);
+        } else if self.keyboard.is_pressed(Key::Escape) {
+            self.set_cursor_grab(false);
+        }
+
+        if self.keyboard.is_pressed(self.keyframe_key) {
+            self.add_keyframe();
+        }
+
+        if self.keyboard.is_pressed(self.reload_shaders_key) {
+            match self.renderer.reload_shaders() {
+                Ok(()) => info!("Reloaded shaders"),
+                Err(err) => error!("Failed to reload shaders: {}", err),
             }
         }
 
+        if self.keyboard.is_pressed(self.grid_key) {
+            self.renderer.grid_enabled = !self.renderer.grid_enabled;
+        }
+
+        if self.keyboard.is_pressed(self.heatmap_key) {
+            self.toggle_render_mode(RenderMode::Steps);
+        }
+
+        if self.keyboard.is_pressed(self.normals_key) {
+            self.toggle_render_mode(RenderMode::Normals);
+        }
+
         Ok(())
     }
 
-    pub fn post_update(&mut self) -> anyhow::Result<()> {
+    pub fn set_edit_mode(&mut self, mode: EditMode) {
+        self.editor.edit_mode = mode;
+        self.update_title();
+    }
+
+    /// Switch to `mode`, or back to [`RenderMode::Color`] if already in it.
+    pub fn toggle_render_mode(&mut self, mode: RenderMode) {
+        self.renderer.render_mode = if self.renderer.render_mode == mode {
+            RenderMode::Color
+        } else {
+            mode
+        };
+
+        self.update_title();
+    }
+
+    fn update_title(&self) {
+        let title = match self.renderer.render_mode {
+            RenderMode::Color => format!("Oakum - {}", self.editor.edit_mode),
+            mode => format!("Oakum - {} - {}", self.editor.edit_mode, mode),
+        };
+
+        self.window.set_title(&title);
+    }
+
+    /// Grab and hide the cursor for free look, or release and show it.
+    pub fn set_cursor_grab(&mut self, grab: bool) {
+        let result = match grab {
+            // macOS only supports locking and Windows only supports confining,
+            // X11 and Wayland support both
+            true => (self.window.set_cursor_grab(CursorGrabMode::Locked))
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined)),
+            false => self.window.set_cursor_grab(CursorGrabMode::None),
+        };
+
+        if let Err(err) = result {
+            warn!("Failed to set cursor grab: {}", err);
+            return;
+        }
+
+        self.window.set_cursor_visible(!grab);
+        self.editor.world.camera.free_look = grab;
+        self.cursor_grabbed = grab;
+    }
+
+    /// Returns the cursor position, or the window center while the cursor is grabbed.
+    pub fn cursor_position(&self) -> Vec2 {
+        if self.cursor_grabbed {
+            return self.renderer.size().as_vec2() / 2.0;
+        }
+
+        self.mouse.position
+    }
+
+    pub fn post_update(&mut self) -> Result<(), OakumError> {
         self.mouse.update();
         self.keyboard.update();
 
-        self.world.post_update();
-
         Ok(())
     }
 
     pub fn event(&mut self, event: &Event<()>) {
-        match event {
-            Event::DeviceEvent { event, .. } => match event {
-                DeviceEvent::MouseMotion { delta } => {
-                    self.mouse.delta += Vec2::new(delta.0 as f32, delta.1 as f32);
-                }
-                DeviceEvent::MouseWheel { delta } => {
-                    self.mouse.scroll = match delta {
-                        LineDelta(x, y) => Vec2::new(*x as f32, *y as f32),
-                        PixelDelta(pos) => Vec2::new(pos.x as f32, pos.y as f32),
-                    };
-                }
-                DeviceEvent::Key(KeyboardInput {
-                    state,
-                    virtual_keycode: Some(key),
-                    ..
-                }) => match state {
-                    ElementState::Pressed => self.keyboard.press(*key),
-                    ElementState::Released => self.keyboard.release(*key),
-                },
-                _ => {}
-            },
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::MouseInput { state, button, .. } => match state {
-                    ElementState::Pressed => self.mouse.press(*button),
-                    ElementState::Released => self.mouse.release(*button),
-                },
-                WindowEvent::CursorMoved { position, .. } => {
-                    self.mouse.position = Vec2::new(position.x as f32, position.y as f32);
-                }
-                _ => {}
-            },
-            _ => {}
+        if let Some(input) = InputEvent::from_event(event) {
+            if let Some((start, ref mut recording)) = self.recording {
+                recording.push(start.elapsed().as_secs_f32(), input);
+            }
+
+            input.apply(&mut self.mouse, &mut self.keyboard);
+        }
+
+        if let Event::WindowEvent {
+            event: WindowEvent::Focused(false),
+            ..
+        } = event
+        {
+            if self.cursor_grabbed {
+                self.set_cursor_grab(false);
+            }
+        }
+    }
+
+    /// Start recording input, replacing any recording in progress.
+    pub fn start_recording(&mut self) {
+        self.recording = Some((Instant::now(), Recording::new()));
+    }
+
+    /// Stop recording input, returns the recording, see [`Editor::replay`].
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take().map(|(_, recording)| recording)
+    }
+
+    /// Add the camera as a keyframe to the camera path, timed from the first
+    /// keyframe, then save the path to [`CameraPath::PATH`].
+    pub fn add_keyframe(&mut self) {
+        let (start, path) =
+            (self.camera_path).get_or_insert_with(|| (Instant::now(), CameraPath::new()));
+        let time = start.elapsed().as_secs_f32();
+        path.push(time, self.editor.world.camera.clone());
+
+        match path.save(CameraPath::PATH) {
+            Ok(()) => info!("Saved keyframe at {:.2}s to {}", time, CameraPath::PATH),
+            Err(err) => error!("Failed to save camera path: {}", err),
+        }
+    }
+
+    /// Load the session at [`Session::PATH`], if there is one.
+    fn restore_session() -> Option<Session> {
+        if !Path::new(Session::PATH).exists() {
+            return None;
+        }
+
+        match Session::load(Session::PATH) {
+            Ok(session) => {
+                info!("Restored session from {}", Session::PATH);
+                Some(session)
+            }
+            Err(err) => {
+                warn!("Failed to restore session: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Returns the current editor state, see [`Session`].
+    pub fn session(&self) -> Session {
+        Session {
+            octree: (*self.editor.world.octree).clone(),
+            camera: self.editor.world.camera.clone(),
+            brush: self.editor.brushes.active().name.clone(),
+            edit_mode: self.editor.edit_mode,
         }
     }
 
     pub fn request_close(&self) -> bool {
+        if self.save_session {
+            match self.session().save(Session::PATH) {
+                Ok(()) => info!("Saved session to {}", Session::PATH),
+                Err(err) => error!("Failed to save session: {}", err),
+            }
+        }
+
         true
     }
 
+    /// Returns when the next frame should be rendered, if the frame rate is limited.
+    pub fn next_frame(&self) -> Option<Instant> {
+        let max_fps = self.max_fps?;
+        Some(self.last_frame + Duration::from_secs_f64(1.0 / max_fps as f64))
+    }
+
     pub fn request_redraw(&self) {
         self.window.request_redraw();
     }
@@ -165,9 +347,20 @@ impl App {
         self.renderer.resize(width, height);
     }
 
-    pub fn render(&mut self) -> anyhow::Result<()> {
+    /// Resize to the new physical size of the window, keeping the cursor where it was.
+    pub fn scale_factor_changed(&mut self, scale_factor: f64, width: u32, height: u32) {
+        self.mouse
+            .rescale((scale_factor / self.scale_factor) as f32);
+        self.scale_factor = scale_factor;
+
+        self.window_resized(width, height);
+    }
+
+    pub fn render(&mut self) -> Result<(), OakumError> {
         self.update()?;
-        self.renderer.render_frame(&self.world)?;
+        if self.renderer.render_frame(&self.editor.world)? {
+            self.editor.world.take_dirty();
+        }
         self.post_update()?;
 
         Ok(())
@@ -0,0 +1,340 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use glam::Vec3;
+use serde::Deserialize;
+
+use crate::{error::OakumError, input::Key, render::Camera, world::Scene, Args};
+
+/// The graphics backend to render with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Vulkan, Metal or DirectX 12, whichever the platform supports.
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    pub const fn backends(&self) -> wgpu::Backends {
+        match self {
+            Self::Primary => wgpu::Backends::PRIMARY,
+            Self::Vulkan => wgpu::Backends::VULKAN,
+            Self::Metal => wgpu::Backends::METAL,
+            Self::Dx12 => wgpu::Backends::DX12,
+            Self::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+/// How frames are presented to the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    /// Present as soon as possible, which may tear.
+    Immediate,
+    /// Wait for vertical sync.
+    Fifo,
+    /// Wait for vertical sync, replacing queued frames instead of blocking.
+    Mailbox,
+}
+
+impl PresentMode {
+    pub const fn present_mode(&self) -> wgpu::PresentMode {
+        match self {
+            Self::Immediate => wgpu::PresentMode::Immediate,
+            Self::Fifo => wgpu::PresentMode::Fifo,
+            Self::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+/// The initial camera, unset fields keep the [`Camera::default`] values.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    pub position: Option<[f32; 3]>,
+    pub distance: Option<f32>,
+    pub fov: Option<f32>,
+}
+
+impl CameraConfig {
+    pub fn apply(&self, camera: &mut Camera) {
+        if let Some(position) = self.position {
+            camera.position = Vec3::from(position);
+        }
+
+        if let Some(distance) = self.distance {
+            camera.distance = distance;
+        }
+
+        if let Some(fov) = self.fov {
+            camera.fov = fov;
+        }
+    }
+}
+
+/// Key bindings, named after [`Key`] variants, e.g. `grab = "Tab"`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyConfig {
+    /// Toggles grabbing the cursor for free look.
+    pub grab: Key,
+    /// Rebuilds the render pipelines from the shader files.
+    pub reload_shaders: Key,
+    /// Cycles through the loaded brushes.
+    pub next_brush: Key,
+    /// Toggles between placing and replacing with the brush.
+    pub edit_mode: Key,
+    /// Undoes the latest edit once all of these are held, e.g. `undo = ["LControl", "Z"]`,
+    /// modifiers match either side, see [`Keyboard::chord`](crate::input::Keyboard::chord).
+    pub undo: Vec<Key>,
+    /// Toggles the ground grid.
+    pub grid: Key,
+    /// Toggles the traversal step heatmap, see [`RenderMode::Steps`](crate::render::RenderMode::Steps).
+    pub heatmap: Key,
+    /// Toggles drawing surface normals, see [`RenderMode::Normals`](crate::render::RenderMode::Normals).
+    pub normals: Key,
+    /// Adds the camera as a keyframe to the camera path being recorded.
+    pub keyframe: Key,
+    /// Moves the camera back to where it starts.
+    pub reset_camera: Key,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            grab: Key::Tab,
+            reload_shaders: Key::F5,
+            next_brush: Key::B,
+            edit_mode: Key::R,
+            undo: vec![Key::LControl, Key::Z],
+            grid: Key::G,
+            heatmap: Key::F3,
+            normals: Key::F4,
+            keyframe: Key::K,
+            reset_camera: Key::Home,
+        }
+    }
+}
+
+/// Startup settings, read from [`Config::PATH`] if it exists.
+///
+/// Every field can be left out, and most can be overridden by [`Args`].
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub log_level: log::LevelFilter,
+    pub backend: Backend,
+    pub present_mode: PresentMode,
+    pub max_fps: Option<u32>,
+    /// The world to open instead of the default scene, in any readable format.
+    pub world: Option<PathBuf>,
+    /// The scene to open when `world` isn't set, [`Scene::Empty`] if left out.
+    pub scene: Option<Scene>,
+    /// Save the session when closing and resume it on startup, unless `world`
+    /// or `scene` is set, see [`Config::resumes_session`].
+    pub session: bool,
+    /// Octrees to load as brushes, next to the default sphere.
+    pub brushes: Vec<PathBuf>,
+    pub camera: CameraConfig,
+    pub keys: KeyConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: log::LevelFilter::Info,
+            backend: Backend::Primary,
+            present_mode: PresentMode::Immediate,
+            max_fps: None,
+            world: None,
+            scene: None,
+            session: true,
+            brushes: Vec::new(),
+            camera: CameraConfig::default(),
+            keys: KeyConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub const PATH: &str = "oakum.toml";
+
+    pub fn parse(source: &str) -> Result<Self, OakumError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, OakumError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Load the config at `path`, or the default config if there is no such file.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, OakumError> {
+        match path.as_ref().exists() {
+            true => Self::load(path),
+            false => Ok(Self::default()),
+        }
+    }
+
+    /// Load the config file given by `args`, overridden by the flags in `args`.
+    pub fn from_args(args: &Args) -> Result<Self, OakumError> {
+        let mut config = match args.config {
+            Some(ref path) => Self::load(path)?,
+            None => Self::load_or_default(Self::PATH)?,
+        };
+
+        config.apply_args(args);
+
+        Ok(config)
+    }
+
+    /// Override the fields set in `args`.
+    pub fn apply_args(&mut self, args: &Args) {
+        if let Some(log_level) = args.log_level {
+            self.log_level = log_level;
+        }
+
+        if let Some(backend) = args.backend {
+            self.backend = backend;
+        }
+
+        if let Some(present_mode) = args.present_mode {
+            self.present_mode = present_mode;
+        }
+
+        if let Some(max_fps) = args.max_fps {
+            self.max_fps = Some(max_fps);
+        }
+
+        if let Some(ref world) = args.world {
+            self.world = Some(world.clone());
+        }
+
+        if let Some(scene) = args.scene {
+            self.scene = Some(scene);
+        }
+    }
+
+    /// Whether to open the last session, which is only done when neither a
+    /// world nor a scene was asked for.
+    pub fn resumes_session(&self) -> bool {
+        self.session && self.world.is_none() && self.scene.is_none()
+    }
+
+    pub fn init_logger(&self) {
+        env_logger::builder()
+            .filter_level(self.log_level)
+            .filter_module("wgpu", log::LevelFilter::Warn)
+            .filter_module("winit", log::LevelFilter::Warn)
+            .filter_module("naga", log::LevelFilter::Warn)
+            .init();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn load_and_override() {
+        let path = std::env::temp_dir().join(format!("oakum-{}.toml", std::process::id()));
+        let source = r#"
+            log_level = "debug"
+            backend = "gl"
+            present_mode = "fifo"
+            max_fps = 30
+            world = "worlds/castle.oak"
+            scene = "demo"
+            brushes = ["brushes/tree.vox"]
+
+            [camera]
+            position = [1.0, 2.0, 3.0]
+            fov = 90.0
+
+            [keys]
+            grab = "G"
+            undo = ["LAlt", "U"]
+        "#;
+        fs::write(&path, source).unwrap();
+
+        let config = Config::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.log_level, log::LevelFilter::Debug);
+        assert_eq!(config.backend, Backend::Gl);
+        assert_eq!(config.present_mode, PresentMode::Fifo);
+        assert_eq!(config.max_fps, Some(30));
+        assert_eq!(config.world, Some(PathBuf::from("worlds/castle.oak")));
+        assert_eq!(config.scene, Some(Scene::Demo));
+        assert_eq!(config.brushes, vec![PathBuf::from("brushes/tree.vox")]);
+        assert_eq!(config.keys.grab, Key::G);
+        assert_eq!(config.keys.reload_shaders, Key::F5);
+        assert_eq!(config.keys.undo, vec![Key::LAlt, Key::U]);
+
+        let mut camera = Camera::default();
+        config.camera.apply(&mut camera);
+        assert_eq!(camera.position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(camera.distance, Camera::default().distance);
+        assert_eq!(camera.fov, 90.0);
+
+        // flags override the file, fields without a flag are kept
+        let args = Args::parse_from([
+            "oakum",
+            "--max-fps",
+            "144",
+            "--backend",
+            "vulkan",
+            "--scene",
+            "empty",
+        ]);
+        let mut overridden = config.clone();
+        overridden.apply_args(&args);
+
+        assert_eq!(overridden.max_fps, Some(144));
+        assert_eq!(overridden.backend, Backend::Vulkan);
+        assert_eq!(overridden.log_level, log::LevelFilter::Debug);
+        assert_eq!(overridden.world, config.world);
+        assert_eq!(overridden.scene, Some(Scene::Empty));
+    }
+
+    #[test]
+    fn resumes_session() {
+        assert!(Config::default().resumes_session());
+
+        // an explicit scene or world wins over the last session, like `--scene empty`
+        for flags in [&["--scene", "empty"][..], &["--world", "model.vox"][..]] {
+            let args = Args::parse_from(["oakum"].iter().chain(flags));
+            let mut config = Config::default();
+            config.apply_args(&args);
+            assert!(!config.resumes_session(), "{flags:?}");
+        }
+
+        let config = Config::parse("scene = \"demo\"").unwrap();
+        assert!(!config.resumes_session());
+
+        let config = Config::parse("session = false").unwrap();
+        assert!(!config.resumes_session());
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(Config::parse("").unwrap() == Config::default());
+        assert!(matches!(
+            Config::parse("frame_rate = 60"),
+            Err(OakumError::Config(_))
+        ));
+        assert!(matches!(
+            Config::parse("backend = \"glide\""),
+            Err(OakumError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,488 @@
+use std::fmt::{self, Display};
+
+use glam::UVec2;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use winit::event::MouseButton;
+
+use crate::{
+    app::UpdateContext,
+    config::KeyConfig,
+    input::{Key, Keyboard, Mouse, Recording},
+    octree::{stamp_regions, Branch, Octree},
+    render::{Camera, CameraPath},
+    world::{BrushLibrary, History, World, WorldHit},
+};
+
+/// What left clicking does with the active brush, right clicking always removes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditMode {
+    /// Add the brush to the world.
+    #[default]
+    Place,
+    /// Paint the solid cells within the brush, without adding or removing any.
+    Replace,
+}
+
+impl EditMode {
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Place => Self::Replace,
+            Self::Replace => Self::Place,
+        }
+    }
+}
+
+impl Display for EditMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Place => write!(f, "place"),
+            Self::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+/// The world and the state of editing it, updated from input alone, so edits
+/// can be made without a window, see [`Editor::replay`].
+pub struct Editor {
+    pub world: World,
+    /// The brushes stamped by left and right clicks.
+    pub brushes: BrushLibrary,
+    pub next_brush_key: Key,
+    pub edit_mode: EditMode,
+    pub edit_mode_key: Key,
+    /// Snapshots taken before each edit, so they can be undone.
+    pub history: History,
+    /// Undoes the latest edit when pressed together, see [`Keyboard::chord`](crate::input::Keyboard::chord).
+    pub undo_keys: Vec<Key>,
+    /// Moves the camera back to [`Camera::default`], see [`Editor::reset_camera`].
+    pub reset_camera_key: Key,
+    /// How long moving the camera back takes in seconds, 0 moves it instantly.
+    pub camera_reset_duration: f32,
+    /// The cell and brush shown in [`World::preview`], if any.
+    previewed: Option<(Branch, String)>,
+    /// The path from the camera to the default one and how far along it the
+    /// camera is, while resetting.
+    camera_reset: Option<(f32, CameraPath)>,
+}
+
+impl Editor {
+    /// The depth of the cells brushes are stamped at.
+    pub const EDIT_DEPTH: u32 = 10;
+
+    /// Short enough not to get in the way, long enough to follow where the camera goes.
+    pub const CAMERA_RESET_DURATION: f32 = 0.3;
+
+    pub fn new(world: World, brushes: BrushLibrary, keys: &KeyConfig) -> Self {
+        Self {
+            world,
+            brushes,
+            next_brush_key: keys.next_brush,
+            edit_mode: EditMode::default(),
+            edit_mode_key: keys.edit_mode,
+            history: History::default(),
+            undo_keys: keys.undo.clone(),
+            reset_camera_key: keys.reset_camera,
+            camera_reset_duration: Self::CAMERA_RESET_DURATION,
+            previewed: None,
+            camera_reset: None,
+        }
+    }
+
+    pub fn update(&mut self, cx: UpdateContext) {
+        self.world.update(cx);
+        self.update_camera_reset(cx.delta);
+
+        if cx.keyboard.is_pressed(self.reset_camera_key) {
+            self.reset_camera();
+        }
+
+        if cx.keyboard.is_pressed(self.next_brush_key) {
+            info!("Selected brush {}", self.brushes.select_next().name);
+        }
+
+        if cx.keyboard.is_pressed(self.edit_mode_key) {
+            self.edit_mode = self.edit_mode.next();
+        }
+
+        let undo = cx.keyboard.chord(&self.undo_keys);
+        if undo && !self.history.undo(&mut self.world.octree) {
+            info!("Nothing to undo");
+        }
+
+        self.update_preview(cx);
+
+        let brush = self.brushes.active();
+
+        let Some(depth) = brush.join_depth(Self::EDIT_DEPTH) else {
+            if cx.mouse.is_pressed(MouseButton::Left) || cx.mouse.is_pressed(MouseButton::Right) {
+                warn!(
+                    "Edit refused: brush {} is deeper than the edit depth",
+                    brush.name
+                );
+            }
+
+            return;
+        };
+
+        // snapshot the cells an edit with the active brush is about to change
+        let snapshot = |octree: &Octree, branch: Branch| {
+            octree.snapshot_regions(stamp_regions(branch, depth, &brush.octree))
+        };
+
+        if cx.mouse.is_pressed(MouseButton::Right)
+            || cx.mouse.is_held(MouseButton::Right) && cx.keyboard.is_held(Key::F)
+        {
+            if let Some(branch) = self.edit_branch(cx, Self::EDIT_DEPTH) {
+                match self.world.check_stamp(branch, depth, &brush.octree) {
+                    Ok(()) => {
+                        self.history.push(snapshot(&self.world.octree, branch));
+                        self.world.octree.difference(branch, depth, &brush.octree);
+                    }
+                    Err(err) => warn!("Edit refused: {}", err),
+                }
+            }
+        } else if cx.mouse.is_pressed(MouseButton::Left)
+            || cx.mouse.is_held(MouseButton::Left) && cx.keyboard.is_held(Key::F)
+        {
+            match self.edit_mode {
+                EditMode::Place => {
+                    if let Some(branch) = self.edit_branch(cx, Self::EDIT_DEPTH) {
+                        let before = snapshot(&self.world.octree, branch);
+
+                        // the preview already holds the brush stamped at `branch`
+                        match self.world.commit_preview() {
+                            Ok(()) => {
+                                self.history.push(before);
+                                self.previewed = None;
+                            }
+                            Err(err) => warn!("Edit refused: {}", err),
+                        }
+                    }
+                }
+                EditMode::Replace => {
+                    if let Some(branch) = self.surface_branch(cx, Self::EDIT_DEPTH) {
+                        match self.world.check_stamp(branch, depth, &brush.octree) {
+                            Ok(()) => {
+                                self.history.push(snapshot(&self.world.octree, branch));
+                                self.world.octree.recolor(branch, depth, &brush.octree);
+                            }
+                            Err(err) => warn!("Edit refused: {}", err),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Show the active brush where placing it would put it.
+    ///
+    /// The preview is only rebuilt when the cell under the cursor or the brush
+    /// changes, so holding still doesn't upload it every frame.
+    fn update_preview(&mut self, cx: UpdateContext) {
+        let branch = match self.edit_mode {
+            EditMode::Place => self.edit_branch(cx, Self::EDIT_DEPTH),
+            EditMode::Replace => None,
+        };
+
+        let brush = self.brushes.active();
+        let previewed = branch.map(|branch| (branch, brush.name.clone()));
+
+        if previewed == self.previewed {
+            return;
+        }
+
+        self.world.clear_preview();
+
+        if let (Some(branch), Some(depth)) = (branch, brush.join_depth(Self::EDIT_DEPTH)) {
+            self.world.preview.union(branch, depth, &brush.octree);
+        }
+
+        self.previewed = previewed;
+    }
+
+    /// Step through `recording` in frames of `delta` seconds, as if it was played
+    /// in a window of `viewport` pixels.
+    ///
+    /// Replaying the same recording on the same editor always makes the same edits.
+    pub fn replay(&mut self, recording: &Recording, delta: f32, viewport: UVec2) {
+        let mut mouse = Mouse::default();
+        let mut keyboard = Keyboard::default();
+
+        for frame in recording.frames(delta) {
+            for &(_, event) in frame {
+                event.apply(&mut mouse, &mut keyboard);
+            }
+
+            self.update(UpdateContext {
+                delta,
+                mouse: &mouse,
+                keyboard: &keyboard,
+                cursor: mouse.position,
+                viewport,
+            });
+
+            mouse.update();
+            keyboard.update();
+        }
+    }
+
+    /// Move the camera back to [`Camera::default`] over [`Editor::camera_reset_duration`],
+    /// overriding any other movement until it's there.
+    pub fn reset_camera(&mut self) {
+        if self.camera_reset_duration <= 0.0 {
+            self.camera_reset = None;
+            self.finish_camera_reset();
+            return;
+        }
+
+        let mut path = CameraPath::new();
+        path.push(0.0, self.world.camera.clone());
+        path.push(self.camera_reset_duration, Camera::default());
+
+        self.camera_reset = Some((0.0, path));
+    }
+
+    fn update_camera_reset(&mut self, delta: f32) {
+        let Some((ref mut elapsed, ref path)) = self.camera_reset else {
+            return;
+        };
+
+        *elapsed += delta;
+
+        if *elapsed >= path.duration() {
+            self.camera_reset = None;
+            self.finish_camera_reset();
+            return;
+        }
+
+        let camera = &mut self.world.camera;
+        let free_look = camera.free_look;
+
+        if let Some(sampled) = path.sample(*elapsed) {
+            *camera = Camera {
+                free_look,
+                ..sampled
+            };
+        }
+    }
+
+    /// Set the default camera exactly, sampling the path only lands close to it.
+    fn finish_camera_reset(&mut self) {
+        let camera = &mut self.world.camera;
+
+        *camera = Camera {
+            free_look: camera.free_look,
+            ..Camera::default()
+        };
+    }
+
+    /// Returns the cell at `depth` in front of the surface under the cursor.
+    pub fn edit_branch(&self, cx: UpdateContext, depth: u32) -> Option<Branch> {
+        let (mut branch, hit) = self.pick_branch(cx, depth)?;
+        branch.path += hit.normal;
+
+        Some(branch)
+    }
+
+    /// Returns the solid cell at `depth` under the cursor.
+    pub fn surface_branch(&self, cx: UpdateContext, depth: u32) -> Option<Branch> {
+        Some(self.pick_branch(cx, depth)?.0)
+    }
+
+    /// Returns the cell at `depth` the surface under the cursor lies in, and the hit.
+    ///
+    /// The hit point always lies inside the hit leaf, so this also works when the
+    /// whole octree is a single solid root.
+    fn pick_branch(&self, cx: UpdateContext, depth: u32) -> Option<(Branch, WorldHit)> {
+        let camera = &self.world.camera;
+        let hit = (self.world).pick(camera, cx.viewport.x, cx.viewport.y, cx.cursor)?;
+
+        let branch = Branch::from_point(self.world.transform(), hit.point, depth);
+        Some((branch, hit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::*;
+    use crate::{generate::Sphere, input::InputEvent, octree::DynamicOctree, world::Brush};
+
+    fn sphere_editor() -> Editor {
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        world.camera.distance = 30.0;
+
+        let brush = Brush::new("sphere", Octree::generate(&Sphere::new(4, 3)));
+        Editor::new(world, BrushLibrary::new(brush), &KeyConfig::default())
+    }
+
+    #[test]
+    fn replay_carve() {
+        use InputEvent::*;
+
+        // click the center of the sphere, then drag across it with `F` held
+        let mut recording = Recording::new();
+        recording.push(0.01, CursorMoved(Vec2::new(32.0, 32.0)));
+        recording.push(0.02, ButtonPressed(MouseButton::Right));
+        recording.push(0.03, ButtonReleased(MouseButton::Right));
+        recording.push(0.1, KeyPressed(Key::F));
+        recording.push(0.1, ButtonPressed(MouseButton::Right));
+
+        for i in 0..8 {
+            let time = 0.12 + i as f32 * 0.02;
+            recording.push(time, CursorMoved(Vec2::new(26.0 + i as f32 * 2.0, 30.0)));
+        }
+
+        recording.push(0.3, ButtonReleased(MouseButton::Right));
+        recording.push(0.3, KeyReleased(Key::F));
+
+        let mut first = sphere_editor();
+        first.replay(&recording, 1.0 / 60.0, UVec2::new(64, 64));
+
+        let mut second = sphere_editor();
+        second.replay(&recording, 1.0 / 60.0, UVec2::new(64, 64));
+
+        let original = sphere_editor();
+        assert!(!first.world.octree.structurally_eq(&original.world.octree));
+        assert!(first.world.octree.structurally_eq(&second.world.octree));
+        assert!(first.history.len() > 2);
+        assert_eq!(first.history.len(), second.history.len());
+
+        // every carve can be undone, back to the untouched sphere
+        while first.history.undo(&mut first.world.octree) {}
+        assert!(first.world.octree.structurally_eq(&original.world.octree));
+    }
+
+    #[test]
+    fn reset_camera() {
+        let mut editor = sphere_editor();
+        let mouse = Mouse::default();
+        let mut keyboard = Keyboard::default();
+
+        let moved = |editor: &mut Editor| {
+            let camera = &mut editor.world.camera;
+            camera.position = Vec3::new(3.0, -1.0, 2.0);
+            camera.distance = 12.0;
+            camera.rotation = Vec3::new(0.3, -1.2, 0.0);
+            camera.fov = 80.0;
+        };
+
+        let assert_default = |camera: &Camera| {
+            let default = Camera::default();
+            assert_eq!(camera.position, default.position);
+            assert_eq!(camera.distance, default.distance);
+            assert_eq!(camera.rotation, default.rotation);
+            assert_eq!(camera.fov, default.fov);
+        };
+
+        let update = |editor: &mut Editor, keyboard: &mut Keyboard, delta| {
+            editor.update(UpdateContext {
+                delta,
+                mouse: &mouse,
+                keyboard,
+                cursor: Vec2::ZERO,
+                viewport: UVec2::new(64, 64),
+            });
+            keyboard.update();
+        };
+
+        // instantly
+        moved(&mut editor);
+        editor.camera_reset_duration = 0.0;
+        keyboard.press(Key::Home);
+        update(&mut editor, &mut keyboard, 0.0);
+        assert_default(&editor.world.camera);
+
+        // over the duration, passing between the two
+        moved(&mut editor);
+        editor.camera_reset_duration = Editor::CAMERA_RESET_DURATION;
+        keyboard.release(Key::Home);
+        keyboard.press(Key::Home);
+        update(&mut editor, &mut keyboard, 0.0);
+        assert_eq!(editor.world.camera.fov, 80.0);
+
+        update(&mut editor, &mut keyboard, 0.15);
+        let fov = editor.world.camera.fov;
+        assert!((fov - 70.0).abs() < 1e-3, "{fov}");
+
+        update(&mut editor, &mut keyboard, 0.15);
+        assert_default(&editor.world.camera);
+    }
+
+    #[test]
+    fn scale_factor_change_keeps_picking() {
+        let editor = sphere_editor();
+        let keyboard = Keyboard::default();
+
+        let pick = |mouse: &Mouse, viewport: UVec2| {
+            let cx = UpdateContext {
+                delta: 0.0,
+                mouse,
+                keyboard: &keyboard,
+                cursor: mouse.position,
+                viewport,
+            };
+
+            editor.surface_branch(cx, 8)
+        };
+
+        // the center, and a point off center that only lines up if rescaled
+        for position in [Vec2::new(32.0, 32.0), Vec2::new(28.0, 36.0)] {
+            let mut mouse = Mouse {
+                position,
+                ..Default::default()
+            };
+
+            let before = pick(&mouse, UVec2::new(64, 64));
+            assert!(before.is_some());
+
+            // the window doubles its physical size, the cursor is still over the same point
+            mouse.rescale(2.0);
+            assert_eq!(pick(&mouse, UVec2::new(128, 128)), before);
+        }
+
+        // without rescaling, the off center cursor lands elsewhere
+        let mouse = Mouse {
+            position: Vec2::new(28.0, 36.0),
+            ..Default::default()
+        };
+        let before = pick(&mouse, UVec2::new(64, 64));
+        assert_ne!(pick(&mouse, UVec2::new(128, 128)), before);
+    }
+
+    #[test]
+    fn place_commits_preview() {
+        use InputEvent::*;
+
+        let mut editor = sphere_editor();
+        let original = (*editor.world.octree).clone();
+
+        // hovering shows the brush in the preview only
+        let mut recording = Recording::new();
+        recording.push(0.01, CursorMoved(Vec2::new(32.0, 32.0)));
+        editor.replay(&recording, 1.0 / 60.0, UVec2::new(64, 64));
+
+        assert!(!editor.world.preview.is_empty());
+        assert!(editor.world.octree.structurally_eq(&original));
+
+        let preview = (*editor.world.preview).clone();
+        recording.push(0.05, ButtonPressed(MouseButton::Left));
+        recording.push(0.06, ButtonReleased(MouseButton::Left));
+
+        let mut editor = sphere_editor();
+        editor.replay(&recording, 1.0 / 60.0, UVec2::new(64, 64));
+
+        let mut expected = original.clone();
+        for (branch, &node) in preview.iter_nodes() {
+            if node.is_solid() {
+                expected.set(branch, node);
+            }
+        }
+
+        assert!(editor.world.octree.structurally_eq(&expected));
+        assert_eq!(editor.history.len(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+
+use crate::format::FormatError;
+#[cfg(feature = "render")]
+use crate::render::ShaderError;
+
+/// Errors returned by the public api, so callers can match on what went wrong.
+#[derive(Debug, thiserror::Error)]
+pub enum OakumError {
+    #[cfg(feature = "render")]
+    #[error(transparent)]
+    Shader(#[from] ShaderError),
+    /// Reading or writing a file failed, this includes invalid octrees.
+    #[error(transparent)]
+    Format(#[from] FormatError),
+    #[error("No suitable adapter found")]
+    NoAdapter,
+    #[error("The surface doesn't support the target format and usage")]
+    UnsupportedSurface,
+    #[cfg(feature = "render")]
+    /// The adapter can't sample or upload the octree texture, or store to it for
+    /// gpu generation, see [`DrawOctree::TEXTURE_FORMAT`].
+    ///
+    /// [`DrawOctree::TEXTURE_FORMAT`]: crate::render::DrawOctree::TEXTURE_FORMAT
+    #[error("The adapter doesn't support the octree texture format {0:?}")]
+    UnsupportedOctreeFormat(wgpu::TextureFormat),
+    #[cfg(feature = "render")]
+    #[error(transparent)]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+    #[cfg(feature = "render")]
+    #[error(transparent)]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[cfg(feature = "render")]
+    #[error(transparent)]
+    Surface(#[from] wgpu::SurfaceError),
+    #[cfg(feature = "render")]
+    #[error(transparent)]
+    BufferAsync(#[from] wgpu::BufferAsyncError),
+    /// An error wgpu reported asynchronously, like a validation error.
+    #[error("Wgpu error: {0}")]
+    Wgpu(String),
+    #[error("Renderer has no offscreen render target")]
+    NoRenderTarget,
+    #[cfg(feature = "headless")]
+    #[error(transparent)]
+    Png(#[from] png::EncodingError),
+    #[error("Taa samples must be between 1 and {max}, got {samples}")]
+    InvalidTaaSamples { samples: u32, max: u32 },
+    /// See [`Octree::MAX_DEPTH`](crate::octree::Octree::MAX_DEPTH).
+    #[error("Octree depth {depth} exceeds the maximum of {max}")]
+    DepthTooLarge { depth: u32, max: u32 },
+    /// Changes from another copy of an octree don't fit this one, see
+    /// [`DynamicOctree::apply_changes`](crate::octree::DynamicOctree::apply_changes).
+    #[error("Invalid octree change at node {start}: {reason}")]
+    InvalidChange { start: u32, reason: &'static str },
+    /// An edit was refused, since the octree could grow past its capacity.
+    #[error("Edit could grow the octree to {nodes} nodes, over the maximum of {max}")]
+    TooManyNodes { nodes: u64, max: u32 },
+    /// Solid nodes of one octree use more colors than fit a
+    /// [`Palette`](crate::octree::Palette).
+    #[error("Octree uses more than {max} colors")]
+    PaletteFull { max: u32 },
+    /// Pointers of an octree this long don't fit a [`PackedNode`](crate::octree::PackedNode).
+    #[error("Octree of {nodes} nodes can't be packed, the maximum is {max}")]
+    TooManyNodesToPack { nodes: u32, max: u32 },
+    #[error("Invalid config: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("Io error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::octree::Octree;
+    #[cfg(feature = "render")]
+    use crate::render::{open_shader, tests::headless};
+
+    #[test]
+    fn load_errors() {
+        let result = Octree::load("does/not/exist.oak").map_err(OakumError::from);
+        assert!(matches!(
+            result,
+            Err(OakumError::Format(FormatError::IoError(ref e))) if e.kind() == io::ErrorKind::NotFound
+        ));
+
+        let mut bytes = Vec::new();
+        Octree::new().write(&mut bytes).unwrap();
+        bytes[4] = 99;
+
+        let result = Octree::read(&mut bytes.as_slice()).map_err(OakumError::from);
+        assert!(matches!(
+            result,
+            Err(OakumError::Format(FormatError::UnsupportedVersion(99)))
+        ));
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn shader_errors() {
+        let Some(renderer) = headless(1, 1) else {
+            return;
+        };
+
+        let result = open_shader(&renderer.device, "assets/shaders/missing.wgsl");
+        assert!(matches!(
+            result.map_err(OakumError::from),
+            Err(OakumError::Shader(ShaderError::FileNotFound(_)))
+        ));
+    }
+}
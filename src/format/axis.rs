@@ -0,0 +1,75 @@
+use glam::{IVec3, Vec3};
+
+/// The axis pointing up in a file format, octrees are always Y-up.
+///
+/// Both conventions are right handed, Z-up is converted to Y-up by rotating
+/// about the X axis, so `+Z` becomes `+Y` and `+Y` becomes `-Z`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    /// Used by MagicaVoxel and Blender.
+    Z,
+}
+
+impl UpAxis {
+    /// Convert a point or direction from this convention to Y-up.
+    pub fn remap_to_y_up(self, v: Vec3) -> Vec3 {
+        match self {
+            Self::Y => v,
+            Self::Z => Vec3::new(v.x, v.z, -v.y),
+        }
+    }
+
+    /// Convert a point or direction from Y-up to this convention.
+    pub fn remap_from_y_up(self, v: Vec3) -> Vec3 {
+        match self {
+            Self::Y => v,
+            Self::Z => Vec3::new(v.x, -v.z, v.y),
+        }
+    }
+
+    /// Convert the cell spanning `cell..cell + 1` from this convention to Y-up.
+    ///
+    /// Unlike [`UpAxis::remap_to_y_up`] this accounts for the flipped axis, so the
+    /// returned cell covers the same volume.
+    pub fn remap_cell_to_y_up(self, cell: IVec3) -> IVec3 {
+        match self {
+            Self::Y => cell,
+            Self::Z => IVec3::new(cell.x, cell.z, -cell.y - 1),
+        }
+    }
+
+    /// Convert the cell spanning `cell..cell + 1` from Y-up to this convention.
+    pub fn remap_cell_from_y_up(self, cell: IVec3) -> IVec3 {
+        match self {
+            Self::Y => cell,
+            Self::Z => IVec3::new(cell.x, -cell.z - 1, cell.y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for axis in [UpAxis::Y, UpAxis::Z] {
+            let v = Vec3::new(1.0, 2.0, 3.0);
+            assert_eq!(axis.remap_from_y_up(axis.remap_to_y_up(v)), v);
+
+            let cell = IVec3::new(-4, 5, -6);
+            assert_eq!(
+                axis.remap_cell_from_y_up(axis.remap_cell_to_y_up(cell)),
+                cell
+            );
+
+            // a cell maps to the cell containing its converted center
+            let center = axis.remap_to_y_up(cell.as_vec3() + 0.5);
+            assert_eq!(axis.remap_cell_to_y_up(cell), center.floor().as_ivec3());
+        }
+
+        assert_eq!(UpAxis::Z.remap_to_y_up(Vec3::Z), Vec3::Y);
+    }
+}
@@ -0,0 +1,312 @@
+mod axis;
+mod obj;
+mod vox;
+
+pub use axis::*;
+pub use obj::*;
+pub use vox::*;
+
+use std::{
+    ffi::OsStr,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::octree::Octree;
+
+/// A file format that octrees can be read from or written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The native octree format, see [`Octree::save`].
+    Oak,
+    /// MagicaVoxel models, import only.
+    Vox,
+    /// Wavefront OBJ meshes, export only.
+    Obj,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Self, FormatError> {
+        let extension = path.extension().and_then(OsStr::to_str);
+
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("oak") => Ok(Self::Oak),
+            Some("vox") => Ok(Self::Vox),
+            Some("obj") => Ok(Self::Obj),
+            _ => Err(FormatError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Oak => "oak",
+            Self::Vox => "vox",
+            Self::Obj => "obj",
+        }
+    }
+
+    pub const fn can_read(&self) -> bool {
+        matches!(self, Self::Oak | Self::Vox)
+    }
+
+    pub const fn can_write(&self) -> bool {
+        matches!(self, Self::Oak | Self::Obj)
+    }
+
+    pub fn read(&self, path: &Path) -> Result<Octree, FormatError> {
+        match self {
+            Self::Oak => Octree::load(path),
+            Self::Vox => import_vox(path),
+            Self::Obj => Err(FormatError::UnsupportedRead(*self)),
+        }
+    }
+
+    pub fn write(&self, octree: &Octree, path: &Path) -> Result<(), FormatError> {
+        match self {
+            Self::Oak => octree.save(path),
+            Self::Obj => export_obj(octree, path),
+            Self::Vox => Err(FormatError::UnsupportedWrite(*self)),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ".{}", self.extension())
+    }
+}
+
+/// Convert the file at `input` to `output`, inferring the formats from their extensions.
+pub fn convert(input: &Path, output: &Path) -> Result<(), FormatError> {
+    let from = Format::from_path(input)?;
+    let to = Format::from_path(output)?;
+
+    if !from.can_read() || !to.can_write() {
+        return Err(FormatError::UnsupportedConversion { from, to });
+    }
+
+    let octree = from.read(input)?;
+    to.write(&octree, output)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error("Unknown file format: {0}")]
+    UnknownFormat(PathBuf),
+    #[error("Reading {0} files is not supported")]
+    UnsupportedRead(Format),
+    #[error("Writing {0} files is not supported")]
+    UnsupportedWrite(Format),
+    #[error("Cannot convert from {from} to {to}")]
+    UnsupportedConversion { from: Format, to: Format },
+    #[error("Invalid file header")]
+    InvalidHeader,
+    #[error("Unsupported file version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("Unexpected end of file")]
+    UnexpectedEof,
+    #[error("Invalid octree: {0}")]
+    InvalidOctree(&'static str),
+    #[error("Invalid session: {0}")]
+    InvalidSession(String),
+    #[error("Invalid camera path: {0}")]
+    InvalidCameraPath(String),
+    #[error("Io error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use glam::{IVec3, Vec3};
+
+    use super::*;
+    use crate::{
+        generate::Sphere,
+        octree::{Branch, Node},
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("oakum-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn convert_sphere_to_obj() {
+        let depth = 4;
+        let sphere = Octree::generate(&Sphere::new(8, depth));
+
+        let oak = temp_path("sphere.oak");
+        let obj = temp_path("sphere.obj");
+
+        sphere.save(&oak).unwrap();
+        convert(&oak, &obj).unwrap();
+
+        let source = fs::read_to_string(&obj).unwrap();
+
+        fs::remove_file(oak).unwrap();
+        fs::remove_file(obj).unwrap();
+
+        // coalesced cells are exported as larger faces, so measure faces in unit cell faces
+        let mut vertices = Vec::new();
+        let mut faces = 0.0;
+
+        for line in source.lines() {
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                Some("v") => {
+                    let v: Vec<f32> = words.map(|w| w.parse().unwrap()).collect();
+                    vertices.push(Vec3::new(v[0], v[1], v[2]));
+                }
+                Some("f") => {
+                    let i: Vec<usize> = words.map(|w| w.parse().unwrap()).collect();
+                    let a = vertices[i[1] - 1] - vertices[i[0] - 1];
+                    let b = vertices[i[3] - 1] - vertices[i[0] - 1];
+                    faces += a.cross(b).length();
+                }
+                _ => {}
+            }
+        }
+
+        let unit = Branch::new(IVec3::ZERO, depth).size_normalized();
+        let faces = (faces / (unit * unit)).round() as usize;
+
+        // count exposed faces of every unit cell
+        let directions = [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ];
+        let half = 1 << (depth - 1);
+        let mut expected = 0;
+
+        for x in -half..half {
+            for y in -half..half {
+                for z in -half..half {
+                    let path = IVec3::new(x, y, z);
+                    if !sphere.get(Branch::new(path, depth)).is_solid() {
+                        continue;
+                    }
+
+                    for direction in directions {
+                        let neighbor = Branch::new(path + direction, depth);
+                        expected += !sphere.get(neighbor).is_solid() as usize;
+                    }
+                }
+            }
+        }
+
+        assert!(expected > 0);
+        assert_eq!(faces, expected);
+    }
+
+    #[test]
+    fn unsupported_conversion() {
+        let result = convert(Path::new("in.obj"), Path::new("out.oak"));
+        assert!(matches!(
+            result,
+            Err(FormatError::UnsupportedConversion {
+                from: Format::Obj,
+                to: Format::Oak,
+            })
+        ));
+    }
+
+    /// Encode a `.vox` file with a single model of `size` and white `voxels`.
+    fn vox_bytes(size: [u32; 3], voxels: &[[u8; 3]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"VOX ");
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        bytes.extend_from_slice(b"SIZE");
+        bytes.extend_from_slice(&12u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        for size in size {
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+
+        let count = voxels.len() as u32;
+        bytes.extend_from_slice(b"XYZI");
+        bytes.extend_from_slice(&(4 + count * 4).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for &[x, y, z] in voxels {
+            bytes.extend_from_slice(&[x, y, z, 1]);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn read_vox_voxels() {
+        let bytes = vox_bytes([2, 2, 2], &[[0, 0, 0], [1, 0, 1]]);
+        let octree = read_vox(&bytes).unwrap();
+
+        // (0, 0, 0) z-up -> (-1, -1, 0) y-up, (1, 0, 1) z-up -> (0, 0, 0) y-up
+        let white = Node::rgb8(255, 255, 255);
+        assert_eq!(octree.get((-1, -1, 0, 1)), white);
+        assert_eq!(octree.get((0, 0, 0, 1)), white);
+        assert_eq!(octree.iter_nodes().count(), 2);
+    }
+
+    #[test]
+    fn vox_odd_size() {
+        let bytes = vox_bytes([3, 3, 3], &[[1, 1, 1], [0, 0, 0], [2, 2, 2]]);
+        let white = Node::rgb8(255, 255, 255);
+
+        // the middle cell lands on the origin for both conventions
+        for up in [UpAxis::Z, UpAxis::Y] {
+            let octree = read_vox_with_axis(&bytes, up).unwrap();
+            assert_eq!(octree.get((0, 0, 0, 2)), white);
+            assert_eq!(octree.iter_nodes().count(), 3);
+        }
+
+        // the corners stay symmetric around it
+        let z_up = read_vox(&bytes).unwrap();
+        assert_eq!(z_up.get((-1, -1, 1, 2)), white);
+        assert_eq!(z_up.get((1, 1, -1, 2)), white);
+    }
+
+    #[test]
+    fn vox_up_axis() {
+        let bytes = vox_bytes([4, 4, 4], &[[1, 2, 3]]);
+
+        // centered at (-1, 0, 1), z-up -> y-up
+        let z_up = read_vox(&bytes).unwrap();
+        let white = Node::rgb8(255, 255, 255);
+        assert_eq!(z_up.get((-1, 1, -1, 2)), white);
+        assert_eq!(z_up.iter_nodes().count(), 1);
+
+        // a y-up model is only centered
+        let y_up = read_vox_with_axis(&bytes, UpAxis::Y).unwrap();
+        assert_eq!(y_up.get((-1, 0, 1, 2)), white);
+
+        // exporting as z-up puts the voxel back where it was in the model
+        let mut source = Vec::new();
+        write_obj_with_axis(&z_up, &mut source, UpAxis::Z).unwrap();
+
+        let min = String::from_utf8(source)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|line| {
+                let v: Vec<f32> = line
+                    .split_whitespace()
+                    .map(|w| w.parse().unwrap())
+                    .collect();
+                Vec3::new(v[0], v[1], v[2])
+            })
+            .fold(Vec3::splat(f32::INFINITY), Vec3::min);
+
+        // normalized space spans the 4 voxels of the model
+        assert_eq!(min * 2.0 + 2.0, Vec3::new(1.0, 2.0, 3.0));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use glam::{IVec3, Vec3};
+
+use crate::octree::{Branch, Node, Octree};
+
+use super::{FormatError, UpAxis};
+
+/// The direction and corners of each face of a unit cube.
+///
+/// Corners are wound counter-clockwise when viewed from outside.
+const FACES: [(IVec3, [Vec3; 4]); 6] = [
+    (
+        IVec3::new(1, 0, 0),
+        [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+        ],
+    ),
+    (
+        IVec3::new(-1, 0, 0),
+        [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ],
+    ),
+    (
+        IVec3::new(0, 1, 0),
+        [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, 1.0, 0.0),
+        ],
+    ),
+    (
+        IVec3::new(0, -1, 0),
+        [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ],
+    ),
+    (
+        IVec3::new(0, 0, 1),
+        [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ],
+    ),
+    (
+        IVec3::new(0, 0, -1),
+        [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ],
+    ),
+];
+
+/// Returns true if `child` lies on the side of its parent facing `direction`.
+fn is_child_facing(child: u32, direction: IVec3) -> bool {
+    let side = |bit: u32, axis: i32| match axis {
+        1 => child & bit != 0,
+        -1 => child & bit == 0,
+        _ => true,
+    };
+
+    side(1, direction.x) && side(2, direction.y) && side(4, direction.z)
+}
+
+/// Collect the exposed parts of `face` of the solid cell at `branch`.
+///
+/// When the neighbouring cell is subdivided, the face is split into the four
+/// children facing it, so that the resulting faces are exact.
+fn collect_faces(octree: &Octree, branch: Branch, face: usize, faces: &mut Vec<(Branch, usize)>) {
+    let (direction, _) = FACES[face];
+    let neighbor = octree.get(Branch::new(branch.path + direction, branch.depth));
+
+    if neighbor.is_parent() {
+        for child in 0..8 {
+            if is_child_facing(child, direction) {
+                collect_faces(octree, branch.with_child(child), face, faces);
+            }
+        }
+    } else if !neighbor.is_solid() {
+        faces.push((branch, face));
+    }
+}
+
+/// Write the exposed faces of `octree` as a Wavefront OBJ mesh.
+///
+/// Vertices are in normalized octree space, and carry the color of their node.
+pub fn write_obj(octree: &Octree, writer: &mut impl Write) -> Result<(), FormatError> {
+    write_obj_with_axis(octree, writer, UpAxis::Y)
+}
+
+/// Same as [`write_obj`], but converts the vertices to have `up` as the up axis.
+pub fn write_obj_with_axis(
+    octree: &Octree,
+    writer: &mut impl Write,
+    up: UpAxis,
+) -> Result<(), FormatError> {
+    let mut faces = Vec::new();
+    let mut vertex_count = 0;
+
+    for (branch, node) in octree.iter_nodes() {
+        faces.clear();
+
+        for face in 0..FACES.len() {
+            collect_faces(octree, branch, face, &mut faces);
+        }
+
+        for &(branch, face) in faces.iter() {
+            write_face(writer, branch, face, node, vertex_count, up)?;
+            vertex_count += 4;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_face(
+    writer: &mut impl Write,
+    branch: Branch,
+    face: usize,
+    node: &Node,
+    first_vertex: u32,
+    up: UpAxis,
+) -> Result<(), FormatError> {
+    let (_, corners) = FACES[face];
+
+    let min = branch.min_normalized();
+    let size = branch.size_normalized();
+
+    let r = node.r() as f32 / 255.0;
+    let g = node.g() as f32 / 255.0;
+    let b = node.b() as f32 / 255.0;
+
+    for corner in corners {
+        let v = up.remap_from_y_up(min + corner * size);
+        writeln!(writer, "v {} {} {} {} {} {}", v.x, v.y, v.z, r, g, b)?;
+    }
+
+    let i = first_vertex + 1;
+    writeln!(writer, "f {} {} {} {}", i, i + 1, i + 2, i + 3)?;
+
+    Ok(())
+}
+
+pub fn export_obj(octree: &Octree, path: impl AsRef<Path>) -> Result<(), FormatError> {
+    export_obj_with_axis(octree, path, UpAxis::Y)
+}
+
+pub fn export_obj_with_axis(
+    octree: &Octree,
+    path: impl AsRef<Path>,
+    up: UpAxis,
+) -> Result<(), FormatError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_obj_with_axis(octree, &mut writer, up)?;
+    writer.flush()?;
+
+    Ok(())
+}
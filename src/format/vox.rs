@@ -0,0 +1,163 @@
+use std::{fs, path::Path};
+
+use glam::{IVec3, UVec3};
+
+use crate::octree::{Branch, Node, Octree, OctreeBuilder};
+
+use super::{FormatError, UpAxis};
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() < len {
+            return Err(FormatError::UnexpectedEof);
+        }
+
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn id(&mut self) -> Result<[u8; 4], FormatError> {
+        let bytes = self.take(4)?;
+        Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.id()?))
+    }
+}
+
+/// Returns the color of `index` in the default MagicaVoxel palette.
+fn default_palette(index: u8) -> [u8; 3] {
+    const STEPS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let index = index as usize;
+
+    if index == 0 {
+        return [0, 0, 0];
+    }
+
+    if index <= 215 {
+        let i = index - 1;
+        return [STEPS[i / 36], STEPS[i / 6 % 6], STEPS[i % 6]];
+    }
+
+    let i = index - 216;
+    let value = RAMP[i % 10];
+
+    match i / 10 {
+        0 => [value, 0, 0],
+        1 => [0, value, 0],
+        2 => [0, 0, value],
+        _ => [value, value, value],
+    }
+}
+
+/// Read a MagicaVoxel `.vox` file.
+///
+/// Only the first model in the file is imported. The model is centered on
+/// the origin, converted from MagicaVoxel's Z-up to Y-up, and stored at the
+/// smallest depth that fits it.
+pub fn read_vox(bytes: &[u8]) -> Result<Octree, FormatError> {
+    read_vox_with_axis(bytes, UpAxis::Z)
+}
+
+/// Same as [`read_vox`], but for models authored with `up` as the up axis.
+pub fn read_vox_with_axis(bytes: &[u8], up: UpAxis) -> Result<Octree, FormatError> {
+    let mut reader = Reader { bytes };
+
+    if reader.id()? != *b"VOX " {
+        return Err(FormatError::InvalidHeader);
+    }
+
+    let _version = reader.u32()?;
+
+    if reader.id()? != *b"MAIN" {
+        return Err(FormatError::InvalidHeader);
+    }
+
+    let main_content = reader.u32()?;
+    let _main_children = reader.u32()?;
+    reader.take(main_content as usize)?;
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = None;
+
+    while !reader.bytes.is_empty() {
+        let id = reader.id()?;
+        let content_len = reader.u32()? as usize;
+        let children_len = reader.u32()? as usize;
+
+        let mut content = Reader {
+            bytes: reader.take(content_len)?,
+        };
+        reader.take(children_len)?;
+
+        match &id {
+            b"SIZE" if size.is_none() => {
+                size = Some(UVec3::new(content.u32()?, content.u32()?, content.u32()?));
+            }
+            b"XYZI" if voxels.is_none() => {
+                let count = content.u32()? as usize;
+                voxels = Some(content.take(count * 4)?);
+            }
+            b"RGBA" => {
+                palette = Some(content.take(256 * 4)?);
+            }
+            _ => {}
+        }
+    }
+
+    let (Some(size), Some(voxels)) = (size, voxels) else {
+        return Err(FormatError::UnexpectedEof);
+    };
+
+    let color = |index: u8| match palette {
+        // palette entry `i` holds the color of index `i + 1`
+        Some(palette) if index > 0 => {
+            let i = (index as usize - 1) * 4;
+            [palette[i], palette[i + 1], palette[i + 2]]
+        }
+        _ => default_palette(index),
+    };
+
+    let extent = size.max_element().max(2);
+    let depth = u32::BITS - (extent - 1).leading_zeros();
+
+    // center the model after remapping it, flipping an odd axis would otherwise
+    // move its middle cell off the origin
+    let first = up.remap_cell_to_y_up(IVec3::ZERO);
+    let last = up.remap_cell_to_y_up(size.as_ivec3() - 1);
+    let min = first.min(last);
+    let center = min + (first.max(last) - min + 1) / 2;
+
+    let mut builder = OctreeBuilder::new();
+
+    for voxel in voxels.chunks_exact(4) {
+        let x = voxel[0] as i32;
+        let y = voxel[1] as i32;
+        let z = voxel[2] as i32;
+
+        let path = up.remap_cell_to_y_up(IVec3::new(x, y, z)) - center;
+
+        let [r, g, b] = color(voxel[3]);
+        builder.set(Branch::new(path, depth), Node::rgb8(r, g, b));
+    }
+
+    Ok(builder.finish())
+}
+
+pub fn import_vox(path: impl AsRef<Path>) -> Result<Octree, FormatError> {
+    import_vox_with_axis(path, UpAxis::Z)
+}
+
+pub fn import_vox_with_axis(path: impl AsRef<Path>, up: UpAxis) -> Result<Octree, FormatError> {
+    let bytes = fs::read(path)?;
+    read_vox_with_axis(&bytes, up)
+}
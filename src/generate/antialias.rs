@@ -0,0 +1,90 @@
+use glam::{UVec3, Vec3};
+
+use crate::octree::Node;
+
+use super::Generate;
+
+/// Softens the edges of another generator by supersampling each cell.
+///
+/// Cells fully inside the shape keep the node of the wrapped generator, cells it
+/// only partially covers get the average color of the covered samples and are made
+/// translucent, see [`Node::blend`]. The wrapped generator's
+/// [`Generate::material`] is not used.
+#[derive(Clone, Copy, Debug)]
+pub struct Antialias<T> {
+    pub inner: T,
+}
+
+impl<T> Antialias<T> {
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: Generate> Generate for Antialias<T> {
+    fn dimensions(&self) -> UVec3 {
+        self.inner.dimensions()
+    }
+
+    fn depth(&self) -> u32 {
+        self.inner.depth()
+    }
+
+    fn get_node(&self, point: Vec3) -> Option<Node> {
+        // the size of a cell in normalized space
+        let step = 1.0 / self.inner.dimensions().as_vec3();
+
+        let mut covered = 0;
+        let mut average = Node::empty();
+
+        for i in 0..8 {
+            let corner = Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32);
+            let offset = (corner - 0.5) * 0.5 * step;
+
+            if let Some(node) = self.inner.get_node(point + offset) {
+                covered += 1;
+                average = Node::blend(average, node, 1.0 / covered as f32);
+            }
+        }
+
+        match covered {
+            0 => None,
+            8 => self.inner.get_node(point).or(Some(average)),
+            _ => Some(Node {
+                flags: average.flags & !Node::SHADOW_BIT,
+                ..average
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate::Sphere, octree::Octree};
+
+    #[test]
+    fn translucent_edges() {
+        let sphere = Sphere::new(8, 4);
+
+        let solid = |octree: &Octree| -> Vec<Node> {
+            (octree.iter_nodes())
+                .map(|(_, &node)| node)
+                .filter(|node| node.is_solid())
+                .collect()
+        };
+
+        let aliased = Octree::generate(&sphere);
+        assert!(solid(&aliased).iter().all(|node| node.is_shadow()));
+
+        let antialiased = Octree::generate(&Antialias::new(sphere));
+        let nodes = solid(&antialiased);
+        assert!(nodes.iter().any(|node| !node.is_shadow()));
+
+        // the inside is untouched, only the boundary is softened
+        assert!(antialiased.get((0, 0, 0, 4)).is_shadow());
+        assert!(antialiased.get((5, 5, 0, 4)).is_solid());
+        assert!(!antialiased.get((5, 5, 0, 4)).is_shadow());
+        assert!(antialiased.get((-8, -8, -8, 4)).is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use glam::{UVec3, Vec3};
+
+use crate::octree::Node;
+
+use super::{sperlin, Generate};
+
+/// A body of translucent water filling everything below `level`.
+#[derive(Clone, Copy, Debug)]
+pub struct Water {
+    /// The height of the surface in normalized space.
+    pub level: f32,
+    pub depth: u32,
+}
+
+impl Water {
+    pub const fn new(level: f32, depth: u32) -> Self {
+        Self { level, depth }
+    }
+}
+
+impl Generate for Water {
+    fn dimensions(&self) -> UVec3 {
+        UVec3::splat(1 << self.depth.saturating_sub(1))
+    }
+
+    fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    fn get_node(&self, point: Vec3) -> Option<Node> {
+        if point.y >= self.level {
+            return None;
+        }
+
+        // darker the further below the surface
+        let shade = 1.0 - (self.level - point.y).min(1.0) * 0.4;
+        Some(Node::rgb_translucent(Vec3::new(0.15, 0.4, 0.8) * shade))
+    }
+}
+
+/// A pool of emissive lava filling everything below `level`.
+#[derive(Clone, Copy, Debug)]
+pub struct Lava {
+    /// The height of the surface in normalized space.
+    pub level: f32,
+    pub depth: u32,
+}
+
+impl Lava {
+    pub const fn new(level: f32, depth: u32) -> Self {
+        Self { level, depth }
+    }
+}
+
+impl Generate for Lava {
+    fn dimensions(&self) -> UVec3 {
+        UVec3::splat(1 << self.depth.saturating_sub(1))
+    }
+
+    fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    fn get_node(&self, point: Vec3) -> Option<Node> {
+        if point.y >= self.level {
+            return None;
+        }
+
+        let heat = sperlin(point * 6.0);
+        let color = Vec3::new(1.0, 0.25, 0.05).lerp(Vec3::new(1.0, 0.8, 0.2), heat);
+
+        Some(Node::emissive(
+            (color.x * 255.0) as u8,
+            (color.y * 255.0) as u8,
+            (color.z * 255.0) as u8,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::octree::Octree;
+
+    #[test]
+    fn water_is_translucent() {
+        let octree = Octree::generate(&Water::new(0.0, 3));
+
+        let nodes: Vec<_> = octree.iter_nodes().map(|(_, node)| *node).collect();
+        assert!(nodes.iter().any(|node| node.is_solid()));
+
+        for node in nodes.iter().filter(|node| node.is_solid()) {
+            assert!(!node.is_shadow());
+            assert!(!node.is_emissive());
+        }
+
+        assert!(octree.get((0, 1, 0, 2)).is_empty());
+        assert!(octree.get((0, -2, 0, 3)).is_solid());
+    }
+
+    #[test]
+    fn lava_is_emissive() {
+        let octree = Octree::generate(&Lava::new(0.0, 3));
+
+        let solid: Vec<_> = (octree.iter_nodes())
+            .map(|(_, node)| *node)
+            .filter(|node| node.is_solid())
+            .collect();
+        assert!(!solid.is_empty());
+
+        for node in solid {
+            assert!(node.is_emissive());
+            assert!(node.is_shadow());
+        }
+
+        // the flag survives packing for the gpu
+        let packed = octree.to_palette().to_octree();
+        assert!(packed.get((0, -2, 0, 3)).is_emissive());
+    }
+}
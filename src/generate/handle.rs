@@ -0,0 +1,332 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+};
+
+use crate::{generate::Generate, octree::Octree};
+
+#[derive(Default)]
+struct Shared {
+    /// The bits of the completed fraction.
+    progress: AtomicU32,
+    cancelled: AtomicBool,
+    /// Whether generation is done, and the waker of the task awaiting it.
+    done: Mutex<(bool, Option<Waker>)>,
+}
+
+/// Marks generation as done when dropped, also when the generator panics,
+/// so awaiting tasks are woken either way.
+struct Finish(Arc<Shared>);
+
+impl Drop for Finish {
+    fn drop(&mut self) {
+        let mut done = (self.0.done.lock()).unwrap_or_else(PoisonError::into_inner);
+        done.0 = true;
+
+        if let Some(waker) = done.1.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Generates an octree on a background thread, see [`GenerateHandle::spawn`].
+///
+/// The handle is also a future resolving to the octree, so it can be awaited
+/// on any async runtime. Cancelling stops generation before the next x slice,
+/// the octree then holds the slices generated so far. Dropping the handle
+/// cancels it too.
+pub struct GenerateHandle {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Octree>>,
+}
+
+impl GenerateHandle {
+    /// Start generating `sdf` like [`Octree::generate`] on a new thread.
+    pub fn spawn<T: Generate + Send + 'static>(sdf: T) -> Self {
+        let shared = Arc::new(Shared::default());
+
+        let thread = thread::spawn({
+            let shared = shared.clone();
+
+            move || {
+                let _finish = Finish(shared.clone());
+
+                Octree::generate_until(&sdf, &mut |fraction| {
+                    shared.progress.store(fraction.to_bits(), Ordering::Relaxed);
+                    !shared.cancelled.load(Ordering::Relaxed)
+                })
+            }
+        });
+
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the completed fraction in `0.0..=1.0`.
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.shared.progress.load(Ordering::Relaxed))
+    }
+
+    /// Stop generating before the next x slice.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Whether generation is done, either completed, cancelled or panicked.
+    pub fn is_finished(&self) -> bool {
+        (self.shared.done.lock())
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
+
+    /// Wait for generation to finish, returns the partial octree if cancelled.
+    ///
+    /// # Panics
+    ///
+    /// If the generator panicked.
+    pub fn join(mut self) -> Octree {
+        self.take_octree()
+    }
+
+    fn take_octree(&mut self) -> Octree {
+        let thread = self.thread.take().expect("octree was already taken");
+
+        match thread.join() {
+            Ok(octree) => octree,
+            Err(err) => std::panic::resume_unwind(err),
+        }
+    }
+}
+
+impl Drop for GenerateHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+impl Future for GenerateHandle {
+    type Output = Octree;
+
+    /// # Panics
+    ///
+    /// If the generator panicked.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Octree> {
+        {
+            let mut done = (self.shared.done.lock()).unwrap_or_else(PoisonError::into_inner);
+
+            if !done.0 {
+                done.1 = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+
+        Poll::Ready(self.take_octree())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::AtomicU64,
+        task::Wake,
+        time::{Duration, Instant},
+    };
+
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::*;
+    use crate::{generate::Sphere, octree::Node};
+
+    /// A sphere that blocks sampling the positive x half until `open` is set.
+    #[derive(Default)]
+    struct Gated {
+        sphere: Sphere,
+        open: Arc<AtomicBool>,
+        /// The number of cells sampled.
+        sampled: Arc<AtomicU64>,
+    }
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    impl Generate for Gated {
+        fn dimensions(&self) -> UVec3 {
+            self.sphere.dimensions()
+        }
+
+        fn depth(&self) -> u32 {
+            self.sphere.depth()
+        }
+
+        fn get_node(&self, point: Vec3) -> Option<Node> {
+            while point.x > 0.0 && !self.open.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            self.sampled.fetch_add(1, Ordering::Relaxed);
+            self.sphere.get_node(point)
+        }
+    }
+
+    fn solid_cells(octree: &Octree, depth: u32) -> Vec<IVec3> {
+        let mut cells: Vec<_> = octree
+            .iter_solid_cells(depth)
+            .map(|(cell, _)| cell)
+            .collect();
+        cells.sort_by_key(|cell| (cell.x, cell.y, cell.z));
+        cells
+    }
+
+    #[test]
+    fn cancel_keeps_partial_tree() {
+        let sphere = Sphere::new(8, 4);
+        let full = Octree::generate(&sphere);
+
+        let open = Arc::new(AtomicBool::new(false));
+        let handle = GenerateHandle::spawn(Gated {
+            sphere,
+            open: open.clone(),
+            ..Default::default()
+        });
+
+        // wait until the first positive slice blocks, then cancel the rest
+        while handle.progress() < 0.5 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        handle.cancel();
+        open.store(true, Ordering::Relaxed);
+        let partial = handle.join();
+
+        // the negative half and the slice that was underway, nothing past it
+        let expected: Vec<_> = (solid_cells(&full, 4).into_iter())
+            .filter(|cell| cell.x <= 0)
+            .collect();
+
+        assert!(!expected.is_empty());
+        assert_eq!(solid_cells(&partial, 4), expected);
+        assert!(partial.count_solid(4) < full.count_solid(4));
+    }
+
+    #[test]
+    fn join_after_completion() {
+        let sphere = Sphere::new(8, 4);
+        let mut handle = GenerateHandle::spawn(sphere);
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        // poll like an executor would, until woken with the octree ready
+        let octree = loop {
+            match Pin::new(&mut handle).poll(&mut cx) {
+                Poll::Ready(octree) => break octree,
+                Poll::Pending => {
+                    while !flag.0.swap(false, Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+            }
+        };
+
+        assert!(octree.structurally_eq(&Octree::generate(&sphere)));
+        assert_eq!(handle.progress(), 1.0);
+
+        let handle = GenerateHandle::spawn(sphere);
+        assert!(handle.join().structurally_eq(&Octree::generate(&sphere)));
+    }
+
+    #[test]
+    fn panic_wakes_task() {
+        struct Broken;
+
+        impl Generate for Broken {
+            fn dimensions(&self) -> UVec3 {
+                UVec3::splat(2)
+            }
+
+            fn depth(&self) -> u32 {
+                2
+            }
+
+            fn get_node(&self, _: Vec3) -> Option<Node> {
+                panic!("broken generator");
+            }
+        }
+
+        let mut handle = GenerateHandle::spawn(Broken);
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        // the task is woken, and polling resumes the panic instead of pending forever
+        loop {
+            match panic::catch_unwind(AssertUnwindSafe(|| Pin::new(&mut handle).poll(&mut cx))) {
+                Ok(Poll::Ready(_)) => panic!("a broken generator completed"),
+                Ok(Poll::Pending) => {
+                    while !flag.0.swap(false, Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn drop_cancels() {
+        let sphere = Sphere::new(8, 4);
+
+        let full = Gated {
+            sphere,
+            open: Arc::new(AtomicBool::new(true)),
+            ..Default::default()
+        };
+        Octree::generate(&full);
+
+        let gated = Gated {
+            sphere,
+            ..Default::default()
+        };
+        let (open, sampled) = (gated.open.clone(), gated.sampled.clone());
+        let handle = GenerateHandle::spawn(gated);
+
+        while handle.progress() < 0.5 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        drop(handle);
+        open.store(true, Ordering::Relaxed);
+
+        // the thread drops its copy of `open` once it stops
+        let start = Instant::now();
+        while Arc::strong_count(&open) > 1 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let sampled = sampled.load(Ordering::Relaxed);
+        assert!(sampled < full.sampled.load(Ordering::Relaxed), "{sampled}");
+    }
+}
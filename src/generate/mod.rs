@@ -1,8 +1,19 @@
+mod antialias;
 mod block;
+mod fluid;
+mod handle;
 mod shape;
+mod smooth;
 
+#[allow(unused_imports)]
+pub use antialias::*;
 pub use block::*;
+#[allow(unused_imports)]
+pub use fluid::*;
+pub use handle::*;
 pub use shape::*;
+#[allow(unused_imports)]
+pub use smooth::*;
 
 use std::cmp::Ordering;
 
@@ -10,11 +21,63 @@ use glam::{UVec3, Vec3};
 
 use crate::octree::Node;
 
+/// Generates an octree by sampling points in normalized space.
+///
+/// [`Generate::get_node`] returns any solid node, not just opaque ones,
+/// [`Node::translucent`] nodes don't cast shadows and [`Node::emissive`] nodes glow,
+/// see [`Water`] and [`Lava`].
 pub trait Generate {
     fn dimensions(&self) -> UVec3;
     fn depth(&self) -> u32;
 
     fn get_node(&self, point: Vec3) -> Option<Node>;
+
+    /// Returns the node for a solid `point`, where `node` was returned by
+    /// [`Generate::get_node`] and `normal` approximates the surface normal.
+    ///
+    /// This separates shading from the shape given by `get_node`,
+    /// by default `node` is used.
+    fn material(&self, point: Vec3, normal: Vec3, node: Node) -> Node {
+        let _ = (point, normal);
+        node
+    }
+
+    /// Whether [`Generate::material`] is passed a normal, it gets zero otherwise.
+    ///
+    /// Estimating normals samples the neighbors of every solid point, so it is opt-in.
+    fn needs_normal(&self) -> bool {
+        false
+    }
+
+    /// Returns the outward surface normal at `point`, see [`SmoothNormals`].
+    ///
+    /// By default this is a finite difference of the occupancy of the neighboring
+    /// cells, generators with a smooth distance field should return its gradient.
+    fn normal(&self, point: Vec3) -> Vec3 {
+        estimate_normal(self, point, 1.0 / self.dimensions().as_vec3())
+    }
+}
+
+/// Estimate the surface normal at `point` from the occupancy of its neighbors,
+/// `step` is the distance between neighboring samples.
+///
+/// Returns zero for points inside the volume.
+pub fn estimate_normal(generate: &(impl Generate + ?Sized), point: Vec3, step: Vec3) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let offset = Vec3::new(x as f32, y as f32, z as f32);
+
+                if generate.get_node(point + offset * step).is_none() {
+                    normal += offset.normalize_or_zero();
+                }
+            }
+        }
+    }
+
+    normal.normalize_or_zero()
 }
 
 #[derive(Clone, Copy, Debug)]
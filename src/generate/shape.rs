@@ -1,24 +1,145 @@
-use glam::{UVec3, Vec3};
+use glam::{UVec3, Vec2, Vec3};
 
 use crate::octree::Node;
 
 use super::Generate;
 
-#[derive(Clone, Copy, Debug, Default)]
+/// A sphere centered in the bounds of the generator.
+///
+/// The shape and the sampling are independent, so the same sphere can be
+/// generated at any resolution.
+#[derive(Clone, Copy, Debug)]
 pub struct Sphere {
-    pub radius: u32,
+    /// The number of cells from the center to each side of the bounds, the
+    /// sampling resolution returned as [`Generate::dimensions`].
+    pub resolution: u32,
+    /// The depth of the generated cells, `resolution` should be at most
+    /// `1 << (depth - 1)` for the bounds to fit within the octree.
     pub depth: u32,
+    /// The radius in normalized space, where 1 touches the sides of the bounds.
+    pub radius: f32,
+    pub color: Node,
+}
+
+impl Default for Sphere {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
 }
 
 impl Sphere {
-    pub const fn new(radius: u32, depth: u32) -> Self {
-        Self { radius, depth }
+    /// A white sphere filling its bounds, see [`Sphere::with_radius`] and [`Sphere::with_color`].
+    pub const fn new(resolution: u32, depth: u32) -> Self {
+        Self {
+            resolution,
+            depth,
+            radius: 1.0,
+            color: Node::solid(255, 255, 255),
+        }
+    }
+
+    pub const fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub const fn with_color(mut self, color: Node) -> Self {
+        self.color = color;
+        self
     }
 }
 
 impl Generate for Sphere {
     fn dimensions(&self) -> UVec3 {
-        UVec3::splat(self.radius)
+        UVec3::splat(self.resolution)
+    }
+
+    fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    fn get_node(&self, point: Vec3) -> Option<Node> {
+        if point.length() < self.radius {
+            Some(self.color)
+        } else {
+            None
+        }
+    }
+
+    fn normal(&self, point: Vec3) -> Vec3 {
+        point.normalize_or_zero()
+    }
+}
+
+/// A box centered in the bounds of the generator.
+#[derive(Clone, Copy, Debug)]
+pub struct Cube {
+    /// Half the size of the box along each axis in normalized space,
+    /// one fills the bounds.
+    pub half_extent: Vec3,
+    pub depth: u32,
+    pub color: Node,
+}
+
+impl Cube {
+    pub const fn new(half_extent: Vec3, depth: u32, color: Node) -> Self {
+        Self {
+            half_extent,
+            depth,
+            color,
+        }
+    }
+}
+
+impl Generate for Cube {
+    fn dimensions(&self) -> UVec3 {
+        UVec3::splat(1 << self.depth.saturating_sub(1))
+    }
+
+    fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    fn get_node(&self, point: Vec3) -> Option<Node> {
+        if point.abs().cmplt(self.half_extent).all() {
+            Some(self.color)
+        } else {
+            None
+        }
+    }
+}
+
+/// A ring lying in the xz plane.
+#[derive(Clone, Copy, Debug)]
+pub struct Torus {
+    /// The distance from the center to the middle of the ring, in normalized space.
+    pub major: f32,
+    /// The radius of the ring itself, in normalized space.
+    pub minor: f32,
+    pub depth: u32,
+    pub color: Node,
+}
+
+impl Torus {
+    /// A white torus, see [`Torus::with_color`].
+    pub const fn new(major: f32, minor: f32, depth: u32) -> Self {
+        Self {
+            major,
+            minor,
+            depth,
+            color: Node::solid(255, 255, 255),
+        }
+    }
+
+    pub const fn with_color(mut self, color: Node) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl Generate for Torus {
+    fn dimensions(&self) -> UVec3 {
+        UVec3::splat(1 << self.depth.saturating_sub(1))
     }
 
     fn depth(&self) -> u32 {
@@ -26,10 +147,103 @@ impl Generate for Sphere {
     }
 
     fn get_node(&self, point: Vec3) -> Option<Node> {
-        if point.length() < 1.0 {
-            Some(Node::solid(255, 255, 255))
+        let ring = Vec2::new(point.x, point.z).length() - self.major;
+
+        if Vec2::new(ring, point.y).length() < self.minor {
+            Some(self.color)
         } else {
             None
         }
     }
+
+    fn normal(&self, point: Vec3) -> Vec3 {
+        // away from the closest point on the middle of the ring
+        let around = Vec2::new(point.x, point.z).normalize_or_zero() * self.major;
+        (point - Vec3::new(around.x, 0.0, around.y)).normalize_or_zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::octree::{Branch, Octree};
+
+    #[test]
+    fn sphere() {
+        let red = Node::solid(255, 0, 0);
+        let sphere = Sphere::new(8, 4).with_color(red);
+
+        assert_eq!(sphere.get_node(Vec3::ZERO), Some(red));
+        assert_eq!(sphere.get_node(Vec3::splat(0.9)), None);
+
+        let octree = Octree::generate(&sphere);
+        assert_eq!(octree.get((0, 0, 0, 4)), red);
+        assert!(octree.get((-8, -8, -8, 4)).is_empty());
+
+        let white = Sphere::new(8, 4).get_node(Vec3::ZERO).unwrap();
+        assert_eq!((white.r(), white.g(), white.b()), (255, 255, 255));
+    }
+
+    #[test]
+    fn sphere_resolution() {
+        let coarse = Octree::generate(&Sphere::new(8, 4).with_radius(0.5));
+        let fine = Octree::generate(&Sphere::new(32, 6).with_radius(0.5));
+
+        // smaller leaves along the surface, covering about the same volume
+        assert!(fine.iter_nodes().count() > coarse.iter_nodes().count());
+
+        let (coarse_volume, fine_volume) = (coarse.count_solid(6), fine.count_solid(6));
+        assert!(coarse_volume.abs_diff(fine_volume) < fine_volume / 5);
+
+        for point in [
+            Vec3::ZERO,
+            Vec3::new(0.3, 0.0, 0.0),
+            Vec3::new(-0.2, 0.2, 0.2),
+            Vec3::new(0.7, 0.0, 0.0),
+            Vec3::new(0.0, -0.4, 0.4),
+            Vec3::splat(-0.9),
+        ] {
+            let inside = point.length() < 0.5;
+            let coarse = coarse.get(Branch::from_point_normalized(point, 4));
+            let fine = fine.get(Branch::from_point_normalized(point, 6));
+
+            assert_eq!(coarse.is_solid(), inside, "{point}");
+            assert_eq!(fine.is_solid(), inside, "{point}");
+        }
+
+        // the radius only changes the shape, not the sampling
+        let sphere = Sphere::new(8, 4);
+        assert_eq!(sphere.with_radius(0.5).dimensions(), sphere.dimensions());
+    }
+
+    #[test]
+    fn cube() {
+        let blue = Node::solid(0, 0, 255);
+        let cube = Cube::new(Vec3::new(0.5, 1.0, 0.25), 3, blue);
+
+        assert_eq!(cube.get_node(Vec3::ZERO), Some(blue));
+        assert_eq!(cube.get_node(Vec3::new(0.4, 0.9, -0.2)), Some(blue));
+        assert_eq!(cube.get_node(Vec3::new(0.6, 0.0, 0.0)), None);
+        assert_eq!(cube.get_node(Vec3::new(0.0, 0.0, -0.3)), None);
+
+        // 4 by 8 by 2 cells
+        let octree = Octree::generate(&cube);
+        assert_eq!(octree.count_solid(3), 4 * 8 * 2);
+    }
+
+    #[test]
+    fn torus() {
+        let torus = Torus::new(0.6, 0.25, 5);
+
+        // the hole is empty, the ring is solid
+        assert_eq!(torus.get_node(Vec3::ZERO), None);
+        assert!(torus.get_node(Vec3::new(0.6, 0.0, 0.0)).is_some());
+        assert!(torus.get_node(Vec3::new(0.0, 0.1, -0.7)).is_some());
+        assert_eq!(torus.get_node(Vec3::new(0.6, 0.3, 0.0)), None);
+
+        let octree = Octree::generate(&torus);
+        assert!(octree.get((0, 0, 0, 5)).is_empty());
+        assert!(octree.get((9, 0, 0, 5)).is_solid());
+        assert!(octree.get((-10, -1, 0, 5)).is_solid());
+    }
 }
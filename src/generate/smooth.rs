@@ -0,0 +1,94 @@
+use glam::{UVec3, Vec3};
+
+use crate::octree::Node;
+
+use super::Generate;
+
+/// Stores the [`Generate::normal`] of another generator in its surface cells,
+/// so curved shapes are shaded smoothly while their silhouettes stay blocky,
+/// see [`Node::with_normal`].
+///
+/// Cells inside the shape are left without a normal, so they still combine.
+#[derive(Clone, Copy, Debug)]
+pub struct SmoothNormals<T> {
+    pub inner: T,
+}
+
+impl<T> SmoothNormals<T> {
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: Generate> Generate for SmoothNormals<T> {
+    fn dimensions(&self) -> UVec3 {
+        self.inner.dimensions()
+    }
+
+    fn depth(&self) -> u32 {
+        self.inner.depth()
+    }
+
+    fn get_node(&self, point: Vec3) -> Option<Node> {
+        self.inner.get_node(point)
+    }
+
+    fn material(&self, point: Vec3, normal: Vec3, node: Node) -> Node {
+        let node = self.inner.material(point, normal, node);
+
+        // the estimated normal is zero inside the shape
+        if normal == Vec3::ZERO {
+            return node;
+        }
+
+        node.with_normal(self.inner.normal(point))
+    }
+
+    fn needs_normal(&self) -> bool {
+        true
+    }
+
+    fn normal(&self, point: Vec3) -> Vec3 {
+        self.inner.normal(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate::Sphere, octree::Octree};
+
+    #[test]
+    fn sphere_normals_point_outward() {
+        let sphere = Sphere::new(16, 5);
+        let octree = Octree::generate(&SmoothNormals::new(sphere));
+
+        let mut surface = 0;
+
+        for (branch, node) in octree.iter_nodes() {
+            if !node.is_solid() {
+                continue;
+            }
+
+            let center = branch.min_normalized() + branch.size_normalized() / 2.0;
+
+            match node.normal() {
+                Some(normal) => {
+                    surface += 1;
+                    assert!(normal.dot(center.normalize()) > 0.95);
+                }
+                // only cells surrounded by solid cells are left without a normal
+                None if branch.depth == sphere.depth => {
+                    assert!(center.length() < 1.0 - branch.size_normalized());
+                }
+                None => {}
+            }
+        }
+
+        assert!(surface > 0);
+
+        // the inside still combines into large leaves
+        let plain = Octree::generate(&sphere);
+        assert!(octree.len() < plain.len() * 4);
+    }
+}
@@ -2,8 +2,18 @@ use std::{collections::HashSet, hash::Hash};
 
 use deref_derive::{Deref, DerefMut};
 use glam::Vec2;
-use winit::event::MouseButton;
+use serde::{Deserialize, Serialize};
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton,
+    MouseScrollDelta::{LineDelta, PixelDelta},
+    WindowEvent,
+};
 
+/// The state of a set of buttons, updated every frame.
+///
+/// The sets are hashed, so iterating them visits the buttons in a different
+/// order from run to run, use [`Input::held_sorted`] for keys where the order
+/// matters, like when replaying a [`Recording`].
 #[derive(Clone, Debug)]
 pub struct Input<T> {
     pub held: HashSet<T>,
@@ -50,6 +60,15 @@ impl<T: Copy + Eq + Hash> Input<T> {
     }
 }
 
+impl<T: Copy + Ord> Input<T> {
+    /// Returns the held buttons in order, the same in every run.
+    pub fn held_sorted(&self) -> Vec<T> {
+        let mut held: Vec<_> = self.held.iter().copied().collect();
+        held.sort_unstable();
+        held
+    }
+}
+
 impl<T> Default for Input<T> {
     fn default() -> Self {
         Self::new()
@@ -72,6 +91,16 @@ impl Mouse {
         self.scroll = Vec2::ZERO;
         self.input.update();
     }
+
+    /// Scale the cursor position by `factor`, the ratio of the new and old
+    /// scale factor of the window.
+    ///
+    /// Positions are in physical pixels, so when the scale factor changes the
+    /// cursor stays at the same logical position, but the last reported position
+    /// is off until the cursor moves again.
+    pub fn rescale(&mut self, factor: f32) {
+        self.position *= factor;
+    }
 }
 
 pub type Key = winit::event::VirtualKeyCode;
@@ -86,4 +115,226 @@ impl Keyboard {
     pub fn update(&mut self) {
         self.input.update();
     }
+
+    /// Whether either control key is held.
+    pub fn ctrl(&self) -> bool {
+        self.is_held(Key::LControl) || self.is_held(Key::RControl)
+    }
+
+    /// Whether either shift key is held.
+    pub fn shift(&self) -> bool {
+        self.is_held(Key::LShift) || self.is_held(Key::RShift)
+    }
+
+    /// Whether either alt key is held.
+    pub fn alt(&self) -> bool {
+        self.is_held(Key::LAlt) || self.is_held(Key::RAlt)
+    }
+
+    /// Whether every key of `keys` is held, and one of them was pressed this
+    /// frame, so a shortcut fires once when its last key goes down.
+    ///
+    /// Modifiers match either side, `LControl` is held while `RControl` is.
+    pub fn chord(&self, keys: &[Key]) -> bool {
+        let held = |key| both_sides(key).iter().any(|&key| self.is_held(key));
+        let pressed = |key| both_sides(key).iter().any(|&key| self.is_pressed(key));
+
+        keys.iter().all(|&key| held(key)) && keys.iter().any(|&key| pressed(key))
+    }
+}
+
+/// Returns the left and right variants of a modifier, or `key` twice for others.
+fn both_sides(key: Key) -> [Key; 2] {
+    match key {
+        Key::LControl | Key::RControl => [Key::LControl, Key::RControl],
+        Key::LShift | Key::RShift => [Key::LShift, Key::RShift],
+        Key::LAlt | Key::RAlt => [Key::LAlt, Key::RAlt],
+        Key::LWin | Key::RWin => [Key::LWin, Key::RWin],
+        key => [key, key],
+    }
+}
+
+/// An input event, independent of the window it came from, so input can be
+/// recorded and replayed, see [`Recording`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    KeyPressed(Key),
+    KeyReleased(Key),
+    ButtonPressed(MouseButton),
+    ButtonReleased(MouseButton),
+    /// The cursor moved to a position in the window.
+    CursorMoved(Vec2),
+    /// The mouse moved, regardless of where the cursor is.
+    MouseMotion(Vec2),
+    Scroll(Vec2),
+}
+
+impl InputEvent {
+    /// Returns the input event `event` describes, if any.
+    pub fn from_event(event: &Event<()>) -> Option<Self> {
+        match event {
+            Event::DeviceEvent { event, .. } => match event {
+                DeviceEvent::MouseMotion { delta } => {
+                    Some(Self::MouseMotion(Vec2::new(delta.0 as f32, delta.1 as f32)))
+                }
+                DeviceEvent::MouseWheel { delta } => Some(Self::Scroll(match delta {
+                    LineDelta(x, y) => Vec2::new(*x, *y),
+                    PixelDelta(pos) => Vec2::new(pos.x as f32, pos.y as f32),
+                })),
+                DeviceEvent::Key(KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                }) => match state {
+                    ElementState::Pressed => Some(Self::KeyPressed(*key)),
+                    ElementState::Released => Some(Self::KeyReleased(*key)),
+                },
+                _ => None,
+            },
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::MouseInput { state, button, .. } => match state {
+                    ElementState::Pressed => Some(Self::ButtonPressed(*button)),
+                    ElementState::Released => Some(Self::ButtonReleased(*button)),
+                },
+                WindowEvent::CursorMoved { position, .. } => Some(Self::CursorMoved(Vec2::new(
+                    position.x as f32,
+                    position.y as f32,
+                ))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, mouse: &mut Mouse, keyboard: &mut Keyboard) {
+        match self {
+            Self::KeyPressed(key) => keyboard.press(key),
+            Self::KeyReleased(key) => keyboard.release(key),
+            Self::ButtonPressed(button) => mouse.press(button),
+            Self::ButtonReleased(button) => mouse.release(button),
+            Self::CursorMoved(position) => mouse.position = position,
+            Self::MouseMotion(delta) => mouse.delta += delta,
+            Self::Scroll(scroll) => mouse.scroll = scroll,
+        }
+    }
+}
+
+/// A sequence of input events, each with the time in seconds since the
+/// recording started.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub events: Vec<(f32, InputEvent)>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `event` at `time`, which must not be before the last event.
+    pub fn push(&mut self, time: f32, event: InputEvent) {
+        debug_assert!(self.duration() <= time);
+        self.events.push((time, event));
+    }
+
+    /// Returns the time of the last event.
+    pub fn duration(&self) -> f32 {
+        self.events.last().map_or(0.0, |&(time, _)| time)
+    }
+
+    /// Split the recording into frames of `delta` seconds, returns the events
+    /// that happened during each frame, until the last event.
+    pub fn frames(&self, delta: f32) -> impl Iterator<Item = &[(f32, InputEvent)]> {
+        assert!(delta > 0.0, "frames must have a positive length");
+
+        let mut rest = self.events.as_slice();
+        let mut frame = 0;
+        std::iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+
+            frame += 1;
+            let end = frame as f32 * delta;
+            let len = rest.iter().take_while(|&&(time, _)| time <= end).count();
+            let (frame, tail) = rest.split_at(len);
+            rest = tail;
+
+            Some(frame)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers_match_either_side() {
+        let mut keyboard = Keyboard::default();
+        assert!(!keyboard.ctrl());
+
+        for key in [Key::LControl, Key::RControl] {
+            keyboard.press(key);
+            assert!(keyboard.ctrl());
+            assert!(!keyboard.shift() && !keyboard.alt());
+
+            keyboard.release(key);
+            assert!(!keyboard.ctrl());
+        }
+
+        keyboard.press(Key::RShift);
+        keyboard.press(Key::RAlt);
+        assert!(keyboard.shift() && keyboard.alt());
+    }
+
+    #[test]
+    fn held_sorted() {
+        let keys = [
+            Key::Z,
+            Key::LControl,
+            Key::A,
+            Key::F5,
+            Key::Space,
+            Key::Key1,
+        ];
+
+        let mut forward = Keyboard::default();
+        for key in keys {
+            forward.press(key);
+        }
+
+        // a different insertion order and new hash seeds, like another run
+        let mut backward = Keyboard::default();
+        for key in keys.into_iter().rev() {
+            backward.press(key);
+        }
+
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+
+        assert_eq!(forward.held_sorted(), sorted);
+        assert_eq!(backward.held_sorted(), sorted);
+
+        forward.release(Key::A);
+        sorted.retain(|&key| key != Key::A);
+        assert_eq!(forward.held_sorted(), sorted);
+    }
+
+    #[test]
+    fn chord() {
+        let mut keyboard = Keyboard::default();
+
+        keyboard.press(Key::RControl);
+        assert!(!keyboard.chord(&[Key::LControl, Key::Z]));
+        keyboard.update();
+
+        // fires on the frame the last key goes down, with either control key
+        keyboard.press(Key::Z);
+        assert!(keyboard.chord(&[Key::LControl, Key::Z]));
+        assert!(!keyboard.chord(&[Key::LControl, Key::LShift, Key::Z]));
+
+        keyboard.update();
+        assert!(!keyboard.chord(&[Key::LControl, Key::Z]));
+    }
 }
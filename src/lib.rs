@@ -0,0 +1,91 @@
+#![allow(dead_code, clippy::identity_op)]
+
+//! Without the default `render` feature only the cpu core is built, the
+//! octree, generation, file formats and raycasting, without winit or wgpu.
+
+#[cfg(feature = "render")]
+use std::path::PathBuf;
+
+#[cfg(feature = "render")]
+use clap::{Parser, Subcommand};
+#[cfg(feature = "render")]
+use config::{Backend, PresentMode};
+#[cfg(feature = "render")]
+use world::Scene;
+
+pub mod error;
+pub mod format;
+pub mod generate;
+pub mod octree;
+pub mod ray;
+
+#[cfg(feature = "render")]
+pub mod app;
+#[cfg(feature = "render")]
+pub mod config;
+#[cfg(feature = "render")]
+pub mod editor;
+#[cfg(feature = "render")]
+pub mod input;
+#[cfg(feature = "render")]
+pub mod render;
+#[cfg(feature = "render")]
+pub mod session;
+#[cfg(feature = "render")]
+pub mod world;
+
+/// Flags override the values in the config file.
+#[cfg(feature = "render")]
+#[derive(Parser)]
+pub struct Args {
+    /// The config file to read, defaults to `oakum.toml` if it exists.
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+    #[clap(short, long)]
+    pub log_level: Option<log::LevelFilter>,
+    #[clap(long, value_enum)]
+    pub backend: Option<Backend>,
+    #[clap(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+    /// The world to open instead of the default scene.
+    #[clap(long)]
+    pub world: Option<PathBuf>,
+    /// The scene to open when no world is given.
+    #[clap(long, value_enum)]
+    pub scene: Option<Scene>,
+    /// Limit the frame rate to reduce power draw, input is still handled as it arrives.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_fps: Option<u32>,
+    #[cfg(feature = "headless")]
+    #[clap(flatten)]
+    pub render_path: RenderPathArgs,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Render a camera path to png frames without opening a window,
+/// e.g. `oakum --render-path flythrough.toml --out frames`.
+#[cfg(feature = "headless")]
+#[derive(clap::Args)]
+pub struct RenderPathArgs {
+    /// The camera path to render, see [`render::CameraPath`].
+    #[clap(long, requires = "out")]
+    pub render_path: Option<PathBuf>,
+    /// The directory to save the frames in.
+    #[clap(long)]
+    pub out: Option<PathBuf>,
+    #[clap(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+    #[clap(long, default_value_t = 1280)]
+    pub width: u32,
+    #[clap(long, default_value_t = 720)]
+    pub height: u32,
+}
+
+#[cfg(feature = "render")]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert a voxel file to another format without opening a window,
+    /// e.g. `oakum convert model.vox model.oak`.
+    Convert { input: PathBuf, output: PathBuf },
+}
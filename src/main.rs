@@ -1,41 +1,27 @@
-#![allow(dead_code)]
+use std::time::Instant;
 
-use app::App;
 use clap::Parser;
+use oakum::{app::App, config::Config, format, Args, Command};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
-mod app;
-mod generate;
-mod input;
-mod octree;
-mod ray;
-mod render;
-mod world;
-
-#[derive(Parser)]
-pub struct Args {
-    #[clap(short, long, default_value = "info")]
-    pub log_level: log::LevelFilter,
-}
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let config = Config::from_args(&args)?;
+    config.init_logger();
 
-impl Args {
-    pub fn init_logger(&self) {
-        env_logger::builder()
-            .filter_level(self.log_level)
-            .filter_module("wgpu", log::LevelFilter::Warn)
-            .filter_module("winit", log::LevelFilter::Warn)
-            .filter_module("naga", log::LevelFilter::Warn)
-            .init();
+    if let Some(Command::Convert { input, output }) = &args.command {
+        format::convert(input, output)?;
+        return Ok(());
     }
-}
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    args.init_logger();
+    #[cfg(feature = "headless")]
+    if let Some(ref path) = args.render_path.render_path {
+        return render_path(&config, &args.render_path, path);
+    }
 
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
@@ -43,7 +29,7 @@ fn main() -> anyhow::Result<()> {
         .build(&event_loop)
         .unwrap();
 
-    let mut app = unsafe { App::new(window) };
+    let mut app = unsafe { App::new(window, &config)? };
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -55,26 +41,65 @@ fn main() -> anyhow::Result<()> {
                 }
             }
             Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => {
-                    if app.request_close() {
-                        *control_flow = ControlFlow::Exit;
-                    }
+                WindowEvent::CloseRequested if app.request_close() => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                &WindowEvent::Resized(size) => {
+                    app.window_resized(size.width, size.height);
                 }
-                &WindowEvent::Resized(size)
-                | WindowEvent::ScaleFactorChanged {
+                &WindowEvent::ScaleFactorChanged {
+                    scale_factor,
                     new_inner_size: &mut size,
-                    ..
                 } => {
-                    app.window_resized(size.width, size.height);
+                    app.scale_factor_changed(scale_factor, size.width, size.height);
                 }
                 _ => {}
             },
-            Event::RedrawEventsCleared => {
-                app.request_redraw();
-            }
+            Event::RedrawEventsCleared => match app.next_frame() {
+                Some(next_frame) if Instant::now() < next_frame => {
+                    *control_flow = ControlFlow::WaitUntil(next_frame);
+                }
+                _ => app.request_redraw(),
+            },
             _ => {}
         }
 
         app.event(&event);
     });
 }
+
+/// Render the camera path at `path` to png frames, see [`oakum::render::CameraPath::render`].
+#[cfg(feature = "headless")]
+fn render_path(
+    config: &Config,
+    args: &oakum::RenderPathArgs,
+    path: &std::path::Path,
+) -> anyhow::Result<()> {
+    use oakum::{
+        format::Format,
+        render::{CameraPath, Renderer},
+        world::World,
+    };
+
+    let camera_path = CameraPath::load(path)?;
+
+    let mut world = World::new();
+    match config.world {
+        Some(ref world_path) => {
+            let octree = Format::from_path(world_path)?.read(world_path)?;
+            world.octree.replace(octree);
+        }
+        None => world
+            .octree
+            .replace(config.scene.unwrap_or_default().build()),
+    }
+
+    // `--out` is required by `--render-path`
+    let out = args.out.as_deref().unwrap_or(std::path::Path::new("."));
+
+    let mut renderer = Renderer::headless(args.width, args.height)?;
+    let frames = camera_path.render(&mut renderer, &mut world, args.fps, out)?;
+    println!("Saved {} frames to {}", frames, out.display());
+
+    Ok(())
+}
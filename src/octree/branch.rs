@@ -1,5 +1,5 @@
 use deref_derive::{Deref, DerefMut};
-use glam::{IVec3, Mat4, Vec3};
+use glam::{IVec3, Mat4, Quat, Vec3};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deref, DerefMut)]
@@ -10,6 +10,16 @@ pub struct Branch {
 }
 
 impl Branch {
+    /// The offsets to the six cells sharing a face with a cell.
+    pub const NEIGHBORS: [IVec3; 6] = [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ];
+
     pub const fn new(path: IVec3, depth: u32) -> Self {
         Self { path, depth }
     }
@@ -26,19 +36,49 @@ impl Branch {
         Self::from_point_normalized(point, depth)
     }
 
+    /// Returns the branch at `depth` containing `point`, at depth 0 this is [`Branch::root`].
+    ///
+    /// Cells include their minimum faces, like [`Branch::min_normalized`], so a
+    /// point on a face between two cells belongs to the one on its positive side.
     pub fn from_point_normalized(point: Vec3, depth: u32) -> Self {
+        if depth == 0 {
+            return Self::root();
+        }
+
         let half = 1 << (depth - 1);
-        let path = IVec3::new(
-            (point.x * half as f32 - 0.5).round() as i32,
-            (point.y * half as f32 - 0.5).round() as i32,
-            (point.z * half as f32 - 0.5).round() as i32,
-        );
+        let path = (point * half as f32).floor().as_ivec3();
 
         Self { path, depth }
     }
 
+    /// Returns true if the branch lies within the bounds of the octree.
+    pub fn is_in_bounds(&self) -> bool {
+        if self.depth == 0 {
+            return self.path == IVec3::ZERO;
+        }
+
+        let half = 1 << (self.depth - 1);
+        let min = self.path.cmpge(IVec3::splat(-half));
+        let max = self.path.cmplt(IVec3::splat(half));
+        (min & max).all()
+    }
+
+    /// Returns the side length of the branch in normalized octree space.
+    pub fn size_normalized(&self) -> f32 {
+        2.0 / (1u32 << self.depth) as f32
+    }
+
+    /// Returns the minimum corner of the branch in normalized octree space.
+    pub fn min_normalized(&self) -> Vec3 {
+        if self.depth == 0 {
+            return Vec3::NEG_ONE;
+        }
+
+        self.path.as_vec3() * self.size_normalized()
+    }
+
     const fn is_positive(&self, depth: u32, axis: i32) -> bool {
-        let half = (1 << (self.depth - 1)) as i32;
+        let half = 1 << (self.depth - 1);
         let mask = 1 << (self.depth - depth - 1);
         let absolute = axis + half;
         absolute & mask != 0
@@ -100,6 +140,63 @@ impl Branch {
 
         branch
     }
+
+    /// Returns the branch `local` names when this branch is treated as the root.
+    pub fn descendant(&self, local: Branch) -> Self {
+        if self.depth == 0 {
+            return local;
+        }
+
+        if local.depth == 0 {
+            return *self;
+        }
+
+        let half = 1 << (local.depth - 1);
+        Self {
+            path: self.path * half * 2 + half + local.path,
+            depth: self.depth + local.depth,
+        }
+    }
+
+    /// Returns the branch at `depth` enclosing this one, at depth 0 this is [`Branch::root`].
+    ///
+    /// # Panics
+    ///
+    /// If `depth` is deeper than the branch.
+    pub fn ancestor_at(&self, depth: u32) -> Self {
+        assert!(
+            depth <= self.depth,
+            "ancestor at depth {} of a branch at depth {}",
+            depth,
+            self.depth,
+        );
+
+        if depth == 0 {
+            return Self::root();
+        }
+
+        // paths are signed, so shifting rounds towards the negative half of each axis
+        Self {
+            path: self.path >> (self.depth - depth) as i32,
+            depth,
+        }
+    }
+
+    /// Returns true if `other` lies within this branch, including this branch itself.
+    pub fn contains(&self, other: &Branch) -> bool {
+        other.depth >= self.depth && other.ancestor_at(self.depth) == *self
+    }
+
+    /// Returns the transform from the normalized space of the branch, as if it
+    /// were the root, to normalized octree space.
+    pub fn transform_normalized(&self) -> Mat4 {
+        let half = self.size_normalized() / 2.0;
+        Mat4::from_scale_rotation_translation(
+            Vec3::splat(half),
+            Quat::IDENTITY,
+            self.min_normalized() + half,
+        )
+    }
 }
 
 impl From<(IVec3, u32)> for Branch {
@@ -132,4 +229,99 @@ mod tests {
         assert_eq!(Branch::new(IVec3::new(-1, 0, 0), 1).child(0), 6);
         assert_eq!(Branch::new(IVec3::new(0, 0, 0), 1).child(0), 7);
     }
+
+    #[test]
+    fn from_point_on_faces() {
+        // rounding half away from zero used to put faces at or below zero one cell off
+        for depth in 1..=6 {
+            let half = 1 << (depth - 1);
+            let size = Branch::new(IVec3::ZERO, depth).size_normalized();
+
+            for path in [-half, -2, -1, 0, 1, half - 1] {
+                let point = Vec3::splat(path as f32 * size);
+                let branch = Branch::from_point_normalized(point, depth);
+
+                assert_eq!(branch, Branch::new(IVec3::splat(path), depth));
+                assert_eq!(branch.min_normalized(), point);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        assert!(Branch::root().is_in_bounds());
+        assert!(!Branch::new(IVec3::X, 0).is_in_bounds());
+        assert_eq!(Branch::root().min_normalized(), Vec3::NEG_ONE);
+        assert_eq!(Branch::root().size_normalized(), 2.0);
+
+        // paths at depth 3 span -4..4 on every axis
+        assert!(Branch::new(IVec3::new(-4, 3, 0), 3).is_in_bounds());
+        assert!(!Branch::new(IVec3::new(-5, 0, 0), 3).is_in_bounds());
+        assert!(!Branch::new(IVec3::new(0, 0, 4), 3).is_in_bounds());
+
+        let corner = Branch::new(IVec3::splat(3), 3);
+        assert_eq!(corner.size_normalized(), 0.25);
+        assert_eq!(
+            corner.min_normalized() + corner.size_normalized(),
+            Vec3::ONE
+        );
+    }
+
+    #[test]
+    fn descendant() {
+        let branch = Branch::new(IVec3::new(0, -1, 0), 1);
+
+        assert_eq!(branch.descendant(Branch::root()), branch);
+        assert_eq!(Branch::root().descendant(branch), branch);
+
+        let local = Branch::new(IVec3::new(-1, 0, 1), 2);
+        let global = branch.descendant(local);
+        assert_eq!(global, Branch::new(IVec3::new(1, -2, 3), 3));
+
+        // the transform maps the local cell onto the global one
+        let transform = branch.transform_normalized();
+        let min = transform.transform_point3(local.min_normalized());
+        assert!(min.abs_diff_eq(global.min_normalized(), 1e-6));
+    }
+
+    #[test]
+    fn ancestors() {
+        let branch = Branch::new(IVec3::new(1, -2, 3), 3);
+
+        assert_eq!(branch.ancestor_at(3), branch);
+        assert_eq!(branch.ancestor_at(1), Branch::new(IVec3::new(0, -1, 0), 1));
+        assert_eq!(branch.ancestor_at(0), Branch::root());
+        assert_eq!(Branch::root().ancestor_at(0), Branch::root());
+
+        // every cell has the root as an ancestor, even at depth 1 where paths are negative
+        let root = Branch::root();
+        assert!(root.contains(&root));
+        assert!(root.contains(&Branch::new(IVec3::splat(-1), 1)));
+        assert!(root.contains(&branch));
+        assert!(!branch.contains(&root));
+
+        // a deep descendant, walked down one child at a time
+        let mut deep = Branch::new(IVec3::new(-1, 0, -1), 1);
+        for depth in 1..20 {
+            deep = deep.with_child(depth % 8);
+        }
+
+        let center = deep.min_normalized() + deep.size_normalized() / 2.0;
+        for depth in 0..=deep.depth {
+            let ancestor = deep.ancestor_at(depth);
+            assert_eq!(ancestor.depth, depth);
+            assert!(ancestor.contains(&deep));
+            assert_eq!(Branch::from_point_normalized(center, depth), ancestor);
+        }
+
+        assert!(Branch::new(IVec3::new(-1, 0, -1), 1).contains(&deep));
+        assert!(!Branch::new(IVec3::new(0, 0, -1), 1).contains(&deep));
+
+        // neighbors share no cells
+        let neighbor = Branch::new(deep.path + IVec3::X, deep.depth);
+        assert!(!deep.contains(&neighbor));
+        assert!(deep
+            .ancestor_at(5)
+            .contains(&deep.descendant(Branch::new(IVec3::ZERO, 3))));
+    }
 }
@@ -0,0 +1,270 @@
+use std::hash::{Hash, Hasher};
+
+use super::{Branch, Node, Octree};
+
+/// Builds an [`Octree`] from many nodes, combining equal leaves once at the end.
+///
+/// [`Octree::set`] combines leaves after every call, which adds up when setting
+/// every cell of an imported model. The result of [`OctreeBuilder::finish`] is
+/// [structurally equal](Octree::structurally_eq) to setting the same nodes in order.
+#[derive(Clone, Debug)]
+pub struct OctreeBuilder {
+    octree: Octree,
+}
+
+impl Default for OctreeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OctreeBuilder {
+    pub fn new() -> Self {
+        let mut octree = Octree::new();
+        octree.merge_leaves = false;
+
+        Self { octree }
+    }
+
+    pub fn set(&mut self, branch: impl Into<Branch>, node: Node) -> &mut Self {
+        self.octree.set(branch, node);
+        self
+    }
+
+    pub fn extend(&mut self, nodes: impl IntoIterator<Item = (Branch, Node)>) -> &mut Self {
+        for (branch, node) in nodes {
+            self.octree.set(branch, node);
+        }
+
+        self
+    }
+
+    pub fn finish(self) -> Octree {
+        let mut octree = self.octree;
+        octree.combine_leaves();
+        octree.merge_leaves = true;
+
+        octree
+    }
+}
+
+impl Octree {
+    /// Replace every parent whose children are equal leaves with that leaf, bottom-up.
+    pub fn combine_leaves(&mut self) {
+        self.combine_node(self.root());
+    }
+
+    /// Combine the leaves below `index`, returning the node at `index` afterwards.
+    fn combine_node(&mut self, index: u32) -> Node {
+        let node = self[index];
+
+        if !node.is_parent() {
+            return node;
+        }
+
+        let pointer = node.pointer();
+
+        let mut combine = true;
+        let first = self.combine_node(pointer);
+
+        for child in 1..8 {
+            combine &= self.combine_node(pointer + child).combinable_with(&first);
+        }
+
+        if !combine || first.is_parent() {
+            return node;
+        }
+
+        self[index] = first;
+        self.remove_branch(pointer);
+
+        first
+    }
+
+    /// Returns true if both trees are subdivided the same way and have equal leaves,
+    /// regardless of where their nodes are stored.
+    pub fn structurally_eq(&self, other: &Octree) -> bool {
+        self.node_eq(self.root(), other, other.root())
+    }
+
+    fn node_eq(&self, index: u32, other: &Octree, other_index: u32) -> bool {
+        let (a, b) = (self[index], other[other_index]);
+
+        match (a.is_parent(), b.is_parent()) {
+            (true, true) => {
+                (0..8).all(|child| self.node_eq(a.pointer() + child, other, b.pointer() + child))
+            }
+            (false, false) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Returns a hash of the tree's shape and leaves, where [structurally
+    /// equal](Octree::structurally_eq) trees always hash equal.
+    ///
+    /// Useful as a cache key for generated trees, the hash is 64 bit FNV-1a, so
+    /// it's the same between runs, platforms and Rust versions.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        self.hash_node(self.root(), &mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_node(&self, index: u32, hasher: &mut impl Hasher) {
+        let node = self[index];
+
+        // parents are hashed by their children, so pointers don't matter
+        if node.is_parent() {
+            Node::PARENT_BIT.hash(hasher);
+
+            for child in 0..8 {
+                self.hash_node(node.pointer() + child, hasher);
+            }
+        } else {
+            node.flags.hash(hasher);
+            node.data.hash(hasher);
+        }
+    }
+}
+
+/// The 64 bit FNV-1a hash, which unlike [`DefaultHasher`](std::collections::hash_map::DefaultHasher)
+/// is fixed, integers are hashed as little endian bytes.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    const fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::*;
+
+    #[test]
+    fn builder_matches_set() {
+        let colors = [
+            Node::solid(255, 0, 0),
+            Node::solid(0, 255, 0),
+            Node::translucent(0, 0, 255),
+            Node::empty(),
+        ];
+
+        // a xorshift, so the cells are the same every run
+        let mut state = 0x2545_f491u32;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        let mut cells = Vec::new();
+        for _ in 0..2000 {
+            let depth = 1 + random() % 4;
+            let half = 1u32 << (depth - 1);
+            let mut axis = || (random() % (2 * half)) as i32 - half as i32;
+            let path = IVec3::new(axis(), axis(), axis());
+
+            let node = colors[random() as usize % colors.len()];
+            cells.push((Branch::new(path, depth), node));
+        }
+
+        // a filled block, which the builder has to combine
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    cells.push((Branch::new(IVec3::new(x, y, z), 4), colors[0]));
+                }
+            }
+        }
+
+        let mut sequential = Octree::new();
+        for &(branch, node) in &cells {
+            sequential.set(branch, node);
+        }
+
+        let mut builder = OctreeBuilder::new();
+        builder.extend(cells);
+        let built = builder.finish();
+
+        assert!(built.structurally_eq(&sequential));
+        assert!(built.merge_leaves);
+        assert_eq!(built.get((0, 0, 0, 2)), colors[0]);
+
+        let mut different = sequential.clone();
+        different.set((3, 3, 3, 4), colors[1]);
+        assert!(!built.structurally_eq(&different));
+    }
+
+    #[test]
+    fn content_hash() {
+        let mut sequential = Octree::new();
+        let mut builder = OctreeBuilder::new();
+
+        for (i, x) in (-4..4).enumerate() {
+            let node = Node::solid(i as u8 * 30, 0, 255);
+            sequential.set((x, 1, -2, 3), node);
+            builder.set((x, 1, -2, 3), node);
+        }
+
+        // removing and setting again leaves a free branch, moving the pointers
+        sequential.set((3, 3, 3, 4), Node::solid(1, 1, 1));
+        sequential.remove((3, 3, 3, 4));
+        sequential.set((-1, -1, -1, 2), Node::solid(9, 9, 9));
+        builder.set((-1, -1, -1, 2), Node::solid(9, 9, 9));
+
+        let built = builder.finish();
+        assert!(built.structurally_eq(&sequential));
+        assert_eq!(built.content_hash(), sequential.content_hash());
+
+        let mut modified = built.clone();
+        modified.set((0, 1, -2, 3), Node::solid(0, 0, 254));
+        assert_ne!(modified.content_hash(), built.content_hash());
+
+        assert_ne!(
+            Octree::new().content_hash(),
+            Octree::solid(Node::solid(0, 0, 0)).content_hash()
+        );
+
+        // pinned, so a change to the hash, which invalidates stored keys, is noticed
+        assert_eq!(Octree::new().content_hash(), 0xa8c7_f832_281a_39c5);
+        assert_eq!(sequential.content_hash(), 0xf9c7_9f35_452a_17f7);
+    }
+
+    #[test]
+    fn fnv1a() {
+        // test vectors of the reference implementation
+        let hash = |bytes: &[u8]| {
+            let mut hasher = Fnv1a::new();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -1,11 +1,14 @@
 use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
     mem,
-    ops::{Index, IndexMut, Range},
+    ops::{Bound, Index, IndexMut, Range},
 };
 
 use deref_derive::{Deref, DerefMut};
 
-use super::{Node, Octree};
+use super::{Branch, Node, Octree, TrackChanges};
+use crate::error::OakumError;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Segment {
@@ -51,6 +54,76 @@ impl Segment {
     }
 }
 
+/// The generation each changed branch was last set at, indexed both ways so
+/// the changes since a generation are found without looking at older ones.
+#[derive(Clone, Debug, Default)]
+struct Changes {
+    generations: HashMap<Branch, u64>,
+    branches: BTreeMap<u64, Branch>,
+}
+
+impl Changes {
+    /// The most branches kept, past this they are folded into their parents
+    /// until at most half as many are left.
+    const LIMIT: usize = 1 << 12;
+
+    fn insert(&mut self, branch: Branch, generation: u64) {
+        // everything was replaced, which covers any earlier change
+        if branch == Branch::root() {
+            self.generations.clear();
+            self.branches.clear();
+        }
+
+        if let Some(old) = self.generations.insert(branch, generation) {
+            self.branches.remove(&old);
+        }
+
+        self.branches.insert(generation, branch);
+
+        if self.generations.len() > Self::LIMIT {
+            self.coalesce();
+        }
+    }
+
+    /// Fold the deepest branches into their parents, keeping the latest
+    /// generation of each, until at most half of [`Changes::LIMIT`] are left.
+    fn coalesce(&mut self) {
+        while self.generations.len() > Self::LIMIT / 2 {
+            let depth = self.generations.keys().map(|branch| branch.depth).max();
+            let Some(depth @ 1..) = depth else {
+                break;
+            };
+
+            let mut folded = HashMap::with_capacity(self.generations.len());
+            for (branch, generation) in self.generations.drain() {
+                let branch = match branch.depth == depth {
+                    true => branch.ancestor_at(depth - 1),
+                    false => branch,
+                };
+
+                let latest = folded.entry(branch).or_insert(generation);
+                *latest = u64::max(*latest, generation);
+            }
+
+            self.generations = folded;
+        }
+
+        self.branches = (self.generations.iter())
+            .map(|(&branch, &generation)| (generation, branch))
+            .collect();
+    }
+
+    /// Returns the branches changed after `generation`, oldest first.
+    fn since(&self, generation: u64) -> impl Iterator<Item = Branch> + '_ {
+        let range = (Bound::Excluded(generation), Bound::Unbounded);
+        self.branches.range(range).map(|(_, &branch)| branch)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.generations.shrink_to_fit();
+    }
+}
+
 #[derive(Clone, Debug, Default, Deref, DerefMut)]
 pub struct DynamicOctree {
     #[deref]
@@ -60,6 +133,15 @@ pub struct DynamicOctree {
     /// The are sorted by their start position,
     /// and overlapping segments are joined.
     segments: Vec<Segment>,
+    /// The first node of the most recently changed segment.
+    last_edit: Option<u32>,
+    /// The generation each changed branch was last set at.
+    ///
+    /// Unlike the segments these are never taken, so any number of observers
+    /// can track changes, see [`DynamicOctree::for_each_changed_since`].
+    changes: Changes,
+    /// Whether [`DynamicOctree::shrink_to_fit`] was called since the last write.
+    shrunk: bool,
 }
 
 impl DynamicOctree {
@@ -67,24 +149,84 @@ impl DynamicOctree {
         Self {
             octree: Octree::new(),
             segments: Vec::new(),
+            last_edit: None,
+            changes: Changes::default(),
+            shrunk: false,
         }
     }
 
     pub fn new(octree: Octree) -> Self {
-        let segment = Segment::new(0, octree.len() as u32);
+        let segment = Segment::new(0, octree.len());
+        let mut changes = Changes::default();
+        changes.insert(Branch::root(), octree.generation);
 
         Self {
             octree,
             segments: vec![segment],
+            last_edit: Some(0),
+            changes,
+            shrunk: false,
         }
     }
 
+    /// Swap in `octree`, marking all of it as changed.
+    ///
+    /// Unlike assigning a new [`DynamicOctree`], this keeps any gpu resources
+    /// tracking this octree, so loading a new world doesn't recreate them.
+    pub fn replace(&mut self, octree: Octree) {
+        // keep counting from the old generation, so earlier queries stay valid
+        let generation = self.octree.generation.max(octree.generation);
+        self.octree = octree;
+        self.octree.generation = generation;
+        self.mark_changed(Branch::root());
+
+        self.segments.clear();
+        self.push_segment(Segment::new(0, self.octree.len()));
+    }
+
     pub fn clear(&mut self) {
         self.octree.clear();
+        self.mark_changed(Branch::root());
+
         self.segments.clear();
         self.push_segment(Segment::new(0, 1));
     }
 
+    /// Compact the octree, see [`Octree::compact`], and release the memory
+    /// freed by removals.
+    ///
+    /// The moved nodes are marked as one changed segment, and the renderer
+    /// reallocates a smaller texture on the next write, see [`DynamicOctree::shrunk`].
+    /// The cells don't change, so [`DynamicOctree::for_each_changed_since`] reports nothing.
+    pub fn shrink_to_fit(&mut self) {
+        self.octree.compact();
+        self.changes.shrink_to_fit();
+        self.shrunk = true;
+
+        self.segments.clear();
+        self.push_segment(Segment::new(0, self.octree.len()));
+        self.segments.shrink_to_fit();
+    }
+
+    /// Whether the octree was shrunk since the segments were last taken,
+    /// gpu copies should then be reallocated to fit.
+    pub fn shrunk(&self) -> bool {
+        self.shrunk
+    }
+
+    /// Call `f` with every branch set after `generation`, oldest first.
+    ///
+    /// Only the latest change to each branch is kept, so a branch set several
+    /// times is reported once. Changes to the whole tree, like [`DynamicOctree::replace`],
+    /// are reported as [`Branch::root`], and changes made with
+    /// [`DynamicOctree::apply_changes`] as well, since their branches aren't known.
+    ///
+    /// At most a few thousand branches are kept, past that changes are reported
+    /// as the ancestors containing them.
+    pub fn for_each_changed_since(&self, generation: u64, f: impl FnMut(Branch)) {
+        self.changes.since(generation).for_each(f);
+    }
+
     pub fn push_branch(&mut self) -> u32 {
         let index = self.octree.push_branch();
         self.push_segment(Segment::new(index, 8));
@@ -104,12 +246,21 @@ impl DynamicOctree {
     }
 }
 
+impl TrackChanges for DynamicOctree {
+    fn mark_changed(&mut self, branch: Branch) {
+        self.octree.generation += 1;
+        self.changes.insert(branch, self.octree.generation);
+    }
+}
+
 impl DynamicOctree {
     fn segment_before(&self, segment: Segment) -> Result<usize, usize> {
         (self.segments).binary_search_by_key(&segment.start, |s| s.start)
     }
 
     pub fn push_segment(&mut self, segment: Segment) {
+        self.last_edit = Some(segment.start);
+
         // find the segment that is before the new one
         let after = match self.segment_before(segment) {
             Ok(index) => {
@@ -148,11 +299,127 @@ impl DynamicOctree {
         &self.segments
     }
 
+    /// Returns the bytes of the nodes in `segment`, like [`Octree::bytes`].
+    ///
+    /// Segments may reach past the end of the octree after it shrank, the
+    /// slice is cut off there and is empty if the segment starts past it.
+    pub fn segment_bytes(&self, segment: Segment) -> &[u8] {
+        let bytes = self.octree.bytes();
+        let end = segment.byte_end().min(bytes.len());
+        let start = segment.byte_start().min(end);
+
+        &bytes[start..end]
+    }
+
+    /// Returns the changed segments with the one containing the most recent
+    /// change first, followed by the rest in order.
+    pub fn prioritized_segments(&self) -> Vec<Segment> {
+        let mut segments = self.segments.clone();
+
+        let last = (self.last_edit)
+            .and_then(|index| (segments.iter()).position(|s| s.start <= index && index < s.end()));
+
+        if let Some(last) = last {
+            let segment = segments.remove(last);
+            segments.insert(0, segment);
+        }
+
+        segments
+    }
+
+    /// Take the changed segments together with their current nodes, to be sent
+    /// to a copy of this octree and applied with [`DynamicOctree::apply_changes`].
+    ///
+    /// Like [`DynamicOctree::take_segments`] this clears the changes, so call it
+    /// after the renderer has uploaded them.
+    pub fn drain_changes(&mut self) -> Vec<(Segment, Vec<Node>)> {
+        let len = self.octree.len();
+
+        (self.take_segments().into_iter())
+            .filter(|segment| segment.start < len)
+            .map(|segment| {
+                let segment = Segment::new(segment.start, segment.end().min(len) - segment.start);
+                (segment, self.octree[segment.start..segment.end()].to_vec())
+            })
+            .collect()
+    }
+
+    /// Write changes drained from another copy of this octree, marking them as
+    /// changed here too.
+    ///
+    /// Free branches overwritten by the changes are no longer free, but branches
+    /// freed by the other copy aren't known, so this copy should only receive edits.
+    ///
+    /// The changes may come from anywhere, so they are checked before any is
+    /// written. Returns an error if a segment doesn't hold as many nodes as it
+    /// is long, starts past the end of the octree, or if a parent points at a
+    /// branch reaching past the end.
+    pub fn apply_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = (Segment, Vec<Node>)>,
+    ) -> Result<(), OakumError> {
+        let changes: Vec<_> = changes.into_iter().collect();
+
+        let mut len = self.octree.len();
+        for (segment, nodes) in &changes {
+            let invalid = |reason| OakumError::InvalidChange {
+                start: segment.start,
+                reason,
+            };
+
+            if segment.len as usize != nodes.len() {
+                return Err(invalid("the segment length doesn't match the nodes"));
+            }
+
+            if segment.start > len {
+                return Err(invalid("the segment starts past the end of the octree"));
+            }
+
+            let end = (segment.start.checked_add(segment.len))
+                .ok_or(invalid("the segment ends past the largest index"))?;
+            len = len.max(end);
+        }
+
+        for (segment, nodes) in &changes {
+            let dangling = (nodes.iter()).any(|node| {
+                node.is_parent() && node.pointer().checked_add(8).is_none_or(|end| end > len)
+            });
+
+            if dangling {
+                return Err(OakumError::InvalidChange {
+                    start: segment.start,
+                    reason: "a parent points past the end of the octree",
+                });
+            }
+        }
+
+        for (segment, nodes) in changes {
+            self.mark_changed(Branch::root());
+
+            let end = segment.end() as usize;
+            if self.octree.nodes.len() < end {
+                self.octree.nodes.resize(end, Node::empty());
+            }
+
+            self.octree.nodes[segment.start as usize..end].copy_from_slice(&nodes);
+            (self.octree.free_branches)
+                .retain(|&Reverse(branch)| branch + 8 <= segment.start || segment.end() <= branch);
+
+            self.push_segment(segment);
+        }
+
+        Ok(())
+    }
+
     pub fn take_segments(&mut self) -> Vec<Segment> {
+        self.last_edit = None;
+        self.shrunk = false;
         mem::take(&mut self.segments)
     }
 
     pub fn clear_segments(&mut self) {
+        self.last_edit = None;
+        self.shrunk = false;
         self.segments.clear();
     }
 }
@@ -189,7 +456,10 @@ impl IndexMut<Range<u32>> for DynamicOctree {
 
 #[cfg(test)]
 mod tests {
+    use glam::IVec3;
+
     use super::*;
+    use crate::generate::Sphere;
 
     #[test]
     fn segment_join() {
@@ -216,4 +486,179 @@ mod tests {
             vec![Segment::new(0, 30), Segment::new(2048, 10)]
         );
     }
+
+    #[test]
+    fn segment_bytes() {
+        let mut octree = DynamicOctree::empty();
+        let index = octree.push_branch();
+        octree[index + 3] = Node::solid(255, 0, 0);
+
+        let segment = Segment::new(index, 8);
+        let nodes = &octree[segment.start..segment.end()];
+        assert_eq!(octree.segment_bytes(segment), bytemuck::cast_slice(nodes));
+        assert_eq!(octree.segment_bytes(segment).len(), segment.byte_len());
+
+        // cut off at the end of the octree
+        let past = Segment::new(index + 4, 16);
+        let nodes = &octree[index + 4..octree.len()];
+        assert_eq!(octree.segment_bytes(past), bytemuck::cast_slice(nodes));
+        assert!(octree.segment_bytes(Segment::new(1024, 8)).is_empty());
+    }
+
+    #[test]
+    fn replace() {
+        let mut octree = DynamicOctree::empty();
+        octree.set((3, 3, 3, 3), Node::solid(255, 255, 255));
+        octree.push_segment(Segment::new(4096, 8));
+
+        let mut other = Octree::new();
+        other.set((-1, 0, 1, 2), Node::solid(1, 2, 3));
+        let len = other.len();
+
+        octree.replace(other);
+
+        assert_eq!(octree.segments(), &[Segment::new(0, len)]);
+        assert_eq!(octree.get((-1, 0, 1, 2)), Node::solid(1, 2, 3));
+    }
+
+    #[test]
+    fn prioritized_segments() {
+        let mut octree = DynamicOctree::empty();
+
+        octree.push_segment(Segment::new(0, 8));
+        octree.push_segment(Segment::new(8192, 8));
+        octree.push_segment(Segment::new(4096, 8));
+
+        assert_eq!(
+            octree.prioritized_segments(),
+            vec![
+                Segment::new(4096, 8),
+                Segment::new(0, 8),
+                Segment::new(8192, 8)
+            ]
+        );
+
+        // an edit joined into an existing segment moves that segment first
+        octree.push_segment(Segment::new(8200, 1));
+        assert_eq!(octree.prioritized_segments()[0], Segment::new(8192, 9));
+
+        octree.take_segments();
+        assert!(octree.prioritized_segments().is_empty());
+    }
+
+    #[test]
+    fn apply_changes() {
+        let mut sender = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        sender.take_segments();
+        let mut receiver = sender.clone();
+
+        sender.set((7, 7, 7, 4), Node::solid(255, 0, 0));
+        sender.remove((0, 0, 0, 2));
+        sender.set((-3, 2, 1, 6), Node::emissive(0, 255, 0));
+
+        let changes = sender.drain_changes();
+        assert!(sender.segments().is_empty());
+        assert!(!changes.is_empty());
+
+        receiver.apply_changes(changes).unwrap();
+
+        assert!(receiver.structurally_eq(&sender));
+        assert_eq!(receiver.get((-3, 2, 1, 6)), Node::emissive(0, 255, 0));
+        assert!(!receiver.segments().is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_changes() {
+        let octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        let len = octree.len();
+
+        let invalid = [
+            // fewer nodes than the segment is long
+            vec![(Segment::new(0, 2), vec![Node::empty()])],
+            // leaving a gap after the end
+            vec![(Segment::new(len + 1, 1), vec![Node::empty()])],
+            // past the largest index
+            vec![(Segment::new(u32::MAX, 1), vec![Node::empty()])],
+            // a parent pointing past the end, even if an earlier change is valid
+            vec![
+                (Segment::new(1, 1), vec![Node::solid(1, 2, 3)]),
+                (Segment::new(0, 1), vec![Node::parent(len - 7)]),
+            ],
+            vec![(Segment::new(0, 1), vec![Node::parent(u32::MAX - 3)])],
+        ];
+
+        for changes in invalid {
+            let mut receiver = octree.clone();
+            receiver.take_segments();
+
+            let result = receiver.apply_changes(changes);
+            assert!(matches!(result, Err(OakumError::InvalidChange { .. })));
+
+            // nothing was written
+            assert!(receiver.structurally_eq(&octree));
+            assert!(receiver.segments().is_empty());
+        }
+
+        // a new branch at the end may be pointed to by a change before it
+        let mut receiver = octree.clone();
+        let changes = vec![
+            (Segment::new(0, 1), vec![Node::parent(len)]),
+            (Segment::new(len, 8), vec![Node::solid(1, 2, 3); 8]),
+        ];
+        receiver.apply_changes(changes).unwrap();
+        assert_eq!(receiver.len(), len + 8);
+    }
+
+    #[test]
+    fn changed_since() {
+        let mut octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        let before = octree.generation;
+
+        octree.set((1, 2, 3, 5), Node::solid(255, 0, 0));
+        let between = octree.generation;
+        octree.remove((-4, 0, 0, 4));
+
+        let changed_since = |octree: &DynamicOctree, generation| {
+            let mut changed = Vec::new();
+            octree.for_each_changed_since(generation, |branch| changed.push(branch));
+            changed
+        };
+
+        let first = Branch::from((1, 2, 3, 5));
+        let second = Branch::from((-4, 0, 0, 4));
+
+        assert_eq!(changed_since(&octree, before), [first, second]);
+        assert_eq!(changed_since(&octree, between), [second]);
+        assert!(changed_since(&octree, octree.generation).is_empty());
+
+        // replacing the octree covers every earlier change
+        octree.replace(Octree::new());
+        assert_eq!(changed_since(&octree, before), [Branch::root()]);
+    }
+
+    #[test]
+    fn changes_coalesce() {
+        let mut octree = DynamicOctree::empty();
+        let before = octree.generation;
+
+        // more cells than are kept, spread over a plane
+        let cells: Vec<_> = (0..Changes::LIMIT as i32 + 1)
+            .map(|i| Branch::new(IVec3::new(i % 128 - 64, 0, i / 128 - 64), 7))
+            .collect();
+
+        for &cell in &cells {
+            octree.set(cell, Node::solid(255, 255, 255));
+        }
+
+        let mut changed = Vec::new();
+        octree.for_each_changed_since(before, |branch| changed.push(branch));
+
+        // the cells are folded into their parents, which still contain every change
+        assert!(changed.len() <= Changes::LIMIT / 2, "{}", changed.len());
+        assert!(changed.iter().all(|branch| branch.depth == 6));
+        assert!((cells.iter()).all(|cell| changed.iter().any(|branch| branch.contains(cell))));
+
+        // and the latest change comes last
+        assert!(changed.last().unwrap().contains(cells.last().unwrap()));
+    }
 }
@@ -0,0 +1,195 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::format::FormatError;
+
+use super::{Node, Octree};
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, FormatError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+impl Octree {
+    pub const MAGIC: [u8; 4] = *b"OAKM";
+    pub const VERSION: u32 = 1;
+
+    /// Write the octree in the `.oak` format.
+    ///
+    /// The format is a small header followed by the raw nodes and free branches,
+    /// all stored as little endian `u32`s.
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), FormatError> {
+        writer.write_all(&Self::MAGIC)?;
+        writer.write_all(&Self::VERSION.to_le_bytes())?;
+        writer.write_all(&self.len().to_le_bytes())?;
+        writer.write_all(&(self.free_branches.len() as u32).to_le_bytes())?;
+
+        for node in self.nodes.iter() {
+            writer.write_all(&node.flags.to_le_bytes())?;
+            writer.write_all(&node.data.to_le_bytes())?;
+        }
+
+        for &Reverse(branch) in self.free_branches.iter() {
+            writer.write_all(&branch.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Read an octree in the `.oak` format, see [`Octree::write`].
+    pub fn read(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if magic != Self::MAGIC {
+            return Err(FormatError::InvalidHeader);
+        }
+
+        let version = read_u32(reader)?;
+        if version != Self::VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        let node_count = read_u32(reader)?;
+        let free_count = read_u32(reader)?;
+
+        // the counts aren't trusted, a short file ends before much is allocated
+        let mut nodes = Vec::new();
+        for _ in 0..node_count {
+            let flags = read_u32(reader)?;
+            let data = read_u32(reader)?;
+            nodes.push(Node { flags, data });
+        }
+
+        let mut free_branches = BinaryHeap::new();
+        for _ in 0..free_count {
+            free_branches.push(Reverse(read_u32(reader)?));
+        }
+
+        let octree = Self {
+            nodes,
+            free_branches,
+            merge_leaves: true,
+            generation: 0,
+        };
+
+        octree.validate()?;
+
+        Ok(octree)
+    }
+
+    /// Check that every pointer in the octree refers to a valid branch, and that
+    /// every node reachable from the root is reached once, so walking the tree ends.
+    pub fn validate(&self) -> Result<(), FormatError> {
+        if self.nodes.is_empty() {
+            return Err(FormatError::InvalidOctree("octree has no root node"));
+        }
+
+        for node in self.nodes.iter() {
+            if node.is_parent() && node.pointer() as u64 + 8 > self.len() as u64 {
+                return Err(FormatError::InvalidOctree("parent pointer out of bounds"));
+            }
+        }
+
+        for &Reverse(branch) in self.free_branches.iter() {
+            if branch as u64 + 8 > self.len() as u64 {
+                return Err(FormatError::InvalidOctree("free branch out of bounds"));
+            }
+        }
+
+        // branches are reused wherever they were freed, so pointers can point
+        // backwards, but never at a node reached before, like an ancestor
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root()];
+        visited[self.root() as usize] = true;
+
+        while let Some(index) = stack.pop() {
+            let node = self[index];
+
+            if !node.is_parent() {
+                continue;
+            }
+
+            for child in node.pointer()..node.pointer() + 8 {
+                if visited[child as usize] {
+                    return Err(FormatError::InvalidOctree("node reached more than once"));
+                }
+
+                visited[child as usize] = true;
+                stack.push(child);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FormatError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read(&mut reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::Sphere;
+
+    fn read(octree: &Octree) -> Result<Octree, FormatError> {
+        let mut bytes = Vec::new();
+        octree.write(&mut bytes).unwrap();
+        Octree::read(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let mut sphere = Octree::generate(&Sphere::new(8, 4));
+        sphere.set((1, 1, 1, 4), Node::solid(255, 0, 0));
+        assert!(read(&sphere).unwrap().structurally_eq(&sphere));
+
+        // a parent pointing at itself
+        let mut octree = Octree::new();
+        octree.set((0, 0, 0, 2), Node::solid(255, 0, 0));
+        let mut looped = octree.clone();
+        let pointer = looped[looped.root()].pointer();
+        looped[pointer] = Node::parent(pointer);
+        assert!(matches!(read(&looped), Err(FormatError::InvalidOctree(_))));
+
+        // a child pointing back at the branch holding its parent
+        let mut looped = octree.clone();
+        let child = looped[pointer + 7].pointer();
+        looped[child] = Node::parent(pointer);
+        assert!(matches!(read(&looped), Err(FormatError::InvalidOctree(_))));
+
+        // and at the root
+        let mut looped = octree;
+        looped[pointer] = Node::parent(0);
+        assert!(matches!(read(&looped), Err(FormatError::InvalidOctree(_))));
+    }
+
+    #[test]
+    fn huge_counts() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&Octree::MAGIC);
+        bytes.extend_from_slice(&Octree::VERSION.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        let result = Octree::read(&mut bytes.as_slice());
+        assert!(matches!(result, Err(FormatError::IoError(_))));
+    }
+}
@@ -1,27 +1,64 @@
 use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
     mem,
     ops::{Index, IndexMut, Range},
 };
 
 mod branch;
+mod builder;
 mod dynamic;
+mod file;
 mod node;
+mod occupancy;
+mod orientation;
+mod palette;
 mod raycast;
+mod resample;
+mod snapshot;
 
 pub use branch::*;
+pub use builder::*;
 pub use dynamic::*;
 pub use node::*;
+pub use occupancy::*;
+pub use orientation::*;
+pub use palette::*;
+#[allow(unused_imports)]
 pub use raycast::*;
+pub use snapshot::*;
 
-use glam::{IVec3, Vec3};
+use glam::{BVec3, IVec3, Vec3};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::generate::Generate;
+use crate::{
+    error::OakumError,
+    generate::{estimate_normal, Generate},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Octree {
     pub nodes: Vec<Node>,
-    pub free_branches: Vec<u32>,
+    /// Branches freed by [`Octree::remove_branch`], the lowest is reused first,
+    /// which keeps live nodes towards the front and edits close together.
+    pub free_branches: BinaryHeap<Reverse<u32>>,
+    /// Whether `set` combines eight identical children into their parent.
+    ///
+    /// Merging keeps uniform regions down to a single node, disabling it keeps
+    /// every cell that was set as its own leaf, at the cost of up to eight
+    /// times the nodes for uniform regions. Parents left with only empty children
+    /// are collapsed either way. Defaults to true.
+    #[serde(skip, default = "merge_leaves_default")]
+    pub merge_leaves: bool,
+    /// Increased by every [`Octree::set`], so changes can be ordered,
+    /// see [`DynamicOctree::for_each_changed_since`].
+    #[serde(skip)]
+    pub generation: u64,
+}
+
+const fn merge_leaves_default() -> bool {
+    true
 }
 
 impl Default for Octree {
@@ -31,10 +68,30 @@ impl Default for Octree {
 }
 
 impl Octree {
+    /// The deepest cells can be, the stacks used to walk the tree hold one node per level.
+    ///
+    /// Paths at this depth span `-2^30..2^30`, so offsetting them by half the
+    /// width of the tree still fits an `i32`.
+    pub const MAX_DEPTH: u32 = 31;
+
     pub fn new() -> Self {
         Self {
             nodes: vec![Node::empty()],
-            free_branches: Vec::new(),
+            free_branches: BinaryHeap::new(),
+            merge_leaves: true,
+            generation: 0,
+        }
+    }
+
+    /// Create an octree completely filled with `node`.
+    ///
+    /// The root is a single solid leaf, which is the most compact representation.
+    pub fn solid(node: Node) -> Self {
+        Self {
+            nodes: vec![node],
+            free_branches: BinaryHeap::new(),
+            merge_leaves: true,
+            generation: 0,
         }
     }
 
@@ -50,7 +107,7 @@ impl Octree {
     }
 
     pub fn push_branch(&mut self) -> u32 {
-        if let Some(i) = self.free_branches.pop() {
+        if let Some(Reverse(i)) = self.free_branches.pop() {
             self[i..i + 8].fill(Node::empty());
 
             return i;
@@ -66,36 +123,369 @@ impl Octree {
         if index == self.len() - 8 {
             self.nodes.truncate(index as usize);
         } else {
-            self.free_branches.push(index);
+            self.free_branches.push(Reverse(index));
+        }
+    }
+
+    /// Move the nodes in use to the front, dropping free branches and shrinking
+    /// the allocation.
+    ///
+    /// This moves nodes, so node indices held from before are invalid after.
+    pub fn compact(&mut self) {
+        let mut nodes = Vec::with_capacity(self.nodes.len() - self.free_branches.len() * 8);
+        nodes.push(self[self.root()]);
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = nodes[index];
+            if !node.is_parent() {
+                continue;
+            }
+
+            let pointer = node.pointer() as usize;
+            let children = nodes.len();
+            nodes.extend_from_slice(&self.nodes[pointer..pointer + 8]);
+            nodes[index].set_pointer(children as u32);
+
+            stack.extend(children..children + 8);
         }
+
+        nodes.shrink_to_fit();
+        self.nodes = nodes;
+        self.free_branches = BinaryHeap::new();
+    }
+
+    /// Returns an error if cells at `depth` are deeper than [`Octree::MAX_DEPTH`].
+    pub fn check_depth(depth: u32) -> Result<(), OakumError> {
+        if depth > Self::MAX_DEPTH {
+            return Err(OakumError::DepthTooLarge {
+                depth,
+                max: Self::MAX_DEPTH,
+            });
+        }
+
+        Ok(())
     }
 
+    /// Returns an error if stamping `other` into `branch` at `depth`, like
+    /// [`Octree::union`] does, sets cells deeper than [`Octree::MAX_DEPTH`].
+    pub fn check_stamp(
+        branch: impl Into<Branch>,
+        depth: u32,
+        other: &Octree,
+    ) -> Result<(), OakumError> {
+        let branch = branch.into();
+        Self::check_depth(branch.depth)?;
+
+        for (other_branch, _) in other.iter_nodes() {
+            Self::check_depth(other_branch.depth.saturating_add(depth))?;
+        }
+
+        Ok(())
+    }
+
+    /// # Panics
+    ///
+    /// If the depth of `sdf` exceeds [`Octree::MAX_DEPTH`], see [`Octree::try_generate`].
     pub fn generate<T: Generate>(sdf: &T) -> Self {
+        Self::generate_with_progress(sdf, &mut |_| {})
+    }
+
+    /// Same as [`Octree::generate`], but returns an error for generators deeper
+    /// than [`Octree::MAX_DEPTH`] instead of panicking.
+    pub fn try_generate<T: Generate>(sdf: &T) -> Result<Self, OakumError> {
+        Self::check_depth(sdf.depth())?;
+        Ok(Self::generate(sdf))
+    }
+
+    /// Generate an octree, calling `progress` with the completed fraction
+    /// in `0.0..=1.0` before each x slice is sampled and once when done.
+    pub fn generate_with_progress<T: Generate>(sdf: &T, progress: &mut dyn FnMut(f32)) -> Self {
+        Self::generate_until(sdf, &mut |fraction| {
+            progress(fraction);
+            true
+        })
+    }
+
+    /// Same as [`Octree::generate_with_progress`], but stops before the next x slice
+    /// once `progress` returns false, keeping the slices generated so far.
+    ///
+    /// The partial octree is as valid as a complete one, see
+    /// [`GenerateHandle`](crate::generate::GenerateHandle).
+    pub fn generate_until<T: Generate + ?Sized>(
+        sdf: &T,
+        progress: &mut dyn FnMut(f32) -> bool,
+    ) -> Self {
+        if let Err(err) = Self::check_depth(sdf.depth()) {
+            panic!("{}", err);
+        }
+
         let mut octree = Self::new();
 
         let dimensions = sdf.dimensions().as_ivec3();
         let depth = sdf.depth();
 
+        let slices = dimensions.x * 2;
+
         for ix in -dimensions.x..dimensions.x {
+            if !progress((ix + dimensions.x) as f32 / slices as f32) {
+                return octree;
+            }
+
             for iy in -dimensions.y..dimensions.y {
                 for iz in -dimensions.z..dimensions.z {
-                    let x = ix as f32 + 0.5;
-                    let y = iy as f32 + 0.5;
-                    let z = iz as f32 + 0.5;
-
-                    let point = Vec3::new(x, y, z) / dimensions.as_vec3();
+                    let cell = IVec3::new(ix, iy, iz);
 
-                    if let Some(node) = sdf.get_node(point) {
-                        let branch = Branch::new(IVec3::new(ix, iy, iz), depth);
-                        octree.set(branch, node);
+                    if let Some(node) = sample_cell(sdf, cell) {
+                        octree.set(Branch::new(cell, depth), node);
                     }
                 }
             }
         }
 
+        progress(1.0);
+
         octree
     }
 
+    /// Generate an octree like [`Octree::generate`], skipping the cells of nodes
+    /// that look uniform.
+    ///
+    /// Starting at the root, the corners and center of each node are sampled,
+    /// if they all agree the node is filled, or left empty, without descending.
+    /// This makes large solid or empty regions cheap, but features that fit between
+    /// the samples of a node can be missed, so it suits smooth shapes.
+    pub fn generate_adaptive<T: Generate>(sdf: &T) -> Self {
+        if let Err(err) = Self::check_depth(sdf.depth()) {
+            panic!("{}", err);
+        }
+
+        let mut octree = Self::new();
+
+        if sdf.depth() == 0 {
+            if let Some(node) = sample_cell(sdf, IVec3::ZERO) {
+                octree.set(Branch::root(), node);
+            }
+
+            return octree;
+        }
+
+        octree.generate_branch(sdf, Branch::root());
+        octree
+    }
+
+    fn generate_branch<T: Generate>(&mut self, sdf: &T, branch: Branch) {
+        let dimensions = sdf.dimensions().as_ivec3();
+        let shift = sdf.depth() - branch.depth;
+
+        // the cells at the depth of `sdf` covered by `branch`
+        let (min, max) = match branch.depth {
+            0 => (
+                IVec3::splat(-1 << (shift - 1)),
+                IVec3::splat(1 << (shift - 1)),
+            ),
+            _ => (branch.path << shift, (branch.path + 1) << shift),
+        };
+
+        if min.cmpge(dimensions).any() || max.cmple(-dimensions).any() {
+            return;
+        }
+
+        if shift == 0 {
+            if let Some(node) = sample_cell(sdf, min) {
+                self.set(branch, node);
+            }
+
+            return;
+        }
+
+        // nodes partially outside the dimensions can't be filled as a whole
+        if min.cmpge(-dimensions).all() && max.cmple(dimensions).all() {
+            let last = max - 1;
+            let corner = |i: u32| {
+                let positive = BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0);
+                sample_cell(sdf, IVec3::select(positive, last, min))
+            };
+
+            let corners: [Option<Node>; 8] = std::array::from_fn(|i| corner(i as u32));
+            let mut uniform = corners.iter().all(|&node| node == corners[0]);
+
+            // one above the cells the corners are the children, and there is no center
+            if shift > 1 && uniform {
+                uniform = sample_cell(sdf, min + (1 << (shift - 1))) == corners[0];
+            }
+
+            if uniform {
+                if let Some(node) = corners[0] {
+                    self.set(branch, node);
+                }
+
+                return;
+            }
+
+            if shift == 1 {
+                for (child, node) in corners.into_iter().enumerate() {
+                    if let Some(node) = node {
+                        self.set(branch.with_child(child as u32), node);
+                    }
+                }
+
+                return;
+            }
+        }
+
+        for child in 0..8 {
+            self.generate_branch(sdf, branch.with_child(child));
+        }
+    }
+
+    /// Returns the node containing `branch`.
+    ///
+    /// If the tree is subdivided further than `branch.depth` the returned node is a parent.
+    pub fn get(&self, branch: impl Into<Branch>) -> Node {
+        match self.find(branch.into()) {
+            Some(index) => self[index],
+            None => Node::empty(),
+        }
+    }
+
+    /// Returns the index of the node containing `branch`.
+    fn find(&self, branch: Branch) -> Option<u32> {
+        if !branch.is_in_bounds() {
+            return None;
+        }
+
+        let mut index = self.root();
+
+        for depth in 0..branch.depth {
+            let node = self[index];
+
+            if !node.is_parent() {
+                break;
+            }
+
+            index = node.pointer() + branch.child(depth);
+        }
+
+        Some(index)
+    }
+
+    /// Count the solid cells at `depth`, expanding coalesced leaves.
+    ///
+    /// Leaves deeper than `depth` are not counted, and the count saturates at
+    /// `u64::MAX`, which a single solid leaf 22 levels above `depth` reaches.
+    pub fn count_solid(&self, depth: u32) -> u64 {
+        let mut count = 0u64;
+
+        for (branch, node) in self.iter_nodes() {
+            if node.is_solid() && branch.depth <= depth {
+                let shift = (depth - branch.depth).saturating_mul(3);
+                let cells = 1u64.checked_shl(shift).unwrap_or(u64::MAX);
+                count = count.saturating_add(cells);
+            }
+        }
+
+        count
+    }
+
+    /// Returns a sphere containing every solid leaf in normalized octree space,
+    /// as its center and radius, or `None` if the octree is empty.
+    ///
+    /// The sphere is centered on the bounding box of the leaves, so it fits
+    /// convex shapes well but isn't the smallest possible sphere.
+    pub fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+
+        for (branch, node) in self.iter_nodes() {
+            if node.is_solid() {
+                min = min.min(branch.min_normalized());
+                max = max.max(branch.min_normalized() + branch.size_normalized());
+            }
+        }
+
+        if min.cmpgt(max).any() {
+            return None;
+        }
+
+        let center = (min + max) / 2.0;
+        let mut radius = 0.0f32;
+
+        for (branch, node) in self.iter_nodes() {
+            if !node.is_solid() {
+                continue;
+            }
+
+            // the farthest corner of the leaf from the center
+            let min = branch.min_normalized() - center;
+            let max = min + branch.size_normalized();
+            let corner = min.abs().max(max.abs());
+
+            radius = radius.max(corner.length());
+        }
+
+        Some((center, radius))
+    }
+
+    /// Iterate every solid cell at `depth`, expanding coalesced leaves into their cells.
+    ///
+    /// A leaf `n` levels above `depth` expands into `8^n` cells, so iterating a large
+    /// solid region at a deep level is expensive, see [`Octree::count_solid`] for the
+    /// number of cells yielded. Like `count_solid`, leaves deeper than `depth` are skipped.
+    ///
+    /// # Panics
+    ///
+    /// If `depth` exceeds [`Octree::MAX_DEPTH`], whose cells are the deepest an
+    /// [`IVec3`] can address.
+    pub fn iter_solid_cells(&self, depth: u32) -> impl Iterator<Item = (IVec3, Node)> + '_ {
+        if let Err(err) = Self::check_depth(depth) {
+            panic!("{}", err);
+        }
+
+        (self.iter_nodes())
+            .filter(move |(branch, node)| node.is_solid() && branch.depth <= depth)
+            .flat_map(move |(branch, &node)| {
+                // at most 2^31 cells per axis, starting at most 2^30 from the center
+                let size = 1i64 << (depth - branch.depth);
+
+                let min = match branch.depth {
+                    0 => [-(size / 2); 3],
+                    _ => (branch.path.to_array()).map(|axis| axis as i64 * size),
+                };
+
+                (0..size).flat_map(move |z| {
+                    (0..size).flat_map(move |y| {
+                        (0..size).map(move |x| {
+                            let cell = IVec3::new(
+                                (min[0] + x) as i32,
+                                (min[1] + y) as i32,
+                                (min[2] + z) as i32,
+                            );
+
+                            (cell, node)
+                        })
+                    })
+                })
+            })
+    }
+
+    /// Keep only the cells that are solid in both `self` and `other`.
+    ///
+    /// Unlike [`Octree::union`] and [`Octree::difference`], `other` is not stamped
+    /// at a branch, both trees are expected to cover the same extent.
+    /// Colors are taken from `self`.
+    pub fn intersect(&mut self, other: &Octree) {
+        let mut octree = Self::new();
+
+        for (branch, node) in self.iter_nodes() {
+            for (other_branch, _) in other.iter_subtree(branch) {
+                octree.set(other_branch, *node);
+            }
+        }
+
+        *self = octree;
+    }
+
     pub fn extend(&mut self, nodes: &[Node]) -> u32 {
         let index = self.nodes.len() as u32;
         self.nodes.extend_from_slice(nodes);
@@ -106,10 +496,119 @@ impl Octree {
         NodeIterator::new(self)
     }
 
+    /// Iterate the leaves for which `pred` returns true, like [`Octree::iter_nodes`].
+    ///
+    /// Coalesced leaves are yielded once, so a large matching region can be a
+    /// single leaf. Collect the branches before editing them, for example with `set`.
+    pub fn iter_nodes_filter<'a>(
+        &'a self,
+        pred: impl Fn(&Node) -> bool + 'a,
+    ) -> impl Iterator<Item = (Branch, &'a Node)> + 'a {
+        self.iter_nodes().filter(move |(_, node)| pred(node))
+    }
+
+    /// Call `f` with every leaf, like [`Octree::iter_nodes`] but in parallel.
+    ///
+    /// The eight subtrees of the root are visited on separate threads,
+    /// so leaves are visited in no particular order.
+    pub fn par_visit(&self, f: impl Fn(Branch, &Node) + Sync) {
+        let root = self[self.root()];
+
+        if !root.is_parent() {
+            if !root.is_empty() {
+                f(Branch::root(), &self[self.root()]);
+            }
+
+            return;
+        }
+
+        (0..8).into_par_iter().for_each(|child| {
+            let branch = Branch::root().with_child(child);
+            let iter = NodeIterator {
+                octree: self,
+                stack: vec![(branch, root.pointer() + child)],
+            };
+
+            for (branch, node) in iter {
+                f(branch, node);
+            }
+        });
+    }
+
+    /// Iterate the leaves within `branch`.
+    ///
+    /// If `branch` lies within a larger leaf, that leaf is yielded as `branch`.
+    pub fn iter_subtree(&self, branch: impl Into<Branch>) -> NodeIterator<'_> {
+        let branch = branch.into();
+
+        NodeIterator {
+            octree: self,
+            stack: self.find(branch).map(|i| (branch, i)).into_iter().collect(),
+        }
+    }
+
+    /// Returns the number of nodes, including empty ones.
+    ///
+    /// This is never zero, since the root always exists, see [`Octree::is_empty`].
     pub fn len(&self) -> u32 {
         self.nodes.len() as u32
     }
 
+    /// Returns at most how many nodes the octree holds after setting each of
+    /// `branches`, without changing it.
+    ///
+    /// Every leaf split on the way to a branch is counted as if it stays split,
+    /// merging can only leave fewer nodes.
+    pub fn len_after_set(&self, branches: impl IntoIterator<Item = Branch>) -> u64 {
+        let mut split = HashSet::new();
+
+        for branch in branches {
+            let mut index = Some(self.root());
+            let mut ancestor = Branch::root();
+
+            for depth in 0..branch.depth {
+                let child = branch.child(depth);
+
+                // below the first leaf on the way, every cell is split by `set`
+                index = match index.map(|index| self[index]) {
+                    Some(node) if node.is_parent() => Some(node.pointer() + child),
+                    _ => {
+                        split.insert(ancestor);
+                        None
+                    }
+                };
+
+                ancestor = ancestor.with_child(child);
+            }
+        }
+
+        let reused = split.len().min(self.free_branches.len());
+        self.len() as u64 + (split.len() - reused) as u64 * 8
+    }
+
+    /// Same as [`Octree::len_after_set`] for stamping `other` into `branch`, which
+    /// [`Octree::union`], [`Octree::difference`] and [`Octree::recolor`] do alike.
+    ///
+    /// # Panics
+    ///
+    /// Like [`Octree::union`].
+    pub fn len_after_stamp(&self, branch: impl Into<Branch>, depth: u32, other: &Octree) -> u64 {
+        let mut branches = Vec::new();
+        for_each_stamped(branch.into(), depth, other, |branch, _| {
+            branches.push(branch);
+        });
+
+        self.len_after_set(branches)
+    }
+
+    /// Returns true if there are no solid nodes, that is if the root is empty.
+    ///
+    /// Parents are collapsed when all their children become empty, so a tree
+    /// without solid nodes always has an empty root.
+    pub fn is_empty(&self) -> bool {
+        self[self.root()].is_empty()
+    }
+
     pub fn size(&self) -> usize {
         self.nodes.len() * mem::size_of::<Node>()
     }
@@ -194,14 +693,120 @@ impl<'a> Iterator for NodeIterator<'a> {
     }
 }
 
+/// Sample the node of `cell` at the depth of `sdf`, `None` if the cell is empty.
+fn sample_cell<T: Generate + ?Sized>(sdf: &T, cell: IVec3) -> Option<Node> {
+    let dimensions = sdf.dimensions().as_vec3();
+    let point = (cell.as_vec3() + 0.5) / dimensions;
+
+    let node = sdf.get_node(point)?;
+
+    let normal = match sdf.needs_normal() {
+        true => estimate_normal(sdf, point, 1.0 / dimensions),
+        false => Vec3::ZERO,
+    };
+
+    Some(sdf.material(point, normal, node))
+}
+
+/// Call `f` with every leaf of `other` moved to where it lands when stamped at
+/// `branch`, with its depth increased by `depth`.
+///
+/// Leaves shallower than `branch` are split into the cells of `branch.depth`.
+///
+/// # Panics
+///
+/// If `branch` or a moved leaf is deeper than [`Octree::MAX_DEPTH`],
+/// before the paths are shifted past the bits they have.
+fn for_each_stamped(branch: Branch, depth: u32, other: &Octree, f: impl FnMut(Branch, Node)) {
+    for_each_stamped_oriented(branch, depth, other, Rotation::IDENTITY, Mirror::NONE, f);
+}
+
+/// Like [`for_each_stamped`], with the leaves of `other` oriented first,
+/// see [`Branch::oriented`].
+fn for_each_stamped_oriented(
+    branch: Branch,
+    depth: u32,
+    other: &Octree,
+    rotation: Rotation,
+    mirror: Mirror,
+    mut f: impl FnMut(Branch, Node),
+) {
+    if let Err(err) = Octree::check_depth(branch.depth) {
+        panic!("{}", err);
+    }
+
+    let oriented = !rotation.turns.is_multiple_of(4) || mirror != Mirror::NONE;
+
+    for (other_branch, node) in other.iter_nodes() {
+        let mut other_branch = other_branch.oriented(rotation, mirror);
+        let node = match oriented {
+            true => node.oriented(rotation, mirror),
+            false => *node,
+        };
+
+        other_branch.depth = other_branch.depth.saturating_add(depth);
+        if let Err(err) = Octree::check_depth(other_branch.depth) {
+            panic!("{}", err);
+        }
+
+        let offset = other_branch.depth as i32 - branch.depth as i32;
+
+        if offset >= 0 {
+            other_branch.path += branch.path << offset;
+            f(other_branch, node);
+
+            continue;
+        }
+
+        let half = 1 << -offset;
+
+        for x in 0..half {
+            for y in 0..half {
+                for z in 0..half {
+                    let mut other_branch = other_branch;
+                    other_branch.path = other_branch.path << -offset;
+                    other_branch.path += branch.path;
+                    other_branch.path += IVec3::new(x, y, z);
+                    other_branch.depth += -offset as u32;
+                    f(other_branch, node);
+                }
+            }
+        }
+    }
+}
+
+/// Called by `set` before changing a branch, implemented for every type
+/// `impl_octree!` expands for.
+///
+/// `set` calls it through the trait of the type it's implemented on, so a type
+/// can't use the implementation of [`Octree`] through `Deref` by accident.
+trait TrackChanges {
+    fn mark_changed(&mut self, branch: Branch);
+}
+
+impl TrackChanges for Octree {
+    fn mark_changed(&mut self, _branch: Branch) {
+        self.generation += 1;
+    }
+}
+
 macro_rules! impl_octree {
     ($ty:ty) => {
         impl $ty {
+            /// # Panics
+            ///
+            /// If `branch` is deeper than [`Octree::MAX_DEPTH`].
             pub fn set(&mut self, branch: impl Into<Branch>, node: Node) {
                 let branch = branch.into();
                 let mut parent = self.root();
 
-                let mut stack = [0; 32];
+                if let Err(err) = Octree::check_depth(branch.depth) {
+                    panic!("{}", err);
+                }
+
+                <$ty as TrackChanges>::mark_changed(self, branch);
+
+                let mut stack = [0; Octree::MAX_DEPTH as usize];
                 let mut stack_len = 0;
 
                 // traverse down the tree until we reach the leaf
@@ -234,6 +839,11 @@ macro_rules! impl_octree {
 
                 self[parent] = node;
 
+                // empty children are always collapsed, so removing cells frees their parents
+                if !self.merge_leaves && !node.is_empty() {
+                    return;
+                }
+
                 // traverse back up the tree and combine leaf nodes
                 for i in (0..stack_len).rev() {
                     let parent = stack[i];
@@ -241,7 +851,7 @@ macro_rules! impl_octree {
 
                     let mut combine = true;
                     for child in 0..8 {
-                        combine &= self[pointer + child] == node;
+                        combine &= self[pointer + child].combinable_with(&node);
                     }
 
                     if combine {
@@ -251,78 +861,1079 @@ macro_rules! impl_octree {
                 }
             }
 
+            /// Sets `branch` to empty, siblings are combined on the way back up like
+            /// in [`Octree::set`].
             pub fn remove(&mut self, branch: impl Into<Branch>) {
                 self.set(branch, Node::empty());
             }
 
+            /// Regenerate the cells from `min` to `max` at the depth of `sdf`, leaving
+            /// the rest of the tree intact.
+            ///
+            /// `min` is inclusive and `max` exclusive, cells are addressed like in
+            /// [`Octree::generate`], so the region is clamped to the dimensions of `sdf`.
+            /// Cells that `sdf` leaves empty are removed.
+            pub fn generate_region<T: Generate>(&mut self, sdf: &T, (min, max): (IVec3, IVec3)) {
+                let dimensions = sdf.dimensions().as_ivec3();
+                let depth = sdf.depth();
+
+                let min = min.max(-dimensions);
+                let max = max.min(dimensions);
+
+                for x in min.x..max.x {
+                    for y in min.y..max.y {
+                        for z in min.z..max.z {
+                            let cell = IVec3::new(x, y, z);
+                            let node = sample_cell(sdf, cell).unwrap_or(Node::empty());
+
+                            self.set(Branch::new(cell, depth), node);
+                        }
+                    }
+                }
+            }
+
+            /// # Panics
+            ///
+            /// If stamped cells are deeper than [`Octree::MAX_DEPTH`],
+            /// see [`Octree::check_stamp`].
             pub fn union(&mut self, branch: impl Into<Branch>, depth: u32, other: &Octree) {
-                let branch = branch.into();
+                for_each_stamped(branch.into(), depth, other, |branch, node| {
+                    self.set(branch, node);
+                });
+            }
 
-                for (other_branch, node) in other.iter_nodes() {
-                    let mut other_branch = other_branch;
-                    other_branch.depth += depth;
+            /// Like [`Octree::union`], with `brush` mirrored by `mirror` and then
+            /// rotated by `rotation` around its center.
+            ///
+            /// The leaves and their stored normals are oriented while stamping,
+            /// so no oriented copy of `brush` is made.
+            ///
+            /// # Panics
+            ///
+            /// Like [`Octree::union`].
+            pub fn stamp(
+                &mut self,
+                at: impl Into<Branch>,
+                depth: u32,
+                brush: &Octree,
+                rotation: Rotation,
+                mirror: Mirror,
+            ) {
+                for_each_stamped_oriented(
+                    at.into(),
+                    depth,
+                    brush,
+                    rotation,
+                    mirror,
+                    |branch, node| {
+                        self.set(branch, node);
+                    },
+                );
+            }
+
+            /// # Panics
+            ///
+            /// Like [`Octree::union`].
+            pub fn difference(&mut self, branch: impl Into<Branch>, depth: u32, other: &Octree) {
+                for_each_stamped(branch.into(), depth, other, |branch, _| {
+                    self.remove(branch);
+                });
+            }
 
-                    let offset = other_branch.depth as i32 - branch.depth as i32;
+            /// Combine `other` into this octree, both spanning the same space, keeping
+            /// whichever has more detail where they overlap.
+            ///
+            /// Where one tree is subdivided further than the other, the subdivided
+            /// subtree is kept whole, including its empty cells. Where both are
+            /// leaves at the same depth, the solid one is kept, and ties go to `self`.
+            pub fn merge(&mut self, other: &Octree) {
+                self.merge_branch(other, Branch::root(), other.root());
+            }
 
-                    if offset >= 0 {
-                        other_branch.path += branch.path << offset;
-                        self.set(other_branch, *node);
+            fn merge_branch(&mut self, other: &Octree, branch: Branch, index: u32) {
+                let theirs = other[index];
+                let ours = self.get(branch);
 
-                        continue;
+                match (ours.is_parent(), theirs.is_parent()) {
+                    (true, true) => {
+                        for child in 0..8 {
+                            let child_branch = branch.with_child(child);
+                            self.merge_branch(other, child_branch, theirs.pointer() + child);
+                        }
                     }
+                    (false, true) => {
+                        self.remove(branch);
 
-                    let half = 1 << -offset;
-
-                    for x in 0..half {
-                        for y in 0..half {
-                            for z in 0..half {
-                                let mut other_branch = other_branch;
-                                other_branch.path = other_branch.path << -offset;
-                                other_branch.path += branch.path;
-                                other_branch.path += IVec3::new(x, y, z);
-                                other_branch.depth += -offset as u32;
-                                self.set(other_branch, *node);
-                            }
+                        for (leaf, &node) in other.iter_subtree(branch) {
+                            self.set(leaf, node);
+                        }
+                    }
+                    (_, false) => {
+                        if ours.is_empty() && !theirs.is_empty() {
+                            self.set(branch, theirs);
                         }
                     }
                 }
             }
 
-            pub fn difference(&mut self, branch: impl Into<Branch>, depth: u32, other: &Octree) {
-                let branch = branch.into();
+            /// Put back the leaves recorded by [`Octree::snapshot_region`], undoing
+            /// any edits within its regions since.
+            pub fn restore_region(&mut self, snapshot: &RegionSnapshot) {
+                for &(branch, node) in &snapshot.changes {
+                    match node {
+                        Some(node) => self.set(branch, node),
+                        None => self.remove(branch),
+                    }
+                }
+            }
 
-                for (other_branch, _) in other.iter_nodes() {
-                    let mut other_branch = other_branch;
-                    other_branch.depth += depth;
+            /// Replace every non-empty leaf with the result of `f`.
+            ///
+            /// Leaves are written with `set`, so siblings that become identical are
+            /// combined, and leaves `f` returns unchanged aren't touched.
+            pub fn apply(&mut self, f: impl Fn(Branch, Node) -> Node) {
+                let leaves: Vec<_> = (self.iter_nodes())
+                    .map(|(branch, &node)| (branch, node))
+                    .collect();
 
-                    let offset = other_branch.depth as i32 - branch.depth as i32;
+                for (branch, node) in leaves {
+                    let new = f(branch, node);
 
-                    if offset >= 0 {
-                        other_branch.path += branch.path << offset;
-                        self.remove(other_branch);
+                    if new != node {
+                        self.set(branch, new);
+                    }
+                }
+            }
 
-                        continue;
+            /// Paint the solid cells covered by `other` with its colors, stamped like
+            /// [`Octree::union`].
+            ///
+            /// Cells are neither added nor removed, and keep their flags and normals.
+            ///
+            /// # Panics
+            ///
+            /// Like [`Octree::union`].
+            pub fn recolor(&mut self, branch: impl Into<Branch>, depth: u32, other: &Octree) {
+                for_each_stamped(branch.into(), depth, other, |branch, node| {
+                    self.paint(branch, node);
+                });
+            }
+
+            /// Like [`Octree::difference`], but paints the cells exposed by the cut
+            /// with the color of `cut`.
+            ///
+            /// Only solid cells next to removed solid cells are painted, at the depth
+            /// of the removed cells, so the rest of the surface and the interior keep
+            /// their colors.
+            ///
+            /// # Panics
+            ///
+            /// Like [`Octree::union`].
+            pub fn difference_with_cut(
+                &mut self,
+                branch: impl Into<Branch>,
+                depth: u32,
+                other: &Octree,
+                cut: Node,
+            ) {
+                let mut removed = Vec::new();
+
+                for_each_stamped(branch.into(), depth, other, |branch, _| {
+                    if !self.get(branch).is_empty() {
+                        removed.push(branch);
                     }
 
-                    let half = 1 << -offset;
-
-                    for x in 0..half {
-                        for y in 0..half {
-                            for z in 0..half {
-                                let mut other_branch = other_branch;
-                                other_branch.path = other_branch.path << -offset;
-                                other_branch.path += branch.path;
-                                other_branch.path += IVec3::new(x, y, z);
-                                other_branch.depth += -offset as u32;
-                                self.remove(other_branch);
-                            }
+                    self.remove(branch);
+                });
+
+                for branch in removed {
+                    for normal in Branch::NEIGHBORS {
+                        let neighbor = Branch::new(branch.path + normal, branch.depth);
+
+                        if !self.get(neighbor).is_empty() {
+                            self.paint(neighbor, cut);
                         }
                     }
                 }
             }
+
+            /// Set the color of the solid leaves within `branch` to that of `color`,
+            /// keeping their flags and stored normals.
+            fn paint(&mut self, branch: Branch, color: Node) {
+                let leaves: Vec<_> = (self.iter_subtree(branch))
+                    .filter(|(_, leaf)| leaf.is_solid())
+                    .map(|(branch, &leaf)| (branch, leaf))
+                    .collect();
+
+                for (branch, leaf) in leaves {
+                    let data = (leaf.data & !Node::COLOR_MASK) | (color.data & Node::COLOR_MASK);
+                    self.set(branch, Node { data, ..leaf });
+                }
+            }
         }
     };
 }
 
 impl_octree!(Octree);
 impl_octree!(DynamicOctree);
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+
+    use super::*;
+    use crate::generate::Sphere;
+
+    #[test]
+    fn generate_progress() {
+        let mut progress = Vec::new();
+        Octree::generate_with_progress(&Sphere::new(8, 4), &mut |p| progress.push(p));
+
+        assert_eq!(progress.first(), Some(&0.0));
+        assert_eq!(progress.last(), Some(&1.0));
+        assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    /// A sphere shaded by how much its surface faces up.
+    struct ShadedSphere(Sphere);
+
+    impl Generate for ShadedSphere {
+        fn dimensions(&self) -> glam::UVec3 {
+            self.0.dimensions()
+        }
+
+        fn depth(&self) -> u32 {
+            self.0.depth()
+        }
+
+        fn get_node(&self, point: Vec3) -> Option<Node> {
+            self.0.get_node(point)
+        }
+
+        fn material(&self, _point: Vec3, normal: Vec3, _node: Node) -> Node {
+            Node::rgb(Vec3::splat(normal.y * 0.5 + 0.5))
+        }
+
+        fn needs_normal(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn generate_material() {
+        let octree = Octree::generate(&ShadedSphere(Sphere::new(8, 4)));
+
+        let top = octree.get((0, 7, 0, 4));
+        let bottom = octree.get((0, -8, 0, 4));
+        let side = octree.get((7, 0, 0, 4));
+
+        assert!(top.is_solid() && bottom.is_solid() && side.is_solid());
+        assert!(top.r() > 240);
+        assert!(bottom.r() < 15);
+        assert!(side.r().abs_diff(128) < 16);
+    }
+
+    #[test]
+    fn material_without_normal() {
+        /// Overrides the material, without asking for a normal.
+        struct Red(Sphere);
+
+        impl Generate for Red {
+            fn dimensions(&self) -> glam::UVec3 {
+                self.0.dimensions()
+            }
+
+            fn depth(&self) -> u32 {
+                self.0.depth()
+            }
+
+            fn get_node(&self, point: Vec3) -> Option<Node> {
+                self.0.get_node(point)
+            }
+
+            fn material(&self, _point: Vec3, normal: Vec3, _node: Node) -> Node {
+                assert_eq!(normal, Vec3::ZERO);
+                Node::solid(255, 0, 0)
+            }
+        }
+
+        let octree = Octree::generate(&Red(Sphere::new(8, 4)));
+        assert!(octree.iter_nodes().count() > 0);
+        assert!((octree.iter_nodes()).all(|(_, node)| *node == Node::solid(255, 0, 0)));
+    }
+
+    fn leaves(octree: &Octree) -> Vec<([i32; 3], u32, u32, u32)> {
+        let mut leaves: Vec<_> = (octree.iter_nodes())
+            .map(|(b, n)| (b.path.to_array(), b.depth, n.flags, n.data))
+            .collect();
+        leaves.sort();
+        leaves
+    }
+
+    #[test]
+    fn iter_solid_cells() {
+        let white = Node::solid(255, 255, 255);
+        let octree = Octree::solid(white);
+
+        for depth in 0..4 {
+            let cells: Vec<_> = octree.iter_solid_cells(depth).collect();
+            assert_eq!(cells.len(), 8usize.pow(depth));
+            assert!(cells
+                .iter()
+                .all(|&(path, node)| { node == white && Branch::new(path, depth).is_in_bounds() }));
+        }
+
+        let sphere = Octree::generate(&Sphere::new(8, 4));
+        let mut cells: Vec<_> = sphere.iter_solid_cells(4).map(|(path, _)| path).collect();
+        cells.sort_by_key(|path| path.to_array());
+        cells.dedup();
+
+        assert_eq!(cells.len() as u64, sphere.count_solid(4));
+        assert!(cells.iter().all(|&path| sphere.get((path, 4)).is_solid()));
+
+        // the cells of a leaf far above `depth` are yielded lazily, without overflowing
+        let depth = Octree::MAX_DEPTH;
+        let half = 1 << (depth - 1);
+        let first: Vec<_> = octree.iter_solid_cells(depth).take(2).collect();
+        assert_eq!(first[0].0, IVec3::splat(-half));
+        assert_eq!(first[1].0, IVec3::new(1 - half, -half, -half));
+
+        let mut corner = Octree::new();
+        corner.set((IVec3::splat(half - 1), depth), white);
+        corner.set((0, 0, 0, 1), white);
+        let cells: Vec<_> = corner.iter_solid_cells(depth).take(2).collect();
+        assert!(cells
+            .iter()
+            .all(|&(path, _)| corner.get((path, depth)).is_solid()));
+
+        let result = std::panic::catch_unwind(|| octree.iter_solid_cells(depth + 1).count());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn iter_nodes_filter() {
+        let grass = Node::solid(0, 255, 0);
+        let dirt = Node::solid(128, 64, 0);
+        let lamp = Node::emissive(255, 255, 0);
+
+        let mut octree = Octree::new();
+        octree.set((0, 0, 0, 2), grass);
+        octree.set((1, 0, 0, 2), grass);
+        octree.set((-1, -1, 0, 2), dirt);
+        octree.set((-2, 1, 1, 3), grass);
+        octree.set((2, -3, -1, 3), lamp);
+
+        let green: Vec<_> = octree.iter_nodes_filter(|node| *node == grass).collect();
+        assert_eq!(green.len(), 3);
+        assert!(green.iter().all(|(branch, _)| octree.get(*branch) == grass));
+        assert_eq!(octree.iter_nodes_filter(Node::is_emissive).count(), 1);
+
+        // repaint the matches, leaving the rest alone
+        let branches: Vec<_> = (octree.iter_nodes_filter(|node| *node == grass))
+            .map(|(branch, _)| branch)
+            .collect();
+        for branch in branches {
+            octree.set(branch, Node::translucent(0, 255, 0));
+        }
+
+        assert_eq!(octree.iter_nodes_filter(|node| *node == grass).count(), 0);
+        assert_eq!(octree.get((-1, -1, 0, 2)), dirt);
+        assert_eq!(octree.iter_nodes().count(), 5);
+    }
+
+    #[test]
+    fn par_visit() {
+        let grass = Octree::generate(&crate::generate::GrassBlock);
+
+        let visited = std::sync::Mutex::new(Vec::new());
+        grass.par_visit(|branch, node| {
+            let leaf = (branch.path.to_array(), branch.depth, node.flags, node.data);
+            visited.lock().unwrap().push(leaf);
+        });
+
+        let mut visited = visited.into_inner().unwrap();
+        visited.sort();
+
+        assert!(!visited.is_empty());
+        assert_eq!(visited, leaves(&grass));
+
+        let root = std::sync::Mutex::new(Vec::new());
+        let solid = Octree::solid(Node::solid(1, 2, 3));
+        solid.par_visit(|branch, _| root.lock().unwrap().push(branch));
+        assert_eq!(root.into_inner().unwrap(), vec![Branch::root()]);
+    }
+
+    #[test]
+    fn is_empty() {
+        let mut octree = Octree::new();
+        assert!(octree.is_empty());
+        assert_eq!(octree.len(), 1);
+
+        octree.set((1, -2, 3, 4), Node::solid(255, 255, 255));
+        assert!(!octree.is_empty());
+
+        octree.remove((1, -2, 3, 4));
+        assert!(octree.is_empty());
+    }
+
+    #[test]
+    fn solid() {
+        let white = Node::solid(255, 255, 255);
+        let octree = Octree::solid(white);
+
+        assert_eq!(octree.len(), 1);
+        assert_eq!(octree.count_solid(0), 1);
+        assert_eq!(octree.count_solid(3), 8 * 8 * 8);
+        assert_eq!(octree.count_solid(21), 1 << 63);
+        assert_eq!(octree.count_solid(22), u64::MAX);
+        assert_eq!(octree.count_solid(u32::MAX), u64::MAX);
+
+        let sphere = Octree::generate(&Sphere::new(8, 4));
+
+        let mut clipped = octree.clone();
+        clipped.intersect(&sphere);
+
+        assert_eq!(leaves(&clipped), leaves(&sphere));
+    }
+
+    #[test]
+    fn generate_region() {
+        let white = Node::solid(255, 255, 255);
+
+        // a grid of cells, some of which the sphere should clear or fill
+        let mut octree = Octree::new();
+        for x in -8..8 {
+            for z in -8..8 {
+                octree.set((x, 0, z, 4), white);
+            }
+        }
+
+        let before: Vec<_> = octree.iter_solid_cells(4).collect();
+
+        let min = IVec3::new(-2, -2, -2);
+        let max = IVec3::new(2, 2, 2);
+        let sphere = Sphere::new(8, 4);
+        octree.generate_region(&sphere, (min, max));
+
+        let inside = |cell: IVec3| cell.cmpge(min).all() && cell.cmplt(max).all();
+        let after: Vec<_> = octree.iter_solid_cells(4).collect();
+
+        // outside the region nothing changed
+        let outside = |cells: &[(IVec3, Node)]| -> Vec<_> {
+            let mut cells: Vec<_> = (cells.iter())
+                .filter(|(cell, _)| !inside(*cell))
+                .map(|(cell, _)| cell.to_array())
+                .collect();
+            cells.sort();
+            cells
+        };
+        assert_eq!(outside(&after), outside(&before));
+
+        // inside the region the cells match the sphere
+        let generated = Octree::generate(&sphere);
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let cell = IVec3::new(x, y, z);
+                    assert_eq!(
+                        octree.get((x, y, z, 4)).is_solid(),
+                        generated.get((x, y, z, 4)).is_solid(),
+                        "{cell}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounding_sphere() {
+        assert_eq!(Octree::new().bounding_sphere(), None);
+
+        let sphere = Octree::generate(&Sphere::new(16, 5));
+        let (center, radius) = sphere.bounding_sphere().unwrap();
+
+        assert!(center.length() < 1e-4, "{center}");
+        assert!((radius - 1.0).abs() < 0.1, "{radius}");
+
+        // a single cell is enclosed by a sphere through its corners
+        let mut cell = Octree::new();
+        cell.set((1, -2, 0, 2), Node::solid(255, 255, 255));
+        let (center, radius) = cell.bounding_sphere().unwrap();
+
+        assert!(
+            center.distance(Vec3::new(0.75, -0.75, 0.25)) < 1e-4,
+            "{center}"
+        );
+        assert!((radius - 3.0f32.sqrt() * 0.25).abs() < 1e-4, "{radius}");
+    }
+
+    #[test]
+    fn merge_leaves() {
+        let white = Node::solid(255, 255, 255);
+
+        let mut merged = Octree::new();
+        let mut unmerged = Octree::new();
+        unmerged.merge_leaves = false;
+
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    merged.set((x, y, z, 2), white);
+                    unmerged.set((x, y, z, 2), white);
+                }
+            }
+        }
+
+        // the eight cells collapse into their parent
+        assert_eq!(merged.iter_nodes().count(), 1);
+        assert_eq!(merged.get((0, 0, 0, 1)), white);
+
+        // without merging every cell stays its own leaf
+        let cells: Vec<_> = unmerged.iter_nodes().collect();
+        assert_eq!(cells.len(), 8);
+        assert!(cells
+            .iter()
+            .all(|(branch, node)| branch.depth == 2 && **node == white));
+        assert!(unmerged.get((0, 0, 0, 1)).is_parent());
+    }
+
+    #[test]
+    fn unmerged_remove_collapses() {
+        let white = Node::solid(255, 255, 255);
+
+        let mut octree = Octree::new();
+        octree.merge_leaves = false;
+
+        octree.set((3, -2, 1, 4), white);
+        octree.set((3, -1, 1, 4), white);
+        octree.remove((3, -2, 1, 4));
+
+        // the emptied sibling stays, next to the cell still set
+        assert_eq!(octree.iter_nodes().count(), 1);
+        assert!(octree.get((1, -1, 0, 3)).is_parent());
+
+        octree.remove((3, -1, 1, 4));
+        assert!(octree.is_empty());
+        assert_eq!(octree.len(), 1);
+    }
+
+    #[test]
+    fn recolor() {
+        let gray = Node::solid(128, 128, 128);
+        let glass = Node::translucent(0, 0, 255);
+
+        // a floor filling the bottom half, coalesced into large leaves
+        let mut octree = Octree::new();
+        for x in -8..8 {
+            for y in -8..0 {
+                for z in -8..8 {
+                    octree.set((x, y, z, 4), gray);
+                }
+            }
+        }
+        octree.set((1, -1, 1, 4), glass);
+
+        let brush = Octree::generate(&Sphere::new(4, 3));
+        let branch = Branch::new(IVec3::new(0, -1, 0), 4);
+        octree.recolor(branch, 1, &brush);
+
+        assert_eq!(octree.count_solid(4), 16 * 16 * 8);
+        assert!(octree.get((0, 2, 0, 4)).is_empty());
+
+        for (cell, node) in octree.iter_solid_cells(4) {
+            let in_brush = brush.get(Branch::new(cell - branch.path, 3)).is_solid();
+
+            let expected = match (in_brush, cell == IVec3::new(1, -1, 1)) {
+                (true, true) => Node::translucent(255, 255, 255),
+                (true, false) => Node::solid(255, 255, 255),
+                (false, true) => glass,
+                (false, false) => gray,
+            };
+            assert_eq!(node, expected, "{}", cell);
+        }
+    }
+
+    #[test]
+    fn recolor_keeps_normals() {
+        let gray = Node::solid(128, 128, 128);
+        let normals = [Vec3::X, Vec3::NEG_Z, Vec3::new(1.0, 1.0, 0.0), Vec3::ZERO];
+
+        let mut octree = Octree::new();
+        for (x, &normal) in normals.iter().enumerate() {
+            octree.set((x as i32, 0, 0, 3), gray.with_normal(normal));
+        }
+
+        // the brush has a normal of its own, which isn't copied
+        let red = Node::solid(255, 0, 0);
+        let brush = Octree::solid(red.with_normal(Vec3::Y));
+        octree.recolor(Branch::root(), 0, &brush);
+
+        for (x, &normal) in normals.iter().enumerate() {
+            let node = octree.get((x as i32, 0, 0, 3));
+            assert_eq!(node, red.with_normal(normal));
+            assert_eq!(node.normal(), gray.with_normal(normal).normal());
+        }
+    }
+
+    #[test]
+    fn difference_with_cut() {
+        let gray = Node::solid(128, 128, 128);
+        let cut = Node::solid(90, 60, 30);
+
+        let mut octree = Octree::solid(gray);
+
+        // dig a 2x2x2 hole into the top of a solid block
+        let hole = Octree::solid(gray);
+        octree.difference_with_cut((0, 6, 0, 4), 3, &hole, cut);
+
+        assert_eq!(octree.count_solid(4), 16 * 16 * 16 - 8);
+
+        for (cell, node) in octree.iter_solid_cells(4) {
+            let near_hole = Branch::NEIGHBORS.iter().any(|&normal| {
+                let neighbor = cell + normal;
+                neighbor.cmpge(IVec3::new(0, 6, 0)).all()
+                    && neighbor.cmplt(IVec3::new(2, 8, 2)).all()
+            });
+
+            match near_hole {
+                true => assert_eq!(node, cut, "{}", cell),
+                false => assert_eq!(node, gray, "{}", cell),
+            }
+        }
+
+        // the walls and floor of the hole were painted
+        assert_eq!(octree.get((0, 5, 0, 4)), cut);
+        assert_eq!(octree.get((2, 7, 1, 4)), cut);
+        assert_eq!(octree.get((0, 4, 0, 4)), gray);
+    }
+
+    #[test]
+    fn combine_keeps_translucent() {
+        let stone = Node::solid(200, 100, 50);
+        let glass = Node::translucent(200, 100, 50);
+        assert!(!stone.combinable_with(&glass));
+        assert!(!stone.combinable_with(&Node::emissive(200, 100, 50)));
+        assert!(!stone.combinable_with(&Node::solid(200, 100, 51)));
+
+        let stale = Node {
+            flags: Node::SHADOW_BIT,
+            data: 7,
+        };
+        assert!(Node::empty().combinable_with(&stale));
+
+        // clearing a cell with a stale empty node still collapses its parent
+        let mut octree = Octree::new();
+        octree.set(Branch::root().with_child(3), stone);
+        assert_eq!(octree.len(), 9);
+        octree.set(Branch::root().with_child(3), stale);
+        assert_eq!(octree.len(), 1);
+
+        // seven stone children and one of glass in the same color stay apart
+        let cells: Vec<_> = (0..8)
+            .map(|child| {
+                let node = if child == 5 { glass } else { stone };
+                (Branch::root().with_child(child), node)
+            })
+            .collect();
+
+        let mut octree = Octree::new();
+        for &(branch, node) in &cells {
+            octree.set(branch, node);
+        }
+
+        assert_eq!(octree.len(), 9);
+        assert_eq!(octree.get(Branch::root().with_child(5)), glass);
+
+        let mut builder = OctreeBuilder::new();
+        builder.extend(cells);
+        assert!(builder.finish().structurally_eq(&octree));
+    }
+
+    #[test]
+    fn edits_straddling_origin() {
+        let solid = Node::solid(255, 0, 0);
+
+        for depth in 1..=6 {
+            for axis in 0..3 {
+                // the cells on either side of zero, off the other axes' zero planes
+                for side in [-1, 0] {
+                    let mut path = IVec3::splat(-1 << (depth - 1)) + IVec3::splat(depth as i32 / 2);
+                    path[axis] = side;
+                    let branch = Branch::new(path, depth);
+
+                    let mut octree = Octree::new();
+                    octree.set(branch, solid);
+                    assert_eq!(octree.get(branch), solid, "{:?}", branch);
+
+                    let mut neighbor = branch;
+                    neighbor.path[axis] = -1 - side;
+                    assert!(octree.get(neighbor).is_empty(), "{:?}", neighbor);
+
+                    // the leaf is found where it was set, and walking down to it agrees
+                    let leaves: Vec<_> =
+                        octree.iter_nodes().filter(|(_, n)| n.is_solid()).collect();
+                    assert_eq!(leaves, [(branch, &solid)]);
+
+                    let walked =
+                        (0..depth).fold(Branch::root(), |b, d| b.with_child(branch.child(d)));
+                    assert_eq!(walked, branch);
+
+                    let center = branch.min_normalized() + branch.size_normalized() / 2.0;
+                    assert_eq!(Branch::from_point_normalized(center, depth), branch);
+
+                    // carving the cell with a single cell stamp removes only it
+                    let mut stamp = Octree::new();
+                    stamp.set(Branch::root(), solid);
+
+                    let mut carved = stamp.clone();
+                    carved.difference(branch, depth, &stamp);
+                    assert!(carved.get(branch).is_empty());
+                    assert_eq!(carved.get(neighbor), solid);
+                    assert_eq!(carved.count_solid(depth), (1 << (3 * depth)) - 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stamp_oriented() {
+        let red = Node::solid(255, 0, 0);
+        let green = Node::solid(0, 255, 0);
+        let blue = Node::solid(0, 0, 255);
+        let white = Node::solid(255, 255, 255);
+
+        let brush = |cells: [(i32, i32, i32); 4]| {
+            let mut brush = Octree::new();
+            brush.set((cells[0].0, cells[0].1, cells[0].2, 2), red);
+            brush.set((cells[1].0, cells[1].1, cells[1].2, 2), green);
+            brush.set((cells[2].0, cells[2].1, cells[2].2, 2), blue);
+            brush.set((cells[3].0, cells[3].1, cells[3].2, 1), white);
+            brush
+        };
+
+        let original = brush([(-2, -2, -2), (-1, -2, -2), (-2, -1, -2), (0, 0, 0)]);
+
+        // a quarter turn around y takes (x, y, z) to (z, y, -x - 1)
+        let rotated = brush([(-2, -2, 1), (-2, -2, 0), (-2, -1, 1), (0, 0, -1)]);
+        // mirroring x first takes x to -x - 1
+        let mirrored = brush([(-2, -2, -2), (-2, -2, -1), (-2, -1, -2), (0, 0, 0)]);
+
+        let cases = [
+            (Mirror::NONE, &rotated),
+            (Mirror::new(true, false, false), &mirrored),
+        ];
+
+        for (mirror, expected) in cases {
+            let mut stamped = Octree::generate(&Sphere::new(8, 4));
+            let mut unioned = stamped.clone();
+
+            let rotation = Rotation::new(Axis::Y, 1);
+            stamped.stamp((1, -1, 0, 2), 2, &original, rotation, mirror);
+            unioned.union((1, -1, 0, 2), 2, expected);
+
+            assert!(stamped.structurally_eq(&unioned));
+        }
+
+        let mut stamped = Octree::new();
+        let mut unioned = Octree::new();
+        stamped.stamp(
+            (1, -1, 0, 2),
+            2,
+            &original,
+            Rotation::IDENTITY,
+            Mirror::NONE,
+        );
+        unioned.union((1, -1, 0, 2), 2, &original);
+        assert!(stamped.structurally_eq(&unioned));
+
+        stamped.stamp(
+            (1, -1, 0, 2),
+            2,
+            &original,
+            Rotation::new(Axis::Y, 1),
+            Mirror::NONE,
+        );
+        assert!(!stamped.structurally_eq(&unioned));
+
+        // stored normals turn with the cells, +x goes to -z, or to +z mirrored first
+        let mut brush = Octree::new();
+        brush.set((-2, -2, -2, 2), red.with_normal(Vec3::X));
+        brush.set((0, 0, 0, 1), white.with_normal(Vec3::Y));
+
+        let cases = [
+            (Mirror::NONE, (-2, -2, 1, 2), Vec3::NEG_Z, (0, 0, -1, 1)),
+            (
+                Mirror::new(true, false, false),
+                (-2, -2, -2, 2),
+                Vec3::Z,
+                (0, 0, 0, 1),
+            ),
+        ];
+
+        for (mirror, cell, normal, white_cell) in cases {
+            let mut stamped = Octree::new();
+            stamped.stamp((0, 0, 0, 0), 0, &brush, Rotation::new(Axis::Y, 1), mirror);
+
+            assert_eq!(stamped.get(cell), red.with_normal(normal));
+            assert_eq!(stamped.get(cell).normal(), Some(normal));
+            assert_eq!(stamped.get(white_cell), white.with_normal(Vec3::Y));
+        }
+    }
+
+    #[test]
+    fn merge_keeps_detail() {
+        let coarse_color = Node::solid(200, 200, 200);
+        let fine_color = Node::solid(50, 100, 150);
+        let octant = Branch::new(IVec3::splat(-1), 1);
+        let hole = Branch::new(IVec3::new(-2, -3, -4), 4);
+
+        // a coarse block in two octants
+        let mut coarse = Octree::new();
+        coarse.set(octant, coarse_color);
+        coarse.set((0, -1, 0, 1), coarse_color);
+
+        // the same octant finely carved, plus a detail where the block is empty
+        let mut detail = Octree::new();
+        detail.set(octant, fine_color);
+        detail.remove(hole);
+        detail.set((1, 1, 1, 3), fine_color);
+        detail.set((0, -1, 0, 1), fine_color);
+
+        for (mut merged, other, tie) in [
+            (coarse.clone(), &detail, coarse_color),
+            (detail.clone(), &coarse, fine_color),
+        ] {
+            merged.merge(other);
+
+            // the carved octant wins whichever side it is on
+            assert!(merged.get(hole).is_empty());
+            assert_eq!(merged.get((-1, -1, -1, 4)), fine_color);
+            assert_eq!(merged.count_solid(4), 512 - 1 + 8 + 512);
+
+            // empty space takes the other side, ties keep `self`
+            assert_eq!(merged.get((1, 1, 1, 3)), fine_color);
+            assert_eq!(merged.get((0, -1, 0, 1)), tie);
+        }
+
+        // merging an empty tree changes nothing
+        let mut merged = detail.clone();
+        merged.merge(&Octree::new());
+        assert!(merged.structurally_eq(&detail));
+    }
+
+    #[test]
+    fn remove_then_refill_collapses() {
+        let solid = Node::solid(20, 200, 90);
+
+        let mut octree = Octree::new();
+        octree.set(Branch::root(), solid);
+
+        // carving a cell splits every level above it
+        octree.remove((1, -2, 3, 3));
+        assert!(octree.len() > 1);
+        assert!(octree.get((1, -2, 3, 3)).is_empty());
+
+        // refilling leaves eight identical solids at each level, which collapse
+        octree.set((1, -2, 3, 3), solid);
+        assert_eq!(octree.len(), 1);
+        assert_eq!(octree.get(Branch::root()), solid);
+
+        // removing every child of a parent collapses it to empty
+        for child in 0..8 {
+            octree.remove(Branch::root().with_child(child));
+        }
+        assert_eq!(octree.len(), 1);
+        assert!(octree.get(Branch::root()).is_empty());
+    }
+
+    #[test]
+    fn generate_too_deep() {
+        struct Deep;
+
+        impl Generate for Deep {
+            fn dimensions(&self) -> UVec3 {
+                UVec3::ONE
+            }
+
+            fn depth(&self) -> u32 {
+                40
+            }
+
+            fn get_node(&self, _: Vec3) -> Option<Node> {
+                Some(Node::solid(255, 255, 255))
+            }
+        }
+
+        assert!(matches!(
+            Octree::try_generate(&Deep),
+            Err(OakumError::DepthTooLarge { depth: 40, max: 31 })
+        ));
+
+        let result = std::panic::catch_unwind(|| Octree::new().set((0, 0, 0, 40), Node::empty()));
+        assert!(result.is_err());
+
+        assert!(Octree::try_generate(&Sphere::new(8, 4)).is_ok());
+
+        // stamping checks the depth before shifting the paths of the brush
+        let brush = Octree::generate(&Sphere::new(8, 4));
+        assert!(Octree::check_stamp((0, 0, 0, 1), 27, &brush).is_ok());
+        assert!(matches!(
+            Octree::check_stamp((0, 0, 0, 1), 28, &brush),
+            Err(OakumError::DepthTooLarge { depth: 32, max: 31 })
+        ));
+        assert!(Octree::check_stamp((0, 0, 0, 40), 0, &brush).is_err());
+
+        for (branch, depth) in [((0, 0, 0, 1), 40), ((0, 0, 0, 40), 0)] {
+            let result = std::panic::catch_unwind(|| {
+                let mut octree = Octree::new();
+                octree.union(branch, depth, &brush);
+            });
+            let message = *result.unwrap_err().downcast::<String>().unwrap();
+            assert!(message.contains("exceeds the maximum"), "{message}");
+        }
+    }
+
+    #[test]
+    fn max_depth_cells() {
+        let depth = Octree::MAX_DEPTH;
+        let half = 1 << (depth - 1);
+        let white = Node::solid(255, 255, 255);
+
+        let mut octree = Octree::new();
+        for path in [
+            IVec3::splat(-half),
+            IVec3::splat(half - 1),
+            IVec3::new(-half, 0, half - 1),
+        ] {
+            let branch = Branch::new(path, depth);
+            assert!(branch.is_in_bounds());
+
+            octree.set(branch, white);
+            assert_eq!(octree.get(branch), white);
+            assert_eq!(octree.count_solid(depth), 1);
+
+            octree.remove(branch);
+            assert!(octree.is_empty());
+        }
+
+        assert!(Octree::check_depth(Octree::MAX_DEPTH + 1).is_err());
+    }
+
+    #[test]
+    fn blend() {
+        let a = Node::solid(0, 100, 200);
+        let b = Node::emissive(200, 50, 0);
+
+        let mixed = Node::blend(a, b, 0.5);
+        assert_eq!((mixed.r(), mixed.g(), mixed.b()), (100, 75, 100));
+        assert!(mixed.is_emissive());
+
+        assert_eq!(Node::blend(a, b, 0.0), a);
+        assert_eq!(Node::blend(a, b, 1.0), b);
+        assert!(!Node::blend(a, b, 0.25).is_emissive());
+    }
+
+    #[test]
+    fn reuse_lowest_free_branch() {
+        let mut octree = Octree::new();
+        let branches: Vec<u32> = (0..4).map(|_| octree.push_branch()).collect();
+
+        for i in [2, 0, 1] {
+            octree.remove_branch(branches[i]);
+        }
+
+        // the lowest index is reused first, regardless of the order they were freed in
+        for i in [0, 1, 2] {
+            assert_eq!(octree.push_branch(), branches[i]);
+        }
+
+        assert!(octree.free_branches.is_empty());
+        assert_eq!(octree.push_branch(), octree.len() - 8);
+    }
+
+    #[test]
+    fn normal() {
+        let node = Node::solid(10, 20, 30);
+        assert_eq!(node.normal(), None);
+
+        for normal in [Vec3::X, Vec3::NEG_Y, Vec3::Z, Vec3::new(0.3, -0.5, -0.8)] {
+            let stored = node.with_normal(normal);
+            assert_eq!((stored.r(), stored.g(), stored.b()), (10, 20, 30));
+            assert!(stored.normal().unwrap().dot(normal.normalize()) > 0.98);
+        }
+
+        // axes are exact
+        assert_eq!(node.with_normal(Vec3::NEG_Z).normal(), Some(Vec3::NEG_Z));
+        assert_eq!(node.with_normal(Vec3::Y).with_normal(Vec3::ZERO), node);
+    }
+
+    #[test]
+    fn apply() {
+        let mut octree = Octree::generate(&ShadedSphere(Sphere::new(8, 4)));
+        let cells = octree.count_solid(4);
+
+        // tint the shading so every leaf is colored
+        octree.apply(|_, node| Node::solid(node.r(), node.g() / 2, 255 - node.b()));
+        assert!((octree.iter_nodes()).all(|(_, n)| n.r() != n.b()));
+        let leaves = octree.iter_nodes().count();
+
+        octree.apply(|_, node| {
+            let gray = ((node.r() as u32 + node.g() as u32 + node.b() as u32) / 3) as u8;
+            Node::solid(gray, gray, gray)
+        });
+
+        assert_eq!(octree.count_solid(4), cells);
+        assert!((octree.iter_nodes()).all(|(_, n)| n.r() == n.g() && n.g() == n.b()));
+
+        // painting everything the same color merges the shading away
+        octree.apply(|_, _| Node::solid(255, 0, 0));
+
+        assert_eq!(octree.count_solid(4), cells);
+        assert!(octree.iter_nodes().count() < leaves);
+        assert!((octree.iter_nodes()).all(|(_, &n)| n == Node::solid(255, 0, 0)));
+    }
+
+    #[test]
+    fn generate_adaptive() {
+        /// Counts the samples taken.
+        struct Counted<T>(T, std::cell::Cell<u64>);
+
+        impl<T: Generate> Generate for Counted<T> {
+            fn dimensions(&self) -> glam::UVec3 {
+                self.0.dimensions()
+            }
+
+            fn depth(&self) -> u32 {
+                self.0.depth()
+            }
+
+            fn get_node(&self, point: Vec3) -> Option<Node> {
+                self.1.set(self.1.get() + 1);
+                self.0.get_node(point)
+            }
+        }
+
+        let naive = Counted(Sphere::new(64, 7), Default::default());
+        let adaptive = Counted(Sphere::new(64, 7), Default::default());
+
+        let expected = Octree::generate(&naive);
+        let octree = Octree::generate_adaptive(&adaptive);
+
+        assert!(octree.structurally_eq(&expected));
+        assert!(adaptive.1.get() * 5 < naive.1.get(), "{}", adaptive.1.get());
+
+        // the interior is made of shallow nodes
+        let shallowest = (octree.iter_nodes())
+            .map(|(branch, _)| branch.depth)
+            .min()
+            .unwrap();
+        assert!(shallowest <= 3);
+
+        // generators smaller than the octree are clamped like the naive loop
+        let small = Sphere::new(6, 4);
+        assert_eq!(
+            Octree::generate_adaptive(&small).count_solid(4),
+            Octree::generate(&small).count_solid(4)
+        );
+    }
+}
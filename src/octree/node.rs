@@ -1,5 +1,5 @@
 use bytemuck::{Pod, Zeroable};
-use glam::Vec3;
+use glam::{Vec2, Vec3};
 use serde::{Deserialize, Serialize};
 
 #[repr(C)]
@@ -13,8 +13,16 @@ impl Node {
     pub const SOLID_BIT: u32 = 1 << 0;
     pub const PARENT_BIT: u32 = 1 << 1;
     pub const SHADOW_BIT: u32 = 1 << 2;
+    pub const EMISSIVE_BIT: u32 = 1 << 3;
     pub const EMPTY_MASK: u32 = Self::PARENT_BIT | Self::SOLID_BIT;
 
+    /// The top byte of the data of solid nodes holds an optional normal, see [`Node::with_normal`].
+    pub const NORMAL_SHIFT: u32 = 24;
+    pub const COLOR_MASK: u32 = (1 << Self::NORMAL_SHIFT) - 1;
+    /// The number of steps along each axis of the octahedral normal encoding,
+    /// odd so the axes are represented exactly.
+    pub const NORMAL_LEVELS: u32 = 15;
+
     pub const fn empty() -> Self {
         Self { flags: 0, data: 0 }
     }
@@ -26,6 +34,7 @@ impl Node {
         }
     }
 
+    /// A node that doesn't cast shadows, drawn blended over whatever lies behind it.
     pub const fn translucent(r: u8, g: u8, b: u8) -> Self {
         Self {
             flags: Self::SOLID_BIT,
@@ -33,6 +42,14 @@ impl Node {
         }
     }
 
+    /// A node that glows with its own color, ignoring lighting and shadows.
+    pub const fn emissive(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            flags: Self::SOLID_BIT | Self::SHADOW_BIT | Self::EMISSIVE_BIT,
+            data: ((b as u32) << 16) | ((g as u32) << 8) | ((r as u32) << 0),
+        }
+    }
+
     pub const fn rgb8(r: u8, g: u8, b: u8) -> Self {
         Self::solid(r, g, b)
     }
@@ -57,6 +74,82 @@ impl Node {
         )
     }
 
+    /// Mix the colors of `a` and `b`, `t` of 0 gives the color of `a` and 1 that of `b`.
+    ///
+    /// The flags are taken from whichever node `t` is closer to, so blending with
+    /// [`Node::empty`] fades the color towards black while keeping the other node.
+    pub fn blend(a: Node, b: Node, t: f32) -> Self {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+        let red = mix(a.r(), b.r()) as u32;
+        let green = mix(a.g(), b.g()) as u32;
+        let blue = mix(a.b(), b.b()) as u32;
+
+        let closest = if t < 0.5 { a } else { b };
+
+        Self {
+            flags: closest.flags,
+            data: (closest.data & !Self::COLOR_MASK) | (blue << 16) | (green << 8) | (red << 0),
+        }
+    }
+
+    /// Store a quantized `normal` in the node, used for shading instead of the
+    /// normal of the face that was hit. A zero `normal` removes the stored one.
+    ///
+    /// The normal is part of the data, so leaves with different normals don't combine.
+    pub fn with_normal(mut self, normal: Vec3) -> Self {
+        self.data &= Self::COLOR_MASK;
+
+        if normal == Vec3::ZERO {
+            return self;
+        }
+
+        // project onto the octahedron, folding the lower half over the upper
+        let normal = normal / normal.abs().dot(Vec3::ONE);
+        let mut oct = Vec2::new(normal.x, normal.y);
+
+        if normal.z < 0.0 {
+            let sign = Vec2::select(oct.cmpge(Vec2::ZERO), Vec2::ONE, Vec2::NEG_ONE);
+            oct = (1.0 - Vec2::new(oct.y, oct.x).abs()) * sign;
+        }
+
+        let steps = (Self::NORMAL_LEVELS - 1) as f32;
+        let level = ((oct * 0.5 + 0.5) * steps).round().as_uvec2();
+
+        // zero means no normal
+        let code = level.x * Self::NORMAL_LEVELS + level.y + 1;
+        self.data |= code << Self::NORMAL_SHIFT;
+
+        self
+    }
+
+    /// Returns the normal stored with [`Node::with_normal`], if any.
+    pub fn normal(&self) -> Option<Vec3> {
+        let code = self.data >> Self::NORMAL_SHIFT;
+
+        if code == 0 {
+            return None;
+        }
+
+        let level = Vec2::new(
+            ((code - 1) / Self::NORMAL_LEVELS) as f32,
+            ((code - 1) % Self::NORMAL_LEVELS) as f32,
+        );
+
+        let oct = level / (Self::NORMAL_LEVELS - 1) as f32 * 2.0 - 1.0;
+        let z = 1.0 - oct.x.abs() - oct.y.abs();
+
+        let xy = match z < 0.0 {
+            true => {
+                let sign = Vec2::select(oct.cmpge(Vec2::ZERO), Vec2::ONE, Vec2::NEG_ONE);
+                (1.0 - Vec2::new(oct.y, oct.x).abs()) * sign
+            }
+            false => oct,
+        };
+
+        Some(Vec3::new(xy.x, xy.y, z).normalize())
+    }
+
     pub const fn parent(pointer: u32) -> Self {
         Self {
             flags: Self::PARENT_BIT,
@@ -76,10 +169,27 @@ impl Node {
         self.flags & Self::SHADOW_BIT != 0
     }
 
+    pub const fn is_emissive(&self) -> bool {
+        self.flags & Self::EMISSIVE_BIT != 0
+    }
+
     pub const fn is_empty(&self) -> bool {
         self.flags & Self::EMPTY_MASK == 0
     }
 
+    /// Returns true if sibling leaves `self` and `other` can be combined into one.
+    ///
+    /// Empty nodes always combine, whatever their other flags and data. Solid
+    /// leaves must match exactly, every flag changes how they are drawn,
+    /// [`Node::SHADOW_BIT`] included as it separates opaque from translucent nodes.
+    pub const fn combinable_with(&self, other: &Node) -> bool {
+        if self.is_empty() && other.is_empty() {
+            return true;
+        }
+
+        self.flags == other.flags && self.data == other.data
+    }
+
     pub const fn pointer(&self) -> u32 {
         self.data
     }
@@ -0,0 +1,236 @@
+use glam::{UVec3, Vec3};
+
+use super::{Branch, Octree};
+
+/// A coarse grid with one bit per cell, marking which cells contain solid nodes.
+///
+/// The raymarcher uses this to step through large empty regions of the octree
+/// without descending the tree for every cell. Trees built on the cpu collapse
+/// empty space into large leaves already, this matters for those generated on the
+/// gpu, which keep every parent, see [`DrawOctree::generate_sphere`](crate::render::DrawOctree::generate_sphere).
+#[derive(Clone, Debug)]
+pub struct OccupancyGrid {
+    depth: u32,
+    bits: Vec<u32>,
+}
+
+impl OccupancyGrid {
+    pub const DEFAULT_DEPTH: u32 = 5;
+
+    pub fn new(depth: u32) -> Self {
+        let resolution = 1usize << depth;
+        let cells = resolution * resolution * resolution;
+
+        Self {
+            depth,
+            bits: vec![0; cells.div_ceil(32)],
+        }
+    }
+
+    pub fn from_octree(octree: &Octree, depth: u32) -> Self {
+        let mut grid = Self::new(depth);
+
+        for (branch, node) in octree.iter_nodes() {
+            if node.is_solid() {
+                grid.mark(branch);
+            }
+        }
+
+        grid
+    }
+
+    pub const fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the number of cells along each axis.
+    pub const fn resolution(&self) -> u32 {
+        1 << self.depth
+    }
+
+    fn index(&self, cell: UVec3) -> usize {
+        let resolution = self.resolution() as usize;
+        let (x, y, z) = (cell.x as usize, cell.y as usize, cell.z as usize);
+        x + (y + z * resolution) * resolution
+    }
+
+    pub fn set(&mut self, cell: UVec3) {
+        let index = self.index(cell);
+        self.bits[index / 32] |= 1 << (index % 32);
+    }
+
+    pub fn clear(&mut self, cell: UVec3) {
+        let index = self.index(cell);
+        self.bits[index / 32] &= !(1 << (index % 32));
+    }
+
+    pub fn is_occupied(&self, cell: UVec3) -> bool {
+        let index = self.index(cell);
+        self.bits[index / 32] & (1 << (index % 32)) != 0
+    }
+
+    /// Mark every cell overlapping `branch` as occupied.
+    pub fn mark(&mut self, branch: Branch) {
+        self.fill(branch, true);
+    }
+
+    /// Recompute the cells overlapping `branch` from `octree`, after `branch` changed.
+    ///
+    /// A change within a cell can fill or empty all of it, so the whole cell is
+    /// recomputed, which only walks the part of the tree within it.
+    pub fn update(&mut self, octree: &Octree, branch: Branch) {
+        // `set` wraps branches outside the bounds around, so where those landed isn't known
+        let region = match branch.is_in_bounds() {
+            true => branch.ancestor_at(branch.depth.min(self.depth)),
+            false => Branch::root(),
+        };
+        self.fill(region, false);
+
+        for (branch, node) in octree.iter_subtree(region) {
+            if node.is_solid() {
+                self.mark(branch);
+            }
+        }
+    }
+
+    /// Set every cell overlapping `branch` to `occupied`.
+    fn fill(&mut self, branch: Branch, occupied: bool) {
+        let absolute = match branch.depth {
+            0 => UVec3::ZERO,
+            depth => (branch.path + (1 << (depth - 1))).as_uvec3(),
+        };
+
+        let fill = |grid: &mut Self, cell| match occupied {
+            true => grid.set(cell),
+            false => grid.clear(cell),
+        };
+
+        if branch.depth >= self.depth {
+            fill(self, absolute >> (branch.depth - self.depth));
+            return;
+        }
+
+        let size = 1 << (self.depth - branch.depth);
+        let min = absolute * size;
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    fill(self, min + UVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// Mark every cell overlapping the ball of `radius` around the center of the
+    /// octree, in normalized space where the bounds span -1 to 1.
+    pub fn mark_ball(&mut self, radius: f32) {
+        let resolution = self.resolution();
+        let cell_size = 2.0 / resolution as f32;
+
+        for x in 0..resolution {
+            for y in 0..resolution {
+                for z in 0..resolution {
+                    let cell = UVec3::new(x, y, z);
+                    let min = cell.as_vec3() * cell_size - 1.0;
+
+                    // the point of the cell closest to the center
+                    let closest = Vec3::ZERO.clamp(min, min + cell_size);
+                    if closest.length() <= radius {
+                        self.set(cell);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.bits
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::*;
+    use crate::{
+        generate::Sphere,
+        octree::{DynamicOctree, Node},
+    };
+
+    #[test]
+    fn mark_cells() {
+        let mut octree = Octree::new();
+        octree.set((-8, -8, -8, 4), Node::solid(255, 255, 255));
+        octree.set((1, 1, 1, 2), Node::solid(255, 255, 255));
+
+        let grid = OccupancyGrid::from_octree(&octree, 3);
+
+        // a deep leaf marks the single cell containing it
+        assert!(grid.is_occupied(UVec3::new(0, 0, 0)));
+        assert!(!grid.is_occupied(UVec3::new(1, 0, 0)));
+
+        // a shallow leaf marks every cell it covers
+        for x in 6..8 {
+            for y in 6..8 {
+                for z in 6..8 {
+                    assert!(grid.is_occupied(UVec3::new(x, y, z)));
+                }
+            }
+        }
+
+        let occupied: u32 = grid.words().iter().map(|w| w.count_ones()).sum();
+        assert_eq!(occupied, 1 + 8);
+    }
+
+    #[test]
+    fn update_changed() {
+        let mut octree = DynamicOctree::new(Octree::generate(&Sphere::new(5, 3)));
+        let mut grid = OccupancyGrid::from_octree(&octree, 3);
+
+        let edits = [
+            // a deep cell in an empty part, and removing it again
+            (
+                Branch::new(IVec3::new(-16, -16, -16), 5),
+                Node::solid(1, 2, 3),
+            ),
+            (Branch::new(IVec3::new(-16, -16, -16), 5), Node::empty()),
+            // clearing a large part of the sphere, then a cell within it
+            (Branch::new(IVec3::new(0, 0, 0), 1), Node::empty()),
+            (Branch::new(IVec3::new(3, 2, 1), 4), Node::solid(4, 5, 6)),
+            // filling a region larger than a grid cell
+            (Branch::new(IVec3::new(-1, 0, -1), 2), Node::solid(7, 8, 9)),
+            // outside the bounds
+            (Branch::new(IVec3::new(-17, 3, 0), 5), Node::solid(1, 1, 1)),
+        ];
+
+        for (branch, node) in edits {
+            let generation = octree.generation;
+            octree.set(branch, node);
+            octree.for_each_changed_since(generation, |branch| grid.update(&octree, branch));
+
+            assert_eq!(grid.words(), OccupancyGrid::from_octree(&octree, 3).words());
+        }
+    }
+
+    #[test]
+    fn mark_ball() {
+        let mut grid = OccupancyGrid::new(3);
+        grid.mark_ball(0.2);
+
+        // the ball only reaches into the 8 cells around the center
+        let occupied: u32 = grid.words().iter().map(|w| w.count_ones()).sum();
+        assert_eq!(occupied, 8);
+        assert!(grid.is_occupied(UVec3::new(3, 3, 3)));
+        assert!(grid.is_occupied(UVec3::new(4, 4, 4)));
+
+        grid.mark_ball(0.3);
+        assert!(grid.is_occupied(UVec3::new(5, 4, 4)));
+        assert!(!grid.is_occupied(UVec3::new(5, 5, 5)));
+    }
+}
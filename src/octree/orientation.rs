@@ -0,0 +1,163 @@
+use glam::{BVec3, IVec3, Vec3};
+
+use super::{Branch, Node};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+/// Quarter turns around an axis through the center of an octree,
+/// counter-clockwise looking down the axis towards the center.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rotation {
+    pub axis: Axis,
+    pub turns: u8,
+}
+
+impl Rotation {
+    pub const IDENTITY: Self = Self::new(Axis::Y, 0);
+
+    pub const fn new(axis: Axis, turns: u8) -> Self {
+        Self { axis, turns }
+    }
+
+    /// Rotate the cell at `path`, cells are addressed around the center like
+    /// [`Branch::path`], so this holds for cells at any depth but the root.
+    pub fn rotate_path(self, path: IVec3) -> IVec3 {
+        let mut path = path;
+
+        // the cell spans `path..path + 1`, so negating it gives `-path - 1`
+        for _ in 0..self.turns % 4 {
+            path = match self.axis {
+                Axis::X => IVec3::new(path.x, -path.z - 1, path.y),
+                Axis::Y => IVec3::new(path.z, path.y, -path.x - 1),
+                Axis::Z => IVec3::new(-path.y - 1, path.x, path.z),
+            };
+        }
+
+        path
+    }
+
+    /// Rotate a direction, like [`Rotation::rotate_path`] without the cell offset.
+    pub fn rotate_vector(self, vector: Vec3) -> Vec3 {
+        let mut vector = vector;
+
+        for _ in 0..self.turns % 4 {
+            vector = match self.axis {
+                Axis::X => Vec3::new(vector.x, -vector.z, vector.y),
+                Axis::Y => Vec3::new(vector.z, vector.y, -vector.x),
+                Axis::Z => Vec3::new(-vector.y, vector.x, vector.z),
+            };
+        }
+
+        vector
+    }
+}
+
+/// Which axes to mirror an octree along, through its center.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Mirror {
+    pub x: bool,
+    pub y: bool,
+    pub z: bool,
+}
+
+impl Mirror {
+    pub const NONE: Self = Self::new(false, false, false);
+
+    pub const fn new(x: bool, y: bool, z: bool) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Mirror the cell at `path`, like [`Rotation::rotate_path`].
+    pub fn mirror_path(self, path: IVec3) -> IVec3 {
+        IVec3::select(BVec3::new(self.x, self.y, self.z), -path - 1, path)
+    }
+
+    /// Mirror a direction, like [`Mirror::mirror_path`] without the cell offset.
+    pub fn mirror_vector(self, vector: Vec3) -> Vec3 {
+        Vec3::select(BVec3::new(self.x, self.y, self.z), -vector, vector)
+    }
+}
+
+impl Branch {
+    /// Returns this branch mirrored by `mirror`, then rotated by `rotation`,
+    /// around the center of the octree.
+    pub fn oriented(self, rotation: Rotation, mirror: Mirror) -> Self {
+        // the root covers the whole octree, which maps onto itself
+        if self.depth == 0 {
+            return self;
+        }
+
+        let path = rotation.rotate_path(mirror.mirror_path(self.path));
+        Self::new(path, self.depth)
+    }
+}
+
+impl Node {
+    /// Returns this node with its stored normal oriented like [`Branch::oriented`],
+    /// see [`Node::normal`].
+    pub fn oriented(self, rotation: Rotation, mirror: Mirror) -> Self {
+        match self.normal() {
+            Some(normal) => {
+                let normal = rotation.rotate_vector(mirror.mirror_vector(normal));
+                self.with_normal(normal)
+            }
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_turns_are_identity() {
+        let branch = Branch::new(IVec3::new(-3, 1, 2), 3);
+
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let rotation = Rotation::new(axis, 1);
+
+            let mut turned = branch;
+            for _ in 0..4 {
+                turned = turned.oriented(rotation, Mirror::NONE);
+                assert!(turned.path.cmpge(IVec3::splat(-4)).all());
+                assert!(turned.path.cmplt(IVec3::splat(4)).all());
+            }
+
+            assert_eq!(turned, branch);
+            assert_eq!(
+                branch.oriented(Rotation::new(axis, 4), Mirror::NONE),
+                branch
+            );
+        }
+
+        let mirror = Mirror::new(true, false, true);
+        assert_eq!(
+            branch.oriented(Rotation::IDENTITY, mirror).path,
+            IVec3::new(2, 1, -3)
+        );
+    }
+
+    #[test]
+    fn vectors_follow_paths() {
+        // a vector from the center of one cell to the center of another turns with them
+        let (a, b) = (IVec3::new(-3, 1, 2), IVec3::new(0, -2, 1));
+        let center = |path: IVec3| path.as_vec3() + 0.5;
+
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let rotation = Rotation::new(axis, 1);
+            let rotated = center(rotation.rotate_path(b)) - center(rotation.rotate_path(a));
+            assert_eq!(rotation.rotate_vector(center(b) - center(a)), rotated);
+        }
+
+        let mirror = Mirror::new(true, false, true);
+        let mirrored = center(mirror.mirror_path(b)) - center(mirror.mirror_path(a));
+        assert_eq!(mirror.mirror_vector(center(b) - center(a)), mirrored);
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    mem,
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use super::{Node, Octree};
+use crate::error::OakumError;
+
+/// A [`Node`] packed into 4 bytes.
+///
+/// The low bits hold the node flags, the rest hold either the pointer of a
+/// parent, or the [`Palette`] index of a solid node below its normal code,
+/// see [`Node::with_normal`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct PackedNode(pub u32);
+
+impl PackedNode {
+    pub const FLAG_BITS: u32 = 4;
+    pub const FLAG_MASK: u32 = (1 << Self::FLAG_BITS) - 1;
+    pub const MAX_PAYLOAD: u32 = u32::MAX >> Self::FLAG_BITS;
+    /// The bits of the payload of solid nodes holding the palette index,
+    /// the normal code takes the rest.
+    pub const INDEX_BITS: u32 = 32 - Self::FLAG_BITS - (32 - Node::NORMAL_SHIFT);
+    pub const INDEX_MASK: u32 = (1 << Self::INDEX_BITS) - 1;
+    /// The longest octree whose pointers fit the payload.
+    pub const MAX_NODES: u32 = Self::MAX_PAYLOAD + 1;
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn new(flags: u32, payload: u32) -> Self {
+        debug_assert!(flags <= Self::FLAG_MASK, "node flags do not fit");
+        assert!(payload <= Self::MAX_PAYLOAD, "node payload does not fit");
+
+        Self(flags | (payload << Self::FLAG_BITS))
+    }
+
+    pub const fn flags(&self) -> u32 {
+        self.0 & Self::FLAG_MASK
+    }
+
+    /// Returns true if this is a solid leaf without [`Node::SHADOW_BIT`].
+    pub const fn is_translucent(&self) -> bool {
+        let mask = Node::SOLID_BIT | Node::PARENT_BIT | Node::SHADOW_BIT;
+        self.flags() & mask == Node::SOLID_BIT
+    }
+
+    /// Returns the pointer of a parent, or the palette index of a solid node.
+    pub const fn payload(&self) -> u32 {
+        self.0 >> Self::FLAG_BITS
+    }
+}
+
+/// A table of node colors, indexed by [`PackedNode`]s.
+///
+/// Only colors are stored, normals are packed into the nodes, so leaves of one
+/// color share an entry whatever their normals.
+///
+/// Entries are never removed, so indices stay valid while the palette grows,
+/// up to [`Palette::MAX_COLORS`]. Once full, it has to be rebuilt from the
+/// colors still in use, and every node packed again.
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    colors: Vec<u32>,
+    indices: HashMap<u32, u32>,
+}
+
+impl Palette {
+    /// The most colors a [`PackedNode`] can index.
+    pub const MAX_COLORS: u32 = 1 << PackedNode::INDEX_BITS;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Returns the index of `color`, adding it if it isn't in the palette,
+    /// `None` if it isn't and the palette is full.
+    pub fn insert(&mut self, color: u32) -> Option<u32> {
+        if let Some(&index) = self.indices.get(&color) {
+            return Some(index);
+        }
+
+        let index = self.colors.len() as u32;
+        if index >= Self::MAX_COLORS {
+            return None;
+        }
+
+        self.colors.push(color);
+        self.indices.insert(color, index);
+
+        Some(index)
+    }
+
+    pub fn get(&self, index: u32) -> Option<u32> {
+        self.colors.get(index as usize).copied()
+    }
+
+    pub fn colors(&self) -> &[u32] {
+        &self.colors
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.colors)
+    }
+
+    /// Pack `node`, adding its color to the palette if it is solid.
+    ///
+    /// # Panics
+    ///
+    /// If the palette is full, or the pointer of a parent doesn't fit,
+    /// see [`Palette::try_pack`].
+    pub fn pack(&mut self, node: Node) -> PackedNode {
+        self.try_pack(node)
+            .expect("node does not fit a packed node")
+    }
+
+    /// Pack `node`, adding its color to the palette if it is solid.
+    ///
+    /// Returns an error if the palette is full, or the pointer of a parent
+    /// reaches past [`PackedNode::MAX_NODES`].
+    pub fn try_pack(&mut self, node: Node) -> Result<PackedNode, OakumError> {
+        let payload = if node.is_parent() {
+            let pointer = node.pointer();
+
+            if pointer > PackedNode::MAX_NODES - 8 {
+                return Err(OakumError::TooManyNodesToPack {
+                    nodes: pointer.saturating_add(8),
+                    max: PackedNode::MAX_NODES,
+                });
+            }
+
+            pointer
+        } else if node.is_solid() {
+            let index =
+                (self.insert(node.data & Node::COLOR_MASK)).ok_or(OakumError::PaletteFull {
+                    max: Self::MAX_COLORS,
+                })?;
+
+            let normal = node.data >> Node::NORMAL_SHIFT;
+            index | (normal << PackedNode::INDEX_BITS)
+        } else {
+            0
+        };
+
+        Ok(PackedNode::new(node.flags, payload))
+    }
+
+    pub fn unpack(&self, node: PackedNode) -> Node {
+        let flags = node.flags();
+
+        let data = if flags & Node::PARENT_BIT != 0 {
+            node.payload()
+        } else if flags & Node::SOLID_BIT != 0 {
+            let color = (self.get(node.payload() & PackedNode::INDEX_MASK))
+                .expect("palette index out of bounds");
+            let normal = node.payload() >> PackedNode::INDEX_BITS;
+
+            color | (normal << Node::NORMAL_SHIFT)
+        } else {
+            0
+        };
+
+        Node { flags, data }
+    }
+}
+
+/// An [`Octree`] with [`PackedNode`]s, storing colors in a [`Palette`].
+///
+/// This halves the size of the nodes, which pays off for scenes with few colors.
+#[derive(Clone, Debug, Default)]
+pub struct PaletteOctree {
+    pub nodes: Vec<PackedNode>,
+    pub free_branches: BinaryHeap<Reverse<u32>>,
+    pub palette: Palette,
+}
+
+impl PaletteOctree {
+    pub fn len(&self) -> u32 {
+        self.nodes.len() as u32
+    }
+
+    /// Returns true if there are no solid nodes, like [`Octree::is_empty`].
+    pub fn is_empty(&self) -> bool {
+        (self.nodes.first()).is_none_or(|node| node.flags() & Node::EMPTY_MASK == 0)
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.nodes)
+    }
+
+    /// Returns the size of the nodes and the palette in bytes.
+    pub fn size(&self) -> usize {
+        self.nodes.len() * mem::size_of::<PackedNode>() + self.palette.bytes().len()
+    }
+
+    pub fn to_octree(&self) -> Octree {
+        Octree {
+            nodes: self.nodes.iter().map(|&n| self.palette.unpack(n)).collect(),
+            free_branches: self.free_branches.clone(),
+            merge_leaves: true,
+            generation: 0,
+        }
+    }
+}
+
+impl Octree {
+    pub fn to_palette(&self) -> PaletteOctree {
+        let mut palette = Palette::new();
+
+        PaletteOctree {
+            nodes: self.nodes.iter().map(|&n| palette.pack(n)).collect(),
+            free_branches: self.free_branches.clone(),
+            palette,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::generate::GrassBlock;
+
+    #[test]
+    fn palette_round_trip() {
+        let grass = Octree::generate(&GrassBlock);
+        let packed = grass.to_palette();
+
+        assert_eq!(packed.len(), grass.len());
+        assert!(packed.palette.len() < 64);
+
+        let unpacked = packed.to_octree();
+        let leaves = |octree: &Octree| -> Vec<_> {
+            (octree.iter_nodes())
+                .map(|(branch, node)| (branch, *node))
+                .collect()
+        };
+        assert_eq!(leaves(&unpacked), leaves(&grass));
+
+        // nodes are half the size, and the palette is tiny in comparison
+        assert!(packed.size() * 100 < grass.size() * 51);
+    }
+
+    #[test]
+    fn normals_share_colors() {
+        let mut palette = Palette::new();
+        let color = Node::solid(10, 20, 30);
+
+        let normals = [Vec3::ZERO, Vec3::X, Vec3::NEG_Y, Vec3::new(1.0, 2.0, -3.0)];
+        for normal in normals {
+            let node = color.with_normal(normal);
+            let packed = palette.pack(node);
+            assert_eq!(palette.unpack(packed), node);
+        }
+
+        assert_eq!(palette.len(), 1);
+    }
+
+    #[test]
+    fn palette_limits() {
+        let mut palette = Palette::new();
+        for color in 0..Palette::MAX_COLORS {
+            palette.insert(color).unwrap();
+        }
+
+        // colors already in the palette still pack
+        let node = Node::solid(0, 0, 7).with_normal(Vec3::X);
+        let packed = palette.try_pack(node).unwrap();
+        assert_eq!(palette.unpack(packed), node);
+
+        let node = Node::solid(255, 255, 255);
+        assert!(matches!(
+            palette.try_pack(node),
+            Err(OakumError::PaletteFull { .. })
+        ));
+
+        let parent = Node::parent(PackedNode::MAX_NODES - 8);
+        let packed = palette.try_pack(parent).unwrap();
+        assert_eq!(palette.unpack(packed), parent);
+        assert!(matches!(
+            palette.try_pack(Node::parent(PackedNode::MAX_NODES)),
+            Err(OakumError::TooManyNodesToPack { .. })
+        ));
+    }
+}
@@ -2,17 +2,72 @@ use glam::{IVec3, Mat4, Vec3};
 
 use crate::ray::Ray;
 
-use super::{Branch, Octree};
+use super::{Branch, Node, Octree};
 
 #[derive(Clone, Copy, Debug)]
 pub struct OctreeHit {
     pub index: u32,
+    /// The leaf that was hit, [`Branch::root`] if `is_root` is set.
     pub branch: Branch,
+    /// True if the root itself is a solid leaf, in which case `branch` is the root,
+    /// use [`Branch::from_point`] with `point` to find the cell at a given depth.
+    ///
+    /// For [`Octree::raycast_from`], the root is the branch the traversal started at.
+    pub is_root: bool,
     pub distance: f32,
     pub point: Vec3,
+    /// The normal of the face that was hit.
+    ///
+    /// If the ray starts inside a solid cell, no face is crossed, and the normal
+    /// faces back along the dominant axis of the ray direction instead. A ray
+    /// entering the octree exactly on an edge or corner gets the sum of the
+    /// normals of the faces meeting there.
     pub normal: IVec3,
 }
 
+impl OctreeHit {
+    /// Returns the normal as a unit vector, see [`OctreeHit::normal`].
+    pub fn normal_vec3(&self) -> Vec3 {
+        self.normal.as_vec3().normalize()
+    }
+
+    /// Returns the min and max corners of the hit cell in the space of `transform`,
+    /// the transform the octree was raycast with.
+    ///
+    /// If `transform` rotates the octree, this is the box around the rotated cell.
+    pub fn cell_aabb(&self, transform: Mat4) -> (Vec3, Vec3) {
+        let min = self.branch.min_normalized();
+        let size = self.branch.size_normalized();
+
+        let mut aabb = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+        for corner in 0..8 {
+            let offset = Vec3::new(
+                (corner & 1) as f32,
+                (corner >> 1 & 1) as f32,
+                (corner >> 2 & 1) as f32,
+            );
+
+            let point = transform.transform_point3(min + offset * size);
+            aabb = (aabb.0.min(point), aabb.1.max(point));
+        }
+
+        aabb
+    }
+}
+
+/// Returns the axis aligned normal facing back along the dominant axis of `direction`.
+fn facing_normal(direction: Vec3) -> IVec3 {
+    let abs = direction.abs();
+
+    if abs.x >= abs.y && abs.x >= abs.z {
+        IVec3::new(-direction.x.signum() as i32, 0, 0)
+    } else if abs.y >= abs.z {
+        IVec3::new(0, -direction.y.signum() as i32, 0)
+    } else {
+        IVec3::new(0, 0, -direction.z.signum() as i32)
+    }
+}
+
 fn in_bounds(point: Vec3) -> bool {
     point.abs().cmple(Vec3::ONE).all()
 }
@@ -113,111 +168,493 @@ fn extract_child(path: IVec3, depth: u32) -> u32 {
     child
 }
 
+/// Map a hit in normalized space back to the space of `ray`.
+fn hit_to_world(transform: Mat4, ray: Ray, hit: OctreeHit) -> OctreeHit {
+    let position = transform.transform_point3(hit.point);
+
+    OctreeHit {
+        distance: (position - ray.origin).length(),
+        point: position,
+        ..hit
+    }
+}
+
 impl Octree {
     pub fn raycast(&self, transform: Mat4, ray: Ray) -> Option<OctreeHit> {
-        let ray = ray.transform(transform.inverse());
-        let hit = self.raycast_normalized(ray)?;
+        let normalized = ray.transform(transform.inverse());
+        let hit = self.raycast_normalized(normalized)?;
+
+        Some(hit_to_world(transform, ray, hit))
+    }
+
+    pub fn raycast_normalized(&self, ray: Ray) -> Option<OctreeHit> {
+        self.traverse::<false>(self.root(), ray, &mut 0)
+    }
+
+    /// Same as [`Octree::raycast`], but only traverses the subtree under `root`,
+    /// so anything outside of it is ignored, even if the ray hits it first.
+    ///
+    /// If `root` lies within a solid leaf, the ray hits `root` as a whole.
+    pub fn raycast_from(
+        &self,
+        transform: Mat4,
+        ray: Ray,
+        root: impl Into<Branch>,
+    ) -> Option<OctreeHit> {
+        let root = root.into();
+        let index = self.find(root)?;
+
+        // traverse the subtree in its own normalized space
+        let transform = transform * root.transform_normalized();
+        let normalized = ray.transform(transform.inverse());
+        let hit = self.traverse::<false>(index, normalized, &mut 0)?;
+
+        let hit = OctreeHit {
+            branch: root.descendant(hit.branch),
+            ..hit
+        };
+
+        Some(hit_to_world(transform, ray, hit))
+    }
 
-        let position = transform.transform_point3(hit.point);
+    /// Same as [`Octree::raycast`], but also returns the number of nodes visited.
+    ///
+    /// Useful for finding slow rays, like ones grazing many small cells.
+    pub fn raycast_debug(&self, transform: Mat4, ray: Ray) -> (Option<OctreeHit>, u32) {
+        let normalized = ray.transform(transform.inverse());
+
+        let mut visited = 0;
+        let hit = self.traverse::<true>(self.root(), normalized, &mut visited);
+
+        (hit.map(|hit| hit_to_world(transform, ray, hit)), visited)
+    }
+
+    /// Iterate the leaves `ray` passes through in order, with their node if it is solid.
+    ///
+    /// Leaves are yielded at the depth they are stored at, so a large empty region
+    /// is a single cell. Cells entered further than `max_distance` from the origin
+    /// of `ray` are not yielded, distances are measured in the space of `ray`.
+    pub fn ray_cells(
+        &self,
+        transform: Mat4,
+        ray: Ray,
+        max_distance: f32,
+    ) -> impl Iterator<Item = (Branch, Option<Node>)> + '_ {
+        let normalized = ray.transform(transform.inverse());
+
+        (Traversal::<false>::new(self, self.root(), normalized)
+            .into_iter()
+            .flatten())
+        .take_while(move |step| {
+            let point = transform.transform_point3(step.point);
+            (point - ray.origin).length() <= max_distance
+        })
+        .map(|step| {
+            let node = self[step.index];
+            (step.branch, node.is_solid().then_some(node))
+        })
+    }
+
+    /// Traverse the subtree at `root` along `ray` in its normalized space, stopping at
+    /// the first solid leaf.
+    ///
+    /// With `COUNT` set, every visited node increments `visited`,
+    /// without it the counting is compiled out.
+    fn traverse<const COUNT: bool>(
+        &self,
+        root: u32,
+        ray: Ray,
+        visited: &mut u32,
+    ) -> Option<OctreeHit> {
+        let mut traversal = Traversal::<COUNT>::new(self, root, ray)?;
+        let step = traversal.find(|step| self[step.index].is_solid());
+        *visited += traversal.visited;
+
+        let step = step?;
         Some(OctreeHit {
-            index: hit.index,
-            branch: hit.branch,
-            distance: (position - ray.origin).length(),
-            point: position,
-            normal: hit.normal,
+            index: step.index,
+            branch: step.branch,
+            is_root: step.branch.depth == 0,
+            distance: (step.point - ray.origin).length(),
+            point: step.point + traversal.direction * 0.0001,
+            normal: step.normal,
         })
     }
+}
 
-    pub fn raycast_normalized(&self, ray: Ray) -> Option<OctreeHit> {
-        let mut point = project(ray.origin, ray.direction)?;
+/// A leaf entered by a [`Traversal`].
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    index: u32,
+    branch: Branch,
+    /// Where the ray enters the leaf, in normalized space.
+    point: Vec3,
+    /// The normal of the face the ray entered through.
+    normal: IVec3,
+}
+
+/// Steps a ray through the leaves of an octree in normalized space.
+///
+/// The traversal keeps its position between leaves, so it can be resumed after
+/// any leaf, including solid ones.
+struct Traversal<'a, const COUNT: bool> {
+    octree: &'a Octree,
+    /// The index of the node the traversal starts at.
+    root: u32,
+    direction: Vec3,
+    dir: IVec3,
+    point: Vec3,
+    normal: IVec3,
+    parent: u32,
+    depth: u32,
+    child: u32,
+    path: IVec3,
+    stack: [u32; Octree::MAX_DEPTH as usize],
+    /// False until the root has been visited.
+    started: bool,
+    done: bool,
+    visited: u32,
+}
+
+impl<'a, const COUNT: bool> Traversal<'a, COUNT> {
+    /// Returns `None` if `ray` misses the octree.
+    fn new(octree: &'a Octree, root: u32, ray: Ray) -> Option<Self> {
+        let point = project(ray.origin, ray.direction)?;
         let direction = ray.direction.normalize();
 
         let side_axis = point.abs().cmpge(Vec3::ONE);
         let side_sign = point.signum().as_ivec3();
         let mut normal = IVec3::select(side_axis, side_sign, IVec3::ZERO);
-        let dir = direction.signum().as_ivec3();
 
-        let root = self[self.root()];
-        if root.is_empty() {
-            return None;
+        // the ray starts inside the octree, so no face has been crossed yet
+        if normal == IVec3::ZERO {
+            normal = facing_normal(direction);
         }
-        if root.is_solid() {
-            return Some(OctreeHit {
-                index: self.root(),
+
+        Some(Self {
+            octree,
+            root,
+            direction,
+            dir: direction.signum().as_ivec3(),
+            point,
+            normal,
+            parent: 0,
+            depth: 0,
+            child: 0,
+            path: IVec3::ZERO,
+            stack: [0; Octree::MAX_DEPTH as usize],
+            started: false,
+            done: false,
+            visited: 0,
+        })
+    }
+
+    fn visit(&mut self) {
+        if COUNT {
+            self.visited += 1;
+        }
+    }
+
+    /// Yield the root if it is a leaf, otherwise prepare to descend into it.
+    fn start(&mut self) -> Option<Step> {
+        self.started = true;
+
+        let index = self.root;
+        let root = self.octree[index];
+        self.visit();
+
+        if !root.is_parent() {
+            self.done = true;
+
+            return Some(Step {
+                index,
                 branch: Branch::root(),
-                distance: 0.0,
-                point,
-                normal,
+                point: self.point,
+                normal: self.normal,
             });
         }
 
-        let mut parent = root.pointer();
-        let mut depth = 0;
-        let mut child = select_initial_child(point);
-        let mut path = add_child(IVec3::ZERO, child);
-        let mut stack = [0; 32];
-        stack[0] = parent;
+        self.parent = root.pointer();
+        self.child = select_initial_child(self.point);
+        self.path = add_child(IVec3::ZERO, self.child);
+        self.stack[0] = self.parent;
+
+        None
+    }
+
+    /// Move to the neighboring cell along the ray, popping up the stack as needed.
+    fn advance(&mut self) {
+        let old_path = self.path;
+        let split = split(self.path, self.depth);
+        let bounds = split + self.dir.as_vec3() / (1 << (self.depth + 1)) as f32;
+        let t = (bounds - self.point) / self.direction;
+
+        let tmin = t.min_element();
+        if tmin == t.x {
+            self.path.x += self.dir.x;
+            self.normal = IVec3::new(-self.dir.x, 0, 0);
+        } else if tmin == t.y {
+            self.path.y += self.dir.y;
+            self.normal = IVec3::new(0, -self.dir.y, 0);
+        } else {
+            self.path.z += self.dir.z;
+            self.normal = IVec3::new(0, 0, -self.dir.z);
+        }
+
+        self.point += self.direction * tmin;
+
+        let path_diff = self.path ^ old_path;
+        let diff = path_diff.x | path_diff.y | path_diff.z;
+        let flip = 31 - diff.leading_zeros();
+
+        if flip > self.depth {
+            self.done = true;
+            return;
+        }
+
+        self.depth -= flip;
+        self.path = self.path >> flip;
+
+        self.parent = self.stack[self.depth as usize];
+        self.child = extract_child(self.path, 0);
+    }
+}
+
+impl<const COUNT: bool> Iterator for Traversal<'_, COUNT> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            if let Some(step) = self.start() {
+                return Some(step);
+            }
+        }
+
+        if self.done {
+            return None;
+        }
 
         loop {
-            let node = self[parent + child];
+            let index = self.parent + self.child;
+            let node = self.octree[index];
+            self.visit();
 
             if node.is_parent() {
-                parent = node.pointer();
-                child = select_child(point, path, depth);
-                path = add_child(path, child);
+                self.parent = node.pointer();
+                self.child = select_child(self.point, self.path, self.depth);
+                self.path = add_child(self.path, self.child);
 
-                depth += 1;
-                stack[depth as usize] = parent;
+                self.depth += 1;
+                self.stack[self.depth as usize] = self.parent;
                 continue;
             }
 
-            if node.is_solid() {
-                let half = 1 << depth;
-                let branch = Branch::new(path - half, depth + 1);
+            let half = 1 << self.depth;
+            let step = Step {
+                index,
+                branch: Branch::new(self.path - half, self.depth + 1),
+                point: self.point,
+                normal: self.normal,
+            };
 
-                let hit = OctreeHit {
-                    index: parent + child,
-                    branch,
-                    distance: (point - point).length(),
-                    point: point + direction * 0.0001,
-                    normal,
-                };
+            self.advance();
 
-                return Some(hit);
-            }
+            return Some(step);
+        }
+    }
+}
 
-            let old_path = path;
-            let split = split(path, depth);
-            let bounds = split + dir.as_vec3() / (1 << depth + 1) as f32;
-            let t = (bounds - point) / direction;
-
-            let tmin = t.min_element();
-            if tmin == t.x {
-                path.x += dir.x;
-                normal = IVec3::new(-dir.x, 0, 0);
-            } else if tmin == t.y {
-                path.y += dir.y;
-                normal = IVec3::new(0, -dir.y, 0);
-            } else {
-                path.z += dir.z;
-                normal = IVec3::new(0, 0, -dir.z);
-            }
+#[cfg(test)]
+mod tests {
+    use glam::Quat;
 
-            point += direction * tmin;
+    use super::*;
 
-            let path_diff = path ^ old_path;
-            let diff = path_diff.x | path_diff.y | path_diff.z;
-            let flip = 31 - diff.leading_zeros() as u32;
+    #[test]
+    fn raycast_solid_root() {
+        let octree = Octree::solid(Node::solid(255, 255, 255));
 
-            if flip > depth {
-                return None;
-            }
+        let ray = Ray::new(Vec3::new(0.1, 0.2, 4.0), Vec3::NEG_Z);
+        let hit = octree.raycast_normalized(ray).unwrap();
+
+        assert!(hit.is_root);
+        assert_eq!(hit.branch, Branch::root());
+        assert_eq!(hit.normal, IVec3::Z);
+        assert!((hit.distance - 3.0).abs() < 1e-4);
+
+        // the hit point lies inside the octree, so it maps to a cell within bounds
+        let branch = Branch::from_point_normalized(hit.point, 10);
+        assert!(branch.is_in_bounds());
+        assert_eq!(branch.path.z, (1 << 9) - 1);
+
+        let branch = Branch::from_point_normalized(hit.point, 0);
+        assert_eq!(branch, Branch::root());
+    }
 
-            depth -= flip;
-            path = path >> flip;
+    #[test]
+    fn cell_aabb() {
+        let mut octree = Octree::new();
+        octree.set((1, -2, 0, 3), Node::solid(255, 255, 255));
+
+        let transform = Mat4::from_scale_rotation_translation(
+            Vec3::splat(4.0),
+            Quat::IDENTITY,
+            Vec3::new(10.0, 0.0, -2.0),
+        );
+
+        // straight down through the center of the cell, which spans an eighth of the octree per axis
+        let ray = Ray::new(Vec3::new(11.5, 10.0, -1.5), Vec3::NEG_Y);
+        let hit = octree.raycast(transform, ray).unwrap();
+        assert_eq!(hit.branch, Branch::new(IVec3::new(1, -2, 0), 3));
+
+        let (min, max) = hit.cell_aabb(transform);
+        assert!(min.abs_diff_eq(Vec3::new(11.0, -2.0, -2.0), 1e-5));
+        assert!(max.abs_diff_eq(Vec3::new(12.0, -1.0, -1.0), 1e-5));
+
+        // the hit lies on the top face of the box
+        assert!((hit.point.y - max.y).abs() < 1e-3);
+        assert!(hit.point.cmpge(min).all() && hit.point.cmple(max).all());
+
+        // rotating the octree a quarter turn rotates the box with it
+        let rotation = Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let (min, max) = hit.cell_aabb(rotation * transform);
+        assert!(min.abs_diff_eq(Vec3::new(-2.0, -2.0, -12.0), 1e-5));
+        assert!(max.abs_diff_eq(Vec3::new(-1.0, -1.0, -11.0), 1e-5));
+    }
+
+    #[test]
+    fn raycast_inside_solid() {
+        let mut octree = Octree::new();
+        octree.set((0, 0, 0, 2), Node::solid(255, 255, 255));
+
+        let ray = Ray::new(Vec3::splat(0.25), Vec3::new(0.2, -1.0, 0.1));
+        let hit = octree.raycast_normalized(ray).unwrap();
+
+        assert_eq!(hit.branch, Branch::new(IVec3::ZERO, 2));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.normal_vec3(), Vec3::Y);
+        assert!(hit.distance < 1e-4);
+
+        // entering on an edge of the octree, between two faces
+        let solid = Octree::solid(Node::solid(255, 255, 255));
+        let ray = Ray::new(Vec3::new(2.0, 2.0, 0.5), Vec3::new(-1.0, -1.0, 0.0));
+        let hit = solid.raycast_normalized(ray).unwrap();
+
+        assert_eq!(hit.normal, IVec3::new(1, 1, 0));
+        assert!((hit.normal_vec3().length() - 1.0).abs() < 1e-6);
+        assert!(hit
+            .normal_vec3()
+            .abs_diff_eq(Vec3::new(1.0, 1.0, 0.0).normalize(), 1e-6));
+    }
 
-            parent = stack[depth as usize];
-            child = extract_child(path, 0);
+    #[test]
+    fn raycast_debug_visits() {
+        let solid = Octree::solid(Node::solid(255, 255, 255));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 4.0), Vec3::NEG_Z);
+
+        let (hit, visited) = solid.raycast_debug(Mat4::IDENTITY, ray);
+        assert!(hit.unwrap().is_root);
+        assert_eq!(visited, 1);
+
+        // a floor of small cells along the bottom of the octree
+        let mut floor = Octree::new();
+        for x in -16..16 {
+            for z in -16..16 {
+                floor.set((x, -16, z, 5), Node::solid(255, 255, 255));
+            }
         }
+
+        // straight down, the floor is hit right away
+        let ray = Ray::new(Vec3::new(0.01, 4.0, 0.01), Vec3::NEG_Y);
+        let (hit, straight) = floor.raycast_debug(Mat4::IDENTITY, ray);
+        assert!(hit.is_some());
+        assert!(straight < 16, "{straight}");
+
+        // grazing just above the floor steps through every cell along the way
+        let ray = Ray::new(Vec3::new(-1.1, -0.93, 0.01), Vec3::new(1.0, -0.005, 0.0));
+        let (hit, grazing) = floor.raycast_debug(Mat4::IDENTITY, ray);
+        assert!(hit.is_some());
+        assert!(grazing > 32, "{grazing}");
+
+        // the instrumented path finds the same hit
+        let expected = floor.raycast(Mat4::IDENTITY, ray).unwrap();
+        assert_eq!(hit.unwrap().index, expected.index);
+    }
+
+    #[test]
+    fn ray_cells() {
+        let red = Node::solid(255, 0, 0);
+        let blue = Node::solid(0, 0, 255);
+
+        // two voxels with a gap of two between them, straddling the center
+        let mut octree = Octree::new();
+        octree.set((-2, 0, 0, 3), red);
+        octree.set((1, 0, 0, 3), blue);
+
+        let transform = Mat4::from_scale(Vec3::splat(10.0));
+        let ray = Ray::new(Vec3::new(-15.0, 1.0, 1.0), Vec3::X);
+
+        let cells: Vec<_> = octree.ray_cells(transform, ray, f32::INFINITY).collect();
+        assert_eq!(
+            cells,
+            [
+                (Branch::new(IVec3::new(-2, 0, 0), 2), None),
+                (Branch::new(IVec3::new(-2, 0, 0), 3), Some(red)),
+                (Branch::new(IVec3::new(-1, 0, 0), 3), None),
+                (Branch::new(IVec3::new(0, 0, 0), 3), None),
+                (Branch::new(IVec3::new(1, 0, 0), 3), Some(blue)),
+                (Branch::new(IVec3::new(1, 0, 0), 2), None),
+            ]
+        );
+
+        // the octree is entered 5 units along the ray, and the red voxel after 10
+        let cells: Vec<_> = octree.ray_cells(transform, ray, 11.0).collect();
+        assert_eq!(cells.len(), 2);
+
+        // the first hit is the first solid cell
+        let hit = octree.raycast(transform, ray).unwrap();
+        assert_eq!(hit.branch, cells[1].0);
+    }
+
+    #[test]
+    fn raycast_from() {
+        let red = Node::solid(255, 0, 0);
+        let blue = Node::solid(0, 0, 255);
+
+        // red blocks the ray before it reaches blue in the positive octant
+        let mut octree = Octree::new();
+        octree.set((-2, 0, 0, 2), red);
+        octree.set((1, 0, 0, 2), blue);
+
+        let transform = Mat4::from_scale(Vec3::splat(10.0));
+        let ray = Ray::new(Vec3::new(-20.0, 2.5, 2.5), Vec3::X);
+
+        let hit = octree.raycast(transform, ray).unwrap();
+        assert_eq!(octree[hit.index], red);
+
+        let hit = octree.raycast_from(transform, ray, (0, 0, 0, 1)).unwrap();
+        assert_eq!(octree[hit.index], blue);
+        assert_eq!(hit.branch, Branch::new(IVec3::new(1, 0, 0), 2));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 25.0).abs() < 1e-3, "{}", hit.distance);
+        assert!((hit.point.x - 5.0).abs() < 1e-3);
+
+        // the subtree holding red, behind which the ray doesn't look
+        let hit = octree.raycast_from(transform, ray, (-1, 0, 0, 1)).unwrap();
+        assert_eq!(octree[hit.index], red);
+
+        // a ray passing beside the subtree misses it
+        let ray = Ray::new(Vec3::new(-20.0, -2.5, 2.5), Vec3::X);
+        assert!(octree.raycast_from(transform, ray, (0, 0, 0, 1)).is_none());
+
+        // a subtree within a solid leaf is hit as a whole
+        let solid = Octree::solid(red);
+        let ray = Ray::new(Vec3::new(0.3, 4.0, 0.3), Vec3::NEG_Y);
+        let hit = solid
+            .raycast_from(Mat4::IDENTITY, ray, (1, 1, 1, 3))
+            .unwrap();
+        assert!(hit.is_root);
+        assert_eq!(hit.branch, Branch::new(IVec3::ONE, 3));
+        assert!((hit.point.y - 0.5).abs() < 1e-3);
     }
 }
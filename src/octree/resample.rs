@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use glam::{IVec3, Vec3};
+
+use super::{Branch, Node, Octree};
+
+/// The solid leaves deeper than the target depth that fall within one cell.
+#[derive(Default)]
+struct Coverage {
+    /// The fraction of the cell covered by solid leaves.
+    volume: f32,
+    /// The sum of the leaf colors, weighted by their volume.
+    color: Vec3,
+    /// The leaf covering the most volume, and that volume.
+    dominant: Option<(Node, f32)>,
+}
+
+impl Coverage {
+    fn add(&mut self, node: Node, volume: f32) {
+        self.volume += volume;
+        self.color += Vec3::new(node.r() as f32, node.g() as f32, node.b() as f32) * volume;
+
+        if self.dominant.is_none_or(|(_, max)| volume > max) {
+            self.dominant = Some((node, volume));
+        }
+    }
+
+    /// Returns the merged node if at least half of the cell is covered.
+    fn node(&self) -> Option<Node> {
+        if self.volume < 0.5 {
+            return None;
+        }
+
+        let (dominant, _) = self.dominant?;
+        let color = (self.color / self.volume).round();
+
+        let mut node = Node::solid(color.x as u8, color.y as u8, color.z as u8);
+        node.flags = dominant.flags;
+
+        Some(node)
+    }
+}
+
+impl Octree {
+    /// Rebuild the octree with leaves no deeper than `new_depth`.
+    ///
+    /// Leaves at or above `new_depth` are kept as they are, a leaf covers the same
+    /// cells at any finer depth, so upsampling doesn't change the tree.
+    /// Leaves below `new_depth` are merged into the cell containing them, which becomes
+    /// solid if at least half of it is covered, with the average color of the leaves.
+    pub fn resample(&self, new_depth: u32) -> Octree {
+        let mut octree = Octree::new();
+        octree.merge_leaves = self.merge_leaves;
+
+        let mut cells: HashMap<IVec3, Coverage> = HashMap::new();
+
+        for (branch, &node) in self.iter_nodes() {
+            if branch.depth <= new_depth {
+                octree.set(branch, node);
+                continue;
+            }
+
+            let levels = branch.depth - new_depth;
+            let path = match new_depth {
+                0 => IVec3::ZERO,
+                _ => branch.path >> levels as i32,
+            };
+
+            let volume = 1.0 / (1u64 << (3 * levels)) as f32;
+            cells.entry(path).or_default().add(node, volume);
+        }
+
+        for (path, coverage) in cells {
+            if let Some(node) = coverage.node() {
+                octree.set(Branch::new(path, new_depth), node);
+            }
+        }
+
+        octree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::Sphere;
+
+    #[test]
+    fn upsample() {
+        let sphere = Octree::generate(&Sphere::new(16, 5));
+        let upsampled = sphere.resample(7);
+
+        assert_eq!(upsampled.count_solid(7), sphere.count_solid(7));
+        assert_eq!(upsampled.count_solid(5), sphere.count_solid(5));
+    }
+
+    #[test]
+    fn downsample() {
+        let fine = Octree::generate(&Sphere::new(32, 6));
+        let coarse = Octree::generate(&Sphere::new(8, 4));
+
+        let downsampled = fine.resample(4);
+
+        // no leaves are left below the new depth
+        assert!(downsampled
+            .iter_nodes()
+            .all(|(branch, _)| branch.depth <= 4));
+
+        let expected = coarse.count_solid(4) as f32;
+        let count = downsampled.count_solid(4) as f32;
+        assert!(
+            (count - expected).abs() / expected < 0.1,
+            "{count} != {expected}"
+        );
+
+        // a single deep cell covers too little to survive
+        let mut speck = Octree::new();
+        speck.set((5, 5, 5, 6), Node::solid(255, 0, 0));
+        assert!(speck.resample(4).is_empty());
+
+        // half of a cell does
+        let mut half = Octree::new();
+        for x in 0..2 {
+            for y in 0..2 {
+                half.set((x, y, 0, 5), Node::solid(255, 0, 0));
+            }
+        }
+        assert_eq!(half.resample(4).get((0, 0, 0, 4)), Node::solid(255, 0, 0));
+    }
+}
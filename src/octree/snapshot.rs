@@ -0,0 +1,60 @@
+use std::mem;
+
+use super::{for_each_stamped, Branch, Node, Octree};
+
+/// The nodes of some regions before an edit, see [`Octree::snapshot_region`].
+///
+/// Each region is stored as `(region, None)` followed by the solid leaves within it,
+/// so replaying the changes in order clears the region and puts the leaves back.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegionSnapshot {
+    pub changes: Vec<(Branch, Option<Node>)>,
+}
+
+impl RegionSnapshot {
+    /// Returns the memory used by the changes in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.changes.len() * mem::size_of::<(Branch, Option<Node>)>()
+    }
+}
+
+/// Returns the cells [`Octree::union`] and friends write when stamping `other`
+/// at `branch`, the regions to snapshot before the edit.
+///
+/// Cells outside the bounds of the octree are left out.
+///
+/// # Panics
+///
+/// Like [`Octree::union`].
+pub fn stamp_regions(branch: impl Into<Branch>, depth: u32, other: &Octree) -> Vec<Branch> {
+    let mut regions = Vec::new();
+    for_each_stamped(branch.into(), depth, other, |branch, _| {
+        if branch.is_in_bounds() {
+            regions.push(branch);
+        }
+    });
+
+    regions
+}
+
+impl Octree {
+    /// Record the leaves within `region`, to be put back with [`Octree::restore_region`].
+    pub fn snapshot_region(&self, region: impl Into<Branch>) -> RegionSnapshot {
+        self.snapshot_regions([region.into()])
+    }
+
+    /// Record the leaves within every one of `regions`, which must not overlap.
+    pub fn snapshot_regions(&self, regions: impl IntoIterator<Item = Branch>) -> RegionSnapshot {
+        let mut changes = Vec::new();
+
+        for region in regions {
+            changes.push((region, None));
+
+            for (branch, &node) in self.iter_subtree(region) {
+                changes.push((branch, Some(node)));
+            }
+        }
+
+        RegionSnapshot { changes }
+    }
+}
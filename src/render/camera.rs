@@ -1,17 +1,25 @@
-use std::{f32::consts::FRAC_2_PI, mem};
+use std::{
+    f32::consts::{FRAC_2_PI, FRAC_PI_2},
+    mem,
+};
 
 use bytemuck::{Pod, Zeroable};
 use glam::{EulerRot, Mat4, Quat, Vec2, Vec3, Vec4, Vec4Swizzles};
+use serde::{Deserialize, Serialize};
 use winit::event::MouseButton;
 
-use crate::{app::UpdateContext, input::Key, ray::Ray};
+use crate::{app::UpdateContext, error::OakumError, input::Key, ray::Ray};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Camera {
     pub position: Vec3,
     pub distance: f32,
     pub rotation: Vec3,
     pub fov: f32,
+    /// Look with the mouse without holding the middle button,
+    /// used while the cursor is grabbed.
+    #[serde(skip)]
+    pub free_look: bool,
 }
 
 impl Default for Camera {
@@ -21,17 +29,27 @@ impl Default for Camera {
             distance: 4.0,
             rotation: Vec3::new(-FRAC_2_PI, FRAC_2_PI, 0.0),
             fov: 60.0,
+            free_look: false,
         }
     }
 }
 
 impl Camera {
+    /// The distance to the near clip plane.
+    pub const NEAR: f32 = 0.01;
+    /// The distance to the far clip plane.
+    pub const FAR: f32 = 100.0;
+    /// The smallest radius [`Camera::frame`] fits, so flat or degenerate bounds
+    /// don't put the eye on the pivot.
+    pub const MIN_FRAME_RADIUS: f32 = 1e-3;
+
     pub fn new(position: Vec3, distance: f32, fov: f32) -> Self {
         Self {
             position,
             distance,
             rotation: Vec3::new(-FRAC_2_PI, FRAC_2_PI, 0.0),
             fov,
+            free_look: false,
         }
     }
 
@@ -44,8 +62,45 @@ impl Camera {
         )
     }
 
+    /// Returns the position of the eye, orbiting [`Camera::position`] at [`Camera::distance`].
+    pub fn eye(&self) -> Vec3 {
+        self.rotation_quat() * Vec3::new(0.0, 0.0, self.distance) + self.position
+    }
+
+    /// Turn the camera to face `target` without moving the eye.
+    ///
+    /// The orbit pivot moves to `target`, so orbiting afterwards circles it.
+    pub fn look_at(&mut self, target: Vec3) {
+        let eye = self.eye();
+        let offset = target - eye;
+
+        let Some(direction) = offset.try_normalize() else {
+            return;
+        };
+
+        // forward is -Z rotated by pitch around X, then yaw around Y
+        let yaw = f32::atan2(-direction.x, -direction.z);
+        let pitch = direction.y.clamp(-1.0, 1.0).asin();
+
+        self.rotation = Vec3::new(pitch, yaw, 0.0);
+        self.position = target;
+        self.distance = offset.length();
+    }
+
+    /// Orbit `center` at the distance where a sphere of `radius` fits the fov,
+    /// keeping the rotation.
+    ///
+    /// The radius is at least [`Camera::MIN_FRAME_RADIUS`], also when it is nan.
+    pub fn frame(&mut self, center: Vec3, radius: f32) {
+        let radius = radius.max(Self::MIN_FRAME_RADIUS);
+        let half_fov = (self.fov.to_radians() / 2.0).clamp(0.01, FRAC_PI_2);
+
+        self.position = center;
+        self.distance = radius / half_fov.sin();
+    }
+
     pub fn update(&mut self, cx: UpdateContext) {
-        if cx.mouse.is_held(MouseButton::Middle) {
+        if self.free_look || cx.mouse.is_held(MouseButton::Middle) {
             self.rotation.y -= cx.mouse.delta.x * 0.003;
             self.rotation.x -= cx.mouse.delta.y * 0.003;
         }
@@ -78,7 +133,7 @@ impl Camera {
             movement += Vec3::Y;
         }
 
-        if cx.keyboard.is_held(Key::LShift) {
+        if cx.keyboard.shift() {
             movement -= Vec3::Y;
         }
 
@@ -87,27 +142,35 @@ impl Camera {
     }
 
     pub fn view(&self) -> Mat4 {
-        let rotation = self.rotation_quat();
-        let position = rotation * Vec3::new(0.0, 0.0, self.distance) + self.position;
-        Mat4::from_rotation_translation(rotation, position)
+        Mat4::from_rotation_translation(self.rotation_quat(), self.eye())
     }
 
     pub fn proj(&self, aspect: f32) -> Mat4 {
-        Mat4::perspective_rh(self.fov.to_radians(), aspect, 0.01, 100.0)
+        Mat4::perspective_rh(self.fov.to_radians(), aspect, Self::NEAR, Self::FAR)
     }
 
     pub fn view_proj(&self, aspect: f32) -> Mat4 {
         self.proj(aspect) * self.view().inverse()
     }
 
+    /// Cast a ray through `position` in pixels of a `width` by `height` view,
+    /// with the origin in the top left corner.
     pub fn mouse_ray(&self, width: u32, height: u32, position: Vec2) -> Ray {
-        let inv = self.view_proj(width as f32 / height as f32).inverse();
-
         let x = position.x / width as f32 * 2.0 - 1.0;
         let y = position.y / height as f32 * -2.0 + 1.0;
 
-        let near = inv * Vec4::new(x, y, 0.0, 1.0);
-        let far = inv * Vec4::new(x, y, 1.0, 1.0);
+        self.ray_from_ndc(Vec2::new(x, y), width as f32 / height as f32)
+    }
+
+    /// Cast a ray through `ndc` in normalized device coordinates, where `(-1, -1)`
+    /// is the bottom left corner and `(1, 1)` the top right.
+    ///
+    /// The ray starts on the near plane.
+    pub fn ray_from_ndc(&self, ndc: Vec2, aspect: f32) -> Ray {
+        let inv = self.view_proj(aspect).inverse();
+
+        let near = inv * Vec4::new(ndc.x, ndc.y, 0.0, 1.0);
+        let far = inv * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
 
         let origin = near.xyz() / near.w;
         let direction = (far.xyz() / far.w - origin).normalize_or_zero();
@@ -132,7 +195,7 @@ pub struct DrawCamera {
 }
 
 impl DrawCamera {
-    pub fn new(device: &wgpu::Device) -> anyhow::Result<Self> {
+    pub fn new(device: &wgpu::Device) -> Result<Self, OakumError> {
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Camera Buffer"),
             size: mem::size_of::<CameraData>() as u64,
@@ -163,3 +226,55 @@ impl DrawCamera {
         queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&data));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn look_at() {
+        let targets = [
+            Vec3::new(3.0, -2.0, 1.0),
+            Vec3::new(-5.0, 4.0, -0.5),
+            Vec3::new(0.0, 0.0, -10.0),
+            Vec3::new(0.1, 7.0, 0.0),
+        ];
+
+        for target in targets {
+            let mut camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), 4.0, 60.0);
+            let eye = camera.eye();
+
+            camera.look_at(target);
+
+            let forward = camera.rotation_quat() * Vec3::NEG_Z;
+            let expected = (target - eye).normalize();
+            assert!(forward.distance(expected) < 1e-4, "{forward} != {expected}");
+
+            // the eye stays put and the pivot moves to the target
+            assert!(camera.eye().distance(eye) < 1e-4);
+            assert!(camera.position.distance(target) < 1e-4);
+        }
+    }
+
+    #[test]
+    fn ray_from_ndc() {
+        let mut camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), 4.0, 60.0);
+        camera.look_at(Vec3::new(-2.0, 0.5, -4.0));
+
+        let forward = camera.rotation_quat() * Vec3::NEG_Z;
+
+        for aspect in [1.0, 16.0 / 9.0, 0.5] {
+            let ray = camera.ray_from_ndc(Vec2::ZERO, aspect);
+
+            assert!(ray.direction.distance(forward) < 1e-4);
+            assert!((ray.origin - camera.eye()).cross(forward).length() < 1e-3);
+        }
+
+        // the center pixel casts the same ray
+        let ray = camera.mouse_ray(800, 600, Vec2::new(400.0, 300.0));
+        assert!(ray.direction.distance(forward) < 1e-4);
+
+        let up = camera.ray_from_ndc(Vec2::Y, 1.0);
+        assert!(up.direction.dot(camera.rotation_quat() * Vec3::Y) > 0.0);
+    }
+}
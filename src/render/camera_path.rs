@@ -0,0 +1,189 @@
+use std::{fs, path::Path};
+
+use glam::{EulerRot, Vec3};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "headless")]
+use crate::{error::OakumError, render::Renderer, world::World};
+use crate::{format::FormatError, render::Camera};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// The time of the keyframe in seconds from the start of the path.
+    pub time: f32,
+    pub camera: Camera,
+}
+
+/// A camera fly-through, keyframes sampled with smooth interpolation.
+///
+/// The pivot follows a catmull-rom spline through the keyframes, the rotation
+/// is slerped, the distance and fov are interpolated linearly.
+/// Paths are stored as toml, like the config and sessions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CameraPath {
+    /// The keyframes, sorted by time.
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub const PATH: &str = "oakum_path.toml";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a keyframe, keeping the keyframes sorted by time.
+    pub fn push(&mut self, time: f32, camera: Camera) {
+        let index = self.keyframes.partition_point(|k| k.time <= time);
+        self.keyframes.insert(index, Keyframe { time, camera });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Returns the time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Returns the camera at `time`, clamped to the first and last keyframes,
+    /// or `None` if the path is empty.
+    pub fn sample(&self, time: f32) -> Option<Camera> {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len().checked_sub(1)?;
+
+        // the keyframe starting the segment `time` lies in
+        let i = keyframes.partition_point(|k| k.time <= time);
+        let i = i.saturating_sub(1).min(last.saturating_sub(1));
+        let j = (i + 1).min(last);
+
+        let (a, b) = (&keyframes[i], &keyframes[j]);
+        let span = b.time - a.time;
+        let t = match span > 0.0 {
+            true => ((time - a.time) / span).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+
+        // the endpoints are repeated where the path has no neighbor
+        let before = &keyframes[i.saturating_sub(1)].camera;
+        let after = &keyframes[(j + 1).min(last)].camera;
+
+        let (a, b) = (&a.camera, &b.camera);
+        let rotation = a.rotation_quat().slerp(b.rotation_quat(), t);
+        let (yaw, pitch, roll) = rotation.to_euler(EulerRot::YXZ);
+
+        Some(Camera {
+            position: catmull_rom(before.position, a.position, b.position, after.position, t),
+            distance: a.distance + (b.distance - a.distance) * t,
+            rotation: Vec3::new(pitch, yaw, roll),
+            fov: a.fov + (b.fov - a.fov) * t,
+            free_look: false,
+        })
+    }
+
+    /// Returns the camera of every frame at `fps` frames per second,
+    /// from the first keyframe to the last.
+    pub fn frames(&self, fps: u32) -> impl Iterator<Item = Camera> + '_ {
+        let start = self.keyframes.first().map_or(0.0, |k| k.time);
+        let count = match self.is_empty() {
+            true => 0,
+            false => ((self.duration() - start) * fps as f32).floor() as u32 + 1,
+        };
+
+        (0..count).filter_map(move |frame| self.sample(start + frame as f32 / fps as f32))
+    }
+
+    /// Render every frame of the path at `fps` frames per second with `renderer`,
+    /// saving them in `out` as `frame_00000.png` onwards, returns the number of frames.
+    ///
+    /// Taa is disabled, it would blend each frame with the previous ones.
+    #[cfg(feature = "headless")]
+    pub fn render(
+        &self,
+        renderer: &mut Renderer,
+        world: &mut World,
+        fps: u32,
+        out: impl AsRef<Path>,
+    ) -> Result<u32, OakumError> {
+        let out = out.as_ref();
+        fs::create_dir_all(out)?;
+        renderer.set_taa_samples(1)?;
+
+        let mut count = 0;
+        for camera in self.frames(fps) {
+            world.camera = camera;
+            renderer.render_frame(world)?;
+            renderer.save_png(out.join(format!("frame_{:05}.png", count)))?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FormatError> {
+        let toml =
+            toml::to_string(self).map_err(|e| FormatError::InvalidCameraPath(e.to_string()))?;
+        fs::write(path, toml)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        let toml = fs::read_to_string(path)?;
+        toml::from_str(&toml).map_err(|e| FormatError::InvalidCameraPath(e.to_string()))
+    }
+}
+
+/// Interpolate between `b` and `c` at `t`, with `a` and `d` shaping the tangents.
+fn catmull_rom(a: Vec3, b: Vec3, c: Vec3, d: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    0.5 * (2.0 * b
+        + (c - a) * t
+        + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+        + (3.0 * b - a - 3.0 * c + d) * t3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_keyframe_midpoint() {
+        let mut start = Camera::new(Vec3::new(-2.0, 0.0, 1.0), 4.0, 60.0);
+        start.rotation = Vec3::new(-0.4, 0.2, 0.0);
+
+        let mut end = Camera::new(Vec3::new(6.0, 2.0, -3.0), 8.0, 90.0);
+        end.rotation = Vec3::new(0.2, 1.4, 0.0);
+
+        let mut path = CameraPath::new();
+        path.push(3.0, end.clone());
+        path.push(1.0, start.clone());
+        assert_eq!(path.duration(), 3.0);
+
+        let mid = path.sample(2.0).unwrap();
+
+        // the spline through two keyframes passes straight between them
+        let halfway = (start.position + end.position) / 2.0;
+        assert!(mid.position.distance(halfway) < 1e-4);
+        assert!((mid.distance - 6.0).abs() < 1e-4);
+        assert!((mid.fov - 75.0).abs() < 1e-4);
+
+        let to_start = mid.rotation_quat().angle_between(start.rotation_quat());
+        let to_end = mid.rotation_quat().angle_between(end.rotation_quat());
+        assert!(to_start > 0.1 && (to_start - to_end).abs() < 1e-3);
+
+        // outside the path the ends are held
+        assert!(path.sample(0.0).unwrap().position.distance(start.position) < 1e-4);
+        assert!(path.sample(5.0).unwrap().position.distance(end.position) < 1e-4);
+        assert!(CameraPath::new().sample(0.0).is_none());
+
+        assert_eq!(path.frames(30).count(), 61);
+
+        let loaded: CameraPath = toml::from_str(&toml::to_string(&path).unwrap()).unwrap();
+        assert_eq!(loaded.keyframes.len(), 2);
+        assert_eq!(loaded.keyframes[1].camera.rotation, end.rotation);
+    }
+}
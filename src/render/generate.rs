@@ -0,0 +1,405 @@
+use std::{mem, num::NonZeroU32, sync::mpsc};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    error::OakumError,
+    generate::Sphere,
+    octree::{Branch, OccupancyGrid, Octree, PackedNode, Palette},
+    render::{catch_validation_errors, open_shader, DrawOctree, OctreePipeline},
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct GenerateUniform {
+    /// The depth of the leaves.
+    pub depth: u32,
+    pub page_height: u32,
+    pub resolution: u32,
+    pub radius: f32,
+    /// The packed node of solid leaves.
+    pub solid: u32,
+    pub padding: [u32; 3],
+}
+
+/// Generates octrees on the gpu, writing the nodes straight into the octree
+/// texture of a [`DrawOctree`], instead of generating and uploading them on the cpu.
+///
+/// The generated tree is complete, every node above the leaves is a parent,
+/// so the index of every node is known up front. This takes up far more nodes
+/// than [`Octree::generate`], which stays the reference for what gets generated.
+pub struct GeneratePipeline {
+    pub layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::ComputePipeline,
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+impl GeneratePipeline {
+    /// The deepest leaves can be, a complete tree one level deeper takes up over 600Mb.
+    pub const MAX_DEPTH: u32 = 8;
+
+    pub const WORKGROUP_SIZE: u32 = 64;
+
+    pub fn new(device: &wgpu::Device) -> Result<Self, OakumError> {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Generate Bind Group Layout"),
+            entries: &[
+                // octree
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: DrawOctree::TEXTURE_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+                // generate uniform
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Generate Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Generate Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &open_shader(device, "assets/shaders/generate.wgsl")?,
+            entry_point: "main",
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Generate Uniform Buffer"),
+            size: mem::size_of::<GenerateUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            layout,
+            pipeline,
+            uniform_buffer,
+        })
+    }
+
+    /// Returns the number of nodes in a complete tree with leaves at `depth`.
+    pub const fn node_count(depth: u32) -> u64 {
+        ((1 << (3 * (depth + 1))) - 1) / 7
+    }
+}
+
+impl DrawOctree {
+    /// Generate `sphere` into the texture with `generate`, returns the number of nodes.
+    ///
+    /// The dispatch is recorded into `encoder`. Whatever the texture held is
+    /// replaced, so it no longer mirrors any octree, and is marked
+    /// [`stale`](DrawOctree::stale) until the next change uploads a whole octree.
+    ///
+    /// The first call recreates the texture with [`DrawOctree::GENERATE_TEXTURE_USAGE`],
+    /// returning [`OakumError::UnsupportedOctreeFormat`] if the adapter can't store to it.
+    pub fn generate_sphere(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &OctreePipeline,
+        generate: &GeneratePipeline,
+        sphere: &Sphere,
+    ) -> Result<u32, OakumError> {
+        if sphere.depth > GeneratePipeline::MAX_DEPTH {
+            return Err(OakumError::DepthTooLarge {
+                depth: sphere.depth,
+                max: GeneratePipeline::MAX_DEPTH,
+            });
+        }
+
+        if !self.usage.contains(DrawOctree::GENERATE_TEXTURE_USAGE) {
+            let usage = DrawOctree::GENERATE_TEXTURE_USAGE;
+            let texture = catch_validation_errors(device, || {
+                Ok(Self::create_texture(
+                    device,
+                    self.page_height,
+                    self.page_count,
+                    usage,
+                ))
+            })
+            .map_err(|_| OakumError::UnsupportedOctreeFormat(Self::TEXTURE_FORMAT))?;
+
+            self.usage = usage;
+            self.set_texture(device, pipeline, texture);
+        }
+
+        let len = GeneratePipeline::node_count(sphere.depth);
+        self.resize(device, encoder, pipeline, len, false);
+
+        self.packed.clear();
+        self.palette = Palette::new();
+        self.palette_written = 0;
+        self.pending.clear();
+        self.stale = true;
+
+        let solid = self.palette.pack(sphere.color);
+
+        // the generated leaves aren't packed on the cpu, one stands for all of them
+        self.translucent_leaves = solid.is_translucent() as usize;
+        self.write_palette(device, queue, pipeline);
+
+        // the sphere fills a ball in the middle of the bounds, grown by the diagonal
+        // of a leaf since leaves are sampled at their centers
+        match sphere.depth {
+            0 => self.occupancy.mark(Branch::root()),
+            depth => {
+                let half = (1u32 << (depth - 1)) as f32;
+                let radius = sphere.radius * sphere.resolution as f32 / half;
+                self.occupancy = OccupancyGrid::new(self.occupancy.depth());
+                self.occupancy.mark_ball(radius + 3f32.sqrt() / half);
+            }
+        }
+        queue.write_buffer(&self.occupancy_buffer, 0, self.occupancy.bytes());
+
+        let uniform = GenerateUniform {
+            depth: sphere.depth,
+            page_height: self.page_height,
+            resolution: sphere.resolution,
+            radius: sphere.radius,
+            solid: solid.0,
+            padding: [0; 3],
+        };
+        queue.write_buffer(&generate.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let view = self.texture.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Generate Bind Group"),
+            layout: &generate.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: generate.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // dispatches are limited to 65535 workgroups along each axis
+        let workgroups = len.div_ceil(GeneratePipeline::WORKGROUP_SIZE as u64);
+        let x = workgroups.min(u16::MAX as u64);
+        let y = workgroups.div_ceil(x);
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Generate Pass"),
+        });
+
+        pass.set_pipeline(&generate.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(x as u32, y as u32, 1);
+
+        Ok(len as u32)
+    }
+
+    /// Read back the first `len` nodes of the texture into an octree.
+    ///
+    /// This waits for the gpu, so it is meant for tests and tools.
+    pub fn read_octree(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        len: u32,
+    ) -> Result<Octree, OakumError> {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Octree Readback"),
+            size: self.byte_size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.bytes_per_row()),
+                    rows_per_image: NonZeroU32::new(self.page_height),
+                },
+            },
+            wgpu::Extent3d {
+                width: Self::PAGE_SIZE,
+                height: self.page_height,
+                depth_or_array_layers: self.page_count,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|_| wgpu::BufferAsyncError)??;
+
+        let bytes = slice.get_mapped_range();
+        let packed: &[PackedNode] = bytemuck::cast_slice(&bytes);
+
+        let mut octree = Octree::new();
+        octree.nodes = (packed[..len as usize].iter())
+            .map(|&node| self.palette.unpack(node))
+            .collect();
+
+        Ok(octree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::*;
+    use crate::{
+        octree::Node,
+        render::{
+            tests::{headless, sphere_world},
+            RenderMode,
+        },
+        world::World,
+    };
+
+    fn solid_cells(octree: &Octree, depth: u32) -> Vec<(IVec3, Node)> {
+        let mut cells: Vec<_> = octree.iter_solid_cells(depth).collect();
+        cells.sort_by_key(|&(cell, _)| (cell.x, cell.y, cell.z));
+        cells
+    }
+
+    #[test]
+    fn sphere_matches_cpu() {
+        let Some(mut renderer) = headless(1, 1) else {
+            return;
+        };
+
+        let spheres = [
+            Sphere::new(8, 4),
+            Sphere::new(5, 4).with_radius(0.8),
+            Sphere::new(16, 5).with_color(Node::emissive(255, 128, 0)),
+        ];
+
+        for sphere in spheres {
+            let len = renderer.generate_sphere(&sphere).unwrap();
+            assert_eq!(len as u64, GeneratePipeline::node_count(sphere.depth));
+
+            let draw_octree = &renderer.octree_phase.draw_octree;
+            let gpu = draw_octree
+                .read_octree(&renderer.device, &renderer.queue, len)
+                .unwrap();
+            let cpu = Octree::generate(&sphere);
+
+            let cells = solid_cells(&gpu, sphere.depth);
+            assert!(!cells.is_empty());
+            assert!(cells == solid_cells(&cpu, sphere.depth));
+        }
+
+        assert!(matches!(
+            renderer.generate_sphere(&Sphere::new(1, GeneratePipeline::MAX_DEPTH + 1)),
+            Err(OakumError::DepthTooLarge { depth: 9, max: 8 })
+        ));
+    }
+
+    #[test]
+    fn edit_replaces_generated() {
+        let blue = Node::solid(0, 0, 255);
+        let mut edited = sphere_world();
+        edited.octree.set((0, 3, 0, 3), blue);
+
+        // what the edited world looks like uploaded from scratch, one renderer at a time
+        let expected = {
+            let Some(mut fresh) = headless(64, 64) else {
+                return;
+            };
+            fresh.set_taa_samples(1).unwrap();
+            fresh.render_frame(&edited).unwrap();
+            fresh.read_target().unwrap()
+        };
+
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let mut world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+
+        let sphere = Sphere::new(16, 5).with_color(Node::solid(255, 0, 0));
+        renderer.generate_sphere(&sphere).unwrap();
+        assert!(renderer.octree_phase.draw_octree.stale);
+
+        // a small edit uploads the whole world octree over the generated sphere
+        world.octree.set((0, 3, 0, 3), blue);
+        renderer.render_frame(&world).unwrap();
+
+        assert!(!renderer.octree_phase.draw_octree.stale);
+        assert!(renderer.read_target().unwrap() == expected);
+    }
+
+    #[test]
+    fn generated_skips_empty() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let world = World::new();
+        renderer.render_frame(&world).unwrap();
+
+        // a small sphere deep in a complete tree, which keeps every empty parent around it
+        renderer.generate_sphere(&Sphere::new(8, 7)).unwrap();
+
+        let mut render = |mode, skip_empty| {
+            renderer.render_mode = mode;
+            renderer.octree_phase.draw_octree.skip_empty = skip_empty;
+            renderer.render_frame(&world).unwrap();
+            renderer.read_target().unwrap()
+        };
+
+        // the red of the heatmap is the steps of each pixel, up to `HEATMAP_STEPS` of 128
+        let steps = |image: Vec<u8>| -> Vec<u32> {
+            let red = image.chunks(4).map(|pixel| pixel[0] as u32 * 128 / 255);
+            red.collect()
+        };
+        let skipped = steps(render(RenderMode::Steps, true));
+        let walked = steps(render(RenderMode::Steps, false));
+
+        // walking the tree leaf by leaf takes 128 steps or more for most rays, with the
+        // grid none do, and fewer than half as many are taken in total
+        let capped = |steps: &[u32]| steps.iter().filter(|&&steps| steps == 128).count();
+        assert!(capped(&walked) > walked.len() / 2, "{}", capped(&walked));
+        assert_eq!(capped(&skipped), 0);
+
+        let (skipped_sum, walked_sum) = (skipped.iter().sum::<u32>(), walked.iter().sum::<u32>());
+        assert!(skipped_sum * 2 < walked_sum, "{skipped_sum} {walked_sum}");
+        assert!(skipped
+            .iter()
+            .zip(&walked)
+            .all(|(skipped, walked)| skipped <= walked));
+
+        // skipping doesn't change what is hit
+        assert!(render(RenderMode::Color, true) == render(RenderMode::Color, false));
+    }
+}
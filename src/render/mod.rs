@@ -1,22 +1,35 @@
 mod camera;
+mod camera_path;
+mod generate;
 mod phase;
+mod pick;
 mod shader;
+mod timing;
 
-use std::num::NonZeroU32;
+use std::{cell::Cell, fmt, num::NonZeroU32, sync::mpsc};
 
 pub use camera::*;
+pub use camera_path::*;
+pub use generate::*;
 pub use phase::*;
+pub use pick::*;
 pub use shader::*;
+pub use timing::*;
 
-use anyhow::bail;
+use glam::{Mat4, UVec2, Vec3};
+use log::error;
 
-use crate::world::World;
+use crate::{error::OakumError, generate::Sphere, world::World};
 
+/// # Safety
+///
+/// `window` must outlive the returned surface, see [`wgpu::Instance::create_surface`].
 pub async unsafe fn init_wgpu_async(
     window: &winit::window::Window,
-) -> anyhow::Result<(wgpu::Surface, wgpu::Device, wgpu::Queue)> {
+    backends: wgpu::Backends,
+) -> Result<(wgpu::Surface, wgpu::Device, wgpu::Queue), OakumError> {
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::PRIMARY,
+        backends,
         ..Default::default()
     });
     let surface = instance.create_surface(window)?;
@@ -28,17 +41,90 @@ pub async unsafe fn init_wgpu_async(
             force_fallback_adapter: false,
         })
         .await
-        .ok_or(anyhow::anyhow!("No suitable adapter found"))?;
+        .ok_or(OakumError::NoAdapter)?;
+
+    let formats = surface.get_capabilities(&adapter).formats;
+    let features = adapter.get_texture_format_features(Renderer::TARGET_FORMAT);
+
+    if !formats.contains(&Renderer::TARGET_FORMAT)
+        || !features.allowed_usages.contains(Renderer::SURFACE_USAGE)
+    {
+        return Err(OakumError::UnsupportedSurface);
+    }
+
+    let (device, queue) = request_device(&adapter).await?;
+
+    Ok((surface, device, queue))
+}
+
+/// Run `f`, returning any validation error wgpu reports while it runs.
+///
+/// Shader compile errors aren't returned by wgpu directly, this catches them
+/// instead of reporting them from the next frame.
+pub fn catch_validation_errors<T>(
+    device: &wgpu::Device,
+    f: impl FnOnce() -> Result<T, OakumError>,
+) -> Result<T, OakumError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = f();
+
+    if let Some(error) = hyena::block_on(device.pop_error_scope()) {
+        return Err(OakumError::Wgpu(error.to_string()));
+    }
+
+    result
+}
+
+/// # Safety
+///
+/// `window` must outlive the returned surface, see [`wgpu::Instance::create_surface`].
+pub unsafe fn init_wgpu(
+    window: &winit::window::Window,
+    backends: wgpu::Backends,
+) -> Result<(wgpu::Surface, wgpu::Device, wgpu::Queue), OakumError> {
+    hyena::block_on(init_wgpu_async(window, backends))
+}
+
+/// Initialize wgpu without a window, any backend is accepted.
+pub async fn init_wgpu_headless_async() -> Result<(wgpu::Device, wgpu::Queue), OakumError> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        })
+        .await
+        .ok_or(OakumError::NoAdapter)?;
+
+    request_device(&adapter).await
+}
+
+pub fn init_wgpu_headless() -> Result<(wgpu::Device, wgpu::Queue), OakumError> {
+    hyena::block_on(init_wgpu_headless_async())
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), OakumError> {
+    DrawOctree::check_format_support(
+        &adapter.get_texture_format_features(DrawOctree::TEXTURE_FORMAT),
+    )?;
 
     let (device, queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                features: wgpu::Features::empty(),
+                // timestamps are optional, see `GpuTimer`
+                features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
                 limits: wgpu::Limits {
                     max_texture_dimension_1d: 4096,
                     max_texture_dimension_2d: 4096,
-                    max_texture_dimension_3d: 4096,
+                    max_texture_dimension_3d: DrawOctree::PAGE_SIZE,
                     ..wgpu::Limits::default()
                 },
             },
@@ -46,20 +132,41 @@ pub async unsafe fn init_wgpu_async(
         )
         .await?;
 
-    Ok((surface, device, queue))
+    Ok((device, queue))
 }
 
-pub unsafe fn init_wgpu(
-    window: &winit::window::Window,
-) -> anyhow::Result<(wgpu::Surface, wgpu::Device, wgpu::Queue)> {
-    hyena::block_on(init_wgpu_async(window))
+/// What the final image shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// The tonemapped scene.
+    #[default]
+    Color,
+    /// The linearized depth buffer, from black at the camera to white at the far plane.
+    Depth,
+    /// The traversal steps of the ray through each pixel as a heatmap, from blue
+    /// for few steps to red for many, not tonemapped, see [`Octree::raycast_debug`](crate::octree::Octree::raycast_debug).
+    Steps,
+    /// The normal of the surface through each pixel, unlit and not tonemapped, with each
+    /// axis mapped from `-1..1` to `0..1`, so a face pointing along +X is `(1.0, 0.5, 0.5)`.
+    Normals,
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Color => write!(f, "color"),
+            Self::Depth => write!(f, "depth"),
+            Self::Steps => write!(f, "steps"),
+            Self::Normals => write!(f, "normals"),
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct RenderContext<'a> {
     pub device: &'a wgpu::Device,
     pub queue: &'a wgpu::Queue,
-    pub surface: &'a wgpu::Surface,
+    pub surface: Option<&'a wgpu::Surface>,
     pub texture: &'a wgpu::Texture,
     pub view: &'a wgpu::TextureView,
     pub hdr_texture: &'a wgpu::Texture,
@@ -72,38 +179,156 @@ pub struct RenderContext<'a> {
     pub height: u32,
     pub taa_sample: u32,
     pub taa_samples: u32,
+    /// The scene time of `world` in seconds.
+    pub time: f32,
+    pub aa_enabled: bool,
+    /// The depth of the ground grid, `None` if it is hidden.
+    pub grid: Option<u32>,
+    pub render_mode: RenderMode,
+    pub fog_color: Vec3,
+    pub fog_density: f32,
+    pub ambient_color: Vec3,
+    /// The color of pixels where no voxel is hit.
+    pub clear_color: wgpu::Color,
+    pub clear_depth: f32,
 }
 
 pub struct Renderer {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface,
+    /// The window surface, `None` when rendering headless.
+    pub surface: Option<wgpu::Surface>,
+    /// The size and format of the final image, also used when rendering headless.
     pub surface_config: wgpu::SurfaceConfiguration,
+    /// The texture rendered to when there is no surface.
+    pub target: Option<wgpu::Texture>,
     pub needs_configure: bool,
+    /// Set while the window has a zero size, nothing is rendered until it is resized.
+    pub minimized: bool,
     pub hdr_texture: wgpu::Texture,
     pub depth_texture: wgpu::Texture,
     pub camera: DrawCamera,
     pub octree_phase: OctreePhase,
     pub tonemap_phase: TonemapPhase,
+    pub accumulate_phase: AccumulatePhase,
+    /// Created by the first [`Renderer::generate_sphere`].
+    pub generate_pipeline: Option<GeneratePipeline>,
+    /// Created by the first [`Renderer::pick`].
+    pub pick_pipeline: Option<PickPipeline>,
     pub taa_sample: u32,
     pub taa_samples: u32,
+    /// Soften voxel edges by estimating their coverage of each pixel, off by default.
+    ///
+    /// Pixels on the edge of a face are blended with what shows through across
+    /// the edge, another face or the background. This is much cheaper than more
+    /// taa samples, but only affects the edges of faces.
+    pub aa_enabled: bool,
+    /// Outline the cells at [`Renderer::grid_depth`] where the view hits the y = 0
+    /// plane without hitting any voxels, off by default.
+    pub grid_enabled: bool,
+    pub grid_depth: u32,
+    pub render_mode: RenderMode,
+    /// Average every frame since the view last changed instead of the last
+    /// [`Renderer::taa_samples`], off by default.
+    ///
+    /// Each frame is jittered differently, so a still view converges on a fully
+    /// antialiased image. Moving the camera or the world transform, or editing the
+    /// octree, preview or chunks, starts over. Other changes don't, like to the lights,
+    /// call [`Renderer::reset_accumulation`] for those.
+    pub accumulate: bool,
+    /// The number of frames averaged so far, see [`Renderer::accumulated_frames`].
+    accumulated: u32,
+    /// The view proj, world transform and octree, preview and chunk generations
+    /// the accumulated frames were rendered with.
+    accumulated_key: Option<(Mat4, Mat4, u64, u64, u64)>,
+    /// The color distant surfaces fade towards, in linear hdr, see [`Renderer::set_fog`].
+    pub fog_color: Vec3,
+    /// How quickly surfaces fade into the fog with distance, 0 disables fog.
+    pub fog_density: f32,
+    /// The light reaching every surface, in linear hdr, see [`Renderer::set_ambient`].
+    pub ambient_color: Vec3,
+    /// The background color, in linear hdr before tonemapping.
+    pub clear_color: wgpu::Color,
+    /// The depth the depth buffer is cleared to, the far plane by default.
+    pub clear_depth: f32,
+    /// Errors reported by wgpu outside of any call that could return them.
+    errors: mpsc::Receiver<String>,
+    /// Measures the passes, `None` if the device doesn't support timestamp queries.
+    timer: Option<GpuTimer>,
+    /// The number of times work was submitted to the queue,
+    /// [`Renderer::render_frame`] submits once per frame.
+    pub submits: Cell<u64>,
 }
 
 impl Renderer {
     pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub unsafe fn new(window: &winit::window::Window) -> anyhow::Result<Self> {
-        let (surface, device, queue) = init_wgpu(window)?;
+    pub const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
+    /// The tonemap pass renders to the surface, nothing writes it as storage,
+    /// which many adapters don't support for surface formats.
+    pub const SURFACE_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::RENDER_ATTACHMENT;
+
+    /// The number of jitter offsets in the poisson disk used by the octree shader.
+    pub const MAX_TAA_SAMPLES: u32 = 64;
+
+    /// Coarse enough that the grid lines stay apart at a normal viewing distance.
+    pub const DEFAULT_GRID_DEPTH: u32 = 6;
+
+    pub const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+        r: 0.48,
+        g: 0.84,
+        b: 0.83,
+        a: 1.0,
+    };
+
+    /// The background color, so surfaces fade into it.
+    pub const DEFAULT_FOG_COLOR: Vec3 = Vec3::new(0.48, 0.84, 0.83);
+
+    /// Faint enough that faces in shadow stay clearly darker than lit ones.
+    pub const DEFAULT_AMBIENT_COLOR: Vec3 = Vec3::splat(0.05);
+
+    /// # Safety
+    ///
+    /// `window` must outlive the renderer, see [`wgpu::Instance::create_surface`].
+    pub unsafe fn new(
+        window: &winit::window::Window,
+        backends: wgpu::Backends,
+    ) -> Result<Self, OakumError> {
+        let (surface, device, queue) = init_wgpu(window, backends)?;
 
         let width = window.inner_size().width;
         let height = window.inner_size().height;
 
+        Self::from_device(device, queue, Some(surface), width, height)
+    }
+
+    /// Create a renderer that draws to an offscreen texture instead of a window,
+    /// the result can be read back with [`Renderer::read_target`].
+    pub fn headless(width: u32, height: u32) -> Result<Self, OakumError> {
+        let (device, queue) = init_wgpu_headless()?;
+        Self::from_device(device, queue, None, width, height)
+    }
+
+    fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface: Option<wgpu::Surface>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, OakumError> {
         let taa_samples = 2;
 
+        // wgpu panics on uncaptured errors by default,
+        // instead collect them and return them from the next frame
+        let (error_sender, errors) = mpsc::channel();
+        device.on_uncaptured_error(Box::new(move |error| {
+            let _ = error_sender.send(error.to_string());
+        }));
+
         let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::STORAGE_BINDING,
-            format: wgpu::TextureFormat::Bgra8Unorm,
+            usage: Self::SURFACE_USAGE,
+            format: Self::TARGET_FORMAT,
             width,
             height,
             present_mode: wgpu::PresentMode::Immediate,
@@ -112,27 +337,56 @@ impl Renderer {
         };
 
         let hdr_texture = Self::create_hdr_texture(&device, width, height, taa_samples);
-        let hdr_view = hdr_texture.create_view(&Default::default());
+        let hdr_view = Self::create_hdr_array_view(&hdr_texture);
 
         let depth_texture = Self::create_depth_texture(&device, width, height);
 
+        let target = match surface {
+            Some(_) => None,
+            None => Some(Self::create_target_texture(&device, width, height)),
+        };
+
         let camera = DrawCamera::new(&device)?;
         let octree_phase = OctreePhase::new(&device, &camera)?;
-        let tonemap_phase = TonemapPhase::new(&device, &hdr_view)?;
+        let depth_view = depth_texture.create_view(&Default::default());
+        let tonemap_phase = TonemapPhase::new(&device, &hdr_view, &depth_view)?;
+        let accumulate_phase = AccumulatePhase::new(&device, &hdr_texture)?;
+
+        let timer = GpuTimer::new(&device, &queue);
 
         Ok(Self {
             device,
             queue,
             surface,
             surface_config,
+            target,
             needs_configure: true,
+            minimized: false,
             hdr_texture,
             depth_texture,
             camera,
             octree_phase,
             tonemap_phase,
+            accumulate_phase,
+            generate_pipeline: None,
+            pick_pipeline: None,
             taa_sample: 0,
             taa_samples,
+            aa_enabled: false,
+            grid_enabled: false,
+            grid_depth: Self::DEFAULT_GRID_DEPTH,
+            render_mode: RenderMode::default(),
+            accumulate: false,
+            accumulated: 0,
+            accumulated_key: None,
+            fog_color: Self::DEFAULT_FOG_COLOR,
+            fog_density: 0.0,
+            ambient_color: Self::DEFAULT_AMBIENT_COLOR,
+            clear_color: Self::DEFAULT_CLEAR_COLOR,
+            clear_depth: 1.0,
+            errors,
+            timer,
+            submits: Cell::new(0),
         })
     }
 
@@ -142,12 +396,16 @@ impl Renderer {
         height: u32,
         taa_samples: u32,
     ) -> wgpu::Texture {
+        // the gl backend treats textures with a single layer as plain 2d textures,
+        // which can't be sampled as arrays, so always allocate at least two
+        let layers = taa_samples.max(2);
+
         device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: wgpu::Extent3d {
                 width,
                 height,
-                depth_or_array_layers: taa_samples,
+                depth_or_array_layers: layers,
             },
             mip_level_count: 1,
             sample_count: 1,
@@ -158,6 +416,35 @@ impl Renderer {
         })
     }
 
+    /// Create a view of every taa sample in `hdr_texture`.
+    ///
+    /// The dimension is explicit, since a texture with a single layer would
+    /// otherwise get a `D2` view.
+    fn create_hdr_array_view(hdr_texture: &wgpu::Texture) -> wgpu::TextureView {
+        hdr_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("hdr_array_view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    }
+
+    fn create_target_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::TARGET_FORMAT,
+            usage: Self::SURFACE_USAGE | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
     fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
         device.create_texture(&wgpu::TextureDescriptor {
             label: None,
@@ -175,55 +462,351 @@ impl Renderer {
         })
     }
 
+    /// Returns the size of the final image in physical pixels, which picking
+    /// should use so it matches what is drawn.
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.surface_config.width, self.surface_config.height)
+    }
+
+    /// Resize the surface before the next frame.
+    ///
+    /// A zero size, like when the window is minimized, keeps the current size
+    /// and pauses rendering instead.
     pub fn resize(&mut self, width: u32, height: u32) {
+        self.minimized = width == 0 || height == 0;
+
+        if self.minimized {
+            return;
+        }
+
         self.surface_config.width = width;
         self.surface_config.height = height;
 
         self.needs_configure = true;
     }
 
+    /// Resize like [`Renderer::resize`], but recreate the surface or target,
+    /// the depth and hdr textures and the tonemap bind group now.
+    ///
+    /// Textures can then be read or copied at the new size before the next frame,
+    /// which offscreen rendering relies on.
+    pub fn set_target_size(&mut self, width: u32, height: u32) {
+        self.resize(width, height);
+
+        if !self.minimized {
+            self.configure();
+        }
+    }
+
     pub fn configure(&mut self) {
+        let width = self.surface_config.width;
+        let height = self.surface_config.height;
+
+        // zero sized surfaces and textures are invalid
+        if width == 0 || height == 0 {
+            return;
+        }
+
         self.needs_configure = false;
 
-        self.surface.configure(&self.device, &self.surface_config);
+        match self.surface {
+            Some(ref surface) => surface.configure(&self.device, &self.surface_config),
+            None => self.target = Some(Self::create_target_texture(&self.device, width, height)),
+        }
+
+        self.depth_texture = Self::create_depth_texture(&self.device, width, height);
+        self.recreate_hdr_texture();
+    }
 
+    fn recreate_hdr_texture(&mut self) {
         let width = self.surface_config.width;
         let height = self.surface_config.height;
+
         self.hdr_texture = Self::create_hdr_texture(&self.device, width, height, self.taa_samples);
-        self.depth_texture = Self::create_depth_texture(&self.device, width, height);
 
-        let hdr_view = self.hdr_texture.create_view(&Default::default());
-        self.tonemap_phase.resized(&self.device, &hdr_view);
+        let hdr_view = Self::create_hdr_array_view(&self.hdr_texture);
+        let depth_view = self.depth_texture.create_view(&Default::default());
+        self.tonemap_phase
+            .resized(&self.device, &hdr_view, &depth_view);
+        self.accumulate_phase
+            .resized(&self.device, &self.hdr_texture);
+        self.reset_accumulation();
+    }
+
+    /// Set the number of taa samples, `1` disables taa.
+    ///
+    /// This reallocates the hdr texture, so accumulated samples are discarded.
+    pub fn set_taa_samples(&mut self, taa_samples: u32) -> Result<(), OakumError> {
+        if !(1..=Self::MAX_TAA_SAMPLES).contains(&taa_samples) {
+            return Err(OakumError::InvalidTaaSamples {
+                samples: taa_samples,
+                max: Self::MAX_TAA_SAMPLES,
+            });
+        }
+
+        self.taa_samples = taa_samples;
+        self.taa_sample = 0;
+        self.recreate_hdr_texture();
+
+        Ok(())
+    }
+
+    /// Rebuild the render pipelines from the current shader files.
+    ///
+    /// Textures, buffers and bind groups are kept. If a shader fails to compile
+    /// the error is returned and the old pipelines stay in use.
+    pub fn reload_shaders(&mut self) -> Result<(), OakumError> {
+        clear_shader_cache();
+
+        let octree = self.octree_phase.pipeline.reload(&self.device);
+        let tonemap = self.tonemap_phase.pipeline.reload(&self.device);
+        let accumulate = self.accumulate_phase.pipeline.reload(&self.device);
+
+        octree.and(tonemap).and(accumulate)
+    }
+
+    /// Fade surfaces towards `color` with distance, by `exp(-density * distance)`.
+    ///
+    /// A density of 0 disables fog, negative densities are clamped to 0.
+    pub fn set_fog(&mut self, color: Vec3, density: f32) {
+        self.fog_color = color;
+        self.fog_density = density.max(0.0);
+        self.reset_accumulation();
+    }
+
+    /// Light every surface with `color`, multiplied by its albedo, on top of the
+    /// sun and lights, negative components are clamped to 0.
+    pub fn set_ambient(&mut self, color: Vec3) {
+        self.ambient_color = color.max(Vec3::ZERO);
+        self.reset_accumulation();
+    }
+
+    /// Start averaging frames over on the next frame, see [`Renderer::accumulate`].
+    pub fn reset_accumulation(&mut self) {
+        self.accumulated = 0;
+    }
+
+    /// Returns the number of frames averaged into the image of the last frame,
+    /// 0 if [`Renderer::accumulate`] is off.
+    pub fn accumulated_frames(&self) -> u32 {
+        self.accumulated
+    }
+
+    /// Returns the number of frames accumulated before this one, starting over
+    /// if the view or the world changed since the last.
+    fn accumulated_before(&mut self, world: &World) -> u32 {
+        let key = (
+            world.camera.view_proj(self.aspect()),
+            world.transform(),
+            world.octree.generation,
+            world.preview.generation,
+            world.chunks.generation(),
+        );
+
+        if self.accumulated_key != Some(key) {
+            self.accumulated_key = Some(key);
+            self.accumulated = 0;
+        }
+
+        self.accumulated
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        self.surface_config.present_mode = present_mode;
+        self.needs_configure = true;
     }
 
     pub fn aspect(&self) -> f32 {
         self.surface_config.width as f32 / self.surface_config.height as f32
     }
 
-    pub fn render_frame(&mut self, world: &World) -> anyhow::Result<()> {
+    /// Returns the first error wgpu reported since the last call, logging any others.
+    pub fn take_error(&self) -> Result<(), OakumError> {
+        let Ok(error) = self.errors.try_recv() else {
+            return Ok(());
+        };
+
+        for other in self.errors.try_iter() {
+            error!("Wgpu error: {}", other);
+        }
+
+        Err(OakumError::Wgpu(error))
+    }
+
+    /// Render `world`, uploading the changed segments of its octree.
+    ///
+    /// Everything the frame needs, including growing the octree textures, is
+    /// recorded into a single encoder and submitted once.
+    ///
+    /// Returns false if no frame was rendered, in which case the changes
+    /// should be kept until the next frame.
+    pub fn render_frame(&mut self, world: &World) -> Result<bool, OakumError> {
+        self.take_error()?;
+
+        if self.minimized {
+            return Ok(false);
+        }
+
+        if let Some(ref mut timer) = self.timer {
+            timer.poll(&self.device);
+        }
+
         if self.needs_configure {
             self.configure();
         }
 
-        let frame = match self.surface.get_current_texture() {
-            Ok(frame) => frame,
-            Err(wgpu::SurfaceError::Outdated) => {
-                self.surface.configure(&self.device, &self.surface_config);
-                self.surface.get_current_texture()?
-            }
-            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
-            Err(e) => bail!(e),
+        let frame = match self.surface {
+            Some(ref surface) => match surface.get_current_texture() {
+                Ok(frame) => Some(frame),
+                Err(wgpu::SurfaceError::Outdated) => {
+                    surface.configure(&self.device, &self.surface_config);
+                    Some(surface.get_current_texture()?)
+                }
+                Err(wgpu::SurfaceError::Timeout) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            },
+            None => None,
+        };
+
+        // the target is taken out of self while rendering, since `main_pass` borrows self mutably
+        let target = self.target.take();
+        let texture = match (&frame, &target) {
+            (Some(frame), _) => &frame.texture,
+            (None, Some(target)) => target,
+            (None, None) => return Err(OakumError::NoRenderTarget),
         };
 
-        let view = frame.texture.create_view(&Default::default());
+        let view = texture.create_view(&Default::default());
         let mut encoder = self.device.create_command_encoder(&Default::default());
 
         (self.camera).write(&self.queue, &world.camera, self.aspect());
 
-        self.main_pass(&mut encoder, &frame.texture, &view, world)?;
+        let result = self.main_pass(&mut encoder, texture, &view, world);
+        self.target = target;
+        result?;
 
+        self.submit(encoder);
+
+        if let Some(ref mut timer) = self.timer {
+            timer.map();
+        }
+
+        if let Some(frame) = frame {
+            frame.present();
+        }
+
+        Ok(true)
+    }
+
+    fn submit(&self, encoder: wgpu::CommandEncoder) {
+        self.submits.set(self.submits.get() + 1);
         self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
+    }
+
+    /// Generate `sphere` on the gpu into the drawn octree, returns the number of nodes,
+    /// see [`DrawOctree::generate_sphere`].
+    ///
+    /// The world octree replaces the sphere the next time it changes.
+    pub fn generate_sphere(&mut self, sphere: &Sphere) -> Result<u32, OakumError> {
+        self.take_error()?;
+
+        if self.generate_pipeline.is_none() {
+            self.generate_pipeline = Some(GeneratePipeline::new(&self.device)?);
+        }
+
+        let generate = self.generate_pipeline.as_ref().unwrap();
+        let phase = &mut self.octree_phase;
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        let len = phase.draw_octree.generate_sphere(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &phase.pipeline,
+            generate,
+            sphere,
+        )?;
+        self.submit(encoder);
+
+        Ok(len)
+    }
+
+    /// Read back the offscreen render target as tightly packed RGBA8 rows.
+    ///
+    /// Only available for renderers created with [`Renderer::headless`].
+    pub fn read_target(&self) -> Result<Vec<u8>, OakumError> {
+        let Some(ref target) = self.target else {
+            return Err(OakumError::NoRenderTarget);
+        };
+
+        let width = self.surface_config.width;
+        let height = self.surface_config.height;
+
+        let row_bytes = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(align) * align;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Render Target Readback"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.submit(encoder);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|_| wgpu::BufferAsyncError)??;
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            for bgra in row[..row_bytes as usize].chunks(4) {
+                pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+            }
+        }
+
+        Ok(pixels)
+    }
+
+    /// Save the offscreen render target as a png image, see [`Renderer::read_target`].
+    #[cfg(feature = "headless")]
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>) -> Result<(), OakumError> {
+        let pixels = self.read_target()?;
+
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let width = self.surface_config.width;
+        let height = self.surface_config.height;
+
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
 
         Ok(())
     }
@@ -234,43 +817,1286 @@ impl Renderer {
         texture: &wgpu::Texture,
         view: &wgpu::TextureView,
         world: &World,
-    ) -> anyhow::Result<()> {
-        let hdr_view = self.hdr_texture.create_view(&wgpu::TextureViewDescriptor {
+    ) -> Result<(), OakumError> {
+        // accumulated frames are drawn with every jitter in turn, and tonemapped
+        // like a single taa sample
+        let accumulated = match self.accumulate {
+            true => Some(self.accumulated_before(world)),
+            false => {
+                self.accumulated = 0;
+                None
+            }
+        };
+
+        let (taa_sample, taa_samples) = match accumulated {
+            Some(frames) => (frames % Self::MAX_TAA_SAMPLES, 1),
+            None => (self.taa_sample, self.taa_samples),
+        };
+
+        let taa_view = self.hdr_texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("hdr_view"),
             dimension: Some(wgpu::TextureViewDimension::D2),
             base_array_layer: self.taa_sample,
             array_layer_count: NonZeroU32::new(1),
             ..Default::default()
         });
+        let hdr_view = match accumulated {
+            Some(_) => &self.accumulate_phase.frame_view,
+            None => &taa_view,
+        };
         let depth_view = self.depth_texture.create_view(&Default::default());
 
         let cx = RenderContext {
             device: &self.device,
             queue: &self.queue,
-            surface: &self.surface,
+            surface: self.surface.as_ref(),
             texture,
             view,
             world,
             hdr_texture: &self.hdr_texture,
-            hdr_view: &hdr_view,
+            hdr_view,
             depth_texture: &self.depth_texture,
             depth_view: &depth_view,
             camera: &self.camera,
             width: self.surface_config.width,
             height: self.surface_config.height,
-            taa_sample: self.taa_sample,
-            taa_samples: self.taa_samples,
+            taa_sample,
+            taa_samples,
+            time: world.time,
+            aa_enabled: self.aa_enabled,
+            grid: self.grid_enabled.then_some(self.grid_depth),
+            render_mode: self.render_mode,
+            fog_color: self.fog_color,
+            fog_density: self.fog_density,
+            ambient_color: self.ambient_color,
+            clear_color: self.clear_color,
+            clear_depth: self.clear_depth,
+        };
+
+        let mut timer = self.timer.as_mut();
+        let mut timestamp = |encoder: &mut wgpu::CommandEncoder, index| {
+            if let Some(ref mut timer) = timer {
+                timer.write(encoder, index);
+            }
         };
 
+        timestamp(encoder, 0);
         self.octree_phase.render(encoder, cx)?;
+
+        if let Some(frames) = accumulated {
+            self.accumulate_phase.render(encoder, frames);
+        }
+
+        timestamp(encoder, 1);
         self.tonemap_phase.render(encoder, cx)?;
+        timestamp(encoder, 2);
+
+        if let Some(timer) = timer {
+            timer.resolve(encoder);
+        }
 
-        self.taa_sample = (self.taa_sample + 1) % self.taa_samples;
+        match accumulated {
+            Some(frames) => self.accumulated = frames + 1,
+            None => self.taa_sample = (self.taa_sample + 1) % self.taa_samples,
+        }
 
         Ok(())
     }
 
+    /// Returns how long the passes of a recent frame took on the gpu.
+    ///
+    /// `None` until the first measurement has been read back, or if the device
+    /// doesn't support timestamp queries.
+    pub fn stats(&self) -> Option<RenderStats> {
+        self.timer.as_ref()?.stats()
+    }
+
     pub fn octree_phase(&self) -> &OctreePhase {
         &self.octree_phase
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{f32::consts::FRAC_PI_2, mem, ops::Range, time::Duration};
+
+    use super::*;
+    use crate::{
+        generate::Sphere,
+        octree::{
+            Branch, DynamicOctree, Node, OccupancyGrid, Octree, PackedNode, Palette, Segment,
+        },
+        world::{ChunkedWorld, Light},
+    };
+    use glam::{IVec3, Mat4, Quat, Vec2, Vec3};
+
+    /// Create a headless renderer, or `None` if no adapter is available.
+    pub fn headless(width: u32, height: u32) -> Option<Renderer> {
+        match Renderer::headless(width, height) {
+            Ok(renderer) => Some(renderer),
+            Err(err) => {
+                eprintln!("Skipping, no headless renderer: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn sphere_world() -> World {
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        world.camera.distance = 30.0;
+        world
+    }
+
+    #[test]
+    fn static_scene_is_stable() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        let world = sphere_world();
+
+        // render enough frames to fill every taa sample, then two more
+        let mut frames = Vec::new();
+        for _ in 0..renderer.taa_samples + 2 {
+            renderer.render_frame(&world).unwrap();
+            frames.push(renderer.read_target().unwrap());
+        }
+
+        let last = &frames[frames.len() - 1];
+        let previous = &frames[frames.len() - 2];
+
+        // the sphere covers part of the image
+        assert!(last.chunks(4).any(|pixel| pixel != &last[..4]));
+        assert!(last == previous);
+    }
+
+    #[test]
+    fn set_taa_samples() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        let world = sphere_world();
+
+        for samples in [1, 4, 2] {
+            renderer.set_taa_samples(samples).unwrap();
+            assert_eq!(renderer.taa_sample, 0);
+            let layers = samples.max(2);
+            assert_eq!(renderer.hdr_texture.depth_or_array_layers(), layers);
+
+            renderer.render_frame(&world).unwrap();
+            assert_eq!(renderer.hdr_texture.depth_or_array_layers(), layers);
+            assert!(renderer.read_target().unwrap().iter().any(|&c| c != 0));
+        }
+
+        assert!(renderer.set_taa_samples(0).is_err());
+        assert!(renderer
+            .set_taa_samples(Renderer::MAX_TAA_SAMPLES + 1)
+            .is_err());
+        assert_eq!(renderer.taa_samples, 2);
+    }
+
+    #[test]
+    fn accumulate() {
+        let Some(mut renderer) = headless(48, 48) else {
+            return;
+        };
+        renderer.accumulate = true;
+
+        let mut world = sphere_world();
+
+        let difference = |a: &[u8], b: &[u8]| -> u64 {
+            (a.iter().zip(b))
+                .map(|(&a, &b)| (a as i64 - b as i64).unsigned_abs())
+                .sum()
+        };
+
+        // the change between consecutive frames, as the average converges
+        let mut frames = Vec::new();
+        for _ in 0..32 {
+            renderer.render_frame(&world).unwrap();
+            frames.push(renderer.read_target().unwrap());
+        }
+
+        assert_eq!(renderer.accumulated_frames(), 32);
+
+        let early = difference(&frames[0], &frames[1]);
+        let late = difference(&frames[30], &frames[31]);
+        assert!(early > 0);
+        assert!(late * 4 < early, "{late} >= {early} / 4");
+
+        // moving the camera starts over
+        world.camera.rotation.y += 0.01;
+        renderer.render_frame(&world).unwrap();
+        assert_eq!(renderer.accumulated_frames(), 1);
+
+        renderer.render_frame(&world).unwrap();
+        assert_eq!(renderer.accumulated_frames(), 2);
+
+        // so does editing the octree
+        world.octree.set((0, 0, 0, 3), Node::solid(255, 0, 0));
+        renderer.render_frame(&world).unwrap();
+        assert_eq!(renderer.accumulated_frames(), 1);
+
+        renderer.accumulate = false;
+        renderer.render_frame(&world).unwrap();
+        assert_eq!(renderer.accumulated_frames(), 0);
+    }
+
+    #[test]
+    fn edge_antialiasing() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // a coarse sphere, so the edges of the cells are visible
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(4, 3)));
+        world.camera.distance = 30.0;
+
+        let mut render = |aa_enabled| {
+            renderer.aa_enabled = aa_enabled;
+            renderer.render_frame(&world).unwrap();
+            renderer.read_target().unwrap()
+        };
+
+        let off = render(false);
+        let on = render(true);
+
+        assert!(on != off);
+        assert_eq!(render(false), off);
+    }
+
+    #[test]
+    fn edge_blends_with_background() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // a solid cube seen head on from +X, its silhouette against the background
+        let mut world = World::new();
+        world.octree.set(Branch::root(), Node::solid(255, 64, 0));
+        world.camera.rotation = Vec3::new(0.0, FRAC_PI_2, 0.0);
+        // far enough that the silhouette falls partway into a pixel
+        world.camera.distance = 57.0;
+
+        let mut render = |aa_enabled| {
+            renderer.aa_enabled = aa_enabled;
+            renderer.render_frame(&world).unwrap();
+            renderer.read_target().unwrap()
+        };
+
+        let row = |image: &[u8]| -> Vec<[u8; 3]> {
+            let row = &image[32 * 64 * 4..33 * 64 * 4];
+            row.chunks(4)
+                .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect()
+        };
+
+        let off = row(&render(false));
+        let on = row(&render(true));
+
+        let (background, face) = (off[0], off[32]);
+        assert_ne!(background, face);
+
+        // without antialiasing every pixel is either the face or the background
+        assert!(off
+            .iter()
+            .all(|&pixel| pixel == background || pixel == face));
+
+        // with it, the pixels on the silhouette lie between them, the rest are unchanged
+        let between = |pixel: [u8; 3]| {
+            (0..3).all(|i| {
+                let (low, high) = (background[i].min(face[i]), background[i].max(face[i]));
+                (low..=high).contains(&pixel[i])
+            })
+        };
+
+        let edges: Vec<_> = (0..64).filter(|&x| on[x] != off[x]).collect();
+        assert!(!edges.is_empty());
+        assert!(edges
+            .iter()
+            .all(|&x| between(on[x]) && on[x] != background && on[x] != face));
+        // only pixels next to one of the other color change, at either end of the row too
+        let on_silhouette = |x: usize| {
+            [x.checked_sub(1), x.checked_add(1)]
+                .into_iter()
+                .flatten()
+                .filter_map(|neighbor| off.get(neighbor))
+                .any(|&neighbor| neighbor != off[x])
+        };
+        assert!(edges.iter().all(|&x| on_silhouette(x)));
+    }
+
+    #[test]
+    fn world_transform() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        let mut world = sphere_world();
+        let center = Vec2::new(32.0, 32.0);
+
+        let pixel = |image: &[u8], position: Vec2| {
+            let index = (position.y as usize * 64 + position.x as usize) * 4;
+            image[index..index + 4].to_vec()
+        };
+
+        for _ in 0..renderer.taa_samples {
+            renderer.render_frame(&world).unwrap();
+        }
+        let image = renderer.read_target().unwrap();
+        let background = image[..4].to_vec();
+
+        assert!(world.pick(&world.camera, 64, 64, center).is_some());
+        assert_ne!(pixel(&image, center), background);
+
+        // move the sphere off center, to the right of the camera
+        let right = world.camera.rotation_quat() * Vec3::X;
+        let offset = right * 16.0;
+        world.set_transform(Mat4::from_scale_rotation_translation(
+            Vec3::splat(5.0),
+            Quat::from_rotation_y(1.0),
+            offset,
+        ));
+
+        for _ in 0..renderer.taa_samples {
+            renderer.render_frame(&world).unwrap();
+        }
+        let image = renderer.read_target().unwrap();
+
+        assert!(world.pick(&world.camera, 64, 64, center).is_none());
+        assert_eq!(pixel(&image, center), background);
+
+        // the new center of the sphere is both drawn and picked
+        let clip = world.camera.view_proj(1.0).project_point3(offset);
+        let moved = Vec2::new(clip.x + 1.0, 1.0 - clip.y) * 32.0;
+
+        let hit = world.pick(&world.camera, 64, 64, moved).unwrap();
+        assert!((hit.point - offset).length() < 5.5);
+        assert_ne!(pixel(&image, moved), background);
+    }
+
+    #[test]
+    fn translucent_pane() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // cells of 1.25 world units, seen head on from 30 units along z
+        let square = |octree: &mut Octree, z: i32, half: i32, node: Node| {
+            for x in -half..half {
+                for y in -half..half {
+                    octree.set((x, y, z, 4), node);
+                }
+            }
+        };
+
+        let mut render = |wall: Option<i32>, pane: Option<i32>| {
+            let mut octree = Octree::new();
+            if let Some(z) = wall {
+                square(&mut octree, z, 8, Node::solid(255, 255, 255));
+            }
+            if let Some(z) = pane {
+                square(&mut octree, z, 4, Node::translucent(255, 0, 0));
+            }
+
+            let mut world = World::new();
+            world.octree = DynamicOctree::new(octree);
+            world.camera.rotation = Vec3::ZERO;
+            world.camera.distance = 30.0;
+
+            renderer.render_frame(&world).unwrap();
+            let image = renderer.read_target().unwrap();
+            let index = (32 * 64 + 32) * 4;
+            image[index..index + 4].to_vec()
+        };
+
+        let wall = render(Some(-8), None);
+        let pane = render(None, Some(4));
+        let both = render(Some(-8), Some(4));
+
+        // the pane tints the wall red, without hiding it
+        assert_ne!(both, wall);
+        assert_ne!(both, pane);
+        assert!(both[0] > both[1] && both[0] > both[2]);
+        assert!(both[1] > 0);
+        assert!(wall[1] > both[1]);
+
+        // behind the wall the pane is hidden
+        let wall = render(Some(0), None);
+        assert_eq!(render(Some(0), Some(-6)), wall);
+    }
+
+    #[test]
+    fn count_translucent_leaves() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        let mut world = sphere_world();
+        let mut render = |world: &mut World| {
+            renderer.render_frame(world).unwrap();
+            world.take_dirty();
+            renderer.octree_phase().draw_octree.translucent_leaves
+        };
+
+        // only opaque leaves, so the translucent pass is skipped
+        assert_eq!(render(&mut world), 0);
+
+        world
+            .octree
+            .set((20, 20, 20, 5), Node::translucent(0, 0, 255));
+        world
+            .octree
+            .set((-20, 20, 20, 5), Node::translucent(0, 0, 255));
+        assert_eq!(render(&mut world), 2);
+
+        world.octree.remove((20, 20, 20, 5));
+        assert_eq!(render(&mut world), 1);
+
+        world.octree.remove((-20, 20, 20, 5));
+        assert_eq!(render(&mut world), 0);
+
+        // shrinking reallocates the texture and packs everything again
+        world
+            .octree
+            .set((20, 20, 20, 5), Node::translucent(0, 0, 255));
+        world.octree.shrink_to_fit();
+        assert_eq!(render(&mut world), 1);
+    }
+
+    #[test]
+    fn fog_tints_distant_surfaces() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // a white wall of cells 1.25 world units deep, seen head on from 12 units along z
+        let mut render = |z: i32, density: f32| {
+            let mut octree = Octree::new();
+            for x in -8..8 {
+                for y in -8..8 {
+                    octree.set((x, y, z, 4), Node::solid(255, 255, 255));
+                }
+            }
+
+            let mut world = World::new();
+            world.octree = DynamicOctree::new(octree);
+            world.camera.rotation = Vec3::ZERO;
+            world.camera.distance = 12.0;
+
+            renderer.set_fog(Vec3::new(1.0, 0.0, 0.0), density);
+            renderer.render_frame(&world).unwrap();
+            let image = renderer.read_target().unwrap();
+            let index = (32 * 64 + 32) * 4;
+            image[index..index + 4].to_vec()
+        };
+
+        // how far fog pulled the green channel towards the red fog color
+        let mut tint = |z| render(z, 0.0)[1] as i32 - render(z, 0.05)[1] as i32;
+
+        // the near wall is about 3 units away, the far one about 21
+        let near = tint(6);
+        let far = tint(-8);
+
+        assert!(far > 64, "far tint {}", far);
+        assert!(near < far / 3, "near tint {} far tint {}", near, far);
+        assert!(near >= 0);
+
+        // no density, no fog
+        assert_eq!(render(-8, 0.0), render(-8, -1.0));
+    }
+
+    #[test]
+    fn point_light_brightens() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // a grey wall facing the camera, its front face at z = 0
+        let mut octree = Octree::new();
+        for x in -8..8 {
+            for y in -8..8 {
+                octree.set((x, y, -1, 4), Node::solid(100, 100, 100));
+            }
+        }
+
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(octree);
+        world.camera.rotation = Vec3::ZERO;
+        world.camera.distance = 20.0;
+
+        let mut render = |lights: Vec<Light>| {
+            world.lights = lights;
+            renderer.render_frame(&world).unwrap();
+            let image = renderer.read_target().unwrap();
+            let index = (32 * 64 + 32) * 4;
+            image[index..index + 4].to_vec()
+        };
+
+        let unlit = render(Vec::new());
+        let lit = render(vec![Light::point(Vec3::new(0.0, 0.0, 2.0), Vec3::ONE, 8.0)]);
+        assert!(lit[..3].iter().zip(&unlit).all(|(lit, unlit)| lit > unlit));
+
+        // further away the light fades, lights behind the wall don't reach it
+        let far = render(vec![Light::point(Vec3::new(0.0, 0.0, 8.0), Vec3::ONE, 8.0)]);
+        assert!(far[0] > unlit[0] && far[0] < lit[0]);
+
+        let behind = Light::directional(Vec3::NEG_Z, Vec3::ONE, 4.0);
+        assert_eq!(render(vec![behind]), unlit);
+
+        // lights past the cap are ignored
+        let mut lights = vec![behind; MAX_LIGHTS];
+        lights.push(Light::directional(Vec3::Z, Vec3::ONE, 4.0));
+        assert_eq!(render(lights), unlit);
+    }
+
+    #[test]
+    fn ambient_brightens_shadows() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        let world = sphere_world();
+        let mut render = |ambient| {
+            renderer.set_ambient(Vec3::splat(ambient));
+            renderer.render_frame(&world).unwrap();
+            renderer.read_target().unwrap()
+        };
+
+        let dark = render(0.0);
+        let bright = render(0.3);
+
+        // the brightest and darkest pixels of the sphere without ambient light
+        let brightness = |image: &[u8], i: usize| {
+            image[i * 4..i * 4 + 3]
+                .iter()
+                .map(|&c| c as f32)
+                .sum::<f32>()
+        };
+        let sphere: Vec<_> = (0..64 * 64)
+            .filter(|&i| {
+                let pixel = Vec2::new((i % 64) as f32 + 0.5, (i / 64) as f32 + 0.5);
+                world.pick(&world.camera, 64, 64, pixel).is_some()
+            })
+            .collect();
+
+        let by_brightness =
+            |a: &&usize, b: &&usize| brightness(&dark, **a).total_cmp(&brightness(&dark, **b));
+        let lit = *sphere.iter().max_by(by_brightness).unwrap();
+        let shadowed = *sphere.iter().min_by(by_brightness).unwrap();
+
+        let gain = |i| brightness(&bright, i) / brightness(&dark, i);
+        assert!(gain(shadowed) > 1.1, "shadowed gain {}", gain(shadowed));
+        assert!(
+            gain(lit) < gain(shadowed),
+            "lit gain {} shadowed gain {}",
+            gain(lit),
+            gain(shadowed)
+        );
+    }
+
+    #[test]
+    fn reload_shaders() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        // a single sample, so every frame renders the same image
+        renderer.set_taa_samples(1).unwrap();
+
+        let world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+        let before = renderer.read_target().unwrap();
+
+        renderer.reload_shaders().unwrap();
+
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() == before);
+
+        // a broken shader fails to reload, and the old pipelines keep rendering
+        let tonemap = "assets/shaders/tonemap.wgsl";
+        shader::tests::override_shader(tonemap, Some("fn main( {}"));
+        let result = renderer.reload_shaders();
+        shader::tests::override_shader(tonemap, None);
+        assert!(matches!(result, Err(OakumError::Wgpu(_))));
+
+        assert!(renderer.render_frame(&world).unwrap());
+        assert!(renderer.read_target().unwrap() == before);
+
+        // fixing the shader reloads it again
+        renderer.reload_shaders().unwrap();
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() == before);
+    }
+
+    #[test]
+    fn static_scene_uploads_nothing() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        let mut world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        let writes = draw_octree.texture_writes.get();
+        assert!(writes > 0);
+
+        renderer.render_frame(&world).unwrap();
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        assert_eq!(draw_octree.texture_writes.get(), writes);
+
+        // an edit is uploaded on the next frame
+        world.octree.set((0, 0, 0, 1), Node::solid(255, 0, 0));
+        renderer.render_frame(&world).unwrap();
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        assert!(draw_octree.texture_writes.get() > writes);
+    }
+
+    #[test]
+    fn segment_upload() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(32, 6)));
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        // recolor the leaves of a segment crossing from one row of the texture to the next
+        let segment = Segment::new(DrawOctree::PAGE_SIZE - 12, 24);
+        assert!(segment.end() < world.octree.len());
+
+        for index in segment.start..segment.end() {
+            if !world.octree[index].is_parent() {
+                world.octree.nodes[index as usize] = Node::solid(255, 0, 0);
+            }
+        }
+
+        world.octree.push_segment(segment);
+        renderer.render_frame(&world).unwrap();
+        let len = world.octree.len();
+
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        let bytes = draw_octree.segment_bytes(segment);
+        let packed = &draw_octree.packed[segment.start as usize..segment.end() as usize];
+        assert_eq!(bytes, bytemuck::cast_slice::<_, u8>(packed));
+        assert_eq!(
+            bytes.len(),
+            segment.len as usize * mem::size_of::<PackedNode>()
+        );
+
+        // cut off at the end of the packed nodes
+        let past = Segment::new(len - 4, 16);
+        assert_eq!(
+            draw_octree.segment_bytes(past).len(),
+            4 * mem::size_of::<PackedNode>()
+        );
+        assert!(draw_octree
+            .segment_bytes(Segment::new(len + 8, 8))
+            .is_empty());
+
+        let gpu = (draw_octree.read_octree(&renderer.device, &renderer.queue, len)).unwrap();
+        assert!(gpu.nodes == world.octree.nodes);
+    }
+
+    #[test]
+    fn upload_latest_edit_first() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(32, 6)));
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        let len = world.octree.len();
+        assert!(len > 2 * Segment::BATCH_THRESHOLD);
+
+        // an older edit at the end of the octree, then a newer one at the start
+        world.octree.push_segment(Segment::new(len - 16, 16));
+        world.octree.push_segment(Segment::new(0, 16));
+
+        renderer.octree_phase.draw_octree.upload_budget = Some(16);
+        let writes = renderer.octree_phase().draw_octree.texture_writes.get();
+
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        assert!(draw_octree.texture_writes.get() > writes);
+        assert_eq!(draw_octree.pending, vec![Segment::new(len - 16, 16)]);
+
+        // the older edit follows on the next frame
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.octree_phase().draw_octree.pending.is_empty());
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(32, 6)));
+        world.camera.distance = 60.0;
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        let len = world.octree.len();
+        let bytes = renderer.octree_phase().draw_octree.byte_size();
+
+        // carve away all but one corner, removals mostly leave free branches
+        for x in -2..2 {
+            for y in -2..2 {
+                for z in -2..2 {
+                    if (x, y, z) != (-2, -2, -2) {
+                        world.octree.remove((x, y, z, 2));
+                    }
+                }
+            }
+        }
+
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        let carved = renderer.read_target().unwrap();
+        let before = world.octree.clone();
+
+        world.octree.shrink_to_fit();
+        assert!(world.octree.shrunk());
+        assert!(world.octree.free_branches.is_empty());
+        assert!(world.octree.structurally_eq(&before));
+
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        assert!(world.octree.len() * 4 < len);
+        assert!(renderer.octree_phase().draw_octree.byte_size() * 4 < bytes);
+        assert_eq!(renderer.read_target().unwrap(), carved);
+    }
+
+    #[test]
+    fn preview_layer() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let mut world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        let before = renderer.read_target().unwrap();
+
+        // a red cube around the sphere, drawn over it without touching the octree
+        world.preview.set(Branch::root(), Node::solid(255, 0, 0));
+        let writes = renderer.octree_phase().draw_octree.texture_writes.get();
+
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        let after = renderer.read_target().unwrap();
+
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        assert_eq!(draw_octree.texture_writes.get(), writes);
+
+        // the sphere shows through the translucent cube, tinted red
+        let reddish = |image: &[u8]| {
+            (image.chunks(4))
+                .filter(|pixel| pixel[0] as i32 > pixel[1] as i32 + 20)
+                .count()
+        };
+        assert_eq!(reddish(&before), 0);
+        assert!(reddish(&after) > 32 * 32 / 4, "{}", reddish(&after));
+
+        let center = &after[(16 * 32 + 16) * 4..][..4];
+        assert!(center[1] > 40, "{:?}", center);
+
+        // without a preview the image is back to the octree alone
+        world.clear_preview();
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() == before);
+    }
+
+    #[test]
+    fn chunks_in_range() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let mut world = World::new();
+        world.camera.distance = 60.0;
+        world.chunks = ChunkedWorld::new(2, 20.0);
+
+        renderer.render_frame(&world).unwrap();
+        let empty = renderer.read_target().unwrap();
+
+        // a cell on either side of the origin, and one far out of range
+        let white = Node::solid(255, 255, 255);
+        world.chunks.set(IVec3::new(1, 1, 1), white);
+        world.chunks.set(IVec3::new(-2, -2, -2), white);
+        world.chunks.set(IVec3::new(40, 0, 0), white);
+
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        let drawn = renderer.read_target().unwrap();
+        assert!(drawn != empty);
+
+        let chunks = &renderer.octree_phase().chunks;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.contains_key(&IVec3::ZERO));
+        assert!(chunks.contains_key(&IVec3::NEG_ONE));
+
+        // out of range, the textures are freed
+        world.chunks.draw_distance = 0.0;
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.octree_phase().chunks.is_empty());
+        assert!(renderer.read_target().unwrap() == empty);
+
+        // back in range, without changes, the chunks are uploaded whole again
+        world.chunks.draw_distance = ChunkedWorld::DEFAULT_DRAW_DISTANCE;
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() == drawn);
+    }
+
+    #[test]
+    fn one_submit_per_frame() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let mut world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        let before = renderer.read_target().unwrap();
+
+        // enough new nodes to grow the octree texture
+        let size = renderer.octree_phase().draw_octree.size();
+        let detail = Octree::generate(&Sphere::new(16, 5));
+        world.octree.union((-1, -1, -1, 1), 1, &detail);
+        assert!(world.octree.len() as u64 > size);
+
+        let submits = renderer.submits.get();
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        assert_eq!(renderer.submits.get(), submits + 1);
+
+        // the changes are copied after the old contents, in the same frame
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        assert!(draw_octree.size() > size);
+        assert!(draw_octree.pending.is_empty());
+
+        // only the cells of the edit were updated, the same as rebuilding the grid
+        let rebuilt = OccupancyGrid::from_octree(&world.octree, draw_octree.occupancy.depth());
+        assert_eq!(draw_octree.occupancy.words(), rebuilt.words());
+
+        let image = renderer.read_target().unwrap();
+        assert!(image != before);
+
+        // the same as uploading the whole octree again
+        world.octree = DynamicOctree::new((*world.octree).clone());
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() == image);
+    }
+
+    #[test]
+    fn palette_rebuilt_when_full() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+        renderer.set_taa_samples(1).unwrap();
+
+        let mut world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+
+        // colors no node uses anymore, filling the palette, none of them blue
+        let palette = &mut renderer.octree_phase.draw_octree.palette;
+        let mut color = 0;
+        while palette.len() < Palette::MAX_COLORS as usize {
+            palette.insert(color);
+            color += 1;
+        }
+
+        world.octree.set((0, 0, 0, 1), Node::solid(0, 0, 255));
+        renderer.render_frame(&world).unwrap();
+        world.take_dirty();
+        let image = renderer.read_target().unwrap();
+
+        let draw_octree = &renderer.octree_phase().draw_octree;
+        assert!(draw_octree.palette.len() < 8);
+
+        // the same as uploading the whole octree again
+        world.octree = DynamicOctree::new((*world.octree).clone());
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() == image);
+    }
+
+    #[test]
+    fn octree_format_support() {
+        let supported = wgpu::TextureFormatFeatures {
+            allowed_usages: wgpu::TextureUsages::all(),
+            flags: wgpu::TextureFormatFeatureFlags::empty(),
+        };
+        assert!(DrawOctree::check_format_support(&supported).is_ok());
+
+        // storing to the texture is only needed for gpu generation
+        let unstored = wgpu::TextureFormatFeatures {
+            allowed_usages: DrawOctree::TEXTURE_USAGE,
+            ..supported
+        };
+        assert!(DrawOctree::check_format_support(&unstored).is_ok());
+
+        let unsampled = wgpu::TextureFormatFeatures {
+            allowed_usages: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            ..supported
+        };
+        assert!(matches!(
+            DrawOctree::check_format_support(&unsampled),
+            Err(OakumError::UnsupportedOctreeFormat(
+                DrawOctree::TEXTURE_FORMAT
+            ))
+        ));
+
+        // the adapter used here passed the check when the device was requested
+        let Some(renderer) = headless(1, 1) else {
+            return;
+        };
+        assert!(DrawOctree::new(&renderer.device, &renderer.octree_phase.pipeline).is_ok());
+    }
+
+    #[test]
+    fn clear_color() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        renderer.clear_color = wgpu::Color::RED;
+        renderer.render_frame(&sphere_world()).unwrap();
+        let image = renderer.read_target().unwrap();
+
+        // red is tonemapped to a bit below full brightness
+        for (x, y) in [(0, 0), (15, 0), (0, 15), (15, 15)] {
+            let pixel = &image[(y * 16 + x) * 4..][..4];
+            assert!(pixel[0] > 100, "{x} {y}");
+            assert_eq!(pixel[1..3], [0, 0], "{x} {y}");
+        }
+
+        // the sphere in the center still covers the background
+        let center = &image[(8 * 16 + 8) * 4..][..4];
+        assert!(center[0] > 0 || center[1] > 0);
+    }
+
+    #[test]
+    fn ground_grid() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+        // cells large enough to stay apart in a small image
+        renderer.grid_depth = 3;
+
+        // look straight ahead from above the ground, at the sphere
+        let mut world = sphere_world();
+        world.camera.position = Vec3::new(0.0, 5.0, 0.0);
+        world.camera.rotation = Vec3::ZERO;
+
+        let mut render = |grid_enabled| {
+            renderer.grid_enabled = grid_enabled;
+            renderer.render_frame(&world).unwrap();
+            renderer.read_target().unwrap()
+        };
+
+        let without = render(false);
+        let with = render(true);
+
+        let rows =
+            |image: &[u8], rows: Range<usize>| image[rows.start * 256..rows.end * 256].to_vec();
+        let pixel = |image: &[u8], x: usize, y: usize| image[(y * 64 + x) * 4..][..4].to_vec();
+
+        // the sky is unchanged, the ground below the horizon gets lines
+        assert!(rows(&with, 0..16) == rows(&without, 0..16));
+        assert!(rows(&with, 56..64) != rows(&without, 56..64));
+
+        // the sphere covers the grid
+        assert_eq!(pixel(&with, 32, 32), pixel(&without, 32, 32));
+        assert_eq!(pixel(&with, 32, 40), pixel(&without, 32, 40));
+    }
+
+    #[test]
+    fn depth_mode() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+
+        renderer.render_mode = RenderMode::Depth;
+
+        let mut world = sphere_world();
+        let mut center_depth = |world: &World| {
+            renderer.render_frame(world).unwrap();
+            let image = renderer.read_target().unwrap();
+            (image[(16 * 32 + 16) * 4], image[0])
+        };
+
+        let (far, background) = center_depth(&world);
+        world.camera.distance *= 0.5;
+        let (near, _) = center_depth(&world);
+
+        // the background is at the far plane, and the sphere gets darker as it gets closer
+        assert_eq!(background, 255);
+        assert!(far < background, "{far}");
+        assert!(near < far, "{near} {far}");
+    }
+
+    #[test]
+    fn steps_heatmap() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // a fine sphere, surrounded by empty cells as small as its own
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(64, 7)));
+        world.camera.distance = 30.0;
+
+        renderer.render_mode = RenderMode::Steps;
+        renderer.render_frame(&world).unwrap();
+        let image = renderer.read_target().unwrap();
+
+        let heat = |x: usize| image[(32 * 64 + x) * 4];
+        let hits = |x: usize| {
+            (world)
+                .pick(&world.camera, 64, 64, Vec2::new(x as f32 + 0.5, 32.5))
+                .is_some()
+        };
+
+        // rays grazing the silhouette step through many more cells than the head on
+        // ray through the center, the heatmap shows them redder
+        let edges: Vec<_> = (0..63).filter(|&x| hits(x) != hits(x + 1)).collect();
+        assert_eq!(edges.len(), 2);
+
+        for edge in edges {
+            let grazing = (edge - 1..=edge + 2).map(heat).max().unwrap();
+            assert!(grazing > heat(32), "{} {}", grazing, heat(32));
+        }
+
+        // the color mode is unaffected
+        renderer.render_mode = RenderMode::Color;
+        renderer.render_frame(&world).unwrap();
+        assert!(renderer.read_target().unwrap() != image);
+    }
+
+    #[test]
+    fn skip_empty_steps() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        // small voxels far apart, the tree already steps through the empty space
+        // between them in large cells
+        let mut world = World::new();
+        for i in -4..4 {
+            let branch = (i * 32 + 5, i * 16 + 3, 20 - i * 24, 8);
+            world.octree.set(branch, Node::solid(255, 255, 255));
+        }
+
+        let mut render = |mode, skip_empty| {
+            renderer.render_mode = mode;
+            renderer.octree_phase.draw_octree.skip_empty = skip_empty;
+            renderer.render_frame(&world).unwrap();
+            renderer.read_target().unwrap()
+        };
+
+        // the grid is only used in trees that keep empty parents, see `generated_skips_empty`,
+        // so it doesn't add any steps to those that don't
+        let skipped = render(RenderMode::Steps, true);
+        let walked = render(RenderMode::Steps, false);
+        assert!(skipped == walked);
+
+        // skipping doesn't change what is hit
+        assert!(render(RenderMode::Color, true) == render(RenderMode::Color, false));
+    }
+
+    #[test]
+    fn normals_mode() {
+        let Some(mut renderer) = headless(32, 32) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+        renderer.render_mode = RenderMode::Normals;
+
+        // a solid cube seen head on from +X
+        let mut world = World::new();
+        world.camera.rotation = Vec3::new(0.0, FRAC_PI_2, 0.0);
+
+        let white = Node::solid(255, 255, 255);
+
+        // the normal is mapped to (1.0, 0.5, 0.5) and drawn as is, without tonemapping,
+        // a stored normal replaces that of the face
+        for (node, expected) in [
+            (white, [255, 128, 128]),
+            (white.with_normal(Vec3::Y), [128, 255, 128]),
+        ] {
+            world.octree.set(Branch::root(), node);
+            renderer.render_frame(&world).unwrap();
+            world.take_dirty();
+            let image = renderer.read_target().unwrap();
+
+            let center = &image[(16 * 32 + 16) * 4..][..3];
+            for (channel, expected) in center.iter().zip(expected) {
+                assert!(
+                    (*channel as i32 - expected).abs() <= 1,
+                    "{center:?} {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn surface_usage() {
+        let instance = wgpu::Instance::new(Default::default());
+        let adapter = instance.request_adapter(&Default::default());
+        let Some(adapter) = hyena::block_on(adapter) else {
+            return;
+        };
+
+        let features = adapter.get_texture_format_features(Renderer::TARGET_FORMAT);
+        assert!(features.allowed_usages.contains(Renderer::SURFACE_USAGE));
+    }
+
+    #[test]
+    fn set_target_size() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        renderer.set_target_size(40, 24);
+        assert!(!renderer.needs_configure);
+
+        let hdr = renderer.hdr_texture.size();
+        assert_eq!((hdr.width, hdr.height), (40, 24));
+        assert_eq!(hdr.depth_or_array_layers, renderer.taa_samples);
+
+        let depth = renderer.depth_texture.size();
+        assert_eq!((depth.width, depth.height), (40, 24));
+        assert_eq!(renderer.read_target().unwrap().len(), 40 * 24 * 4);
+
+        // a zero size pauses rendering and keeps the textures
+        renderer.set_target_size(0, 24);
+        assert!(renderer.minimized);
+        assert_eq!(renderer.hdr_texture.width(), 40);
+
+        renderer.set_target_size(8, 8);
+        assert!(!renderer.minimized);
+        assert_eq!(renderer.hdr_texture.width(), 8);
+        assert!(renderer.render_frame(&sphere_world()).unwrap());
+    }
+
+    #[test]
+    fn minimize() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        renderer.set_taa_samples(1).unwrap();
+
+        let world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+        let before = renderer.read_target().unwrap();
+
+        renderer.resize(0, 0);
+        assert!(!renderer.render_frame(&world).unwrap());
+        assert!(renderer.aspect().is_finite());
+
+        renderer.resize(0, 16);
+        assert!(!renderer.render_frame(&world).unwrap());
+
+        // restoring renders the same image as before minimizing
+        renderer.resize(16, 16);
+        assert!(renderer.render_frame(&world).unwrap());
+        assert!(renderer.read_target().unwrap() == before);
+
+        // and a new size takes effect
+        renderer.resize(8, 4);
+        assert!(renderer.render_frame(&world).unwrap());
+        assert_eq!(renderer.read_target().unwrap().len(), 8 * 4 * 4);
+    }
+
+    #[test]
+    fn pass_timings() {
+        let Some(mut renderer) = headless(64, 64) else {
+            return;
+        };
+
+        if renderer.timer.is_none() {
+            assert!(renderer.stats().is_none());
+            return;
+        }
+
+        let world = sphere_world();
+
+        // timestamps are read back asynchronously, so render until they arrive
+        for _ in 0..100 {
+            renderer.render_frame(&world).unwrap();
+            renderer.device.poll(wgpu::Maintain::Wait);
+
+            if renderer.stats().is_some() {
+                break;
+            }
+        }
+
+        let stats = renderer.stats().unwrap();
+        assert!(stats.octree_pass > Duration::ZERO, "{:?}", stats);
+        assert!(stats.tonemap_pass > Duration::ZERO, "{:?}", stats);
+    }
+
+    #[test]
+    fn uncaptured_errors() {
+        let Some(mut renderer) = headless(16, 16) else {
+            return;
+        };
+
+        let world = sphere_world();
+        renderer.render_frame(&world).unwrap();
+
+        // mappable buffers can't be used as storage
+        renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Invalid Buffer"),
+            size: 16,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let result = renderer.render_frame(&world);
+        assert!(matches!(result, Err(OakumError::Wgpu(_))));
+
+        // the error is only reported once
+        assert!(renderer.render_frame(&world).unwrap());
+    }
+}
@@ -0,0 +1,216 @@
+use std::num::NonZeroU32;
+
+use crate::{
+    error::OakumError,
+    render::{catch_validation_errors, open_shader, Renderer},
+};
+
+pub struct AccumulatePipeline {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub layout: wgpu::PipelineLayout,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl AccumulatePipeline {
+    const SHADER: &str = "assets/shaders/accumulate.wgsl";
+
+    pub fn new(device: &wgpu::Device) -> Result<Self, OakumError> {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Accumulate Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Accumulate Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Self::create_pipeline(device, &layout)?;
+
+        Ok(Self {
+            bind_group_layout,
+            layout,
+            pipeline,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+    ) -> Result<wgpu::RenderPipeline, OakumError> {
+        let vertex_shader = open_shader(device, "assets/shaders/fullscreen.wgsl")?;
+        let fragment_shader = open_shader(device, Self::SHADER)?;
+
+        // the accumulated frames are weighted by one minus the weight of the latest one
+        let blend = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::OneMinusConstant,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(Self::SHADER),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_shader,
+                entry_point: "main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: Renderer::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: blend,
+                        alpha: blend,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+        });
+
+        Ok(pipeline)
+    }
+
+    /// Recreate the pipeline from the shader, keeping the bind group layout.
+    pub fn reload(&mut self, device: &wgpu::Device) -> Result<(), OakumError> {
+        self.pipeline =
+            catch_validation_errors(device, || Self::create_pipeline(device, &self.layout))?;
+
+        Ok(())
+    }
+}
+
+/// Blends each frame into a running average of the frames since the view last
+/// changed, see [`Renderer::accumulate`].
+///
+/// The octree pass draws into [`AccumulatePhase::frame_texture`], which is blended
+/// into [`AccumulatePhase::ACCUMULATED_LAYER`] of the hdr texture for tonemapping.
+pub struct AccumulatePhase {
+    pub pipeline: AccumulatePipeline,
+    /// The latest frame, a texture of its own since the hdr texture can't be
+    /// read while it's drawn to.
+    pub frame_texture: wgpu::Texture,
+    pub frame_view: wgpu::TextureView,
+    pub accumulated_view: wgpu::TextureView,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl AccumulatePhase {
+    /// The layer of the hdr texture holding the average, read like a single taa sample.
+    pub const ACCUMULATED_LAYER: u32 = 0;
+
+    pub fn new(device: &wgpu::Device, hdr_texture: &wgpu::Texture) -> Result<Self, OakumError> {
+        let pipeline = AccumulatePipeline::new(device)?;
+
+        let frame_texture = Self::create_frame_texture(device, hdr_texture);
+        let frame_view = frame_texture.create_view(&Default::default());
+        let accumulated_view = Self::create_accumulated_view(hdr_texture);
+        let bind_group = Self::create_bind_group(device, &pipeline, &frame_view);
+
+        Ok(Self {
+            pipeline,
+            frame_texture,
+            frame_view,
+            accumulated_view,
+            bind_group,
+        })
+    }
+
+    /// Create a texture the size of `hdr_texture`, with a single layer so it can
+    /// be sampled as a plain 2d texture.
+    fn create_frame_texture(device: &wgpu::Device, hdr_texture: &wgpu::Texture) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Accumulate Frame Texture"),
+            size: wgpu::Extent3d {
+                width: hdr_texture.width(),
+                height: hdr_texture.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Renderer::HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    fn create_accumulated_view(hdr_texture: &wgpu::Texture) -> wgpu::TextureView {
+        hdr_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("hdr_accumulated_view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: Self::ACCUMULATED_LAYER,
+            array_layer_count: NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        pipeline: &AccumulatePipeline,
+        frame_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Accumulate Bind Group"),
+            layout: &pipeline.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(frame_view),
+            }],
+        })
+    }
+
+    pub fn resized(&mut self, device: &wgpu::Device, hdr_texture: &wgpu::Texture) {
+        self.frame_texture = Self::create_frame_texture(device, hdr_texture);
+        self.frame_view = self.frame_texture.create_view(&Default::default());
+        self.accumulated_view = Self::create_accumulated_view(hdr_texture);
+        self.bind_group = Self::create_bind_group(device, &self.pipeline, &self.frame_view);
+    }
+
+    /// Blend the frame texture into the accumulated layer, `frames` is the number
+    /// of frames accumulated before this one, 0 replaces the accumulated layer.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, frames: u32) {
+        let load = match frames {
+            0 => wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            _ => wgpu::LoadOp::Load,
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Accumulate Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.accumulated_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        let weight = 1.0 / (frames as f64 + 1.0);
+
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_blend_constant(wgpu::Color {
+            r: weight,
+            g: weight,
+            b: weight,
+            a: weight,
+        });
+        pass.draw(0..6, 0..1);
+    }
+}
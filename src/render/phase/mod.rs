@@ -1,5 +1,7 @@
+mod accumulate;
 mod octree;
 mod tonemap;
 
+pub use accumulate::*;
 pub use octree::*;
 pub use tonemap::*;
@@ -1,24 +1,53 @@
-use std::{mem, num::NonZeroU32};
+#[cfg(test)]
+use std::cell::Cell;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    mem,
+    num::NonZeroU32,
+    ops::Range,
+};
 
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, UVec2, Vec3};
+use glam::{IVec3, Mat4, UVec2, Vec3};
 use log::trace;
+use wgpu::util::DeviceExt;
 
 use crate::{
-    octree::{DynamicOctree, Node, Segment},
-    render::{open_shader, DrawCamera, RenderContext, Renderer},
+    error::OakumError,
+    octree::{DynamicOctree, OccupancyGrid, PackedNode, Palette, Segment},
+    render::{
+        catch_validation_errors, open_shader, DrawCamera, RenderContext, RenderMode, Renderer,
+    },
+    world::{Light, LightKind},
 };
 
+/// Where [`DrawOctree::write_segment`] sends the packed nodes.
+enum TextureUpload<'a> {
+    /// Written with the queue, which runs before any command buffer submitted after.
+    Queue(&'a wgpu::Queue),
+    /// Copied from `buffer` by commands recorded into `encoder`, the bytes of
+    /// the segment start `offset` bytes into `buffer`.
+    Staged {
+        encoder: &'a mut wgpu::CommandEncoder,
+        buffer: &'a wgpu::Buffer,
+        offset: u64,
+    },
+}
+
 pub struct OctreePipeline {
     pub uniform_layout: wgpu::BindGroupLayout,
     pub light_layout: wgpu::BindGroupLayout,
     pub octree_layout: wgpu::BindGroupLayout,
     pub layout: wgpu::PipelineLayout,
+    /// Draws opaque nodes, writing depth.
     pub render_pipeline: wgpu::RenderPipeline,
+    /// Draws translucent nodes over the opaque ones, without writing depth,
+    /// see [`Node::translucent`](crate::octree::Node::translucent).
+    pub translucent_pipeline: wgpu::RenderPipeline,
 }
 
 impl OctreePipeline {
-    pub fn new(device: &wgpu::Device) -> anyhow::Result<Self> {
+    pub fn new(device: &wgpu::Device) -> Result<Self, OakumError> {
         let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Uniform Bind Group Layout"),
             entries: &[
@@ -48,7 +77,19 @@ impl OctreePipeline {
 
         let light_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Light Bind Group Layout"),
-            entries: &[],
+            entries: &[
+                // lights
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
 
         let octree_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -57,7 +98,7 @@ impl OctreePipeline {
                 // octree
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Texture {
                         sample_type: wgpu::TextureSampleType::Uint,
                         view_dimension: wgpu::TextureViewDimension::D3,
@@ -68,7 +109,7 @@ impl OctreePipeline {
                 // octree uniform
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -76,6 +117,28 @@ impl OctreePipeline {
                     },
                     count: None,
                 },
+                // occupancy grid
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // palette
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -85,19 +148,51 @@ impl OctreePipeline {
             push_constant_ranges: &[],
         });
 
+        let render_pipeline = Self::create_render_pipeline(device, &layout, false)?;
+        let translucent_pipeline = Self::create_render_pipeline(device, &layout, true)?;
+
+        /*
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Octree Pipeline"),
+            layout: Some(&layout),
+            module: &open_shader(device, "assets/shaders/pbr_comp.wgsl")?,
+            entry_point: "main",
+        });
+        */
+
+        Ok(Self {
+            uniform_layout,
+            light_layout,
+            octree_layout,
+            layout,
+            render_pipeline,
+            translucent_pipeline,
+        })
+    }
+
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        translucent: bool,
+    ) -> Result<wgpu::RenderPipeline, OakumError> {
         let vertex_shader = open_shader(device, "assets/shaders/fullscreen.wgsl")?;
         let fragment_shader = open_shader(device, "assets/shaders/pbr_frag.wgsl")?;
 
+        let (label, entry_point) = match translucent {
+            true => ("Translucent Octree Pipeline", "translucent"),
+            false => ("Octree Pipeline", "main"),
+        };
+
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Octree Pipeline"),
-            layout: Some(&layout),
+            label: Some(label),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 entry_point: "main",
                 module: &vertex_shader,
                 buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
-                entry_point: "main",
+                entry_point,
                 module: &fragment_shader,
                 targets: &[Some(wgpu::ColorTargetState {
                     format: Renderer::HDR_FORMAT,
@@ -108,7 +203,7 @@ impl OctreePipeline {
             primitive: Default::default(),
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Renderer::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled: !translucent,
                 depth_compare: wgpu::CompareFunction::LessEqual,
                 stencil: Default::default(),
                 bias: Default::default(),
@@ -117,22 +212,22 @@ impl OctreePipeline {
             multiview: None,
         });
 
-        /*
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Octree Pipeline"),
-            layout: Some(&layout),
-            module: &open_shader(device, "assets/shaders/pbr_comp.wgsl")?,
-            entry_point: "main",
-        });
-        */
+        Ok(render_pipeline)
+    }
 
-        Ok(Self {
-            uniform_layout,
-            light_layout,
-            octree_layout,
-            layout,
-            render_pipeline,
-        })
+    /// Recreate the render pipeline from the shaders, keeping the bind group layouts,
+    /// so bind groups created for the old pipeline stay valid.
+    pub fn reload(&mut self, device: &wgpu::Device) -> Result<(), OakumError> {
+        let (render_pipeline, translucent_pipeline) = catch_validation_errors(device, || {
+            let render_pipeline = Self::create_render_pipeline(device, &self.layout, false)?;
+            let translucent_pipeline = Self::create_render_pipeline(device, &self.layout, true)?;
+            Ok((render_pipeline, translucent_pipeline))
+        })?;
+
+        self.render_pipeline = render_pipeline;
+        self.translucent_pipeline = translucent_pipeline;
+
+        Ok(())
     }
 }
 
@@ -141,35 +236,105 @@ impl OctreePipeline {
 pub struct OctreeUniform {
     pub model: Mat4,
     pub model_inv: Mat4,
+    pub occupancy_resolution: u32,
+    /// The alpha the octree is blended with, below 1 nothing is drawn where it's missed.
+    pub opacity: f32,
+    pub padding: [u32; 2],
 }
 
 pub struct DrawOctree {
     /// The octree is stored in a 2d texture array,
     /// where each layer is a page of the octree.
+    /// Nodes are stored as [`PackedNode`]s, with colors in the palette buffer.
     ///
     /// Indices are encoded as follows:
-    /// | 12 | 12 |  8   |
+    /// | 11 | 11 |  10  |
     /// |----|----|------|
     /// |  x |  y | page |
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    /// The usage of `texture`, either [`DrawOctree::TEXTURE_USAGE`] or
+    /// [`DrawOctree::GENERATE_TEXTURE_USAGE`].
+    pub usage: wgpu::TextureUsages,
+    /// Set when the texture no longer mirrors the octree last written, like after
+    /// [`DrawOctree::generate_sphere`], the next change uploads the whole octree.
+    pub stale: bool,
     /// The height of each page in the octree.
     pub page_height: u32,
     /// The number of pages in the octree.
     pub page_count: u32,
     /// The uniform buffer for the octree.
     pub uniform_buffer: wgpu::Buffer,
+    /// Coarse occupancy grid used to skip empty space, see [`OccupancyGrid`].
+    pub occupancy: OccupancyGrid,
+    /// The generation of the octree `occupancy` was last updated for, `None`
+    /// rebuilds all of it on the next write.
+    pub occupancy_generation: Option<u64>,
+    /// Step through empty space with [`DrawOctree::occupancy`], on by default.
+    ///
+    /// Turning it off draws the same image, but traverses the tree cell by cell,
+    /// which is only useful to compare [`RenderMode::Steps`](crate::render::RenderMode::Steps).
+    pub skip_empty: bool,
+    pub occupancy_buffer: wgpu::Buffer,
+    /// The colors referenced by the packed nodes.
+    pub palette: Palette,
+    /// The packed nodes, mirroring the octree as of the last write.
+    pub packed: Vec<PackedNode>,
+    /// The number of translucent leaves in `packed`, octrees without any skip
+    /// the translucent pass.
+    pub translucent_leaves: usize,
+    pub palette_buffer: wgpu::Buffer,
+    /// The number of palette colors already written to `palette_buffer`.
+    pub palette_written: usize,
     pub bind_group: wgpu::BindGroup,
+    /// The model matrix, opacity and occupancy resolution last written to `uniform_buffer`.
+    pub uniform: Option<(Mat4, f32, u32)>,
+    /// The number of writes and copies to the texture issued so far, for tests.
+    #[cfg(test)]
+    pub texture_writes: Cell<u64>,
+    /// The most nodes [`DrawOctree::write_dynamic`] uploads per frame, `None`
+    /// uploads every change right away.
+    pub upload_budget: Option<u32>,
+    /// Changed segments left over by the budget, uploaded on later frames.
+    pub pending: Vec<Segment>,
 }
 
 impl DrawOctree {
-    pub const PAGE_SIZE: u32 = 1 << 12;
+    /// The width and maximum height of a page,
+    /// chosen to fit within the default `max_texture_dimension_3d`.
+    pub const PAGE_SIZE: u32 = 1 << 11;
+
+    /// The initial number of colors the palette buffer can hold.
+    pub const PALETTE_CAPACITY: u64 = 256;
+
+    /// One [`PackedNode`] per texel, a format every adapter can sample, and that
+    /// adapters meeting the default limits requested for the device can store to.
+    pub const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+    pub const TEXTURE_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::TEXTURE_BINDING
+        .union(wgpu::TextureUsages::COPY_DST)
+        .union(wgpu::TextureUsages::COPY_SRC);
+    /// Storage bindings let [`GeneratePipeline`](crate::render::GeneratePipeline) write
+    /// nodes without uploading them, only requested once it does, so adapters that
+    /// can't store to the format can still draw.
+    pub const GENERATE_TEXTURE_USAGE: wgpu::TextureUsages =
+        Self::TEXTURE_USAGE.union(wgpu::TextureUsages::STORAGE_BINDING);
+
+    /// Returns an error if `features`, as reported by the adapter for
+    /// [`DrawOctree::TEXTURE_FORMAT`], lack any of [`DrawOctree::TEXTURE_USAGE`].
+    pub fn check_format_support(features: &wgpu::TextureFormatFeatures) -> Result<(), OakumError> {
+        if !features.allowed_usages.contains(Self::TEXTURE_USAGE) {
+            return Err(OakumError::UnsupportedOctreeFormat(Self::TEXTURE_FORMAT));
+        }
 
-    pub fn new(device: &wgpu::Device, pipeline: &OctreePipeline) -> anyhow::Result<Self> {
+        Ok(())
+    }
+
+    pub fn new(device: &wgpu::Device, pipeline: &OctreePipeline) -> Result<Self, OakumError> {
         let page_height = 1;
         let page_count = 1;
 
-        let texture = Self::create_texture(device, page_height, page_count);
+        let usage = Self::TEXTURE_USAGE;
+        let texture = Self::create_texture(device, page_height, page_count, usage);
         let view = texture.create_view(&Default::default());
 
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -179,15 +344,48 @@ impl DrawOctree {
             mapped_at_creation: false,
         });
 
-        let bind_group = Self::create_bind_group(device, pipeline, &view, &uniform_buffer);
+        let occupancy = OccupancyGrid::new(OccupancyGrid::DEFAULT_DEPTH);
+        let occupancy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Octree Occupancy Buffer"),
+            size: occupancy.bytes().len() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let palette_buffer = Self::create_palette_buffer(device, Self::PALETTE_CAPACITY);
+
+        let bind_group = Self::create_bind_group(
+            device,
+            pipeline,
+            &view,
+            &uniform_buffer,
+            &occupancy_buffer,
+            &palette_buffer,
+        );
 
         Ok(Self {
             texture,
             view,
+            usage,
+            stale: false,
             page_height,
             page_count,
             uniform_buffer,
+            occupancy,
+            occupancy_generation: None,
+            occupancy_buffer,
+            skip_empty: true,
+            palette: Palette::new(),
+            packed: Vec::new(),
+            translucent_leaves: 0,
+            palette_buffer,
+            palette_written: 0,
             bind_group,
+            uniform: None,
+            #[cfg(test)]
+            texture_writes: Cell::new(0),
+            upload_budget: None,
+            pending: Vec::new(),
         })
     }
 
@@ -198,7 +396,7 @@ impl DrawOctree {
 
     /// Returns the size of the texture in bytes.
     pub const fn byte_size(&self) -> u64 {
-        self.size() * mem::size_of::<Node>() as u64
+        self.size() * mem::size_of::<PackedNode>() as u64
     }
 
     /// Returns the size of a page in nodes.
@@ -208,15 +406,20 @@ impl DrawOctree {
 
     /// Returns the size of a page in bytes.
     pub const fn bytes_per_page(&self) -> u64 {
-        self.page_size() as u64 * mem::size_of::<Node>() as u64
+        self.page_size() as u64 * mem::size_of::<PackedNode>() as u64
     }
 
     /// Returns the number of bytes in a row of the texture.
     pub const fn bytes_per_row(&self) -> u32 {
-        Self::PAGE_SIZE * mem::size_of::<Node>() as u32
+        Self::PAGE_SIZE * mem::size_of::<PackedNode>() as u32
     }
 
-    fn create_texture(device: &wgpu::Device, height: u32, page_count: u32) -> wgpu::Texture {
+    pub(crate) fn create_texture(
+        device: &wgpu::Device,
+        height: u32,
+        page_count: u32,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
         device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Octree Texture"),
             size: wgpu::Extent3d {
@@ -227,20 +430,29 @@ impl DrawOctree {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D3,
-            format: wgpu::TextureFormat::Rg32Uint,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::COPY_DST
-                | wgpu::TextureUsages::COPY_SRC,
+            format: Self::TEXTURE_FORMAT,
+            usage,
             view_formats: &[],
         })
     }
 
+    /// Create a palette buffer holding `capacity` colors.
+    fn create_palette_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Octree Palette Buffer"),
+            size: capacity * mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
     fn create_bind_group(
         device: &wgpu::Device,
         pipeline: &OctreePipeline,
         view: &wgpu::TextureView,
         uniform_buffer: &wgpu::Buffer,
+        occupancy_buffer: &wgpu::Buffer,
+        palette_buffer: &wgpu::Buffer,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Octree Bind Group"),
@@ -254,22 +466,37 @@ impl DrawOctree {
                     binding: 1,
                     resource: uniform_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: occupancy_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: palette_buffer.as_entire_binding(),
+                },
             ],
         })
     }
 
-    /// Resize the octree texture.
+    /// Resize the octree texture, returns true if it grew.
     ///
     /// - `size` is the number of nodes that can be stored in the texture.
+    /// - `copy` is whether to copy the old contents to the new texture, the copy
+    ///   is recorded into `encoder`.
+    ///
+    /// Writes issued with the queue run before any command buffer submitted after
+    /// them, so until `encoder` is submitted, anything written to the texture
+    /// within the old size is overwritten by the copy.
     pub fn resize(
         &mut self,
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
         pipeline: &OctreePipeline,
         size: u64,
-    ) {
+        copy: bool,
+    ) -> bool {
         if self.size() >= size {
-            return;
+            return false;
         }
 
         let old_page_height = self.page_height;
@@ -291,64 +518,399 @@ impl DrawOctree {
             self.byte_size() as f64 / 1024.0 / 1024.0 / 1024.0,
         );
 
-        let texture = Self::create_texture(device, self.page_height, self.page_count);
+        let texture = Self::create_texture(device, self.page_height, self.page_count, self.usage);
 
-        let mut encoder = device.create_command_encoder(&Default::default());
-        encoder.copy_texture_to_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::Extent3d {
-                width: Self::PAGE_SIZE,
-                height: old_page_height,
-                depth_or_array_layers: old_page_count,
-            },
-        );
-        queue.submit(Some(encoder.finish()));
+        if copy {
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d {
+                    width: Self::PAGE_SIZE,
+                    height: old_page_height,
+                    depth_or_array_layers: old_page_count,
+                },
+            );
+        }
 
+        self.set_texture(device, pipeline, texture);
+
+        true
+    }
+
+    /// Replace the texture, and the view and bind group of it.
+    pub(crate) fn set_texture(
+        &mut self,
+        device: &wgpu::Device,
+        pipeline: &OctreePipeline,
+        texture: wgpu::Texture,
+    ) {
         self.texture = texture;
         self.view = self.texture.create_view(&Default::default());
-        self.bind_group =
-            Self::create_bind_group(device, pipeline, &self.view, &self.uniform_buffer);
+        self.bind_group = Self::create_bind_group(
+            device,
+            pipeline,
+            &self.view,
+            &self.uniform_buffer,
+            &self.occupancy_buffer,
+            &self.palette_buffer,
+        );
+    }
+
+    /// Reallocate the texture at the smallest size holding `size` nodes,
+    /// returns true if it shrunk.
+    ///
+    /// The old contents are dropped, so everything has to be written again.
+    pub fn shrink(&mut self, device: &wgpu::Device, pipeline: &OctreePipeline, size: u64) -> bool {
+        let old = (self.page_height, self.page_count);
+        let old_size = self.size();
+
+        self.page_height = 1;
+        self.page_count = 1;
+
+        while self.size() < size {
+            if self.page_height < Self::PAGE_SIZE {
+                self.page_height *= 2;
+            } else {
+                self.page_count += 1;
+            }
+        }
+
+        if self.size() >= old_size {
+            (self.page_height, self.page_count) = old;
+            return false;
+        }
+
+        trace!(
+            "Shrinking octree texture to {}x{}x{}, taking up {}Gb",
+            Self::PAGE_SIZE,
+            self.page_height,
+            self.page_count,
+            self.byte_size() as f64 / 1024.0 / 1024.0 / 1024.0,
+        );
+
+        let texture = Self::create_texture(device, self.page_height, self.page_count, self.usage);
+        self.set_texture(device, pipeline, texture);
+
+        // everything is packed again, dropping colors no longer used
+        self.packed.clear();
+        self.palette = Palette::new();
+        self.palette_written = 0;
+        self.translucent_leaves = 0;
+        self.pending.clear();
+
+        true
     }
 
-    pub fn write_uniform(&self, queue: &wgpu::Queue, model: Mat4) {
+    /// Upload the changes to `octree`, and the uniform.
+    ///
+    /// If the texture has to grow, the copy of its old contents is recorded into
+    /// `encoder`, which runs after every write issued this frame, see [`DrawOctree::resize`].
+    /// The changes are then copied from a staging buffer by commands recorded after
+    /// it, unless they rewrite everything the copy would have kept anyway.
+    /// If `octree` was [shrunk](DynamicOctree::shrunk) the texture is reallocated to fit.
+    pub fn write(
+        &mut self,
+        cx: RenderContext,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &OctreePipeline,
+        octree: &DynamicOctree,
+        model: Mat4,
+        opacity: f32,
+    ) -> Result<(), OakumError> {
+        if octree.shrunk() {
+            self.shrink(cx.device, pipeline, octree.len() as u64);
+        }
+
+        // the texture holds something else, so the first change rewrites all of it
+        if self.stale && !octree.segments().is_empty() {
+            self.stale = false;
+            self.pending = vec![Segment::new(0, octree.len())];
+            self.occupancy_generation = None;
+        }
+
+        let rewrites_all = (octree.segments().first()).is_some_and(|segment| {
+            segment.start == 0 && segment.end() as usize >= self.packed.len()
+        });
+        let copy = !self.packed.is_empty() && !rewrites_all;
+
+        let copied = self.resize(cx.device, encoder, pipeline, octree.len() as u64, copy) && copy;
+        let staging = copied.then_some(encoder);
+        self.write_dynamic(cx.device, cx.queue, staging, pipeline, octree)?;
+
+        self.write_occupancy(cx.queue, octree);
+        self.write_uniform(cx.queue, model, opacity);
+
+        Ok(())
+    }
+
+    /// Write the uniform if `model`, `opacity` or [`DrawOctree::skip_empty`] have
+    /// changed since the last write.
+    pub fn write_uniform(&mut self, queue: &wgpu::Queue, model: Mat4, opacity: f32) {
+        // a resolution of zero turns the grid off
+        let occupancy_resolution = match self.skip_empty {
+            true => self.occupancy.resolution(),
+            false => 0,
+        };
+
+        if self.uniform == Some((model, opacity, occupancy_resolution)) {
+            return;
+        }
+
+        self.uniform = Some((model, opacity, occupancy_resolution));
+
         let uniform = OctreeUniform {
             model,
             model_inv: model.inverse(),
+            occupancy_resolution,
+            opacity,
+            padding: [0; 2],
         };
 
         queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
     }
 
+    /// Update the occupancy grid with the branches of `octree` changed since the last write.
+    ///
+    /// Only the cells overlapping changed branches are recomputed, see
+    /// [`OccupancyGrid::update`]. The whole grid is rebuilt when all of `octree`
+    /// was replaced, since it may not be the octree the grid was built from.
+    pub fn write_occupancy(&mut self, queue: &wgpu::Queue, octree: &DynamicOctree) {
+        let replaced = (octree.segments().first())
+            .is_some_and(|segment| segment.start == 0 && segment.end() >= octree.len());
+
+        if replaced {
+            self.occupancy_generation = None;
+        }
+
+        match self.occupancy_generation {
+            Some(generation) if generation == octree.generation => return,
+            Some(generation) if generation < octree.generation => {
+                let occupancy = &mut self.occupancy;
+                octree.for_each_changed_since(generation, |branch| {
+                    occupancy.update(octree, branch);
+                });
+            }
+            _ => self.occupancy = OccupancyGrid::from_octree(octree, self.occupancy.depth()),
+        }
+
+        self.occupancy_generation = Some(octree.generation);
+        queue.write_buffer(&self.occupancy_buffer, 0, self.occupancy.bytes());
+    }
+
     /// Write changes from a [`DynamicOctree`] to the texture.
-    pub fn write_dynamic(&self, queue: &wgpu::Queue, octree: &DynamicOctree) {
-        for &segment in octree.segments() {
+    ///
+    /// Changed nodes are packed first, which may add colors to the palette.
+    /// If the palette fills up, it is rebuilt from the colors of `octree` and
+    /// every node is written again, an error is only returned if those don't fit
+    /// either, or `octree` is longer than [`PackedNode::MAX_NODES`].
+    /// With an [`upload_budget`](Self::upload_budget) the segment with the most
+    /// recent edit is written first, and whatever doesn't fit is kept for later frames.
+    ///
+    /// With `staging`, the nodes are copied from a staging buffer by commands
+    /// recorded into it instead of written with the queue, so they land after
+    /// anything recorded into it before, like the copy of [`DrawOctree::resize`].
+    pub fn write_dynamic(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        staging: Option<&mut wgpu::CommandEncoder>,
+        pipeline: &OctreePipeline,
+        octree: &DynamicOctree,
+    ) -> Result<(), OakumError> {
+        if octree.segments().is_empty() && self.pending.is_empty() {
+            return Ok(());
+        }
+
+        if octree.len() > PackedNode::MAX_NODES {
+            return Err(OakumError::TooManyNodesToPack {
+                nodes: octree.len(),
+                max: PackedNode::MAX_NODES,
+            });
+        }
+
+        // new changes go before older ones, dropping older ones they cover
+        let mut segments = octree.prioritized_segments();
+        self.pending.retain(|old| {
+            !(segments.iter()).any(|new| new.start <= old.start && old.end() <= new.end())
+        });
+        segments.append(&mut self.pending);
+
+        let mut budget = self.upload_budget.unwrap_or(u32::MAX);
+        let mut writes = Vec::new();
+
+        for segment in segments {
+            // the octree may have shrunk since the segment was kept
+            let end = segment.end().min(octree.len());
+            if end <= segment.start {
+                continue;
+            }
+
+            let len = (end - segment.start).min(budget);
+            budget -= len;
+
+            if len < end - segment.start {
+                let start = segment.start + len;
+                self.pending.push(Segment::new(start, end - start));
+            }
+
+            if len > 0 {
+                writes.push(Segment::new(segment.start, len));
+            }
+        }
+
+        let len = octree.len() as usize;
+        if len < self.packed.len() {
+            let removed = self.packed[len..]
+                .iter()
+                .filter(|node| node.is_translucent());
+            self.translucent_leaves -= removed.count();
+        }
+
+        self.packed.resize(len, PackedNode::empty());
+
+        match self.pack_segments(octree, &writes) {
+            Err(OakumError::PaletteFull { .. }) => {
+                // every index may change, so everything is packed and written again
+                self.palette = Palette::new();
+                self.palette_written = 0;
+                self.pending.clear();
+
+                writes = vec![Segment::new(0, octree.len())];
+                self.pack_segments(octree, &writes)?;
+            }
+            result => result?,
+        }
+
+        self.write_palette(device, queue, pipeline);
+
+        let Some(encoder) = staging else {
+            for &segment in &writes {
+                self.write_segment(&mut TextureUpload::Queue(queue), segment);
+            }
+
+            return Ok(());
+        };
+
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        let bytes: Vec<u8> = (writes.iter())
+            .flat_map(|&segment| self.segment_bytes(segment))
+            .copied()
+            .collect();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Octree Staging Buffer"),
+            contents: &bytes,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let mut upload = TextureUpload::Staged {
+            encoder,
+            buffer: &buffer,
+            offset: 0,
+        };
+
+        for &segment in &writes {
+            self.write_segment(&mut upload, segment);
+
+            if let TextureUpload::Staged { offset, .. } = &mut upload {
+                *offset += self.segment_bytes(segment).len() as u64;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pack the nodes of `octree` in `segments` into [`packed`](Self::packed).
+    fn pack_segments(
+        &mut self,
+        octree: &DynamicOctree,
+        segments: &[Segment],
+    ) -> Result<(), OakumError> {
+        for &segment in segments {
             assert!(segment.byte_end() <= octree.size());
 
-            self.write_segment(queue, segment, octree.bytes());
+            for index in segment.start..segment.end() {
+                let packed = self.palette.try_pack(octree[index])?;
+                let old = mem::replace(&mut self.packed[index as usize], packed);
+
+                self.translucent_leaves -= old.is_translucent() as usize;
+                self.translucent_leaves += packed.is_translucent() as usize;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write colors added since the last write to the palette buffer,
+    /// growing it if needed.
+    pub fn write_palette(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline: &OctreePipeline,
+    ) {
+        let bytes = self.palette.bytes();
+
+        if bytes.len() as u64 > self.palette_buffer.size() {
+            let capacity = (self.palette.len() as u64).next_power_of_two();
+            self.palette_buffer = Self::create_palette_buffer(device, capacity);
+            self.palette_written = 0;
+
+            self.bind_group = Self::create_bind_group(
+                device,
+                pipeline,
+                &self.view,
+                &self.uniform_buffer,
+                &self.occupancy_buffer,
+                &self.palette_buffer,
+            );
+        }
+
+        if self.palette_written < self.palette.len() {
+            let offset = self.palette_written * mem::size_of::<u32>();
+            queue.write_buffer(&self.palette_buffer, offset as u64, &bytes[offset..]);
+
+            self.palette_written = self.palette.len();
         }
     }
 
+    /// Returns the packed bytes of the nodes in `segment`, as uploaded by
+    /// [`DrawOctree::write_segment`].
+    ///
+    /// Like [`DynamicOctree::segment_bytes`], the slice is cut off at the end of
+    /// the packed nodes, and is empty if the segment starts past it.
+    pub fn segment_bytes(&self, segment: Segment) -> &[u8] {
+        let end = (segment.end() as usize).min(self.packed.len());
+        let start = (segment.start as usize).min(end);
+
+        bytemuck::cast_slice(&self.packed[start..end])
+    }
+
+    /// Write the start of a segment that doesn't begin on a row, `row_offset`
+    /// bytes into it, returns the number of bytes written.
+    ///
+    /// The offsets of the other parts are relative to the bytes of the segment.
     fn write_first_row(
         &self,
-        queue: &wgpu::Queue,
-        offset: &mut usize,
+        upload: &mut TextureUpload,
+        row_offset: usize,
         size: &mut usize,
         row: &mut usize,
         page: &mut usize,
         bytes: &[u8],
-    ) {
-        let row_offset = *offset % self.bytes_per_row() as usize;
+    ) -> usize {
         if row_offset > 0 {
             let row_size = usize::min(self.bytes_per_row() as usize - row_offset, *size);
 
@@ -357,15 +919,16 @@ impl DrawOctree {
                 row_size,
                 row,
                 page,
-                offset,
+                row_offset,
             );
 
             self.write_row(
-                queue,
+                upload,
                 row_offset as u32,
                 *row as u32,
                 *page as u32,
-                &bytes[*offset..*offset + row_size],
+                bytes,
+                0..row_size,
             );
 
             if *row < self.page_height as usize - 1 {
@@ -375,14 +938,16 @@ impl DrawOctree {
                 *row = 0;
             }
 
-            *offset += row_size;
             *size -= row_size;
+            return row_size;
         }
+
+        0
     }
 
     fn write_first_rows(
         &self,
-        queue: &wgpu::Queue,
+        upload: &mut TextureUpload,
         offset: &mut usize,
         size: &mut usize,
         row: &mut usize,
@@ -404,11 +969,12 @@ impl DrawOctree {
             );
 
             self.write_rows(
-                queue,
+                upload,
                 page_offset as u32,
                 rows as u32,
                 *page as u32,
-                &bytes[*offset..],
+                bytes,
+                *offset,
             );
 
             let written = rows * self.bytes_per_row() as usize;
@@ -427,7 +993,7 @@ impl DrawOctree {
 
     fn write_full_pages(
         &self,
-        queue: &wgpu::Queue,
+        upload: &mut TextureUpload,
         offset: &mut usize,
         size: &mut usize,
         page: &mut usize,
@@ -437,7 +1003,7 @@ impl DrawOctree {
         if pages > 0 {
             trace!("Writing {} pages to offset {} (full pages)", pages, offset,);
 
-            self.write_pages(queue, *page as u32, pages as u32, &bytes[*offset..]);
+            self.write_pages(upload, *page as u32, pages as u32, bytes, *offset);
             let written = pages * self.bytes_per_page() as usize;
 
             *page += pages;
@@ -448,7 +1014,7 @@ impl DrawOctree {
 
     fn write_last_rows(
         &self,
-        queue: &wgpu::Queue,
+        upload: &mut TextureUpload,
         offset: &mut usize,
         size: &mut usize,
         row: &mut usize,
@@ -464,7 +1030,7 @@ impl DrawOctree {
                 offset,
             );
 
-            self.write_rows(queue, 0, rows as u32, page as u32, &bytes[*offset..]);
+            self.write_rows(upload, 0, rows as u32, page as u32, bytes, *offset);
 
             let written = rows * self.bytes_per_row() as usize;
 
@@ -476,7 +1042,7 @@ impl DrawOctree {
 
     fn write_last_row(
         &self,
-        queue: &wgpu::Queue,
+        upload: &mut TextureUpload,
         offset: usize,
         size: usize,
         row: usize,
@@ -492,102 +1058,147 @@ impl DrawOctree {
                 offset,
             );
 
-            let data = &bytes[offset..offset + size];
-            self.write_row(queue, 0, row as u32, page as u32, data);
+            let range = offset..offset + size;
+            self.write_row(upload, 0, row as u32, page as u32, bytes, range);
         }
     }
 
-    fn write_segment(&self, queue: &wgpu::Queue, segment: Segment, bytes: &[u8]) {
-        let mut offset = segment.byte_start();
-        let mut size = segment.byte_len();
+    /// Upload the packed nodes in `segment`, see [`DrawOctree::segment_bytes`].
+    fn write_segment(&self, upload: &mut TextureUpload, segment: Segment) {
+        let bytes = self.segment_bytes(segment);
+        let start = segment.start as usize * mem::size_of::<PackedNode>();
+
+        let mut size = bytes.len();
 
-        let mut row = offset / self.bytes_per_row() as usize;
+        let mut row = start / self.bytes_per_row() as usize;
         let mut page = row / self.page_height as usize;
         row %= self.page_height as usize;
 
-        self.write_first_row(queue, &mut offset, &mut size, &mut row, &mut page, bytes);
-        self.write_first_rows(queue, &mut offset, &mut size, &mut row, &mut page, bytes);
-        self.write_full_pages(queue, &mut offset, &mut size, &mut page, bytes);
-        self.write_last_rows(queue, &mut offset, &mut size, &mut row, page, bytes);
-        self.write_last_row(queue, offset, size, row, page, bytes);
-    }
-
-    fn write_row(&self, queue: &wgpu::Queue, offset: u32, row: u32, page: u32, bytes: &[u8]) {
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: offset / mem::size_of::<Node>() as u32,
-                    y: row,
-                    z: page,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytes,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(self.bytes_per_row() as u32),
-                rows_per_image: None,
-            },
-            wgpu::Extent3d {
-                width: bytes.len() as u32 / mem::size_of::<Node>() as u32,
-                height: 1,
-                depth_or_array_layers: 1,
-            },
-        );
+        let row_offset = start % self.bytes_per_row() as usize;
+        let mut offset =
+            self.write_first_row(upload, row_offset, &mut size, &mut row, &mut page, bytes);
+        self.write_first_rows(upload, &mut offset, &mut size, &mut row, &mut page, bytes);
+        self.write_full_pages(upload, &mut offset, &mut size, &mut page, bytes);
+        self.write_last_rows(upload, &mut offset, &mut size, &mut row, page, bytes);
+        self.write_last_row(upload, offset, size, row, page, bytes);
     }
 
-    pub fn write_rows(&self, queue: &wgpu::Queue, row: u32, rows: u32, page: u32, bytes: &[u8]) {
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: 0,
-                    y: row,
-                    z: page,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytes,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(self.bytes_per_row() as u32),
-                rows_per_image: None,
-            },
-            wgpu::Extent3d {
-                width: Self::PAGE_SIZE,
-                height: rows,
-                depth_or_array_layers: 1,
-            },
-        );
+    /// Copy `bytes`, starting `offset` bytes in, to the texture at `origin`.
+    fn copy_to_texture(
+        &self,
+        upload: &mut TextureUpload,
+        bytes: &[u8],
+        offset: usize,
+        origin: wgpu::Origin3d,
+        rows_per_image: Option<NonZeroU32>,
+        size: wgpu::Extent3d,
+    ) {
+        #[cfg(test)]
+        self.texture_writes.set(self.texture_writes.get() + 1);
+
+        let texture = wgpu::ImageCopyTexture {
+            texture: &self.texture,
+            mip_level: 0,
+            origin,
+            aspect: wgpu::TextureAspect::All,
+        };
+
+        match upload {
+            TextureUpload::Queue(queue) => {
+                let layout = wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.bytes_per_row()),
+                    rows_per_image,
+                };
+
+                queue.write_texture(texture, &bytes[offset..], layout, size);
+            }
+            TextureUpload::Staged {
+                encoder,
+                buffer,
+                offset: start,
+            } => {
+                let buffer = wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: *start + offset as u64,
+                        bytes_per_row: NonZeroU32::new(self.bytes_per_row()),
+                        rows_per_image,
+                    },
+                };
+
+                encoder.copy_buffer_to_texture(buffer, texture, size);
+            }
+        }
     }
 
-    fn write_pages(&self, queue: &wgpu::Queue, page: u32, pages: u32, bytes: &[u8]) {
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: 0,
-                    y: 0,
-                    z: page as u32,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytes,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: NonZeroU32::new(self.bytes_per_row() as u32),
-                rows_per_image: NonZeroU32::new(self.page_height),
-            },
-            wgpu::Extent3d {
-                width: Self::PAGE_SIZE,
-                height: self.page_height,
-                depth_or_array_layers: pages,
-            },
-        );
+    fn write_row(
+        &self,
+        upload: &mut TextureUpload,
+        offset: u32,
+        row: u32,
+        page: u32,
+        bytes: &[u8],
+        range: Range<usize>,
+    ) {
+        let origin = wgpu::Origin3d {
+            x: offset / mem::size_of::<PackedNode>() as u32,
+            y: row,
+            z: page,
+        };
+        let size = wgpu::Extent3d {
+            width: range.len() as u32 / mem::size_of::<PackedNode>() as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+
+        self.copy_to_texture(upload, bytes, range.start, origin, None, size);
+    }
+
+    fn write_rows(
+        &self,
+        upload: &mut TextureUpload,
+        row: u32,
+        rows: u32,
+        page: u32,
+        bytes: &[u8],
+        offset: usize,
+    ) {
+        let origin = wgpu::Origin3d {
+            x: 0,
+            y: row,
+            z: page,
+        };
+        let size = wgpu::Extent3d {
+            width: Self::PAGE_SIZE,
+            height: rows,
+            depth_or_array_layers: 1,
+        };
+
+        self.copy_to_texture(upload, bytes, offset, origin, None, size);
+    }
+
+    fn write_pages(
+        &self,
+        upload: &mut TextureUpload,
+        page: u32,
+        pages: u32,
+        bytes: &[u8],
+        offset: usize,
+    ) {
+        let origin = wgpu::Origin3d {
+            x: 0,
+            y: 0,
+            z: page,
+        };
+        let size = wgpu::Extent3d {
+            width: Self::PAGE_SIZE,
+            height: self.page_height,
+            depth_or_array_layers: pages,
+        };
+        let rows_per_image = NonZeroU32::new(self.page_height);
+
+        self.copy_to_texture(upload, bytes, offset, origin, rows_per_image, size);
     }
 }
 
@@ -595,20 +1206,86 @@ impl DrawOctree {
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 pub struct OctreePhaseUniforms {
     pub taa_sample: u32,
-    pub padding: [u8; 4],
+    pub time: f32,
     pub dimensions: UVec2,
+    pub aa_enabled: u32,
+    /// The depth of the cells outlined on the ground plane, 0 hides the grid.
+    pub grid_depth: u32,
+    /// Draw traversal steps instead of the scene, see [`RenderMode::Steps`].
+    pub steps: u32,
+    /// Draw surface normals instead of the scene, see [`RenderMode::Normals`].
+    pub normals: u32,
+    pub fog_color: Vec3,
+    /// Zero disables fog, see [`Renderer::set_fog`].
+    pub fog_density: f32,
+    /// See [`Renderer::set_ambient`].
+    pub ambient_color: Vec3,
+    pub padding_1: u32,
+}
+
+/// The most lights drawn, the light uniform has a fixed size.
+pub const MAX_LIGHTS: usize = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct LightData {
+    pub position: Vec3,
+    /// 0 for directional lights, 1 for point lights.
+    pub kind: u32,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct LightUniform {
+    pub lights: [LightData; MAX_LIGHTS],
+    pub count: u32,
+    pub padding: [u32; 3],
+}
+
+impl LightUniform {
+    /// Pack the first [`MAX_LIGHTS`] of `lights`.
+    pub fn new(lights: &[Light]) -> Self {
+        let mut uniform = Self::zeroed();
+
+        for (data, light) in uniform.lights.iter_mut().zip(lights) {
+            *data = LightData {
+                position: light.position,
+                kind: match light.kind {
+                    LightKind::Directional => 0,
+                    LightKind::Point => 1,
+                },
+                color: light.color,
+                intensity: light.intensity,
+            };
+        }
+
+        uniform.count = lights.len().min(MAX_LIGHTS) as u32;
+        uniform
+    }
 }
 
 pub struct OctreePhase {
     pub pipeline: OctreePipeline,
     pub uniform_buffer: wgpu::Buffer,
     pub uniform_bind_group: wgpu::BindGroup,
+    /// The [`LightUniform`] of [`World::lights`](crate::world::World::lights).
+    pub light_buffer: wgpu::Buffer,
     pub light_bind_group: wgpu::BindGroup,
     pub draw_octree: DrawOctree,
+    /// Draws [`World::preview`](crate::world::World::preview) over the octree.
+    pub preview: DrawOctree,
+    /// Draws the chunks of [`World::chunks`](crate::world::World::chunks) in range
+    /// of the camera, by chunk coordinate.
+    pub chunks: HashMap<IVec3, DrawOctree>,
 }
 
 impl OctreePhase {
-    pub fn new(device: &wgpu::Device, camera: &DrawCamera) -> anyhow::Result<Self> {
+    /// The opacity the preview is blended over the octree with.
+    pub const PREVIEW_OPACITY: f32 = 0.5;
+
+    pub fn new(device: &wgpu::Device, camera: &DrawCamera) -> Result<Self, OakumError> {
         let pipeline = OctreePipeline::new(device)?;
 
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -621,19 +1298,33 @@ impl OctreePhase {
         let uniform_bind_group =
             Self::create_uniform_bind_group(&pipeline, device, camera, &uniform_buffer);
 
+        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: mem::size_of::<LightUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Light Bind Group"),
             layout: &pipeline.light_layout,
-            entries: &[],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
         });
 
         let draw_octree = DrawOctree::new(device, &pipeline)?;
+        let preview = DrawOctree::new(device, &pipeline)?;
 
         Ok(Self {
             pipeline,
             uniform_buffer,
+            light_buffer,
             light_bind_group,
             draw_octree,
+            preview,
+            chunks: HashMap::new(),
             uniform_bind_group,
         })
     }
@@ -660,60 +1351,142 @@ impl OctreePhase {
         })
     }
 
+    /// Upload the chunks within [`ChunkedWorld::draw_distance`](crate::world::ChunkedWorld::draw_distance)
+    /// of the camera, and free the textures of chunks that are out of range or unloaded.
+    ///
+    /// Chunks coming into range get a new texture, which is uploaded whole.
+    fn write_chunks(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        cx: RenderContext,
+    ) -> Result<(), OakumError> {
+        let chunks = &cx.world.chunks;
+        let eye = cx.world.camera.eye();
+
+        let near: HashMap<_, _> = chunks.chunks_near(eye, chunks.draw_distance).collect();
+        self.chunks.retain(|chunk, _| near.contains_key(chunk));
+
+        for (chunk, octree) in near {
+            let draw_octree = match self.chunks.entry(chunk) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let mut draw_octree = DrawOctree::new(cx.device, &self.pipeline)?;
+                    draw_octree.pending = vec![Segment::new(0, octree.len())];
+                    entry.insert(draw_octree)
+                }
+            };
+
+            let transform = chunks.chunk_transform(chunk);
+            draw_octree.write(cx, encoder, &self.pipeline, octree, transform, 1.0)?;
+        }
+
+        Ok(())
+    }
+
     pub fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
         cx: RenderContext,
-    ) -> anyhow::Result<()> {
-        (self.draw_octree).resize(
-            cx.device,
-            cx.queue,
+    ) -> Result<(), OakumError> {
+        let transform = cx.world.transform();
+        let draw_octree = &mut self.draw_octree;
+        draw_octree.write(
+            cx,
+            encoder,
             &self.pipeline,
-            cx.world.octree.len() as u64,
-        );
-        (self.draw_octree).write_dynamic(cx.queue, &cx.world.octree);
-        (self.draw_octree).write_uniform(cx.queue, Mat4::from_scale(Vec3::splat(10.0)));
+            &cx.world.octree,
+            transform,
+            1.0,
+        )?;
+
+        let opacity = Self::PREVIEW_OPACITY;
+        let preview = &mut self.preview;
+        preview.write(
+            cx,
+            encoder,
+            &self.pipeline,
+            &cx.world.preview,
+            transform,
+            opacity,
+        )?;
+
+        self.write_chunks(encoder, cx)?;
 
         let uniforms = OctreePhaseUniforms {
             taa_sample: cx.taa_sample,
+            time: cx.time,
             dimensions: UVec2::new(cx.width, cx.height),
-            ..Default::default()
+            aa_enabled: cx.aa_enabled as u32,
+            grid_depth: cx.grid.unwrap_or(0),
+            steps: (cx.render_mode == RenderMode::Steps) as u32,
+            normals: (cx.render_mode == RenderMode::Normals) as u32,
+            fog_color: cx.fog_color,
+            fog_density: cx.fog_density,
+            ambient_color: cx.ambient_color,
+            padding_1: 0,
         };
 
         cx.queue
             .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
 
+        let lights = LightUniform::new(&cx.world.lights);
+        cx.queue
+            .write_buffer(&self.light_buffer, 0, bytemuck::bytes_of(&lights));
+
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Octree Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &cx.hdr_view,
+                view: cx.hdr_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.48,
-                        g: 0.84,
-                        b: 0.83,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(cx.clear_color),
                     store: true,
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &cx.depth_view,
+                view: cx.depth_view,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: wgpu::LoadOp::Clear(cx.clear_depth),
                     store: true,
                 }),
                 stencil_ops: None,
             }),
         });
 
-        pass.set_pipeline(&self.pipeline.render_pipeline);
         pass.set_bind_group(0, &self.uniform_bind_group, &[]);
         pass.set_bind_group(1, &self.light_bind_group, &[]);
-        pass.set_bind_group(2, &self.draw_octree.bind_group, &[]);
 
-        pass.draw(0..6, 0..1);
+        // the steps or normals of a single traversal of the octree, without anything drawn over it
+        if matches!(cx.render_mode, RenderMode::Steps | RenderMode::Normals) {
+            pass.set_pipeline(&self.pipeline.render_pipeline);
+            pass.set_bind_group(2, &self.draw_octree.bind_group, &[]);
+            pass.draw(0..6, 0..1);
+
+            return Ok(());
+        }
+
+        let mut octrees = vec![&self.draw_octree];
+        octrees.extend(self.chunks.values());
+        if !cx.world.preview.is_empty() {
+            octrees.push(&self.preview);
+        }
+
+        // every opaque node is drawn first, so the depth hides translucent nodes behind them
+        for (pipeline, translucent) in [
+            (&self.pipeline.render_pipeline, false),
+            (&self.pipeline.translucent_pipeline, true),
+        ] {
+            pass.set_pipeline(pipeline);
+
+            for draw_octree in &octrees {
+                if translucent && draw_octree.translucent_leaves == 0 {
+                    continue;
+                }
+
+                pass.set_bind_group(2, &draw_octree.bind_group, &[]);
+                pass.draw(0..6, 0..1);
+            }
+        }
 
         Ok(())
     }
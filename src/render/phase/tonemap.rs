@@ -1,25 +1,51 @@
-use crate::render::{open_shader, RenderContext};
+use std::mem;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    error::OakumError,
+    render::{catch_validation_errors, open_shader, Camera, RenderContext, RenderMode, Renderer},
+};
 
 pub struct TonemapPipeline {
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub layout: wgpu::PipelineLayout,
     pub pipeline: wgpu::RenderPipeline,
+    /// Averages the hdr texture without tonemapping, for [`RenderMode::Steps`] and
+    /// [`RenderMode::Normals`], whose colors are data rather than light.
+    pub raw_pipeline: wgpu::RenderPipeline,
+    /// Shows the depth texture instead of the hdr texture, see [`RenderMode::Depth`].
+    pub depth_bind_group_layout: wgpu::BindGroupLayout,
+    pub depth_layout: wgpu::PipelineLayout,
+    pub depth_pipeline: wgpu::RenderPipeline,
 }
 
 impl TonemapPipeline {
-    pub fn new(device: &wgpu::Device) -> anyhow::Result<Self> {
+    pub fn new(device: &wgpu::Device) -> Result<Self, OakumError> {
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Tonemap Bind Group Layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    view_dimension: wgpu::TextureViewDimension::D2Array,
-                    multisampled: false,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+            ],
         });
 
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -28,12 +54,72 @@ impl TonemapPipeline {
             push_constant_ranges: &[],
         });
 
+        let pipeline = Self::create_pipeline(device, &layout, Self::TONEMAP_SHADER, "main")?;
+        let raw_pipeline = Self::create_pipeline(device, &layout, Self::TONEMAP_SHADER, "raw")?;
+
+        let depth_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Depth Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            // depth textures can't be loaded from on gl, but unfilterable floats can
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let depth_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth Pipeline Layout"),
+            bind_group_layouts: &[&depth_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let depth_pipeline =
+            Self::create_pipeline(device, &depth_layout, Self::DEPTH_SHADER, "main")?;
+
+        Ok(Self {
+            bind_group_layout,
+            layout,
+            pipeline,
+            raw_pipeline,
+            depth_bind_group_layout,
+            depth_layout,
+            depth_pipeline,
+        })
+    }
+
+    const TONEMAP_SHADER: &str = "assets/shaders/tonemap.wgsl";
+    const DEPTH_SHADER: &str = "assets/shaders/depth.wgsl";
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        fragment: &str,
+        entry_point: &str,
+    ) -> Result<wgpu::RenderPipeline, OakumError> {
         let vertex_shader = open_shader(device, "assets/shaders/fullscreen.wgsl")?;
-        let fragment_shader = open_shader(device, "assets/shaders/tonemap.wgsl")?;
+        let fragment_shader = open_shader(device, fragment)?;
 
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Tonemap Pipeline"),
-            layout: Some(&layout),
+            label: Some(fragment),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: &vertex_shader,
                 entry_point: "main",
@@ -41,9 +127,9 @@ impl TonemapPipeline {
             },
             fragment: Some(wgpu::FragmentState {
                 module: &fragment_shader,
-                entry_point: "main",
+                entry_point,
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Bgra8Unorm,
+                    format: Renderer::TARGET_FORMAT,
                     blend: None,
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -54,59 +140,146 @@ impl TonemapPipeline {
             multiview: Default::default(),
         });
 
-        Ok(Self {
-            bind_group_layout,
-            layout,
-            pipeline,
-        })
+        Ok(pipeline)
+    }
+
+    /// Recreate the pipelines from the shaders, keeping the bind group layouts.
+    pub fn reload(&mut self, device: &wgpu::Device) -> Result<(), OakumError> {
+        let pipeline = catch_validation_errors(device, || {
+            Self::create_pipeline(device, &self.layout, Self::TONEMAP_SHADER, "main")
+        })?;
+        let raw_pipeline = catch_validation_errors(device, || {
+            Self::create_pipeline(device, &self.layout, Self::TONEMAP_SHADER, "raw")
+        })?;
+        let depth_pipeline = catch_validation_errors(device, || {
+            Self::create_pipeline(device, &self.depth_layout, Self::DEPTH_SHADER, "main")
+        })?;
+
+        self.pipeline = pipeline;
+        self.raw_pipeline = raw_pipeline;
+        self.depth_pipeline = depth_pipeline;
+
+        Ok(())
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct TonemapUniforms {
+    /// The number of taa samples in the hdr texture, all of which are averaged.
+    pub taa_samples: u32,
+    /// The clip planes of the camera, used to linearize depth.
+    pub near: f32,
+    pub far: f32,
+    pub padding: u32,
+}
+
 pub struct TonemapPhase {
     pub pipeline: TonemapPipeline,
+    pub uniform_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
+    pub depth_bind_group: wgpu::BindGroup,
 }
 
 impl TonemapPhase {
-    pub fn new(device: &wgpu::Device, hdr_view: &wgpu::TextureView) -> anyhow::Result<Self> {
+    pub fn new(
+        device: &wgpu::Device,
+        hdr_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+    ) -> Result<Self, OakumError> {
         let pipeline = TonemapPipeline::new(device)?;
 
-        let bind_group = Self::create_bind_group(&pipeline, device, hdr_view);
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tonemap Uniform Buffer"),
+            size: mem::size_of::<TonemapUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &pipeline.bind_group_layout,
+            hdr_view,
+            &uniform_buffer,
+        );
+        let depth_bind_group = Self::create_bind_group(
+            device,
+            &pipeline.depth_bind_group_layout,
+            depth_view,
+            &uniform_buffer,
+        );
 
         Ok(Self {
             pipeline,
+            uniform_buffer,
             bind_group,
+            depth_bind_group,
         })
     }
 
     fn create_bind_group(
-        pipeline: &TonemapPipeline,
         device: &wgpu::Device,
-        hdr_view: &wgpu::TextureView,
+        layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+        uniform_buffer: &wgpu::Buffer,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Tonemap Bind Group"),
-            layout: &pipeline.bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(hdr_view),
-            }],
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
         })
     }
 
-    pub fn resized(&mut self, device: &wgpu::Device, hdr_view: &wgpu::TextureView) {
-        self.bind_group = Self::create_bind_group(&self.pipeline, device, hdr_view);
+    pub fn resized(
+        &mut self,
+        device: &wgpu::Device,
+        hdr_view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+    ) {
+        let pipeline = &self.pipeline;
+
+        self.bind_group = Self::create_bind_group(
+            device,
+            &pipeline.bind_group_layout,
+            hdr_view,
+            &self.uniform_buffer,
+        );
+        self.depth_bind_group = Self::create_bind_group(
+            device,
+            &pipeline.depth_bind_group_layout,
+            depth_view,
+            &self.uniform_buffer,
+        );
     }
 
     pub fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
         cx: RenderContext,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), OakumError> {
+        let uniforms = TonemapUniforms {
+            taa_samples: cx.taa_samples,
+            near: Camera::NEAR,
+            far: Camera::FAR,
+            ..Default::default()
+        };
+
+        cx.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Tonemap Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &cx.view,
+                view: cx.view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -116,8 +289,21 @@ impl TonemapPhase {
             depth_stencil_attachment: None,
         });
 
-        pass.set_pipeline(&self.pipeline.pipeline);
-        pass.set_bind_group(0, &self.bind_group, &[]);
+        match cx.render_mode {
+            RenderMode::Color => {
+                pass.set_pipeline(&self.pipeline.pipeline);
+                pass.set_bind_group(0, &self.bind_group, &[]);
+            }
+            RenderMode::Steps | RenderMode::Normals => {
+                pass.set_pipeline(&self.pipeline.raw_pipeline);
+                pass.set_bind_group(0, &self.bind_group, &[]);
+            }
+            RenderMode::Depth => {
+                pass.set_pipeline(&self.pipeline.depth_pipeline);
+                pass.set_bind_group(0, &self.depth_bind_group, &[]);
+            }
+        }
+
         pass.draw(0..6, 0..1);
 
         Ok(())
@@ -0,0 +1,252 @@
+use std::{mem, sync::mpsc};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec4, UVec2, Vec2};
+
+use crate::{
+    error::OakumError,
+    octree::Branch,
+    render::{open_shader, OctreePipeline, Renderer},
+    world::World,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PickUniform {
+    pub dimensions: UVec2,
+    pub count: u32,
+    pub padding: u32,
+}
+
+/// Casts rays through pixels with the traversal of the octree shader, so the
+/// cells it picks are exactly the ones drawn, see [`Renderer::pick`].
+pub struct PickPipeline {
+    pub layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::ComputePipeline,
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+impl PickPipeline {
+    pub const WORKGROUP_SIZE: u32 = 64;
+
+    pub fn new(device: &wgpu::Device, octree: &OctreePipeline) -> Result<Self, OakumError> {
+        let buffer = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Pick Bind Group Layout"),
+            entries: &[
+                // camera
+                buffer(0, wgpu::BufferBindingType::Uniform),
+                // pixels
+                buffer(1, wgpu::BufferBindingType::Storage { read_only: true }),
+                // picks
+                buffer(2, wgpu::BufferBindingType::Storage { read_only: false }),
+                // pick uniform
+                buffer(3, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick Pipeline Layout"),
+            bind_group_layouts: &[&layout, &octree.light_layout, &octree.octree_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Pick Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &open_shader(device, "assets/shaders/pick.wgsl")?,
+            entry_point: "main",
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Uniform Buffer"),
+            size: mem::size_of::<PickUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            layout,
+            pipeline,
+            uniform_buffer,
+        })
+    }
+}
+
+impl Renderer {
+    /// Returns the cell drawn at each of `pixels`, in physical pixels from the top
+    /// left of the view, `None` where no cell is drawn.
+    ///
+    /// This is the first solid cell along the ray of the pixel, translucent or not,
+    /// against the octree uploaded by the last [`Renderer::render_frame`] of
+    /// `world`, without the taa jitter.
+    /// It waits for the gpu, so editing uses [`World::pick`] on the cpu instead,
+    /// which is authoritative and agrees with this at the centers of pixels.
+    pub fn pick(
+        &mut self,
+        world: &World,
+        pixels: &[Vec2],
+    ) -> Result<Vec<Option<Branch>>, OakumError> {
+        self.take_error()?;
+
+        if pixels.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if self.pick_pipeline.is_none() {
+            let pipeline = PickPipeline::new(&self.device, &self.octree_phase.pipeline)?;
+            self.pick_pipeline = Some(pipeline);
+        }
+
+        let pick = self.pick_pipeline.as_ref().unwrap();
+        let phase = &self.octree_phase;
+
+        (self.camera).write(&self.queue, &world.camera, self.aspect());
+
+        let uniform = PickUniform {
+            dimensions: self.size(),
+            count: pixels.len() as u32,
+            padding: 0,
+        };
+        self.queue
+            .write_buffer(&pick.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let pixel_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Pixels"),
+            size: mem::size_of_val(pixels) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        self.queue
+            .write_buffer(&pixel_buffer, 0, bytemuck::cast_slice(pixels));
+
+        let size = (pixels.len() * mem::size_of::<IVec4>()) as u64;
+        let pick_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picks"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Readback"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Pick Bind Group"),
+            layout: &pick.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.camera.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: pixel_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: pick_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: pick.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Pick Pass"),
+            });
+
+            pass.set_pipeline(&pick.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_bind_group(1, &phase.light_bind_group, &[]);
+            pass.set_bind_group(2, &phase.draw_octree.bind_group, &[]);
+
+            let workgroups = (pixels.len() as u32).div_ceil(PickPipeline::WORKGROUP_SIZE);
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+
+        encoder.copy_buffer_to_buffer(&pick_buffer, 0, &readback, 0, size);
+        self.submit(encoder);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|_| wgpu::BufferAsyncError)??;
+
+        let bytes = slice.get_mapped_range();
+        let picks: &[IVec4] = bytemuck::cast_slice(&bytes);
+
+        let branches = (picks.iter())
+            .map(|pick| match pick.w {
+                -1 => None,
+                depth => Some(Branch::new(pick.truncate(), depth as u32)),
+            })
+            .collect();
+
+        Ok(branches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::{
+        octree::Node,
+        render::tests::{headless, sphere_world},
+    };
+
+    #[test]
+    fn cpu_and_gpu_picks_agree() {
+        let Some(mut renderer) = headless(48, 32) else {
+            return;
+        };
+
+        let mut world = sphere_world();
+        world.camera.look_at(Vec3::new(1.0, 0.5, 0.0));
+        world.octree.set((3, 3, 3, 4), Node::solid(255, 0, 0));
+
+        renderer.render_frame(&world).unwrap();
+
+        let pixels: Vec<_> = (0..32)
+            .flat_map(|y| (0..48).map(move |x| Vec2::new(x as f32 + 0.5, y as f32 + 0.5)))
+            .collect();
+
+        let gpu = renderer.pick(&world, &pixels).unwrap();
+        let cpu: Vec<_> = (pixels.iter())
+            .map(|&pixel| world.pick(&world.camera, 48, 32, pixel))
+            .map(|hit| hit.map(|hit| hit.hit.branch))
+            .collect();
+
+        assert!(gpu.iter().any(Option::is_some));
+        assert!(gpu.iter().any(Option::is_none));
+
+        let disagree: Vec<_> = (pixels.iter().zip(cpu.iter().zip(&gpu)))
+            .filter(|(_, (cpu, gpu))| cpu != gpu)
+            .collect();
+        assert!(disagree.is_empty(), "{:?}", disagree);
+    }
+}
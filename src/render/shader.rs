@@ -12,9 +12,10 @@ struct EmbeddedShader {
     source: &'static str,
 }
 
+/// Shaders are always embedded in the binary, so it runs without the `assets` directory,
+/// files on disk take precedence so shaders can be edited during development.
 macro_rules! embedded_shader {
     ($path:literal) => {
-        #[cfg(feature = "embedded-assets")]
         EmbeddedShader {
             path: concat!("embedded://", $path),
             source: include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path)),
@@ -23,13 +24,17 @@ macro_rules! embedded_shader {
 }
 
 const EMBEDDED_SHADERS: &[EmbeddedShader] = &[
+    embedded_shader!("assets/shaders/accumulate.wgsl"),
     embedded_shader!("assets/shaders/camera.wgsl"),
     embedded_shader!("assets/shaders/common.wgsl"),
+    embedded_shader!("assets/shaders/depth.wgsl"),
     embedded_shader!("assets/shaders/fullscreen.wgsl"),
     embedded_shader!("assets/shaders/fullscreen_input.wgsl"),
+    embedded_shader!("assets/shaders/generate.wgsl"),
     embedded_shader!("assets/shaders/octree.wgsl"),
     embedded_shader!("assets/shaders/pbr_comp.wgsl"),
     embedded_shader!("assets/shaders/pbr_frag.wgsl"),
+    embedded_shader!("assets/shaders/pick.wgsl"),
     embedded_shader!("assets/shaders/poisson.wgsl"),
     embedded_shader!("assets/shaders/ray.wgsl"),
     embedded_shader!("assets/shaders/tonemap.wgsl"),
@@ -55,11 +60,66 @@ impl ShaderFile {
     pub const PRAGMA_ONCE: &'static str = "#pragma once";
     pub const INCLUDE: &'static str = "#include";
 
-    fn strip_pragma_once(source: String) -> (String, bool) {
-        match source.strip_prefix(Self::PRAGMA_ONCE) {
-            Some(source) => (source.to_string(), true),
-            None => (source, false),
+    /// Remove `#pragma once` if it appears before any code, on a line of its own,
+    /// only preceded by whitespace, line comments and block comments.
+    fn strip_pragma_once(mut source: String) -> (String, bool) {
+        let mut rest = source.trim_start();
+
+        loop {
+            if let Some(comment) = rest.strip_prefix("//") {
+                rest = comment.split_once('\n').map_or("", |(_, after)| after);
+            } else if rest.starts_with("/*") {
+                let Some(after) = Self::skip_block_comment(rest) else {
+                    return (source, false);
+                };
+
+                rest = after;
+            } else {
+                break;
+            }
+
+            rest = rest.trim_start();
+        }
+
+        let Some(after) = rest.strip_prefix(Self::PRAGMA_ONCE) else {
+            return (source, false);
+        };
+
+        if !after.lines().next().unwrap_or("").trim().is_empty() {
+            return (source, false);
+        }
+
+        let start = source.len() - rest.len();
+        source.replace_range(start..start + Self::PRAGMA_ONCE.len(), "");
+
+        (source, true)
+    }
+
+    /// Returns what follows the block comment `source` starts with, block comments
+    /// nest like in wgsl, `None` if it isn't closed.
+    fn skip_block_comment(source: &str) -> Option<&str> {
+        let mut depth = 0;
+        let mut offset = 0;
+
+        while offset < source.len() {
+            let rest = &source.as_bytes()[offset..];
+
+            if rest.starts_with(b"/*") {
+                depth += 1;
+                offset += 2;
+            } else if rest.starts_with(b"*/") {
+                depth -= 1;
+                offset += 2;
+
+                if depth == 0 {
+                    return Some(&source[offset..]);
+                }
+            } else {
+                offset += 1;
+            }
         }
+
+        None
     }
 
     fn find_include_directives(source: &str) -> Result<Vec<ShaderInclude>, ShaderError> {
@@ -99,7 +159,8 @@ impl ShaderFile {
             .find(|shader| Path::new(shader.path) == path)
     }
 
-    fn open_shader_source(path: &Path) -> Result<String, ShaderError> {
+    /// Read the source of `path`, if `filesystem` is false only embedded shaders are used.
+    fn open_shader_source(path: &Path, filesystem: bool) -> Result<String, ShaderError> {
         // first try to find the shader in the embedded shaders
         if let Some(embedded_shader) = Self::find_embedded_shader(path) {
             debug!("Loading embedded shader: {}", path.display());
@@ -108,7 +169,7 @@ impl ShaderFile {
 
         // if the shader does not exist on the filesystem
         // try to find it in the embedded shaders
-        if !path.exists() {
+        if !filesystem || !path.exists() {
             let embedded_path = Path::new("embedded://").join(path);
             debug!(
                 "Failed to find shader: {}, trying find embedded shader",
@@ -119,6 +180,8 @@ impl ShaderFile {
                 debug!("Loading embedded shader: {}", path.display());
                 return Ok(embedded_shader.source.to_string());
             }
+
+            return Err(ShaderError::FileNotFound(path.to_path_buf()));
         }
 
         // otherwise just read the file from the filesystem
@@ -126,7 +189,11 @@ impl ShaderFile {
     }
 
     pub fn open(path: &Path) -> Result<Self, ShaderError> {
-        let source = Self::open_shader_source(path)?;
+        Self::open_with(path, true)
+    }
+
+    fn open_with(path: &Path, filesystem: bool) -> Result<Self, ShaderError> {
+        let source = Self::open_shader_source(path, filesystem)?;
         let (source, pragma_once) = Self::strip_pragma_once(source);
         let includes = Self::find_include_directives(&source)?;
 
@@ -150,11 +217,35 @@ impl ShaderFile {
 #[derive(Clone, Debug)]
 pub struct ShaderProcessor {
     pub files: Vec<ShaderFile>,
+    /// Whether shaders on the filesystem override the embedded ones.
+    pub filesystem: bool,
+}
+
+impl Default for ShaderProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ShaderProcessor {
     pub const fn new() -> Self {
-        Self { files: Vec::new() }
+        Self {
+            files: Vec::new(),
+            filesystem: true,
+        }
+    }
+
+    /// Create a processor that only uses the shaders embedded in the binary.
+    pub const fn embedded() -> Self {
+        Self {
+            files: Vec::new(),
+            filesystem: false,
+        }
+    }
+
+    /// Forget every opened file, so they are read again when next opened.
+    pub fn clear(&mut self) {
+        self.files.clear();
     }
 
     pub fn contains_shader(&self, path: &Path) -> bool {
@@ -170,9 +261,9 @@ impl ShaderProcessor {
             return Ok(self.get_shader(path).unwrap());
         }
 
-        let shader = ShaderFile::open(path)?;
+        let shader = ShaderFile::open_with(path, self.filesystem)?;
         self.files.push(shader);
-        Ok(self.get_shader(&path).unwrap())
+        Ok(self.get_shader(path).unwrap())
     }
 
     fn process_shader_recursive(
@@ -220,12 +311,27 @@ impl ShaderProcessor {
     }
 }
 
+static GLOBAL_PROCESSOR: Mutex<ShaderProcessor> = Mutex::new(ShaderProcessor::new());
+
+/// Forget the shader files read by [`open_shader`], so changes on disk are picked up.
+pub fn clear_shader_cache() {
+    GLOBAL_PROCESSOR.lock().unwrap().clear();
+}
+
 pub fn open_shader(
     device: &wgpu::Device,
     path: impl AsRef<Path>,
 ) -> Result<wgpu::ShaderModule, ShaderError> {
-    static GLOBAL_PROCESSOR: Mutex<ShaderProcessor> = Mutex::new(ShaderProcessor::new());
-    let source = GLOBAL_PROCESSOR.lock().unwrap().process_shader(&path)?;
+    #[cfg(test)]
+    let overridden =
+        tests::SHADER_OVERRIDES.with(|overrides| overrides.borrow().get(path.as_ref()).cloned());
+    #[cfg(not(test))]
+    let overridden = None;
+
+    let source = match overridden {
+        Some(source) => source,
+        None => GLOBAL_PROCESSOR.lock().unwrap().process_shader(&path)?,
+    };
 
     Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some(&format!("Oakum shader: {}", path.as_ref().display())),
@@ -242,3 +348,117 @@ pub enum ShaderError {
     #[error("Shader file not found: {0}")]
     IoError(#[from] io::Error),
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use super::*;
+
+    thread_local! {
+        /// Sources used by [`open_shader`] instead of the files, on this thread only.
+        pub static SHADER_OVERRIDES: RefCell<HashMap<PathBuf, String>> = RefCell::default();
+    }
+
+    /// Make [`open_shader`] use `source` for `path` on this thread, `None` reads the file again.
+    pub fn override_shader(path: impl AsRef<Path>, source: Option<&str>) {
+        SHADER_OVERRIDES.with(|overrides| {
+            let mut overrides = overrides.borrow_mut();
+
+            match source {
+                Some(source) => overrides.insert(path.as_ref().to_path_buf(), source.to_string()),
+                None => overrides.remove(path.as_ref()),
+            };
+        });
+    }
+
+    fn validate(path: &str) {
+        let source = ShaderProcessor::new().process_shader(path).unwrap();
+        let module = naga::front::wgsl::parse_str(&source)
+            .unwrap_or_else(|e| panic!("{}: {}", path, e.emit_to_string(&source)));
+
+        let flags = naga::valid::ValidationFlags::all();
+        let capabilities = naga::valid::Capabilities::empty();
+        naga::valid::Validator::new(flags, capabilities)
+            .validate(&module)
+            .unwrap_or_else(|e| panic!("{}: {:?}", path, e));
+    }
+
+    #[test]
+    fn shaders_validate() {
+        validate("assets/shaders/accumulate.wgsl");
+        validate("assets/shaders/depth.wgsl");
+        validate("assets/shaders/fullscreen.wgsl");
+        validate("assets/shaders/pbr_frag.wgsl");
+        validate("assets/shaders/pick.wgsl");
+        validate("assets/shaders/tonemap.wgsl");
+    }
+
+    #[test]
+    fn every_shader_is_embedded() {
+        for entry in fs::read_dir("assets/shaders").unwrap() {
+            let path = entry.unwrap().path();
+            let embedded = Path::new("embedded://").join(&path);
+
+            assert!(
+                ShaderFile::find_embedded_shader(&embedded).is_some(),
+                "{} is not embedded",
+                path.display(),
+            );
+        }
+    }
+
+    #[test]
+    fn pragma_once() {
+        let strip = |source: &str| ShaderFile::strip_pragma_once(source.to_string());
+
+        assert_eq!(
+            strip("#pragma once\nfn a() {}"),
+            (String::from("\nfn a() {}"), true)
+        );
+        assert_eq!(
+            strip("// common helpers\n#pragma once\nfn a() {}"),
+            (String::from("// common helpers\n\nfn a() {}"), true),
+        );
+        assert_eq!(
+            strip("\n  \t#pragma once\r\nfn a() {}"),
+            (String::from("\n  \t\r\nfn a() {}"), true),
+        );
+
+        assert_eq!(
+            strip("/* license\n * text */\n#pragma once\nfn a() {}"),
+            (String::from("/* license\n * text */\n\nfn a() {}"), true),
+        );
+        assert_eq!(
+            strip("/* a /* nested */ comment */ #pragma once\n"),
+            (String::from("/* a /* nested */ comment */ \n"), true),
+        );
+
+        // the pragma must come before any code, outside of comments
+        for source in [
+            "fn a() {}\n#pragma once\n",
+            "/* #pragma once */\nfn a() {}",
+            "/* unclosed\n#pragma once\n",
+            "/* a /* nested */ #pragma once\n",
+            "#pragma once fn a() {}",
+        ] {
+            assert_eq!(strip(source), (String::from(source), false), "{source}");
+        }
+    }
+
+    #[test]
+    fn embedded_fallback() {
+        let mut processor = ShaderProcessor::embedded();
+        let embedded = processor
+            .process_shader("assets/shaders/tonemap.wgsl")
+            .unwrap();
+        let disk = ShaderProcessor::new()
+            .process_shader("assets/shaders/tonemap.wgsl")
+            .unwrap();
+
+        assert_eq!(embedded, disk);
+        assert!(processor
+            .process_shader("assets/shaders/missing.wgsl")
+            .is_err());
+    }
+}
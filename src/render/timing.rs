@@ -0,0 +1,140 @@
+use std::{sync::mpsc, time::Duration};
+
+/// How long the passes of a frame took on the gpu.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub octree_pass: Duration,
+    pub tonemap_pass: Duration,
+}
+
+impl RenderStats {
+    pub fn total(&self) -> Duration {
+        self.octree_pass + self.tonemap_pass
+    }
+}
+
+/// Measures the render passes with timestamp queries.
+///
+/// Timestamps are read back without blocking, so [`GpuTimer::stats`] lags a few
+/// frames behind. Frames rendered while a read back is pending aren't measured.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    read_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Set while `read_buffer` is being mapped.
+    pending: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+    /// True if timestamps were written this frame and need to be resolved.
+    recording: bool,
+    stats: Option<RenderStats>,
+}
+
+impl GpuTimer {
+    /// Before the octree pass, between the passes and after the tonemap pass.
+    const QUERIES: u32 = 3;
+    const SIZE: u64 = Self::QUERIES as u64 * wgpu::QUERY_SIZE as u64;
+
+    /// Returns `None` if `device` doesn't support timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Pass Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: Self::QUERIES,
+        });
+
+        let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Readback"),
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            read_buffer,
+            period: queue.get_timestamp_period(),
+            pending: None,
+            recording: false,
+            stats: None,
+        })
+    }
+
+    /// Returns the stats of the latest measured frame.
+    pub fn stats(&self) -> Option<RenderStats> {
+        self.stats
+    }
+
+    /// Write timestamp `index` if this frame is measured.
+    pub fn write(&mut self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        if self.pending.is_some() {
+            return;
+        }
+
+        encoder.write_timestamp(&self.query_set, index);
+        self.recording = true;
+    }
+
+    /// Resolve the timestamps written this frame into the read back buffer.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.recording {
+            return;
+        }
+
+        encoder.resolve_query_set(&self.query_set, 0..Self::QUERIES, &self.read_buffer, 0);
+    }
+
+    /// Start reading back the timestamps, call after submitting the frame.
+    pub fn map(&mut self) {
+        if !self.recording {
+            return;
+        }
+
+        self.recording = false;
+
+        let (sender, receiver) = mpsc::channel();
+        (self.read_buffer.slice(..)).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        self.pending = Some(receiver);
+    }
+
+    /// Update the stats if the read back has finished.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        let Some(ref pending) = self.pending else {
+            return;
+        };
+
+        device.poll(wgpu::Maintain::Poll);
+
+        let result = match pending.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+        };
+
+        self.pending = None;
+
+        if result.is_err() {
+            return;
+        }
+
+        let slice = self.read_buffer.slice(..);
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        self.read_buffer.unmap();
+
+        let duration = |start: u64, end: u64| {
+            let nanos = end.saturating_sub(start) as f64 * self.period as f64;
+            Duration::from_nanos(nanos as u64)
+        };
+
+        self.stats = Some(RenderStats {
+            octree_pass: duration(timestamps[0], timestamps[1]),
+            tonemap_pass: duration(timestamps[1], timestamps[2]),
+        });
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{editor::EditMode, format::FormatError, octree::Octree, render::Camera};
+
+/// Everything needed to resume editing where the last run left off.
+///
+/// Sessions are stored as a small header, the other fields as toml, then the
+/// octree in the `.oak` format, see [`Octree::write`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(skip)]
+    pub octree: Octree,
+    pub camera: Camera,
+    /// The name of the active brush.
+    pub brush: String,
+    pub edit_mode: EditMode,
+}
+
+impl Session {
+    pub const PATH: &str = "oakum.session";
+    pub const MAGIC: [u8; 4] = *b"OAKS";
+    pub const VERSION: u32 = 1;
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), FormatError> {
+        let header =
+            toml::to_string(self).map_err(|e| FormatError::InvalidSession(e.to_string()))?;
+
+        writer.write_all(&Self::MAGIC)?;
+        writer.write_all(&Self::VERSION.to_le_bytes())?;
+        writer.write_all(&(header.len() as u32).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+
+        self.octree.write(writer)
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+
+        if bytes != Self::MAGIC {
+            return Err(FormatError::InvalidHeader);
+        }
+
+        reader.read_exact(&mut bytes)?;
+        let version = u32::from_le_bytes(bytes);
+        if version != Self::VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        // the length isn't trusted, only what the reader actually holds is allocated
+        reader.read_exact(&mut bytes)?;
+        let len = u32::from_le_bytes(bytes) as u64;
+
+        let mut header = Vec::new();
+        reader.by_ref().take(len).read_to_end(&mut header)?;
+
+        if (header.len() as u64) < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let header = String::from_utf8(header)
+            .map_err(|_| FormatError::InvalidSession(String::from("header isn't utf-8")))?;
+        let mut session: Self =
+            toml::from_str(&header).map_err(|e| FormatError::InvalidSession(e.to_string()))?;
+
+        session.octree = Octree::read(reader)?;
+
+        Ok(session)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FormatError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read(&mut reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use glam::Vec3;
+
+    use super::*;
+    use crate::{generate::Sphere, octree::Node};
+
+    #[test]
+    fn round_trip() {
+        let mut octree = Octree::generate(&Sphere::new(8, 4));
+        octree.set((1, 2, 3, 5), Node::emissive(255, 128, 0));
+
+        let mut camera = Camera::new(Vec3::new(1.0, -2.0, 3.5), 7.25, 75.0);
+        camera.look_at(Vec3::new(-4.0, 0.5, 2.0));
+
+        let session = Session {
+            octree,
+            camera,
+            brush: String::from("tree"),
+            edit_mode: EditMode::Replace,
+        };
+
+        let path = std::env::temp_dir().join(format!("oakum-{}.session", std::process::id()));
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.octree.structurally_eq(&session.octree));
+        assert_eq!(loaded.octree.len(), session.octree.len());
+        assert_eq!(loaded.camera.position, session.camera.position);
+        assert_eq!(loaded.camera.rotation, session.camera.rotation);
+        assert_eq!(loaded.camera.distance, session.camera.distance);
+        assert_eq!(loaded.camera.fov, session.camera.fov);
+        assert_eq!(loaded.brush, session.brush);
+        assert_eq!(loaded.edit_mode, session.edit_mode);
+
+        let mut bytes = Vec::new();
+        session.write(&mut bytes).unwrap();
+        bytes[0] = b'X';
+        assert!(matches!(
+            Session::read(&mut bytes.as_slice()),
+            Err(FormatError::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn huge_header() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&Session::MAGIC);
+        bytes.extend_from_slice(&Session::VERSION.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"brush = \"tree\"");
+
+        assert!(matches!(
+            Session::read(&mut bytes.as_slice()),
+            Err(FormatError::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use crate::{
+    format::{Format, FormatError},
+    octree::Octree,
+};
+
+/// An octree stamped into the world by [`Octree::union`] and [`Octree::difference`].
+#[derive(Clone, Debug)]
+pub struct Brush {
+    pub name: String,
+    pub octree: Octree,
+    /// The depth of the deepest leaf, one brush cell maps to one edited cell.
+    pub depth: u32,
+}
+
+impl Brush {
+    pub fn new(name: impl Into<String>, octree: Octree) -> Self {
+        let depth = (octree.iter_nodes())
+            .map(|(branch, _)| branch.depth)
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            name: name.into(),
+            octree,
+            depth,
+        }
+    }
+
+    /// Load a brush from any readable format, named after the file stem.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FormatError> {
+        let path = path.as_ref();
+        let octree = Format::from_path(path)?.read(path)?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        Ok(Self::new(name, octree))
+    }
+
+    /// Returns the depth to pass to `union` and `difference` when stamping at a
+    /// branch of `edit_depth`, so the brush keeps its resolution.
+    ///
+    /// Returns `None` if the brush is deeper than `edit_depth`, it can't be
+    /// stamped without losing cells.
+    pub const fn join_depth(&self, edit_depth: u32) -> Option<u32> {
+        edit_depth.checked_sub(self.depth)
+    }
+}
+
+/// The brushes available for editing, one of which is active.
+#[derive(Clone, Debug)]
+pub struct BrushLibrary {
+    brushes: Vec<Brush>,
+    active: usize,
+}
+
+impl BrushLibrary {
+    /// Create a library with `brush` as the only, active brush.
+    pub fn new(brush: Brush) -> Self {
+        Self {
+            brushes: vec![brush],
+            active: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.brushes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brushes.is_empty()
+    }
+
+    pub fn brushes(&self) -> &[Brush] {
+        &self.brushes
+    }
+
+    /// Add `brush`, returning its index.
+    pub fn insert(&mut self, brush: Brush) -> usize {
+        self.brushes.push(brush);
+        self.brushes.len() - 1
+    }
+
+    /// Load a brush from `path`, returning its index.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<usize, FormatError> {
+        Ok(self.insert(Brush::load(path)?))
+    }
+
+    pub fn active(&self) -> &Brush {
+        &self.brushes[self.active]
+    }
+
+    pub const fn active_index(&self) -> usize {
+        self.active
+    }
+
+    /// Make the brush at `index` active, returns false if there is no such brush.
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.brushes.len() {
+            return false;
+        }
+
+        self.active = index;
+        true
+    }
+
+    /// Make the brush called `name` active, returns false if there is no such brush.
+    pub fn select_name(&mut self, name: &str) -> bool {
+        match self.brushes.iter().position(|brush| brush.name == name) {
+            Some(index) => self.select(index),
+            None => false,
+        }
+    }
+
+    /// Cycle to the next brush, wrapping around to the first.
+    pub fn select_next(&mut self) -> &Brush {
+        self.active = (self.active + 1) % self.brushes.len();
+        self.active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use glam::IVec3;
+
+    use super::*;
+    use crate::{
+        generate::Sphere,
+        octree::{Branch, Node},
+    };
+
+    #[test]
+    fn stamp_loaded_brush() {
+        // an asymmetric shape, so a misplaced or mirrored stamp is caught
+        let mut shape = Octree::new();
+        shape.set((-2, -2, -2, 3), Node::solid(255, 0, 0));
+        shape.set((1, -2, 0, 3), Node::solid(0, 255, 0));
+        shape.set((0, 0, 0, 2), Node::solid(0, 0, 255));
+
+        let path = std::env::temp_dir().join(format!("oakum-{}-brush.oak", std::process::id()));
+        shape.save(&path).unwrap();
+
+        let mut library =
+            BrushLibrary::new(Brush::new("sphere", Octree::generate(&Sphere::new(32, 6))));
+        let index = library.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(library.select_name(&format!("oakum-{}-brush", std::process::id())));
+        assert_eq!(library.active_index(), index);
+
+        let brush = library.active();
+        assert_eq!(brush.depth, 3);
+
+        let edit_depth = 6;
+        let branch = Branch::new(IVec3::new(5, -7, 12), edit_depth);
+
+        let mut octree = Octree::new();
+        let depth = brush.join_depth(edit_depth).unwrap();
+        octree.union(branch, depth, &brush.octree);
+
+        // every brush cell lands at the same offset from the stamp location
+        let cells: Vec<_> = brush.octree.iter_solid_cells(brush.depth).collect();
+        assert_eq!(octree.count_solid(edit_depth), cells.len() as u64);
+
+        for (cell, node) in cells {
+            assert_eq!(
+                octree.get(Branch::new(branch.path + cell, edit_depth)),
+                node
+            );
+        }
+
+        octree.difference(branch, depth, &brush.octree);
+        assert_eq!(octree.count_solid(edit_depth), 0);
+
+        // a brush deeper than the edit depth can't be stamped
+        assert_eq!(brush.join_depth(2), None);
+
+        assert_eq!(library.select_next().name, "sphere");
+        assert!(!library.select(2));
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use glam::{IVec3, Mat4, Vec3};
+
+use crate::{
+    format::FormatError,
+    octree::{Branch, DynamicOctree, Node, Octree, OctreeHit},
+    ray::Ray,
+};
+
+/// A world split into a grid of equally sized octree chunks.
+///
+/// Cells are addressed by global coordinates at [`ChunkedWorld::chunk_depth`],
+/// so edits don't need to know which chunk they land in. Chunks are created
+/// when first written to, and can be unloaded to disk and loaded back.
+///
+/// The renderer draws the chunks within [`ChunkedWorld::draw_distance`] of the
+/// camera, each from its own texture, and frees the textures of chunks out of range.
+pub struct ChunkedWorld {
+    /// The depth of the cells within each chunk.
+    pub chunk_depth: u32,
+    /// The side length of a chunk in world space.
+    pub chunk_size: f32,
+    /// Chunks further than this from the camera aren't drawn.
+    pub draw_distance: f32,
+    chunks: HashMap<IVec3, DynamicOctree>,
+    /// Bumped whenever a chunk may have changed, loaded or unloaded.
+    generation: u64,
+}
+
+impl Default for ChunkedWorld {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CHUNK_DEPTH, Self::DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl ChunkedWorld {
+    pub const DEFAULT_CHUNK_DEPTH: u32 = 6;
+    /// The size of the octree of [`World`](super::World) with its default transform.
+    pub const DEFAULT_CHUNK_SIZE: f32 = 20.0;
+    pub const DEFAULT_DRAW_DISTANCE: f32 = 100.0;
+
+    pub fn new(chunk_depth: u32, chunk_size: f32) -> Self {
+        Self {
+            chunk_depth,
+            chunk_size,
+            draw_distance: Self::DEFAULT_DRAW_DISTANCE,
+            chunks: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// Returns a counter that changes whenever a chunk may have changed, or
+    /// chunks were loaded or unloaded.
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the number of cells along each axis of a chunk.
+    pub const fn chunk_cells(&self) -> i32 {
+        1 << self.chunk_depth
+    }
+
+    /// Returns the chunk containing `cell`, and the branch of `cell` within it.
+    pub fn locate(&self, cell: IVec3) -> (IVec3, Branch) {
+        let cells = self.chunk_cells();
+
+        let chunk = IVec3::new(
+            cell.x.div_euclid(cells),
+            cell.y.div_euclid(cells),
+            cell.z.div_euclid(cells),
+        );
+        let local = cell - chunk * cells - cells / 2;
+
+        (chunk, Branch::new(local, self.chunk_depth))
+    }
+
+    /// Returns the transform from the normalized space of `chunk` to world space.
+    pub fn chunk_transform(&self, chunk: IVec3) -> Mat4 {
+        let center = (chunk.as_vec3() + 0.5) * self.chunk_size;
+        Mat4::from_scale_rotation_translation(
+            Vec3::splat(self.chunk_size / 2.0),
+            Default::default(),
+            center,
+        )
+    }
+
+    pub fn chunk(&self, chunk: IVec3) -> Option<&DynamicOctree> {
+        self.chunks.get(&chunk)
+    }
+
+    pub fn chunk_mut(&mut self, chunk: IVec3) -> Option<&mut DynamicOctree> {
+        self.generation += 1;
+        self.chunks.get_mut(&chunk)
+    }
+
+    /// Iterate the loaded chunks and their coordinates.
+    pub fn chunks(&self) -> impl Iterator<Item = (IVec3, &DynamicOctree)> {
+        self.chunks.iter().map(|(&chunk, octree)| (chunk, octree))
+    }
+
+    /// Iterate the loaded chunks that are at least partially within `radius` of `point`.
+    pub fn chunks_near(
+        &self,
+        point: Vec3,
+        radius: f32,
+    ) -> impl Iterator<Item = (IVec3, &DynamicOctree)> {
+        self.chunks().filter(move |&(chunk, _)| {
+            let min = chunk.as_vec3() * self.chunk_size;
+            let max = min + self.chunk_size;
+            point.clamp(min, max).distance(point) <= radius
+        })
+    }
+
+    pub fn get(&self, cell: IVec3) -> Node {
+        let (chunk, branch) = self.locate(cell);
+
+        match self.chunks.get(&chunk) {
+            Some(octree) => octree.get(branch),
+            None => Node::empty(),
+        }
+    }
+
+    /// Set `cell` to `node`, creating its chunk if needed.
+    pub fn set(&mut self, cell: IVec3, node: Node) {
+        let (chunk, branch) = self.locate(cell);
+
+        if node.is_empty() && !self.chunks.contains_key(&chunk) {
+            return;
+        }
+
+        let octree = self.chunks.entry(chunk).or_default();
+        octree.set(branch, node);
+        self.generation += 1;
+    }
+
+    pub fn remove(&mut self, cell: IVec3) {
+        self.set(cell, Node::empty());
+    }
+
+    /// Cast `ray` against the chunks within `max_distance` of its origin,
+    /// returning the closest hit and the chunk it is in.
+    pub fn raycast(&self, ray: Ray, max_distance: f32) -> Option<(IVec3, OctreeHit)> {
+        let mut closest: Option<(IVec3, OctreeHit)> = None;
+
+        for (chunk, octree) in self.chunks_near(ray.origin, max_distance) {
+            let Some(hit) = octree.raycast(self.chunk_transform(chunk), ray) else {
+                continue;
+            };
+
+            if hit.distance > max_distance {
+                continue;
+            }
+
+            if closest.is_none_or(|(_, closest)| hit.distance < closest.distance) {
+                closest = Some((chunk, hit));
+            }
+        }
+
+        closest
+    }
+
+    /// Returns the path `chunk` is stored at within `directory`.
+    pub fn chunk_path(directory: &Path, chunk: IVec3) -> PathBuf {
+        directory.join(format!("{}_{}_{}.oak", chunk.x, chunk.y, chunk.z))
+    }
+
+    /// Save `chunk` to `directory` and remove it from memory.
+    ///
+    /// Does nothing if the chunk isn't loaded.
+    pub fn unload(&mut self, chunk: IVec3, directory: &Path) -> Result<(), FormatError> {
+        if let Some(octree) = self.chunks.get(&chunk) {
+            octree.save(Self::chunk_path(directory, chunk))?;
+            self.chunks.remove(&chunk);
+            self.generation += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Load `chunk` from `directory`, replacing it if it is already loaded.
+    ///
+    /// Returns false if the chunk was never saved.
+    pub fn load(&mut self, chunk: IVec3, directory: &Path) -> Result<bool, FormatError> {
+        let path = Self::chunk_path(directory, chunk);
+
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        let octree = Octree::load(path)?;
+        self.chunks.insert(chunk, DynamicOctree::new(octree));
+        self.generation += 1;
+
+        Ok(true)
+    }
+
+    /// Drop the changed segments of every chunk, see [`World::take_dirty`](super::World::take_dirty).
+    ///
+    /// Chunks out of range are uploaded whole once they come back into range,
+    /// so their segments aren't kept either.
+    pub fn clear_segments(&mut self) {
+        for octree in self.chunks.values_mut() {
+            octree.clear_segments();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn edit_across_boundary() {
+        let mut world = ChunkedWorld::new(4, 16.0);
+        let white = Node::solid(255, 255, 255);
+
+        // a line of cells from the first chunk into its neighbor
+        for x in 12..20 {
+            world.set(IVec3::new(x, 3, -1), white);
+        }
+
+        let chunks: Vec<_> = world.chunks().map(|(chunk, _)| chunk).collect();
+        assert_eq!(chunks.len(), 2);
+
+        let first = world.chunk(IVec3::new(0, 0, -1)).unwrap();
+        let second = world.chunk(IVec3::new(1, 0, -1)).unwrap();
+        assert_eq!(first.count_solid(4), 4);
+        assert_eq!(second.count_solid(4), 4);
+
+        assert_eq!(world.get(IVec3::new(15, 3, -1)), white);
+        assert_eq!(world.get(IVec3::new(16, 3, -1)), white);
+        assert!(world.get(IVec3::new(20, 3, -1)).is_empty());
+
+        // a ray along the line hits the first cell in the first chunk
+        let ray = Ray::new(Vec3::new(0.0, 3.5, -0.5), Vec3::X);
+        let (chunk, hit) = world.raycast(ray, 100.0).unwrap();
+        assert_eq!(chunk, IVec3::new(0, 0, -1));
+        assert!((hit.distance - 12.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn unload_and_load() {
+        let directory = std::env::temp_dir().join(format!("oakum-chunks-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut world = ChunkedWorld::new(3, 8.0);
+        let cell = IVec3::new(-3, 9, 2);
+        world.set(cell, Node::solid(1, 2, 3));
+
+        let (chunk, _) = world.locate(cell);
+        world.unload(chunk, &directory).unwrap();
+        assert!(world.chunk(chunk).is_none());
+        assert!(world.get(cell).is_empty());
+
+        assert!(world.load(chunk, &directory).unwrap());
+        assert!(!world.load(IVec3::ZERO, &directory).unwrap());
+        assert_eq!(world.get(cell), Node::solid(1, 2, 3));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use crate::octree::{DynamicOctree, RegionSnapshot};
+
+/// Snapshots of the regions touched by recent edits, undone newest first.
+///
+/// When the snapshots take up more than `max_bytes`, the oldest are dropped.
+#[derive(Clone, Debug)]
+pub struct History {
+    snapshots: VecDeque<RegionSnapshot>,
+    bytes: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_BYTES)
+    }
+}
+
+impl History {
+    pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Returns the memory used by the snapshots in bytes.
+    pub const fn byte_size(&self) -> usize {
+        self.bytes
+    }
+
+    /// Record `snapshot`, taken right before an edit.
+    pub fn push(&mut self, snapshot: RegionSnapshot) {
+        self.bytes += snapshot.byte_size();
+        self.snapshots.push_back(snapshot);
+
+        while self.bytes > self.max_bytes {
+            let Some(oldest) = self.snapshots.pop_front() else {
+                break;
+            };
+
+            self.bytes -= oldest.byte_size();
+        }
+    }
+
+    /// Undo the latest edit, returns false if there is nothing to undo.
+    pub fn undo(&mut self, octree: &mut DynamicOctree) -> bool {
+        let Some(snapshot) = self.snapshots.pop_back() else {
+            return false;
+        };
+
+        self.bytes -= snapshot.byte_size();
+        octree.restore_region(&snapshot);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate::Sphere,
+        octree::{stamp_regions, Node, Octree},
+    };
+
+    #[test]
+    fn undo_restores_edited_cells() {
+        let brush = Octree::generate(&Sphere::new(4, 3));
+
+        let mut octree = DynamicOctree::new(Octree::generate(&Sphere::new(16, 5)));
+        let mut history = History::default();
+
+        // carve into the surface, then place outside of it
+        for (branch, remove) in [((0, 12, 0, 5), true), ((24, 24, -24, 6), false)] {
+            let regions = stamp_regions(branch, 2, &brush);
+            history.push(octree.snapshot_regions(regions));
+
+            match remove {
+                true => octree.difference(branch, 2, &brush),
+                false => octree.union(branch, 2, &brush),
+            }
+        }
+
+        let before_undo = (*octree).clone();
+
+        // an edit that isn't recorded, which undoing must leave alone
+        octree.set((-15, 0, 0, 5), Node::solid(255, 0, 0));
+
+        assert!(history.undo(&mut octree));
+        assert_eq!(history.len(), 1);
+
+        let mut expected = before_undo.clone();
+        expected.difference((24, 24, -24, 6), 2, &brush);
+        expected.set((-15, 0, 0, 5), Node::solid(255, 0, 0));
+        assert!(octree.structurally_eq(&expected));
+
+        assert!(history.undo(&mut octree));
+        assert!(!history.undo(&mut octree));
+        assert_eq!(history.byte_size(), 0);
+
+        let mut expected = Octree::generate(&Sphere::new(16, 5));
+        expected.set((-15, 0, 0, 5), Node::solid(255, 0, 0));
+        assert!(octree.structurally_eq(&expected));
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let octree = Octree::generate(&Sphere::new(8, 4));
+        let snapshot = octree.snapshot_region((0, 0, 0, 1));
+        let size = snapshot.byte_size();
+
+        let mut history = History::new(size * 2);
+        for _ in 0..3 {
+            history.push(snapshot.clone());
+        }
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.byte_size(), size * 2);
+    }
+}
@@ -0,0 +1,44 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Lights every surface from the same direction, like the sun.
+    Directional,
+    /// Lights surfaces from a point, fading with the square of the distance.
+    Point,
+}
+
+/// A light added to the sun, see [`World::lights`](crate::world::World::lights).
+///
+/// Lights don't cast shadows, only the sun does.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    /// The position of a point light in world space, or the direction towards
+    /// a directional light.
+    pub position: Vec3,
+    /// The color of the light, in linear hdr.
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            position: direction.normalize_or_zero(),
+            color,
+            intensity,
+        }
+    }
+
+    pub fn point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            position,
+            color,
+            intensity,
+        }
+    }
+}
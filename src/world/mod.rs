@@ -0,0 +1,398 @@
+mod brush;
+mod chunked;
+mod history;
+mod light;
+mod scene;
+
+pub use brush::*;
+pub use chunked::*;
+pub use history::*;
+pub use light::*;
+pub use scene::*;
+
+use deref_derive::{Deref, DerefMut};
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::{
+    app::UpdateContext,
+    error::OakumError,
+    octree::{Branch, DynamicOctree, Octree, OctreeHit, Segment},
+    ray::Ray,
+    render::Camera,
+};
+
+/// A surface found by [`World::pick`], in world space.
+#[derive(Clone, Copy, Debug, Deref, DerefMut)]
+pub struct WorldHit {
+    /// The ray that was cast.
+    pub ray: Ray,
+    #[deref]
+    pub hit: OctreeHit,
+}
+
+pub struct World {
+    pub camera: Camera,
+    pub octree: DynamicOctree,
+    /// A pending edit drawn translucently over `octree` without changing it,
+    /// see [`World::commit_preview`].
+    ///
+    /// The preview is uploaded on its own, so changing it doesn't re-upload `octree`.
+    pub preview: DynamicOctree,
+    /// Seconds of scene time, advanced by [`World::update`] and used to animate shaders.
+    pub time: f32,
+    /// Lights added to the sun, only the first
+    /// [`MAX_LIGHTS`](crate::render::MAX_LIGHTS) are drawn.
+    pub lights: Vec<Light>,
+    /// The most nodes edits may grow `octree` to, edits that could grow it
+    /// further are refused, see [`World::check_stamp`].
+    pub max_nodes: u32,
+    /// Chunks drawn alongside `octree` in world space, without the world transform.
+    pub chunks: ChunkedWorld,
+    transform: Mat4,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    /// An octree texture of 1Gb.
+    pub const DEFAULT_MAX_NODES: u32 = 1 << 28;
+
+    pub fn new() -> Self {
+        Self {
+            camera: Camera::default(),
+            octree: DynamicOctree::empty(),
+            preview: DynamicOctree::empty(),
+            time: 0.0,
+            lights: Vec::new(),
+            max_nodes: Self::DEFAULT_MAX_NODES,
+            chunks: ChunkedWorld::default(),
+            transform: Mat4::from_scale(Vec3::splat(10.0)),
+        }
+    }
+
+    /// Returns the transform from normalized octree space to world space.
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    /// Move, rotate or scale the octree as a whole.
+    ///
+    /// Both rendering and [`World::pick`] use this transform, so they stay consistent.
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    /// Cast a ray through `screen_position` of a `width` by `height` view from `camera`.
+    ///
+    /// This is what edits are made against, [`Renderer::pick`](crate::render::Renderer::pick)
+    /// reads back what the gpu drew for checking that the two agree.
+    pub fn pick(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        screen_position: Vec2,
+    ) -> Option<WorldHit> {
+        let ray = camera.mouse_ray(width, height, screen_position);
+        let hit = self.octree.raycast(self.transform(), ray)?;
+
+        Some(WorldHit { ray, hit })
+    }
+
+    /// Point the camera at the solid leaves of the octree so they fill the view,
+    /// returns false if the octree is empty.
+    ///
+    /// Transforms flattening the octree are fine, the camera keeps a minimum
+    /// distance, see [`Camera::frame`].
+    pub fn frame_octree(&mut self) -> bool {
+        let Some((center, radius)) = self.octree.bounding_sphere() else {
+            return false;
+        };
+
+        // the longest axis of the transform, so the sphere keeps enclosing the leaves
+        let transform = self.transform();
+        let scale = Vec3::new(
+            transform.x_axis.truncate().length(),
+            transform.y_axis.truncate().length(),
+            transform.z_axis.truncate().length(),
+        );
+
+        let center = transform.transform_point3(center);
+        self.camera.frame(center, radius * scale.max_element());
+
+        true
+    }
+
+    pub fn update(&mut self, cx: UpdateContext) {
+        self.time += cx.delta;
+        self.camera.update(cx);
+    }
+
+    pub fn clear_preview(&mut self) {
+        if !self.preview.is_empty() {
+            self.preview.clear();
+        }
+    }
+
+    /// Returns an error if stamping `other` into `branch` could grow the octree
+    /// past [`World::max_nodes`], or set cells deeper than [`Octree::MAX_DEPTH`].
+    pub fn check_stamp(
+        &self,
+        branch: Branch,
+        depth: u32,
+        other: &Octree,
+    ) -> Result<(), OakumError> {
+        Octree::check_stamp(branch, depth, other)?;
+        self.check_len(self.octree.len_after_stamp(branch, depth, other))
+    }
+
+    fn check_len(&self, nodes: u64) -> Result<(), OakumError> {
+        match nodes > self.max_nodes as u64 {
+            true => Err(OakumError::TooManyNodes {
+                nodes,
+                max: self.max_nodes,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Add the solid leaves of the preview to the octree, then clear the preview.
+    ///
+    /// If that could grow the octree past [`World::max_nodes`], neither is changed.
+    pub fn commit_preview(&mut self) -> Result<(), OakumError> {
+        let solid = (self.preview.iter_nodes())
+            .filter(|(_, node)| node.is_solid())
+            .map(|(branch, _)| branch);
+        self.check_len(self.octree.len_after_set(solid))?;
+
+        for (branch, &node) in self.preview.iter_nodes() {
+            if node.is_solid() {
+                self.octree.set(branch, node);
+            }
+        }
+
+        self.clear_preview();
+
+        Ok(())
+    }
+
+    /// Take the segments of the octree changed since the last call.
+    ///
+    /// Segments accumulate until taken, so whoever uploads the octree should call
+    /// this once the changes have been uploaded, this also drops the changes to
+    /// the preview and the chunks.
+    pub fn take_dirty(&mut self) -> Vec<Segment> {
+        self.preview.clear_segments();
+        self.chunks.clear_segments();
+        self.octree.take_segments()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec2, UVec3};
+
+    use super::*;
+    use crate::{
+        generate::{Generate, Sphere},
+        input::{Keyboard, Mouse},
+        octree::{Node, Octree},
+    };
+
+    #[test]
+    fn time_advances() {
+        let mut world = World::new();
+        let mouse = Mouse::default();
+        let keyboard = Keyboard::default();
+
+        let mut last = world.time;
+        for delta in [0.016, 0.0, 0.5, 0.001] {
+            let cx = UpdateContext {
+                delta,
+                mouse: &mouse,
+                keyboard: &keyboard,
+                cursor: Vec2::ZERO,
+                viewport: UVec2::ZERO,
+            };
+
+            world.update(cx);
+
+            assert!(world.time >= last);
+            last = world.time;
+        }
+
+        assert!((world.time - 0.517).abs() < 1e-6);
+    }
+
+    #[test]
+    fn frame_flat_octree() {
+        /// A single layer of cells just above the xz plane.
+        struct Plane;
+
+        impl Generate for Plane {
+            fn dimensions(&self) -> UVec3 {
+                UVec3::splat(16)
+            }
+
+            fn depth(&self) -> u32 {
+                5
+            }
+
+            fn get_node(&self, point: Vec3) -> Option<Node> {
+                (point.y > 0.0 && point.y < 1.0 / 16.0).then_some(Node::solid(255, 255, 255))
+            }
+        }
+
+        let mut world = World::new();
+        assert!(!world.frame_octree());
+
+        world.octree = DynamicOctree::new(Octree::generate(&Plane));
+        assert_eq!(world.octree.count_solid(5), 32 * 32);
+
+        assert!(world.frame_octree());
+        assert!(
+            world
+                .camera
+                .position
+                .distance(Vec3::new(0.0, 10.0 / 32.0, 0.0))
+                < 1e-4
+        );
+        assert!(world.camera.distance > 10.0 * 2.0f32.sqrt());
+
+        // squashed to zero thickness, and then to a point
+        for scale in [Vec3::new(10.0, 0.0, 10.0), Vec3::ZERO] {
+            world.set_transform(Mat4::from_scale(scale));
+            assert!(world.frame_octree());
+
+            let camera = &world.camera;
+            assert!(camera.position.is_finite() && camera.eye().is_finite());
+            assert!(camera.distance.is_finite() && camera.distance > 0.0);
+        }
+
+        let mut camera = Camera::default();
+        camera.frame(Vec3::ONE, f32::NAN);
+        assert!(camera.distance.is_finite() && camera.distance > 0.0);
+    }
+
+    #[test]
+    fn pick_center() {
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        world.camera.distance = 30.0;
+
+        let hit = world
+            .pick(&world.camera, 64, 64, Vec2::new(32.0, 32.0))
+            .unwrap();
+
+        // the sphere has a radius of 10 world units
+        assert!((hit.distance - 20.0).abs() < 1.5);
+        assert!((hit.point.length() - 10.0).abs() < 1.5);
+        assert!(hit.normal_vec3().dot(hit.ray.direction) < 0.0);
+
+        let miss = world.pick(&world.camera, 64, 64, Vec2::ZERO);
+        assert!(miss.is_none());
+    }
+
+    #[test]
+    fn dirty_accumulates() {
+        let mut world = World::new();
+        assert!(world.take_dirty().is_empty());
+
+        world.octree.set((0, 0, 0, 1), Node::solid(255, 255, 255));
+        world.octree.set((5, 5, 5, 4), Node::solid(255, 255, 255));
+        let mouse = Mouse::default();
+        let keyboard = Keyboard::default();
+        world.update(UpdateContext {
+            delta: 0.016,
+            mouse: &mouse,
+            keyboard: &keyboard,
+            cursor: Vec2::ZERO,
+            viewport: UVec2::ZERO,
+        });
+        world
+            .octree
+            .set((-8, -8, -8, 4), Node::solid(255, 255, 255));
+
+        let dirty = world.take_dirty();
+        let written: u32 = dirty.iter().map(|s| s.len).sum();
+        assert!(!dirty.is_empty());
+        assert_eq!(dirty.last().unwrap().end(), world.octree.len());
+        assert!(written > 1);
+
+        assert!(world.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn preview_is_independent() {
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+        world.take_dirty();
+
+        let before = (*world.octree).clone();
+
+        let brush = Octree::generate(&Sphere::new(2, 2));
+        world.preview.union((12, 0, 0, 5), 3, &brush);
+        world.preview.set((0, 0, 0, 2), Node::emissive(255, 0, 0));
+        assert!(!world.preview.is_empty());
+
+        assert!(world.octree.structurally_eq(&before));
+        assert!(world.octree.segments().is_empty());
+
+        // dropping the preview leaves the octree alone
+        world.clear_preview();
+        assert!(world.preview.is_empty());
+        assert!(world.octree.structurally_eq(&before));
+
+        // committing applies it once, and empties the preview
+        world.preview.union((12, 0, 0, 5), 3, &brush);
+        world.commit_preview().unwrap();
+        assert!(world.preview.is_empty());
+
+        let mut expected = before.clone();
+        expected.union((12, 0, 0, 5), 3, &brush);
+        assert!(world.octree.structurally_eq(&expected));
+        assert!(!world.take_dirty().is_empty());
+        assert!(world.preview.segments().is_empty());
+    }
+
+    #[test]
+    fn refuse_edits_over_max_nodes() {
+        let mut world = World::new();
+        world.octree = DynamicOctree::new(Octree::generate(&Sphere::new(8, 4)));
+
+        let brush = Octree::generate(&Sphere::new(4, 3));
+        let branch = Branch::new(IVec3::new(12, 0, 0), 5);
+
+        let estimate = world.octree.len_after_stamp(branch, 2, &brush);
+        assert!(estimate > world.octree.len() as u64);
+
+        let mut expected = (*world.octree).clone();
+        expected.union(branch, 2, &brush);
+        assert!(expected.len() as u64 <= estimate);
+
+        // room for the octree as it is, but not the edit
+        world.max_nodes = world.octree.len();
+        assert!(matches!(
+            world.check_stamp(branch, 2, &brush),
+            Err(OakumError::TooManyNodes { .. })
+        ));
+
+        let before = (*world.octree).clone();
+        world.preview.union(branch, 2, &brush);
+        assert!(matches!(
+            world.commit_preview(),
+            Err(OakumError::TooManyNodes { .. })
+        ));
+        assert!(world.octree.structurally_eq(&before));
+        assert_eq!(world.octree.len(), before.len());
+        assert!(!world.preview.is_empty());
+
+        world.max_nodes = estimate as u32;
+        world.commit_preview().unwrap();
+        assert!(world.octree.structurally_eq(&expected));
+    }
+}
@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    generate::{GrassBlock, Sphere, Water},
+    octree::{Node, Octree},
+};
+
+/// The world opened when no world file is given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scene {
+    /// Nothing, so startup is instant.
+    #[default]
+    Empty,
+    /// A small island of grass blocks in water, lit by a lamp.
+    Demo,
+}
+
+impl Scene {
+    /// The depth of the cells scenes are assembled at.
+    pub const DEPTH: u32 = 10;
+
+    pub fn build(self) -> Octree {
+        match self {
+            Self::Empty => Octree::new(),
+            Self::Demo => Self::demo(),
+        }
+    }
+
+    fn demo() -> Octree {
+        let mut octree = Octree::new();
+
+        // a 64 cell wide pool, the grass blocks cover the water inside them
+        let water = Octree::generate(&Water::new(0.2, 6));
+        octree.union((0, 0, 0, Self::DEPTH), Self::DEPTH - 6, &water);
+
+        let grass = Octree::generate(&GrassBlock);
+
+        for x in -2..2 {
+            for z in -2..2 {
+                // leave a gap for the water to show through
+                if x == 1 && z == -2 {
+                    continue;
+                }
+
+                octree.union((x * 16 + 8, 8, z * 16 + 8, Self::DEPTH), 5, &grass);
+            }
+        }
+
+        let post = Node::solid(90, 70, 50);
+        for y in 14..20 {
+            octree.set((0, y, 0, Self::DEPTH), post);
+        }
+
+        let mut lamp = Octree::generate(&Sphere::new(4, 3));
+        lamp.apply(|_, _| Node::emissive(255, 220, 150));
+        octree.union((0, 23, 0, Self::DEPTH), 7, &lamp);
+
+        octree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demo_scene() {
+        let octree = Scene::Demo.build();
+
+        let nodes: Vec<_> = (octree.iter_nodes())
+            .map(|(_, &node)| node)
+            .filter(|node| node.is_solid())
+            .collect();
+
+        assert!(nodes.iter().any(|n| n.is_shadow() && !n.is_emissive()));
+        assert!(nodes.iter().any(|n| !n.is_shadow()));
+        assert!(nodes.iter().any(|n| n.is_emissive()));
+
+        assert_eq!(Scene::Empty.build().len(), 1);
+    }
+}
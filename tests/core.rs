@@ -0,0 +1,30 @@
+//! Uses the cpu core the way a tool without a window would, run it with
+//! `cargo test --no-default-features --test core` to check that the core
+//! still builds without the `render` feature.
+
+use glam::{Mat4, Vec3};
+use oakum::{
+    format::write_obj,
+    generate::Sphere,
+    octree::{Node, Octree},
+    ray::Ray,
+};
+
+#[test]
+fn core_without_render() {
+    let mut octree = Octree::generate(&Sphere::new(8, 4));
+    octree.difference((0, 4, 0, 4), 1, &Octree::generate(&Sphere::new(4, 3)));
+    octree.set((-4, 0, 0, 4), Node::solid(255, 0, 0));
+
+    let ray = Ray::new(Vec3::new(-4.0, 0.1, 0.1), Vec3::X);
+    assert!(octree.raycast(Mat4::IDENTITY, ray).is_some());
+
+    let mut bytes = Vec::new();
+    octree.write(&mut bytes).unwrap();
+    let read = Octree::read(&mut bytes.as_slice()).unwrap();
+    assert!(read.structurally_eq(&octree));
+
+    let mut obj = Vec::new();
+    write_obj(&octree, &mut obj).unwrap();
+    assert!(!obj.is_empty());
+}